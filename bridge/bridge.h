@@ -7,7 +7,27 @@
 
 #define AIRX_VERSION 20230802
 
-#define AIRX_COMPATIBLE_NUMBER 4
+#define AIRX_COMPATIBLE_NUMBER 8
+
+#define AIRX_OK 0
+
+#define AIRX_ERROR_INVALID_ARGUMENT 1
+
+#define AIRX_ERROR_NOT_FOUND 2
+
+#define AIRX_ERROR_IO 3
+
+#define AIRX_ERROR_TIMED_OUT 4
+
+#define AIRX_ERROR_PERMISSION_DENIED 5
+
+#define AIRX_ERROR_MALFORMED_PACKET 6
+
+#define AIRX_ERROR_FRAME_TOO_LARGE 7
+
+#define AIRX_ERROR_CRYPTO 8
+
+#define AIRX_ERROR_INCOMPATIBLE_PEER 9
 
 typedef struct AirXService AirXService;
 
@@ -17,7 +37,9 @@ int32_t airx_compatibility_number(void);
 
 uint64_t airx_version_string(char *buffer);
 
-void airx_init(void);
+uint32_t airx_last_error_message(char *buffer, uint32_t buffer_len);
+
+int32_t airx_init(void);
 
 struct AirXService *airx_create(uint16_t discovery_service_server_port,
                                 uint16_t discovery_service_client_port,
@@ -26,38 +48,114 @@ struct AirXService *airx_create(uint16_t discovery_service_server_port,
                                 uint16_t text_service_listen_port,
                                 uint32_t group_identifier);
 
-void airx_lan_discovery_service(struct AirXService *airx_ptr, bool (*should_interrupt)(void));
+int32_t airx_set_group_passphrase(struct AirXService *airx_ptr,
+                                  const char *passphrase,
+                                  uint32_t passphrase_len);
+
+int32_t airx_set_accept_unsigned_discovery(struct AirXService *airx_ptr, bool accept);
+
+int32_t airx_set_discovery_mode(struct AirXService *airx_ptr, uint32_t mode);
+
+int32_t airx_set_multicast_group(struct AirXService *airx_ptr,
+                                 const char *group,
+                                 uint32_t group_len,
+                                 uint32_t ttl);
+
+int32_t airx_set_mdns_enabled(struct AirXService *airx_ptr, bool enabled);
+
+int32_t airx_set_peer_liveness(struct AirXService *airx_ptr,
+                               uint64_t heartbeat_interval_millis,
+                               uint64_t peer_ttl_millis);
+
+int32_t airx_set_data_directory(struct AirXService *airx_ptr, const char *path, uint32_t path_len);
+
+int32_t airx_set_device_info(struct AirXService *airx_ptr,
+                             const char *display_name,
+                             uint32_t display_name_len,
+                             uint32_t device_type);
+
+int32_t airx_set_untrusted_policy(struct AirXService *airx_ptr, uint32_t policy);
+
+uint32_t airx_verification_code(struct AirXService *airx_ptr,
+                                const char *device_id,
+                                uint32_t device_id_len,
+                                char *buffer,
+                                uint32_t buffer_len);
+
+int32_t airx_pair(struct AirXService *airx_ptr, const char *device_id, uint32_t device_id_len);
+
+int32_t airx_unpair(struct AirXService *airx_ptr, const char *device_id, uint32_t device_id_len);
+
+uint32_t airx_list_trusted(struct AirXService *airx_ptr, char *buffer, uint32_t buffer_len);
+
+uint32_t airx_list_quarantined(struct AirXService *airx_ptr, char *buffer, uint32_t buffer_len);
+
+int32_t airx_release_quarantined(struct AirXService *airx_ptr, const char *host, uint32_t host_len);
+
+int32_t airx_set_default_access(struct AirXService *airx_ptr, bool allow);
+
+int32_t airx_add_access_rule(struct AirXService *airx_ptr, const char *rule, uint32_t rule_len, bool allow);
+
+int32_t airx_clear_access_rules(struct AirXService *airx_ptr);
+
+int32_t airx_set_connection_limits(struct AirXService *airx_ptr,
+                                   uint32_t worker_pool_size,
+                                   uint32_t max_connections,
+                                   uint32_t max_connections_per_peer,
+                                   uint32_t backlog_policy);
+
+int32_t airx_set_frame_limits(struct AirXService *airx_ptr,
+                              uint32_t max_text_frame_size,
+                              uint32_t max_file_part_frame_size,
+                              uint64_t oversized_frame_penalty_millis);
+
+int32_t airx_stop(struct AirXService *airx_ptr);
+
+int32_t airx_lan_discovery_service(struct AirXService *airx_ptr, bool (*should_interrupt)(void));
+
+int32_t airx_lan_discovery_service_with_events(struct AirXService *airx_ptr,
+                                               void (*peer_event_callback_c)(uint8_t, const char*, uint32_t, const char*, uint32_t),
+                                               bool (*should_interrupt)(void));
 
-void airx_data_service(struct AirXService *airx_ptr,
-                       void (*text_callback_c)(const char*, uint32_t, const char*, uint32_t),
-                       void (*file_coming_callback_c)(uint64_t, const char*, uint32_t, const char*, uint32_t),
-                       void (*file_sending_callback_c)(uint8_t, uint64_t, uint64_t, uint8_t),
-                       bool (*file_part_callback_c)(uint8_t, uint64_t, uint64_t, const uint8_t*),
-                       bool (*should_interrupt)(void));
+int32_t airx_data_service(struct AirXService *airx_ptr,
+                          void (*text_callback_c)(const char*, uint32_t, const char*, uint32_t),
+                          void (*file_coming_callback_c)(const char*, uint32_t, uint64_t, const char*, uint32_t, const char*, uint32_t),
+                          void (*file_sending_callback_c)(const char*, uint32_t, uint64_t, uint64_t, uint8_t),
+                          bool (*file_part_callback_c)(const char*, uint32_t, uint64_t, uint64_t, const uint8_t*),
+                          bool (*should_interrupt)(void));
 
-bool airx_lan_broadcast(struct AirXService *airx_ptr);
+int32_t airx_lan_broadcast(struct AirXService *airx_ptr);
 
 uint32_t airx_get_peers(struct AirXService *airx_ptr, char *buffer);
 
-void airx_send_text(struct AirXService *airx_ptr,
-                    const char *host,
-                    uint32_t host_len,
-                    char *text,
-                    uint32_t text_len);
-
-void airx_broadcast_text(struct AirXService *airx_ptr, char *text, uint32_t len);
-
-void airx_try_send_file(struct AirXService *airx_ptr,
-                        const char *host,
-                        uint32_t host_len,
-                        const char *file_path,
-                        uint32_t file_path_len);
-
-void airx_respond_to_file(struct AirXService *airx_ptr,
-                          const char *host,
-                          uint32_t host_len,
-                          uint8_t file_id,
-                          uint64_t file_size,
-                          const char *file_path,
-                          uint32_t file_path_len,
-                          bool accept);
+uint32_t airx_get_peer_details(struct AirXService *airx_ptr, char *buffer, uint32_t buffer_len);
+
+int32_t airx_send_text(struct AirXService *airx_ptr,
+                       const char *host,
+                       uint32_t host_len,
+                       char *text,
+                       uint32_t text_len);
+
+int32_t airx_broadcast_text(struct AirXService *airx_ptr, char *text, uint32_t len);
+
+int32_t airx_try_send_file(struct AirXService *airx_ptr,
+                           const char *host,
+                           uint32_t host_len,
+                           const char *file_path,
+                           uint32_t file_path_len,
+                           char *transfer_id,
+                           uint32_t transfer_id_len);
+
+int32_t airx_respond_to_file(struct AirXService *airx_ptr,
+                             const char *host,
+                             uint32_t host_len,
+                             const char *transfer_id,
+                             uint32_t transfer_id_len,
+                             uint64_t file_size,
+                             const char *file_path,
+                             uint32_t file_path_len,
+                             bool accept);
+
+int32_t airx_resume_file(struct AirXService *airx_ptr,
+                         const char *transfer_id,
+                         uint32_t transfer_id_len);
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use airx::packet::data_packet::DataPacket;
+use airx::packet::protocol::serialize::Serialize;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(packet) = DataPacket::deserialize(&data.to_vec()) {
+        // Legacy packets come back as the current version, with the same content.
+        let again = DataPacket::deserialize(&packet.serialize()).unwrap();
+        assert_eq!(again.magic_number(), packet.magic_number());
+        assert_eq!(again.data(), packet.data());
+    }
+});
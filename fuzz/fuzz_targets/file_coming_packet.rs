@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use airx::packet::data::file_coming_packet::FileComingPacket;
+use airx::packet::protocol::serialize::Serialize;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(packet) = FileComingPacket::deserialize(&data.to_vec()) {
+        let again = FileComingPacket::deserialize(&packet.serialize()).unwrap();
+        assert_eq!(again, packet);
+    }
+});
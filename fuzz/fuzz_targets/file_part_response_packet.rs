@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use airx::packet::data::file_part_response_packet::FilePartResponsePacket;
+use airx::packet::protocol::serialize::Serialize;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(packet) = FilePartResponsePacket::deserialize(&data.to_vec()) {
+        let again = FilePartResponsePacket::deserialize(&packet.serialize()).unwrap();
+        assert_eq!(again, packet);
+    }
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use airx::packet::data::text_packet::TextPacket;
+use airx::packet::protocol::serialize::Serialize;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(packet) = TextPacket::deserialize(&data.to_vec()) {
+        let again = TextPacket::deserialize(&packet.serialize()).unwrap();
+        assert_eq!(again.text(), packet.text());
+    }
+});
@@ -0,0 +1,179 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::io;
+use crate::packet::data::file_coming_packet::FileComingPacketError;
+use crate::packet::data::file_part_packet::FilePartPacketError;
+use crate::packet::data::file_part_response_packet::FilePartResponsePacketError;
+use crate::packet::data::file_receive_response_packet::FileReceiveResponsePacketError;
+use crate::packet::data::local::file_sending_packet::FileSendingStatusError;
+use crate::packet::data::text_packet::TextPacketError;
+use crate::packet::data_packet::DataPacketError;
+use crate::packet::frame_limits::FrameError;
+use crate::packet::hello_packet::{HandshakeError, HelloPacketError};
+use crate::packet::mdns_packet::MdnsPacketError;
+use crate::packet::protocol::reader::ReadError;
+use crate::security::access_policy::AccessPolicyError;
+use crate::security::discovery_auth::DiscoveryAuthError;
+use crate::security::secure_channel::SecureChannelError;
+
+// Stable codes returned by the C and JNI functions. Never renumber, only append.
+pub const AIRX_OK: i32 = 0;
+pub const AIRX_ERROR_INVALID_ARGUMENT: i32 = 1;
+pub const AIRX_ERROR_NOT_FOUND: i32 = 2;
+pub const AIRX_ERROR_IO: i32 = 3;
+pub const AIRX_ERROR_TIMED_OUT: i32 = 4;
+pub const AIRX_ERROR_PERMISSION_DENIED: i32 = 5;
+pub const AIRX_ERROR_MALFORMED_PACKET: i32 = 6;
+pub const AIRX_ERROR_FRAME_TOO_LARGE: i32 = 7;
+pub const AIRX_ERROR_CRYPTO: i32 = 8;
+pub const AIRX_ERROR_INCOMPATIBLE_PEER: i32 = 9;
+
+/// Any error of the library, each kind with a stable code.
+pub enum AirXError {
+    /// A value passed in by the caller was rejected.
+    InvalidArgument(String),
+    /// A peer, device or file that was asked for is unknown.
+    NotFound(String),
+    Io(io::Error),
+    TimedOut(String),
+    /// Refused by the peer, or by our own policy.
+    PermissionDenied(String),
+    /// A packet could not be parsed.
+    MalformedPacket(String),
+    FrameTooLarge { size: usize, limit: usize },
+    /// Encryption, decryption or a signature failed.
+    Crypto(String),
+    /// The peer speaks a protocol version or feature set this build cannot talk to.
+    IncompatiblePeer(String),
+}
+
+impl AirXError {
+    pub fn code(&self) -> i32 {
+        match self {
+            AirXError::InvalidArgument(_) => AIRX_ERROR_INVALID_ARGUMENT,
+            AirXError::NotFound(_) => AIRX_ERROR_NOT_FOUND,
+            AirXError::Io(_) => AIRX_ERROR_IO,
+            AirXError::TimedOut(_) => AIRX_ERROR_TIMED_OUT,
+            AirXError::PermissionDenied(_) => AIRX_ERROR_PERMISSION_DENIED,
+            AirXError::MalformedPacket(_) => AIRX_ERROR_MALFORMED_PACKET,
+            AirXError::FrameTooLarge { .. } => AIRX_ERROR_FRAME_TOO_LARGE,
+            AirXError::Crypto(_) => AIRX_ERROR_CRYPTO,
+            AirXError::IncompatiblePeer(_) => AIRX_ERROR_INCOMPATIBLE_PEER,
+        }
+    }
+}
+
+impl Debug for AirXError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for AirXError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AirXError::InvalidArgument(s) => write!(f, "Invalid argument: {}", s),
+            AirXError::NotFound(s) => write!(f, "Not found: {}", s),
+            AirXError::Io(e) => write!(f, "I/O error: {}", e),
+            AirXError::TimedOut(s) => write!(f, "Timed out: {}", s),
+            AirXError::PermissionDenied(s) => write!(f, "Permission denied: {}", s),
+            AirXError::MalformedPacket(s) => write!(f, "Malformed packet: {}", s),
+            AirXError::FrameTooLarge { size, limit } =>
+                write!(f, "Frame of {} bytes exceeds the limit of {} bytes.", size, limit),
+            AirXError::Crypto(s) => write!(f, "Crypto error: {}", s),
+            AirXError::IncompatiblePeer(s) => write!(f, "Incompatible peer: {}", s),
+        }
+    }
+}
+
+impl Error for AirXError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AirXError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Keeps the typed errors carried inside an `io::Error` by the data transmits.
+impl From<io::Error> for AirXError {
+    fn from(e: io::Error) -> Self {
+        if let Some(FrameError::TooLarge { size, limit }) = FrameError::of(&e) {
+            return AirXError::FrameTooLarge { size: *size, limit: *limit };
+        }
+        if let Some(inner) = HandshakeError::of(&e) {
+            return AirXError::from(inner);
+        }
+        if let Some(inner) = e.get_ref().and_then(|i| i.downcast_ref::<SecureChannelError>()) {
+            return AirXError::Crypto(inner.to_string());
+        }
+        match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => AirXError::TimedOut(e.to_string()),
+            io::ErrorKind::PermissionDenied => AirXError::PermissionDenied(e.to_string()),
+            io::ErrorKind::NotFound => AirXError::NotFound(e.to_string()),
+            io::ErrorKind::InvalidInput => AirXError::InvalidArgument(e.to_string()),
+            _ => AirXError::Io(e),
+        }
+    }
+}
+
+impl From<FrameError> for AirXError {
+    fn from(e: FrameError) -> Self {
+        match e {
+            FrameError::TooLarge { size, limit } => AirXError::FrameTooLarge { size, limit },
+        }
+    }
+}
+
+macro_rules! impl_from_packet_error {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for AirXError {
+                fn from(e: $t) -> Self {
+                    AirXError::MalformedPacket(e.to_string())
+                }
+            }
+        )*
+    };
+}
+
+impl_from_packet_error!(
+    ReadError,
+    DataPacketError,
+    TextPacketError,
+    FileComingPacketError,
+    FileReceiveResponsePacketError,
+    FilePartPacketError,
+    FilePartResponsePacketError,
+    FileSendingStatusError,
+    MdnsPacketError,
+    HelloPacketError
+);
+
+impl From<&HandshakeError> for AirXError {
+    fn from(e: &HandshakeError) -> Self {
+        match e {
+            HandshakeError::IdentityMismatch => AirXError::Crypto(e.to_string()),
+            _ => AirXError::IncompatiblePeer(e.to_string()),
+        }
+    }
+}
+
+impl From<SecureChannelError> for AirXError {
+    fn from(e: SecureChannelError) -> Self {
+        AirXError::Crypto(e.to_string())
+    }
+}
+
+impl From<DiscoveryAuthError> for AirXError {
+    fn from(e: DiscoveryAuthError) -> Self {
+        AirXError::Crypto(e.to_string())
+    }
+}
+
+impl From<AccessPolicyError> for AirXError {
+    fn from(e: AccessPolicyError) -> Self {
+        AirXError::InvalidArgument(e.to_string())
+    }
+}
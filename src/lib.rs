@@ -5,6 +5,8 @@ pub mod util;
 pub mod compatibility;
 pub mod proto;
 pub mod extension;
+pub mod security;
+pub mod error;
 
 pub mod lib_util;
 pub mod lib_generic;
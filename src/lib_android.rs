@@ -4,21 +4,26 @@ use crate::network::peer::{Peer};
 use crate::service::airx_service::{AirXService};
 use crate::service::discovery_service::DiscoveryService;
 use std::sync::{Arc};
-use std::time::Duration;
 use android_logger::{Config, FilterBuilder};
 use jni::objects::{JObject, JValue};
 use jni::sys::{jboolean, jint, jlong, jshort};
 use log::{error, info, LevelFilter};
-use crate::lib_util::{AIRX_COMPATIBLE_NUMBER, AIRX_VERSION, shared_airx_version_code, CONNECTION_TIMEOUT_MILLIS, shared_airx_init, shared_airx_broadcast_text, shared_airx_try_send_file, shared_airx_respond_to_file, shared_airx_data_service};
+use crate::error::{AIRX_OK, AirXError};
+use crate::lib_util::{AIRX_COMPATIBLE_NUMBER, AIRX_VERSION, shared_airx_version_code, shared_airx_result, shared_airx_set_last_error, shared_airx_last_error_message, shared_airx_send_text, shared_airx_init, shared_airx_broadcast_text, shared_airx_try_send_file, shared_airx_respond_to_file, shared_airx_resume_file, shared_airx_data_service, shared_airx_set_group_passphrase, shared_airx_set_accept_unsigned_discovery, shared_airx_set_discovery_mode, shared_airx_set_multicast_group, shared_airx_set_mdns_enabled, shared_airx_set_peer_liveness, shared_airx_set_data_directory, shared_airx_set_device_info, shared_airx_peer_details, shared_airx_set_untrusted_policy, shared_airx_verification_code, shared_airx_pair, shared_airx_unpair, shared_airx_list_trusted, shared_airx_list_quarantined, shared_airx_release_quarantined, shared_airx_set_default_access, shared_airx_add_access_rule, shared_airx_clear_access_rules, shared_airx_set_connection_limits, shared_airx_set_frame_limits, shared_airx_stop};
 use crate::packet::data::file_coming_packet::FileComingPacket;
 use crate::packet::data::file_part_packet::FilePartPacket;
 use crate::packet::data::local::file_sending_packet::FileSendingPacket;
-use crate::packet::data::magic_numbers::MagicNumbers;
 use crate::packet::data::text_packet::TextPacket;
-use crate::packet::protocol::serialize::Serialize;
 use crate::service;
-use crate::service::context::data_service_context::DataServiceContext;
-use crate::service::data_service::{DataService};
+use crate::packet::frame_limits::FrameLimits;
+use crate::service::context::data_service_context::{ConnectionLimits, DataServiceContext};
+use crate::network::device_info::DeviceType;
+use crate::service::context::discovery_service_context::{DEFAULT_HEARTBEAT_INTERVAL_MILLIS, DEFAULT_PEER_TTL_MILLIS, DiscoveryMode, MulticastConfig};
+use crate::util::device_id::DeviceId;
+use crate::security::identity::DeviceIdentity;
+use crate::security::trust_store::UntrustedPolicy;
+use crate::security::access_policy::AccessPolicy;
+use crate::service::peer_event::PeerEvent;
 
 use self::jni::JNIEnv;
 use self::jni::objects::{JClass, JString};
@@ -49,11 +54,26 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXVersionString(
     env.new_string(version).unwrap().into_raw()
 }
 
+/// Fails with `AIRX_ERROR_INVALID_ARGUMENT` naming the argument Java passed negative.
+fn negative_argument(name: &str) -> jint {
+    shared_airx_set_last_error(&AirXError::InvalidArgument(format!("{} must not be negative.", name)))
+}
+
+/// Message of the most recent failure on the calling thread, empty if nothing failed yet.
+/// Successful calls don't clear it.
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXLastErrorMessage(
+    env: JNIEnv,
+    _: JClass,
+) -> jstring {
+    env.new_string(shared_airx_last_error_message()).unwrap().into_raw()
+}
+
 #[no_mangle]
 pub extern "C" fn Java_com_airx_AirXBridge_airXInit(
     _: JNIEnv,
     _: JClass,
-) {
+) -> jint {
     android_logger::init_once(
         Config::default()
             .with_max_level(LevelFilter::Trace)
@@ -65,7 +85,8 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXInit(
             ),
     );
 
-    shared_airx_init()
+    shared_airx_init();
+    AIRX_OK
 }
 
 #[no_mangle]
@@ -85,11 +106,31 @@ pub unsafe extern "C" fn Java_com_airx_AirXBridge_airXCreateService(
         text_service_listen_addr: addr.clone(),
         data_service_listen_port: text_service_listen_port as u16,
         group_identifier: group_identifier as u32,
+        group_key: None,
+        accept_unsigned_discovery: false,
+        discovery_mode: DiscoveryMode::Broadcast,
+        multicast: MulticastConfig::default(),
+        mdns_enabled: false,
+        heartbeat_interval_millis: DEFAULT_HEARTBEAT_INTERVAL_MILLIS,
+        peer_ttl_millis: DEFAULT_PEER_TTL_MILLIS,
+        device_id: DeviceId::generate(),
+        data_directory: None,
+        identity: DeviceIdentity::generate(),
+        untrusted_policy: UntrustedPolicy::Allow,
+        access_policy: AccessPolicy::default(),
+        connection_limits: ConnectionLimits::default(),
+        frame_limits: FrameLimits::default(),
+        oversized_frame_penalty_millis: 0,
+        display_name: None,
+        device_type: DeviceType::Phone,
     };
     let airx = AirXService::new(&config);
     let airx = match airx {
         Ok(airx) => Box::into_raw(Box::new(airx)),
-        Err(_) => std::ptr::null_mut(),
+        Err(e) => {
+            shared_airx_set_last_error(&AirXError::from(e));
+            std::ptr::null_mut()
+        }
     };
 
     info!("lib: AirX config created (addr={}:{},gid={})",
@@ -99,11 +140,298 @@ pub unsafe extern "C" fn Java_com_airx_AirXBridge_airXCreateService(
 }
 
 #[no_mangle]
-pub extern "C" fn Java_com_airx_AirXBridge_airXLanDiscoveryService(
+pub extern "C" fn Java_com_airx_AirXBridge_airXSetGroupPassphrase(
+    mut env: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    passphrase: JString,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    let passphrase = env.get_string(passphrase.as_ref()).expect("Couldn't get java string").into();
+    shared_airx_set_group_passphrase(airx, passphrase);
+    AIRX_OK
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXSetAcceptUnsignedDiscovery(
+    _: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    accept: jboolean,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    shared_airx_set_accept_unsigned_discovery(airx, accept != 0);
+    AIRX_OK
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXSetDiscoveryMode(
+    _: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    mode: jint,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    if mode < 0 {
+        return negative_argument("Discovery mode");
+    }
+    shared_airx_result(shared_airx_set_discovery_mode(airx, mode as u32))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXSetMulticastGroup(
+    mut env: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    group: JString,
+    ttl: jint,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    let group = env.get_string(group.as_ref()).expect("Couldn't get java string").into();
+    if ttl < 0 {
+        return negative_argument("TTL");
+    }
+    shared_airx_result(shared_airx_set_multicast_group(airx, group, ttl as u32))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXSetMdnsEnabled(
+    _: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    enabled: jboolean,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    shared_airx_set_mdns_enabled(airx, enabled != 0);
+    AIRX_OK
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXSetPeerLiveness(
+    _: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    heartbeat_interval_millis: jlong,
+    peer_ttl_millis: jlong,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    if heartbeat_interval_millis < 0 || peer_ttl_millis < 0 {
+        return negative_argument("Heartbeat interval or peer TTL");
+    }
+    shared_airx_result(shared_airx_set_peer_liveness(
+        airx, heartbeat_interval_millis as u64, peer_ttl_millis as u64))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXSetDataDirectory(
+    mut env: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    path: JString,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    let path = env.get_string(path.as_ref()).expect("Couldn't get java string").into();
+    shared_airx_result(shared_airx_set_data_directory(airx, path))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXSetDeviceInfo(
+    mut env: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    display_name: JString,
+    device_type: jint,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    let display_name = env.get_string(display_name.as_ref()).expect("Couldn't get java string").into();
+    if device_type < 0 {
+        return negative_argument("Device type");
+    }
+    shared_airx_result(shared_airx_set_device_info(airx, display_name, device_type as u32))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXSetUntrustedPolicy(
+    _: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    policy: jint,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    if policy < 0 {
+        return negative_argument("Untrusted policy");
+    }
+    shared_airx_result(shared_airx_set_untrusted_policy(airx, policy as u32))
+}
+
+/// Empty string, with the last error set, if the device has not been discovered.
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXVerificationCode(
+    mut env: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    device_id: JString,
+) -> jstring {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    let device_id = env.get_string(device_id.as_ref()).expect("Couldn't get java string").into();
+    let code = shared_airx_verification_code(airx, device_id).unwrap_or_else(|e| {
+        shared_airx_set_last_error(&e);
+        String::new()
+    });
+    env.new_string(code).unwrap().into_raw()
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXPair(
+    mut env: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    device_id: JString,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    let device_id = env.get_string(device_id.as_ref()).expect("Couldn't get java string").into();
+    shared_airx_result(shared_airx_pair(airx, device_id))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXUnpair(
+    mut env: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    device_id: JString,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    let device_id = env.get_string(device_id.as_ref()).expect("Couldn't get java string").into();
+    shared_airx_result(shared_airx_unpair(airx, device_id))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXListTrusted(
+    env: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+) -> jstring {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    env.new_string(shared_airx_list_trusted(airx)).unwrap().into_raw()
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXListQuarantined(
+    env: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+) -> jstring {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    env.new_string(shared_airx_list_quarantined(airx)).unwrap().into_raw()
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXReleaseQuarantined(
+    mut env: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    host: JString,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    let host = env.get_string(host.as_ref()).expect("Couldn't get java string").into();
+    shared_airx_result(shared_airx_release_quarantined(airx, host))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXSetDefaultAccess(
+    _: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    allow: jboolean,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    shared_airx_set_default_access(airx, allow != 0);
+    AIRX_OK
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXAddAccessRule(
+    mut env: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    rule: JString,
+    allow: jboolean,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    let rule = env.get_string(rule.as_ref()).expect("Couldn't get java string").into();
+    shared_airx_result(shared_airx_add_access_rule(airx, rule, allow != 0))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXClearAccessRules(
+    _: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    shared_airx_clear_access_rules(airx);
+    AIRX_OK
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXSetConnectionLimits(
+    _: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    worker_pool_size: jint,
+    max_connections: jint,
+    max_connections_per_peer: jint,
+    backlog_policy: jint,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    if worker_pool_size < 0 || max_connections < 0 || max_connections_per_peer < 0 || backlog_policy < 0 {
+        return negative_argument("Connection limit");
+    }
+    shared_airx_result(shared_airx_set_connection_limits(
+        airx,
+        worker_pool_size as u32,
+        max_connections as u32,
+        max_connections_per_peer as u32,
+        backlog_policy as u32))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXSetFrameLimits(
     _: JNIEnv,
     _: JClass,
     airx_ptr: jlong,
-) {
+    max_text_frame_size: jint,
+    max_file_part_frame_size: jint,
+    oversized_frame_penalty_millis: jlong,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    if max_text_frame_size < 0 || max_file_part_frame_size < 0 || oversized_frame_penalty_millis < 0 {
+        return negative_argument("Frame limit");
+    }
+    shared_airx_result(shared_airx_set_frame_limits(
+        airx,
+        max_text_frame_size as u32,
+        max_file_part_frame_size as u32,
+        oversized_frame_penalty_millis as u64))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXStop(
+    _: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+) -> jint {
+    let airx = unsafe { &*(airx_ptr as *mut AirXService) };
+    shared_airx_stop(airx);
+    AIRX_OK
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXLanDiscoveryService(
+    env: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+) -> jint {
     let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
     let config = airx.config();
 
@@ -116,15 +444,46 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXLanDiscoveryService(
           config.discovery_service_server_port,
           config.group_identifier);
 
-    let _ = DiscoveryService::run(
-        config.discovery_service_client_port,
-        config.discovery_service_server_port,
+    let jvm = Arc::new(env.get_java_vm().unwrap());
+    let call_peer_event_callback = move |kind: u8, peer: String, previous_peer: String| {
+        let mut env = jvm.attach_current_thread().unwrap();
+        let peer = env.new_string(peer).unwrap();
+        let previous_peer = env.new_string(previous_peer).unwrap();
+        let result = env.call_static_method(
+            "com/airx/AirXBridge",
+            "onPeerEvent",
+            "(ILjava/lang/String;Ljava/lang/String;)V",
+            &[
+                JValue::Int(kind as jint),
+                JValue::Object(JObject::from(peer).as_ref()),
+                JValue::Object(JObject::from(previous_peer).as_ref()),
+            ],
+        );
+        // Older apps do not implement onPeerEvent, which is not fatal.
+        if result.is_err() {
+            let _ = env.exception_clear();
+            error!("lib: Unable to call method onPeerEvent");
+        }
+    };
+    let peer_event_callback = move |event: &PeerEvent| {
+        call_peer_event_callback(
+            event.value(),
+            event.peer().to_string(),
+            event.previous().map(|p| p.to_string()).unwrap_or_default(),
+        );
+    };
+
+    let mut context = config.discovery_service_context();
+    context.set_shutdown(airx.shutdown_handle());
+    context.set_peer_event_callback(Some(Arc::new(Box::new(peer_event_callback))));
+    let result = DiscoveryService::run(
+        context,
         peers_ptr,
         Box::new(|| false),
-        config.group_identifier,
     );
 
     info!("lib: Discovery service stopped.");
+    shared_airx_result(result)
 }
 
 //noinspection Duplicates
@@ -133,7 +492,7 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXDataService(
     env: JNIEnv,
     _: JClass,
     airx_ptr: jlong,
-) {
+) -> jint {
     let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
     let config = airx.config();
 
@@ -156,31 +515,34 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXDataService(
     };
 
     let call_file_coming_callback_jvm = jvm.clone();
-    let call_file_coming_callback = move |file_size: u64, remote_full_path: String, socket_address: String| {
+    let call_file_coming_callback = move |transfer_id: String, file_size: u64, file_name: String, socket_address: String| {
         let mut env = call_file_coming_callback_jvm.attach_current_thread().unwrap();
-        let remote_full_path = env.new_string(remote_full_path).unwrap();
+        let transfer_id = env.new_string(transfer_id).unwrap();
+        let file_name = env.new_string(file_name).unwrap();
         let socket_address = env.new_string(socket_address).unwrap();
         env.call_static_method(
             "com/airx/AirXBridge",
             "onFileComingPacketReceived",
-            "(JLjava/lang/String;Ljava/lang/String)V",
+            "(Ljava/lang/String;JLjava/lang/String;Ljava/lang/String)V",
             &[
+                JValue::Object(JObject::from(transfer_id).as_ref()),
                 JValue::Long(file_size as jlong),
-                JValue::Object(JObject::from(remote_full_path).as_ref()),
+                JValue::Object(JObject::from(file_name).as_ref()),
                 JValue::Object(JObject::from(socket_address).as_ref()),
             ],
         ).expect("Unable to call method onFileComingPacketReceived");
     };
 
     let call_file_sending_callback_jvm = jvm.clone();
-    let call_file_sending_callback = move |file_id: u8, progress: u64, total: u64, status: u8| {
+    let call_file_sending_callback = move |transfer_id: String, progress: u64, total: u64, status: u8| {
         let mut env = call_file_sending_callback_jvm.attach_current_thread().unwrap();
+        let transfer_id = env.new_string(transfer_id).unwrap();
         env.call_static_method(
             "com/airx/AirXBridge",
             "onFileSendingPacketReceived",
-            "(SJJS)V",
+            "(Ljava/lang/String;JJS)V",
             &[
-                JValue::Short(file_id as jshort),
+                JValue::Object(JObject::from(transfer_id).as_ref()),
                 JValue::Long(progress as jlong),
                 JValue::Short(status as jshort),
                 JValue::Long(total as jlong),
@@ -189,15 +551,16 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXDataService(
     };
 
     let call_file_part_callback_jvm = jvm.clone();
-    let call_file_part_callback = move |file_id: u8, offset: u64, length: u64, data: Vec<u8>| {
+    let call_file_part_callback = move |transfer_id: String, offset: u64, length: u64, data: Vec<u8>| {
         let mut env = call_file_part_callback_jvm.attach_current_thread().unwrap();
+        let transfer_id = env.new_string(transfer_id).unwrap();
         let data = env.byte_array_from_slice(&data).unwrap();
         env.call_static_method(
             "com/airx/AirXBridge",
             "onFilePartPacketReceived",
-            "(SJJ[B)V",
+            "(Ljava/lang/String;JJ[B)V",
             &[
-                JValue::Short(file_id as jshort),
+                JValue::Object(JObject::from(transfer_id).as_ref()),
                 JValue::Long(offset as jlong),
                 JValue::Long(length as jlong),
                 JValue::Object(JObject::from(data).as_ref()),
@@ -219,6 +582,7 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXDataService(
             None => Peer::default().to_string(),
         };
         call_file_coming_callback(
+            file_coming_packet.transfer_id().to_string(),
             file_coming_packet.file_size(),
             file_coming_packet.file_name().to_string(),
             socket_addr_str,
@@ -227,7 +591,7 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXDataService(
 
     let file_sending_callback = move |file_sending_packet: &FileSendingPacket, _: Option<&Peer>| {
         call_file_sending_callback(
-            file_sending_packet.file_id(),
+            file_sending_packet.transfer_id().to_string(),
             file_sending_packet.progress(),
             file_sending_packet.total(),
             file_sending_packet.status().to_u8(),
@@ -238,7 +602,7 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXDataService(
         let data = file_part_packet.data().clone();
 
         call_file_part_callback(
-            file_part_packet.file_id(),
+            file_part_packet.transfer_id().to_string(),
             file_part_packet.offset(),
             file_part_packet.length(),
             data,
@@ -246,7 +610,7 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXDataService(
         false
     };
 
-    let context = DataServiceContext::new(
+    let mut context = DataServiceContext::new(
         config.text_service_listen_addr.to_string(),
         config.data_service_listen_port,
         Arc::new(Box::new(text_callback)),
@@ -254,9 +618,18 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXDataService(
         Arc::new(Box::new(file_sending_callback)),
         Arc::new(Box::new(file_part_callback)),
         airx.discovery_service().clone(),
+        airx.metrics(),
+        config.group_key.clone(),
     );
-
-    shared_airx_data_service(context, &config, Box::new(|| false));
+    context.set_identity(config.identity.clone());
+    context.set_trust(airx.trust_store(), config.untrusted_policy, airx.quarantine());
+    context.set_transfer_manager(airx.transfer_manager());
+    context.set_access_control(airx.access_control(), None);
+    context.set_shutdown(airx.shutdown_handle());
+    context.set_connection_limits(config.connection_limits);
+    context.set_frame_limits(config.frame_limits, config.oversized_frame_penalty());
+
+    shared_airx_result(shared_airx_data_service(context, &config, Box::new(|| false)))
 }
 
 #[deprecated]
@@ -265,17 +638,11 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXLanBroadcast(
     _: JNIEnv,
     _: JClass,
     airx_ptr: jlong,
-) -> jboolean {
+) -> jint {
     let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
     let config = airx.config();
-    match DiscoveryService::broadcast_discovery_request(
-        config.discovery_service_client_port,
-        config.discovery_service_server_port,
-        config.group_identifier,
-    ) {
-        Ok(_) => 1,
-        Err(_) => 0,
-    }
+    shared_airx_result(DiscoveryService::broadcast_discovery_request(&config.discovery_service_context())
+        .map_err(AirXError::from))
 }
 
 #[no_mangle]
@@ -297,10 +664,24 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXGetPeers(
         info!("lib: Get peers (peers={})", joined);
         return env.new_string(joined).unwrap().into_raw();
     }
-    error!("lib: Failed to get peers");
+    shared_airx_set_last_error(&AirXError::Io(std::io::Error::other("Failed to get peers.")));
     env.new_string("").unwrap().into_raw()
 }
 
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXGetPeerDetails(
+    env: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+) -> jstring {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    let details = shared_airx_peer_details(airx).unwrap_or_else(|e| {
+        shared_airx_set_last_error(&e);
+        String::new()
+    });
+    env.new_string(details).unwrap().into_raw()
+}
+
 #[no_mangle]
 pub extern "C" fn Java_com_airx_AirXBridge_airXSendText(
     mut env: JNIEnv,
@@ -308,31 +689,14 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXSendText(
     airx_ptr: jlong,
     host: JString,
     text: JString,
-) {
+) -> jint {
     let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
     let config = airx.config();
 
     let host = env.get_string(host.as_ref()).expect("Couldn't get java string").into();
     let text = env.get_string(text.as_ref()).expect("Couldn't get java string").into();
 
-    info!("lib: Sending text to (addr={}:{})",
-        host, config.data_service_listen_port);
-
-    let text_packet = match TextPacket::new(text) {
-        Ok(packet) => packet,
-        Err(err) => {
-            error!("lib: Failed to create text packet: {}", err);
-            return;
-        }
-    };
-
-    let _ = DataService::send_once_with_retry(
-        &Peer::new(&host, config.data_service_listen_port, None),
-        config.data_service_listen_port,
-        MagicNumbers::Text,
-        &text_packet.serialize(),
-        Duration::from_millis(CONNECTION_TIMEOUT_MILLIS),
-    );
+    shared_airx_result(shared_airx_send_text(host, text, &config))
 }
 
 #[no_mangle]
@@ -341,15 +705,16 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXBroadcastText(
     _: JClass,
     airx_ptr: jlong,
     text: JString,
-) {
+) -> jint {
     let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
     let config = airx.config();
     let service_disc = airx.discovery_service();
     let text = env.get_string(text.as_ref()).expect("Couldn't get java string").into();
 
-    shared_airx_broadcast_text(text, service_disc.clone(), &config);
+    shared_airx_result(shared_airx_broadcast_text(text, service_disc.clone(), &config))
 }
 
+/// The hex id the transfer goes by in the callbacks, or an empty string with the last error set.
 #[no_mangle]
 pub extern "C" fn Java_com_airx_AirXBridge_airXTrySendFile(
     mut env: JNIEnv,
@@ -357,13 +722,19 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXTrySendFile(
     airx_ptr: jlong,
     host: JString,
     file_path: JString,
-) {
+) -> jstring {
     let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
     let config = airx.config();
     let host = env.get_string(host.as_ref()).expect("Couldn't get java string").into();
     let file_path = env.get_string(file_path.as_ref()).expect("Couldn't get java string").into();
 
-    shared_airx_try_send_file(host, file_path, &config);
+    let transfer_id = shared_airx_try_send_file(host, file_path, &airx.transfer_manager(), &config)
+        .map(|id| id.to_string())
+        .unwrap_or_else(|e| {
+            shared_airx_set_last_error(&e);
+            String::new()
+        });
+    env.new_string(transfer_id).unwrap().into_raw()
 }
 
 #[no_mangle]
@@ -372,19 +743,34 @@ pub extern "C" fn Java_com_airx_AirXBridge_airXRespondToFile(
     _: JClass,
     airx_ptr: jlong,
     host: JString,
-    file_id: jshort,
+    transfer_id: JString,
     file_size: jlong,
     file_path: JString,
     accept: jboolean,
-) {
+) -> jint {
     let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
     let config = airx.config();
     let host = env.get_string(host.as_ref()).expect("Couldn't get java string").into();
+    let transfer_id = env.get_string(transfer_id.as_ref()).expect("Couldn't get java string").into();
     let file_path = env.get_string(file_path.as_ref()).expect("Couldn't get java string").into();
     let accept = match accept {
         0 => false,
         _ => true,
     };
 
-    shared_airx_respond_to_file(host, file_id as u8, file_size as u64, file_path, accept, &config);
+    shared_airx_result(shared_airx_respond_to_file(host, transfer_id, file_size as u64, file_path, accept, &airx.transfer_manager(), &config))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_airx_AirXBridge_airXResumeFile(
+    mut env: JNIEnv,
+    _: JClass,
+    airx_ptr: jlong,
+    transfer_id: JString,
+) -> jint {
+    let airx = unsafe { &mut *(airx_ptr as *mut AirXService) };
+    let config = airx.config();
+    let transfer_id = env.get_string(transfer_id.as_ref()).expect("Couldn't get java string").into();
+
+    shared_airx_result(shared_airx_resume_file(transfer_id, &airx.transfer_manager(), &config))
 }
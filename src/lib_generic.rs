@@ -1,3 +1,6 @@
+// The C interface. Callers keep the pointers they pass in valid for the duration of the call.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
 extern crate core;
 
 use crate::network::peer::{Peer};
@@ -6,18 +9,23 @@ use crate::service::discovery_service::DiscoveryService;
 use std::os::raw::c_char;
 use std::ptr::copy;
 use std::sync::Arc;
-use std::time::Duration;
-use log::{error, info};
-use crate::lib_util::{AIRX_COMPATIBLE_NUMBER, AIRX_VERSION, shared_airx_version_code, CONNECTION_TIMEOUT_MILLIS, shared_string_from_lengthen_ptr, shared_airx_init, shared_airx_broadcast_text, shared_airx_try_send_file, shared_airx_respond_to_file, shared_airx_data_service};
+use log::info;
+use crate::error::{AIRX_OK, AirXError};
+use crate::lib_util::{AIRX_COMPATIBLE_NUMBER, AIRX_VERSION, shared_airx_version_code, shared_airx_result, shared_airx_set_last_error, shared_airx_last_error_message, shared_airx_send_text, shared_string_from_lengthen_ptr, shared_airx_init, shared_airx_broadcast_text, shared_airx_try_send_file, shared_airx_respond_to_file, shared_airx_resume_file, shared_airx_data_service, shared_airx_set_group_passphrase, shared_airx_set_accept_unsigned_discovery, shared_airx_set_discovery_mode, shared_airx_set_multicast_group, shared_airx_set_mdns_enabled, shared_airx_set_peer_liveness, shared_airx_set_data_directory, shared_airx_set_device_info, shared_airx_peer_details, shared_airx_set_untrusted_policy, shared_airx_verification_code, shared_airx_pair, shared_airx_unpair, shared_airx_list_trusted, shared_airx_list_quarantined, shared_airx_release_quarantined, shared_airx_set_default_access, shared_airx_add_access_rule, shared_airx_clear_access_rules, shared_airx_set_connection_limits, shared_airx_set_frame_limits, shared_airx_stop};
 use crate::packet::data::file_coming_packet::FileComingPacket;
 use crate::packet::data::file_part_packet::FilePartPacket;
 use crate::packet::data::local::file_sending_packet::FileSendingPacket;
-use crate::packet::data::magic_numbers::MagicNumbers;
 use crate::packet::data::text_packet::TextPacket;
-use crate::packet::protocol::serialize::Serialize;
 use crate::service;
-use crate::service::context::data_service_context::DataServiceContext;
-use crate::service::data_service::{DataService};
+use crate::packet::frame_limits::FrameLimits;
+use crate::service::context::data_service_context::{ConnectionLimits, DataServiceContext};
+use crate::network::device_info::DeviceType;
+use crate::service::context::discovery_service_context::{DEFAULT_HEARTBEAT_INTERVAL_MILLIS, DEFAULT_PEER_TTL_MILLIS, DiscoveryMode, MulticastConfig};
+use crate::util::device_id::DeviceId;
+use crate::security::identity::DeviceIdentity;
+use crate::security::trust_store::UntrustedPolicy;
+use crate::security::access_policy::AccessPolicy;
+use crate::service::peer_event::{OnPeerEventFunctionType, PeerEvent};
 
 #[export_name = "airx_version"]
 pub extern "C" fn airx_version() -> i32 {
@@ -41,9 +49,32 @@ pub extern "C" fn airx_version_string(buffer: *mut c_char) -> u64 {
     len as u64
 }
 
+/// Copy `bytes` into `buffer` with a terminating zero, truncated to fit `buffer_len`.
+/// Returns the full length, so callers can tell truncation apart.
+fn copy_to_buffer(bytes: &[u8], buffer: *mut c_char, buffer_len: u32) -> u32 {
+    if !buffer.is_null() && buffer_len > 0 {
+        let copied = bytes.len().min(buffer_len as usize - 1);
+        unsafe {
+            copy(bytes.as_ptr(), buffer as *mut u8, copied);
+            *buffer.add(copied) = 0;
+        }
+    }
+    bytes.len() as u32
+}
+
+/// Writes the message of the most recent failure on the calling thread into `buffer`,
+/// truncated to `buffer_len` bytes including the terminating zero. Successful calls
+/// don't clear it, so read it right after a call that returned an error code.
+/// Returns the full length of the message, 0 if nothing failed yet.
+#[export_name = "airx_last_error_message"]
+pub extern "C" fn airx_last_error_message(buffer: *mut c_char, buffer_len: u32) -> u32 {
+    copy_to_buffer(shared_airx_last_error_message().as_bytes(), buffer, buffer_len)
+}
+
 #[export_name = "airx_init"]
-pub extern "C" fn airx_init() {
-    shared_airx_init()
+pub extern "C" fn airx_init() -> i32 {
+    shared_airx_init();
+    AIRX_OK
 }
 
 #[export_name = "airx_create"]
@@ -64,11 +95,31 @@ pub unsafe extern "C" fn airx_create_service(
         text_service_listen_addr: addr.clone(),
         data_service_listen_port: text_service_listen_port,
         group_identifier,
+        group_key: None,
+        accept_unsigned_discovery: false,
+        discovery_mode: DiscoveryMode::Broadcast,
+        multicast: MulticastConfig::default(),
+        mdns_enabled: false,
+        heartbeat_interval_millis: DEFAULT_HEARTBEAT_INTERVAL_MILLIS,
+        peer_ttl_millis: DEFAULT_PEER_TTL_MILLIS,
+        device_id: DeviceId::generate(),
+        data_directory: None,
+        identity: DeviceIdentity::generate(),
+        untrusted_policy: UntrustedPolicy::Allow,
+        access_policy: AccessPolicy::default(),
+        connection_limits: ConnectionLimits::default(),
+        frame_limits: FrameLimits::default(),
+        oversized_frame_penalty_millis: 0,
+        display_name: None,
+        device_type: DeviceType::Desktop,
     };
     let airx = AirXService::new(&config);
     let airx = match airx {
         Ok(airx) => Box::into_raw(Box::new(airx)),
-        Err(_) => std::ptr::null_mut(),
+        Err(e) => {
+            shared_airx_set_last_error(&AirXError::from(e));
+            std::ptr::null_mut()
+        }
     };
 
     info!("lib: AirX config created (addr={}:{},gid={})",
@@ -77,12 +128,283 @@ pub unsafe extern "C" fn airx_create_service(
     airx
 }
 
+#[export_name = "airx_set_group_passphrase"]
+pub extern "C" fn airx_set_group_passphrase(
+    airx_ptr: *mut AirXService,
+    passphrase: *const c_char,
+    passphrase_len: u32,
+) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    let passphrase = shared_string_from_lengthen_ptr(passphrase, passphrase_len);
+    shared_airx_set_group_passphrase(airx, passphrase);
+    AIRX_OK
+}
+
+#[export_name = "airx_set_accept_unsigned_discovery"]
+pub extern "C" fn airx_set_accept_unsigned_discovery(airx_ptr: *mut AirXService, accept: bool) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    shared_airx_set_accept_unsigned_discovery(airx, accept);
+    AIRX_OK
+}
+
+/// 0 = broadcast, 1 = multicast, 2 = both.
+#[export_name = "airx_set_discovery_mode"]
+pub extern "C" fn airx_set_discovery_mode(airx_ptr: *mut AirXService, mode: u32) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    shared_airx_result(shared_airx_set_discovery_mode(airx, mode))
+}
+
+/// `group` is an IPv4 or IPv6 multicast address.
+#[export_name = "airx_set_multicast_group"]
+pub extern "C" fn airx_set_multicast_group(
+    airx_ptr: *mut AirXService,
+    group: *const c_char,
+    group_len: u32,
+    ttl: u32,
+) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    let group = shared_string_from_lengthen_ptr(group, group_len);
+    shared_airx_result(shared_airx_set_multicast_group(airx, group, ttl))
+}
+
+#[export_name = "airx_set_mdns_enabled"]
+pub extern "C" fn airx_set_mdns_enabled(airx_ptr: *mut AirXService, enabled: bool) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    shared_airx_set_mdns_enabled(airx, enabled);
+    AIRX_OK
+}
+
+/// The TTL must exceed the heartbeat interval.
+#[export_name = "airx_set_peer_liveness"]
+pub extern "C" fn airx_set_peer_liveness(
+    airx_ptr: *mut AirXService,
+    heartbeat_interval_millis: u64,
+    peer_ttl_millis: u64,
+) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    shared_airx_result(shared_airx_set_peer_liveness(airx, heartbeat_interval_millis, peer_ttl_millis))
+}
+
+/// Directory for persistent state such as the device ID.
+#[export_name = "airx_set_data_directory"]
+pub extern "C" fn airx_set_data_directory(
+    airx_ptr: *mut AirXService,
+    path: *const c_char,
+    path_len: u32,
+) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    let path = shared_string_from_lengthen_ptr(path, path_len);
+    shared_airx_result(shared_airx_set_data_directory(airx, path))
+}
+
+/// Name and type announced to peers, an empty name announces the hostname.
+/// `device_type`: 0 = unknown, 1 = phone, 2 = tablet, 3 = desktop.
+#[export_name = "airx_set_device_info"]
+pub extern "C" fn airx_set_device_info(
+    airx_ptr: *mut AirXService,
+    display_name: *const c_char,
+    display_name_len: u32,
+    device_type: u32,
+) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    let display_name = shared_string_from_lengthen_ptr(display_name, display_name_len);
+    shared_airx_result(shared_airx_set_device_info(airx, display_name, device_type))
+}
+
+/// 0 = deliver, 1 = drop, 2 = quarantine offers from unpaired devices.
+#[export_name = "airx_set_untrusted_policy"]
+pub extern "C" fn airx_set_untrusted_policy(airx_ptr: *mut AirXService, policy: u32) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    shared_airx_result(shared_airx_set_untrusted_policy(airx, policy))
+}
+
+/// Writes the 6 digit pairing code for a discovered device into `buffer`, truncated to `buffer_len`.
+/// Returns the full length, or 0 and sets the last error if the device is unknown.
+#[export_name = "airx_verification_code"]
+pub extern "C" fn airx_verification_code(
+    airx_ptr: *mut AirXService,
+    device_id: *const c_char,
+    device_id_len: u32,
+    buffer: *mut c_char,
+    buffer_len: u32,
+) -> u32 {
+    let airx = unsafe { &mut *airx_ptr };
+    let device_id = shared_string_from_lengthen_ptr(device_id, device_id_len);
+    let code = match shared_airx_verification_code(airx, device_id) {
+        Ok(code) => code,
+        Err(e) => {
+            shared_airx_set_last_error(&e);
+            return 0;
+        }
+    };
+    copy_to_buffer(code.as_bytes(), buffer, buffer_len)
+}
+
+/// Trust a discovered device once the user confirmed the verification code.
+#[export_name = "airx_pair"]
+pub extern "C" fn airx_pair(
+    airx_ptr: *mut AirXService,
+    device_id: *const c_char,
+    device_id_len: u32,
+) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    let device_id = shared_string_from_lengthen_ptr(device_id, device_id_len);
+    shared_airx_result(shared_airx_pair(airx, device_id))
+}
+
+/// Fails with `AIRX_ERROR_NOT_FOUND` if the device was not paired.
+#[export_name = "airx_unpair"]
+pub extern "C" fn airx_unpair(
+    airx_ptr: *mut AirXService,
+    device_id: *const c_char,
+    device_id_len: u32,
+) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    let device_id = shared_string_from_lengthen_ptr(device_id, device_id_len);
+    shared_airx_result(shared_airx_unpair(airx, device_id))
+}
+
+/// Paired devices as "host_name@device_id", comma separated.
+/// Truncated to `buffer_len` like `airx_last_error_message`, returns the full length.
+#[export_name = "airx_list_trusted"]
+pub extern "C" fn airx_list_trusted(airx_ptr: *mut AirXService, buffer: *mut c_char, buffer_len: u32) -> u32 {
+    let airx = unsafe { &mut *airx_ptr };
+    copy_to_buffer(shared_airx_list_trusted(airx).as_bytes(), buffer, buffer_len)
+}
+
+/// Offers held back from unpaired devices, see `shared_airx_list_quarantined`.
+/// Truncated to `buffer_len` like `airx_last_error_message`, returns the full length.
+#[export_name = "airx_list_quarantined"]
+pub extern "C" fn airx_list_quarantined(airx_ptr: *mut AirXService, buffer: *mut c_char, buffer_len: u32) -> u32 {
+    let airx = unsafe { &mut *airx_ptr };
+    copy_to_buffer(shared_airx_list_quarantined(airx).as_bytes(), buffer, buffer_len)
+}
+
+/// Deliver what is held from `host` to the data service callbacks without pairing it.
+/// Fails with `AIRX_ERROR_NOT_FOUND` if there is nothing, or the data service is not running.
+#[export_name = "airx_release_quarantined"]
+pub extern "C" fn airx_release_quarantined(
+    airx_ptr: *mut AirXService,
+    host: *const c_char,
+    host_len: u32,
+) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    let host = shared_string_from_lengthen_ptr(host, host_len);
+    shared_airx_result(shared_airx_release_quarantined(airx, host))
+}
+
+/// Whether connections matching no access rule are accepted. Defaults to true.
+#[export_name = "airx_set_default_access"]
+pub extern "C" fn airx_set_default_access(airx_ptr: *mut AirXService, allow: bool) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    shared_airx_set_default_access(airx, allow);
+    AIRX_OK
+}
+
+/// `rule` is an address, a CIDR range such as "192.168.1.0/24", or a device ID.
+/// Deny rules win over allow rules.
+#[export_name = "airx_add_access_rule"]
+pub extern "C" fn airx_add_access_rule(
+    airx_ptr: *mut AirXService,
+    rule: *const c_char,
+    rule_len: u32,
+    allow: bool,
+) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    let rule = shared_string_from_lengthen_ptr(rule, rule_len);
+    shared_airx_result(shared_airx_add_access_rule(airx, rule, allow))
+}
+
+#[export_name = "airx_clear_access_rules"]
+pub extern "C" fn airx_clear_access_rules(airx_ptr: *mut AirXService) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    shared_airx_clear_access_rules(airx);
+    AIRX_OK
+}
+
+/// Worker threads, total and per address connection caps (0 = no cap),
+/// and what happens while every worker is busy (0 = reject, 1 = queue).
+/// Takes effect the next time the data service starts.
+#[export_name = "airx_set_connection_limits"]
+pub extern "C" fn airx_set_connection_limits(
+    airx_ptr: *mut AirXService,
+    worker_pool_size: u32,
+    max_connections: u32,
+    max_connections_per_peer: u32,
+    backlog_policy: u32,
+) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    shared_airx_result(shared_airx_set_connection_limits(
+        airx, worker_pool_size, max_connections, max_connections_per_peer, backlog_policy))
+}
+
+/// Largest text and file part frames accepted, the latter at least one file chunk,
+/// and how long to deny peers sending larger ones (0 = no penalty).
+/// Takes effect the next time the data service starts.
+#[export_name = "airx_set_frame_limits"]
+pub extern "C" fn airx_set_frame_limits(
+    airx_ptr: *mut AirXService,
+    max_text_frame_size: u32,
+    max_file_part_frame_size: u32,
+    oversized_frame_penalty_millis: u64,
+) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    shared_airx_result(shared_airx_set_frame_limits(
+        airx, max_text_frame_size, max_file_part_frame_size, oversized_frame_penalty_millis))
+}
+
+/// Stop the discovery and data services of this instance and wait for them to return.
+/// In-flight file transfers are cancelled. Do not call from a service callback.
+#[export_name = "airx_stop"]
+pub extern "C" fn airx_stop(airx_ptr: *mut AirXService) -> i32 {
+    let airx = unsafe { &*airx_ptr };
+    shared_airx_stop(airx);
+    AIRX_OK
+}
+
 #[export_name = "airx_lan_discovery_service"]
 pub extern "C" fn airx_lan_discovery_service(
     airx_ptr: *mut AirXService,
     should_interrupt: extern "C" fn() -> bool,
-) {
+) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    shared_airx_result(lan_discovery_service(airx, None, should_interrupt))
+}
+
+/// Like `airx_lan_discovery_service`, and reports peer set changes.
+/// Event kinds: 1 = added, 2 = removed, 3 = updated (previous peer is then set).
+#[export_name = "airx_lan_discovery_service_with_events"]
+pub extern "C" fn airx_lan_discovery_service_with_events(
+    airx_ptr: *mut AirXService,
+    peer_event_callback_c: extern "C" fn(
+        u8, /* kind */
+        *const c_char, /* peer */
+        u32, /* peer_len */
+        *const c_char, /* previous_peer */
+        u32, /* previous_peer_len */
+    ),
+    should_interrupt: extern "C" fn() -> bool,
+) -> i32 {
     let airx = unsafe { &mut *airx_ptr };
+    let peer_event_callback = move |event: &PeerEvent| {
+        let peer_str = event.peer().to_string();
+        let previous_peer_str = event.previous().map(|p| p.to_string()).unwrap_or_default();
+        peer_event_callback_c(
+            event.value(),
+            peer_str.as_ptr() as *const c_char,
+            peer_str.len() as u32,
+            previous_peer_str.as_ptr() as *const c_char,
+            previous_peer_str.len() as u32,
+        );
+    };
+    shared_airx_result(lan_discovery_service(airx, Some(Arc::new(Box::new(peer_event_callback))), should_interrupt))
+}
+
+fn lan_discovery_service(
+    airx: &mut AirXService,
+    peer_event_callback: Option<OnPeerEventFunctionType>,
+    should_interrupt: extern "C" fn() -> bool,
+) -> Result<(), AirXError> {
     let config = airx.config();
 
     let service_disc = airx.discovery_service();
@@ -94,15 +416,17 @@ pub extern "C" fn airx_lan_discovery_service(
           config.discovery_service_server_port,
           config.group_identifier);
 
-    let _ = DiscoveryService::run(
-        config.discovery_service_client_port,
-        config.discovery_service_server_port,
+    let mut context = config.discovery_service_context();
+    context.set_shutdown(airx.shutdown_handle());
+    context.set_peer_event_callback(peer_event_callback);
+    let result = DiscoveryService::run(
+        context,
         peers_ptr,
         Box::new(move || should_interrupt()),
-        config.group_identifier,
     );
 
     info!("lib: Discovery service stopped.");
+    result
 }
 
 //noinspection Duplicates
@@ -116,6 +440,8 @@ pub extern "C" fn airx_data_service(
         u32, /* socket_addr_len */
     ),
     file_coming_callback_c: extern "C" fn(
+        *const c_char, /* transfer_id */
+        u32, /* transfer_id_len */
         u64, /* file_size */
         *const c_char, /* file_name */
         u32, /* file_name_len */
@@ -123,19 +449,21 @@ pub extern "C" fn airx_data_service(
         u32, /* socket_addr_len */
     ),
     file_sending_callback_c: extern "C" fn(
-        u8, /* file_id */
+        *const c_char, /* transfer_id */
+        u32, /* transfer_id_len */
         u64, /* progress */
         u64, /* total */
         u8, /* status */
     ),
     file_part_callback_c: extern "C" fn(
-        u8, /* file_id */
+        *const c_char, /* transfer_id */
+        u32, /* transfer_id_len */
         u64, /* offset */
         u64, /* length */
         *const u8, /* data */
     ) -> bool,
     should_interrupt: extern "C" fn() -> bool,
-) {
+) -> i32 {
     let airx = unsafe { &mut *airx_ptr };
     let config = airx.config();
 
@@ -163,7 +491,10 @@ pub extern "C" fn airx_data_service(
             None => Peer::default().to_string(),
         };
         let socket_addr_cstr = socket_addr_str.as_ptr();
+        let transfer_id_str = file_coming_packet.transfer_id().to_string();
         file_coming_callback_c(
+            transfer_id_str.as_ptr() as *const c_char,
+            transfer_id_str.len() as u32,
             file_coming_packet.file_size(),
             file_name_cstr as *const c_char,
             file_coming_packet.file_name().len() as u32,
@@ -173,8 +504,10 @@ pub extern "C" fn airx_data_service(
     };
 
     let file_sending_callback = move |file_sending_packet: &FileSendingPacket, _: Option<&Peer>| {
+        let transfer_id_str = file_sending_packet.transfer_id().to_string();
         file_sending_callback_c(
-            file_sending_packet.file_id(),
+            transfer_id_str.as_ptr() as *const c_char,
+            transfer_id_str.len() as u32,
             file_sending_packet.progress(),
             file_sending_packet.total(),
             file_sending_packet.status().to_u8(),
@@ -184,16 +517,18 @@ pub extern "C" fn airx_data_service(
     let file_part_callback = move |file_part_packet: &FilePartPacket, _: Option<&Peer>| -> bool {
         let data = file_part_packet.data().clone();
         let data_cstr = data.as_ptr();
+        let transfer_id_str = file_part_packet.transfer_id().to_string();
 
         file_part_callback_c(
-            file_part_packet.file_id(),
+            transfer_id_str.as_ptr() as *const c_char,
+            transfer_id_str.len() as u32,
             file_part_packet.offset(),
             file_part_packet.length(),
             data_cstr,
         )
     };
 
-    let context = DataServiceContext::new(
+    let mut context = DataServiceContext::new(
         config.text_service_listen_addr.to_string(),
         config.data_service_listen_port,
         Arc::new(Box::new(text_callback)),
@@ -201,21 +536,27 @@ pub extern "C" fn airx_data_service(
         Arc::new(Box::new(file_sending_callback)),
         Arc::new(Box::new(file_part_callback)),
         airx.discovery_service().clone(),
+        airx.metrics(),
+        config.group_key.clone(),
     );
-
-    shared_airx_data_service(context, &config, Box::new(should_interrupt_callback));
+    context.set_identity(config.identity.clone());
+    context.set_trust(airx.trust_store(), config.untrusted_policy, airx.quarantine());
+    context.set_transfer_manager(airx.transfer_manager());
+    context.set_access_control(airx.access_control(), None);
+    context.set_shutdown(airx.shutdown_handle());
+    context.set_connection_limits(config.connection_limits);
+    context.set_frame_limits(config.frame_limits, config.oversized_frame_penalty());
+
+    shared_airx_result(shared_airx_data_service(context, &config, Box::new(should_interrupt_callback)))
 }
 
 #[deprecated]
 #[export_name = "airx_lan_broadcast"]
-pub extern "C" fn airx_lan_broadcast(airx_ptr: *mut AirXService) -> bool {
+pub extern "C" fn airx_lan_broadcast(airx_ptr: *mut AirXService) -> i32 {
     let airx = unsafe { &mut *airx_ptr };
     let config = airx.config();
-    DiscoveryService::broadcast_discovery_request(
-        config.discovery_service_client_port,
-        config.discovery_service_server_port,
-        config.group_identifier,
-    ).is_ok()
+    shared_airx_result(DiscoveryService::broadcast_discovery_request(&config.discovery_service_context())
+        .map_err(AirXError::from))
 }
 
 #[export_name = "airx_get_peers"]
@@ -242,10 +583,29 @@ pub extern "C" fn airx_get_peers(
         }
         return bytes.len() as u32;
     }
-    error!("lib: Failed to get peers");
+    shared_airx_set_last_error(&AirXError::Io(std::io::Error::other("Failed to get peers.")));
     0
 }
 
+/// Discovered peers with what they announced, see `shared_airx_peer_details` for the format.
+/// Truncated to `buffer_len` like `airx_last_error_message`, returns the full length.
+#[export_name = "airx_get_peer_details"]
+pub extern "C" fn airx_get_peer_details(
+    airx_ptr: *mut AirXService,
+    buffer: *mut c_char,
+    buffer_len: u32,
+) -> u32 {
+    let airx = unsafe { &mut *airx_ptr };
+    let details = match shared_airx_peer_details(airx) {
+        Ok(details) => details,
+        Err(e) => {
+            shared_airx_set_last_error(&e);
+            return 0;
+        }
+    };
+    copy_to_buffer(details.as_bytes(), buffer, buffer_len)
+}
+
 #[export_name = "airx_send_text"]
 pub extern "C" fn airx_send_text(
     airx_ptr: *mut AirXService,
@@ -253,30 +613,13 @@ pub extern "C" fn airx_send_text(
     host_len: u32,
     text: *mut c_char,
     text_len: u32,
-) {
+) -> i32 {
     let airx = unsafe { &mut *airx_ptr };
     let config = airx.config();
     let text = shared_string_from_lengthen_ptr(text, text_len);
     let host = shared_string_from_lengthen_ptr(host, host_len);
 
-    info!("lib: Sending text to (addr={}:{})",
-        host, config.data_service_listen_port);
-
-    let text_packet = match TextPacket::new(text) {
-        Ok(packet) => packet,
-        Err(err) => {
-            error!("lib: Failed to create text packet: {}", err);
-            return;
-        }
-    };
-
-    let _ = DataService::send_once_with_retry(
-        &Peer::new(&host, config.data_service_listen_port, None),
-        config.data_service_listen_port,
-        MagicNumbers::Text,
-        &text_packet.serialize(),
-        Duration::from_millis(CONNECTION_TIMEOUT_MILLIS),
-    );
+    shared_airx_result(shared_airx_send_text(host, text, &config))
 }
 
 #[export_name = "airx_broadcast_text"]
@@ -284,9 +627,9 @@ pub extern "C" fn airx_broadcast_text(
     airx_ptr: *mut AirXService,
     text: *mut c_char,
     len: u32,
-) {
+) -> i32 {
     if text == std::ptr::null_mut() || len < 1 {
-        return;
+        return shared_airx_set_last_error(&AirXError::InvalidArgument(String::from("Text is empty.")));
     }
 
     let airx = unsafe { &mut *airx_ptr };
@@ -294,9 +637,11 @@ pub extern "C" fn airx_broadcast_text(
     let service_disc = airx.discovery_service();
     let text = shared_string_from_lengthen_ptr(text, len);
 
-    shared_airx_broadcast_text(text, service_disc, &config);
+    shared_airx_result(shared_airx_broadcast_text(text, service_disc, &config))
 }
 
+/// Writes the hex id the transfer goes by in the callbacks into `transfer_id`,
+/// truncated to `transfer_id_len` bytes including the terminating zero, 33 fit it.
 #[export_name = "airx_try_send_file"]
 pub extern "C" fn airx_try_send_file(
     airx_ptr: *mut AirXService,
@@ -304,13 +649,21 @@ pub extern "C" fn airx_try_send_file(
     host_len: u32,
     file_path: *const c_char,
     file_path_len: u32,
-) {
+    transfer_id: *mut c_char,
+    transfer_id_len: u32,
+) -> i32 {
     let airx = unsafe { &mut *airx_ptr };
     let config = airx.config();
     let host = shared_string_from_lengthen_ptr(host, host_len);
     let file_path = shared_string_from_lengthen_ptr(file_path, file_path_len);
 
-    shared_airx_try_send_file(host, file_path, &config);
+    match shared_airx_try_send_file(host, file_path, &airx.transfer_manager(), &config) {
+        Ok(id) => {
+            copy_to_buffer(id.to_string().as_bytes(), transfer_id, transfer_id_len);
+            AIRX_OK
+        }
+        Err(e) => shared_airx_set_last_error(&e),
+    }
 }
 
 #[export_name = "airx_respond_to_file"]
@@ -318,16 +671,31 @@ pub extern "C" fn airx_respond_to_file(
     airx_ptr: *mut AirXService,
     host: *const c_char,
     host_len: u32,
-    file_id: u8,
+    transfer_id: *const c_char,
+    transfer_id_len: u32,
     file_size: u64,
     file_path: *const c_char,
     file_path_len: u32,
     accept: bool,
-) {
+) -> i32 {
     let airx = unsafe { &mut *airx_ptr };
     let config = airx.config();
     let host = shared_string_from_lengthen_ptr(host, host_len);
+    let transfer_id = shared_string_from_lengthen_ptr(transfer_id, transfer_id_len);
     let file_path = shared_string_from_lengthen_ptr(file_path, file_path_len);
 
-    shared_airx_respond_to_file(host, file_id, file_size, file_path, accept, &config);
+    shared_airx_result(shared_airx_respond_to_file(host, transfer_id, file_size, file_path, accept, &airx.transfer_manager(), &config))
+}
+
+#[export_name = "airx_resume_file"]
+pub extern "C" fn airx_resume_file(
+    airx_ptr: *mut AirXService,
+    transfer_id: *const c_char,
+    transfer_id_len: u32,
+) -> i32 {
+    let airx = unsafe { &mut *airx_ptr };
+    let config = airx.config();
+    let transfer_id = shared_string_from_lengthen_ptr(transfer_id, transfer_id_len);
+
+    shared_airx_result(shared_airx_resume_file(transfer_id, &airx.transfer_manager(), &config))
 }
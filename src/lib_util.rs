@@ -1,39 +1,76 @@
-use std::fs::File;
+use std::cell::RefCell;
+use std::fs::{File, Metadata};
 use std::os::raw::c_char;
+use std::net::IpAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, UNIX_EPOCH};
 use log4rs::append::console::ConsoleAppender;
 use log4rs::Config;
 use log4rs::config::{Appender, Logger, Root};
 use log::{error, info, LevelFilter};
+use crate::error::{AIRX_OK, AirXError};
+use crate::network::device_info::DeviceType;
 use crate::network::peer::Peer;
-use crate::packet::data::file_coming_packet::FileComingPacket;
+use crate::packet::data::file_coming_packet::{FileComingPacket, FileMetadata};
+use crate::packet::data::byte_ranges::ByteRanges;
 use crate::packet::data::file_receive_response_packet::FileReceiveResponsePacket;
+use crate::packet::data::transfer_id::TransferId;
 use crate::packet::data::magic_numbers::MagicNumbers;
 use crate::packet::data::text_packet::TextPacket;
 use crate::packet::protocol::serialize::Serialize;
-use crate::service::airx_service::AirXServiceConfig;
-use crate::service::context::data_service_context::DataServiceContext;
+use crate::service::airx_service::{AirXService, AirXServiceConfig};
+use crate::packet::frame_limits::DEFAULT_FILE_PART_FRAME_LIMIT;
+use crate::service::context::data_service_context::{BacklogPolicy, ConnectionLimits, DataServiceContext};
+use crate::service::context::discovery_service_context::DiscoveryMode;
 use crate::service::data_service::DataService;
 use crate::service::discovery_service::DiscoveryService;
 use crate::service::ShouldInterruptFunctionType;
+use crate::service::transfer_journal::JournalEntry;
+use crate::service::transfer_manager::{TransferManager, TransferSide, TransferState};
+use crate::security::access_policy::{AccessAction, AccessRule};
+use crate::security::group_key::GroupKey;
+use crate::security::identity::{DeviceIdentity, IdentityKey, verification_code};
+use crate::security::trust_store::{TrustedDevice, UntrustedPolicy};
+use crate::util::device_id::DeviceId;
+use crate::util::file_name::FileNameUtil;
 
 pub const CONNECTION_TIMEOUT_MILLIS: u64 = 3000;
 pub const AIRX_VERSION: i32 = 20230802;
-pub const AIRX_COMPATIBLE_NUMBER: i32 = 4;
+pub const AIRX_COMPATIBLE_NUMBER: i32 = 8;
+
+thread_local! {
+    static LAST_ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Log the error and keep its message for `shared_airx_last_error_message`. Returns its code.
+pub fn shared_airx_set_last_error(e: &AirXError) -> i32 {
+    error!("lib: {}", e);
+    LAST_ERROR.with(|last| *last.borrow_mut() = e.to_string());
+    e.code()
+}
+
+/// The code to hand to C or Java for `result`.
+pub fn shared_airx_result<T>(result: Result<T, AirXError>) -> i32 {
+    match result {
+        Ok(_) => AIRX_OK,
+        Err(e) => shared_airx_set_last_error(&e),
+    }
+}
+
+/// Message of the most recent failure on the calling thread, empty if nothing failed yet.
+/// Successful calls leave it as is, so it only describes a call that just returned an error.
+pub fn shared_airx_last_error_message() -> String {
+    LAST_ERROR.with(|last| last.borrow().clone())
+}
 
 pub fn shared_airx_version_code() -> String {
     String::from("\\^O^/")
 }
 
-pub fn shared_airx_broadcast_text(text: String, service_disc: Arc<DiscoveryService>, config: &AirXServiceConfig) {
-    let packet = match TextPacket::new(text) {
-        Ok(packet) => packet,
-        Err(err) => {
-            error!("lib: Failed to create text packet: {}", err);
-            return;
-        }
-    };
+/// Sends in the background, so only an invalid text is reported.
+pub fn shared_airx_broadcast_text(text: String, service_disc: Arc<DiscoveryService>, config: &AirXServiceConfig) -> Result<(), AirXError> {
+    let packet = TextPacket::new(text)?;
     let text_serialized = Arc::new(packet.serialize());
 
     if let Ok(peers_ptr) = service_disc.peers().lock() {
@@ -50,6 +87,8 @@ pub fn shared_airx_broadcast_text(text: String, service_disc: Arc<DiscoveryServi
                     MagicNumbers::Text,
                     &thread_text_serialized,
                     Duration::from_millis(CONNECTION_TIMEOUT_MILLIS),
+                    thread_config.group_key.as_ref(),
+                    Some(&thread_config.identity),
                 ) {
                     error!(
                         "lib: Failed to send text to (addr={}:{}): {}",
@@ -58,84 +97,433 @@ pub fn shared_airx_broadcast_text(text: String, service_disc: Arc<DiscoveryServi
             });
         }
     }
+    Ok(())
 }
 
-pub fn shared_airx_try_send_file(host: String, file_path: String, config: &AirXServiceConfig) {
+pub fn shared_airx_send_text(host: String, text: String, config: &AirXServiceConfig) -> Result<(), AirXError> {
+    info!("lib: Sending text to (addr={}:{})",
+        host, config.data_service_listen_port);
+
+    let text_packet = TextPacket::new(text)?;
+    DataService::send_once_with_retry(
+        &Peer::new(&host, config.data_service_listen_port, None),
+        config.data_service_listen_port,
+        MagicNumbers::Text,
+        &text_packet.serialize(),
+        Duration::from_millis(CONNECTION_TIMEOUT_MILLIS),
+        config.group_key.as_ref(),
+        Some(&config.identity),
+    )?;
+    Ok(())
+}
+
+/// Offer a file to `host` and track its transfer in `transfers`.
+/// Returns the id the transfer goes by in the callbacks.
+pub fn shared_airx_try_send_file(host: String, file_path: String, transfers: &TransferManager, config: &AirXServiceConfig) -> Result<TransferId, AirXError> {
     info!("lib: Sending file info {} to (addr={}:{})",
         file_path, host, config.data_service_listen_port);
 
     info!("lib: Reading file info {}", file_path);
-    let file_info = match File::open(file_path.clone()) {
-        Ok(f) => f,
-        Err(e) => {
-            error!("lib: Failed to open file {}: {}", file_path, e);
-            return;
-        }
-    };
+    let file_info = File::open(file_path.clone())?;
 
     info!("lib: Reading metadata of file {}", file_path);
-    let metadata = match file_info.metadata() {
-        Ok(d) => d,
-        Err(e) => {
-            error!("lib: Failed to read metadata of file {}: {}", file_path, e);
-            return;
-        }
-    };
+    let metadata = file_info.metadata()?;
 
     info!("lib: Sending file info {} to (addr={}:{})",
         file_path, host, config.data_service_listen_port);
-    let packet = FileComingPacket::new(metadata.len(), file_path.clone());
-    match DataService::send_once_with_retry(
+    // The receiver only learns the file name, we map the transfer id back to the path.
+    let transfer_id = TransferId::generate();
+    let file_name = FileNameUtil::display_name(&file_path);
+    let packet = FileComingPacket::new(transfer_id, metadata.len(), file_name.clone())
+        .with_metadata(shared_file_metadata(&file_name, &metadata));
+    transfers.offer(transfer_id, host.clone(), file_path.clone(), metadata.len());
+    if let Err(e) = DataService::send_packet_with_retry(
         &Peer::new(&host, config.data_service_listen_port, None),
         config.data_service_listen_port,
         MagicNumbers::FileComing,
-        &packet.serialize(),
+        &|format| packet.serialize_as(format),
         Duration::from_millis(CONNECTION_TIMEOUT_MILLIS),
+        config.group_key.as_ref(),
+        Some(&config.identity),
     ) {
-        Ok(_) => {
-            info!("lib: File info {} sent to (addr={}:{})",
-                file_path, host, config.data_service_listen_port);
-        }
-        Err(e) => {
-            error!("lib: Failed to send file info {}: {}", file_path, e);
-        }
+        let _ = transfers.transition(&transfer_id, TransferState::Failed, 0);
+        return Err(e.into());
     }
+    info!("lib: File info {} sent to (addr={}:{}, transfer_id={})",
+        file_path, host, config.data_service_listen_port, transfer_id);
+    Ok(transfer_id)
 }
 
-pub fn shared_airx_data_service(context: DataServiceContext, config: &AirXServiceConfig, should_interrupt: ShouldInterruptFunctionType) {
+/// What we tell receivers about a file besides its name and size.
+fn shared_file_metadata(file_name: &str, metadata: &Metadata) -> FileMetadata {
+    #[cfg(unix)]
+    let permissions = {
+        use std::os::unix::fs::PermissionsExt;
+        Some(metadata.permissions().mode() & 0o777)
+    };
+    #[cfg(not(unix))]
+    let permissions = None;
+
+    FileMetadata {
+        mime_type: FileNameUtil::mime_type(file_name).map(String::from),
+        modified_at: metadata.modified().ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs()),
+        permissions,
+    }
+}
+
+pub fn shared_airx_data_service(context: DataServiceContext, config: &AirXServiceConfig, should_interrupt: ShouldInterruptFunctionType) -> Result<(), AirXError> {
     info!("lib: Data service starting (addr={},port={})",
           config.text_service_listen_addr, config.data_service_listen_port);
 
-    let _ = DataService::run(context, should_interrupt);
+    let result = DataService::run(context, should_interrupt);
 
     info!("lib: Data service stopped");
+    result
 }
 
-pub fn shared_airx_respond_to_file(host: String, file_id: u8, file_size: u64, file_path: String, accept: bool, config: &AirXServiceConfig) {
+fn shared_parse_transfer_id(transfer_id: &str) -> Result<TransferId, AirXError> {
+    TransferId::parse(transfer_id)
+        .ok_or_else(|| AirXError::InvalidArgument(format!("Invalid transfer id {}.", transfer_id)))
+}
+
+/// `transfer_id` is the hex id given by the file coming callback.
+/// Accepting a file accepted before resumes it.
+pub fn shared_airx_respond_to_file(host: String, transfer_id: String, file_size: u64, file_path: String, accept: bool, transfers: &TransferManager, config: &AirXServiceConfig) -> Result<(), AirXError> {
+    let transfer_id = shared_parse_transfer_id(&transfer_id)?;
+    let journal = transfers.journal();
+    let received = match journal.get(&transfer_id, TransferSide::Receiver) {
+        Some(entry) if accept => entry.received().clone(),
+        _ => ByteRanges::new(),
+    };
+    // Only the name, where the file is saved is none of the sender's business.
     let packet = FileReceiveResponsePacket::new(
-        file_id,
+        transfer_id,
         file_size,
-        file_path,
+        FileNameUtil::display_name(&file_path),
         accept,
-    );
-    match DataService::send_once_with_retry(
-        &Peer::new(&host, config.data_service_listen_port, None),
+    ).with_received(received.clone());
+
+    // Journaled first, parts may arrive as soon as the sender has the response.
+    if !accept {
+        journal.remove(&transfer_id, TransferSide::Receiver)?;
+    } else if received.is_empty() {
+        journal.record(JournalEntry::receiving(transfer_id, host.clone(), file_path, file_size))?;
+    }
+    shared_send_file_response(&host, &packet, config)
+}
+
+/// Ask the sender of an interrupted file to send the rest, even after a restart.
+pub fn shared_airx_resume_file(transfer_id: String, transfers: &TransferManager, config: &AirXServiceConfig) -> Result<(), AirXError> {
+    let transfer_id = shared_parse_transfer_id(&transfer_id)?;
+    let entry = transfers.journal().get(&transfer_id, TransferSide::Receiver)
+        .ok_or_else(|| AirXError::InvalidArgument(format!("No interrupted transfer {}.", transfer_id)))?;
+    info!("lib: Resuming transfer {} ({}/{} bytes received).",
+        transfer_id, entry.received().len(), entry.file_size());
+    let packet = FileReceiveResponsePacket::new(
+        transfer_id,
+        entry.file_size(),
+        FileNameUtil::display_name(entry.file()),
+        true,
+    ).with_received(entry.received().clone());
+    shared_send_file_response(entry.host(), &packet, config)
+}
+
+fn shared_send_file_response(host: &String, packet: &FileReceiveResponsePacket, config: &AirXServiceConfig) -> Result<(), AirXError> {
+    DataService::send_packet_with_retry(
+        &Peer::new(host, config.data_service_listen_port, None),
         config.data_service_listen_port,
         MagicNumbers::FileReceiveResponse,
-        &packet.serialize(),
+        &|format| packet.serialize_as(format),
         Duration::from_millis(CONNECTION_TIMEOUT_MILLIS),
-    ) {
-        Ok(_) => {
-            info!("lib: Successfully sent file response to (addr={}:{})",
-                host, config.data_service_listen_port);
-        }
-        Err(e) => {
-            error!("lib: Failed to send file response to (addr={}:{}): {}",
-                host, config.data_service_listen_port, e);
+        config.group_key.as_ref(),
+        Some(&config.identity),
+    )?;
+    info!("lib: Successfully sent file response to (addr={}:{})",
+        host, config.data_service_listen_port);
+    Ok(())
+}
+
+/// Empty passphrase turns encryption off.
+pub fn shared_airx_set_group_passphrase(airx: &mut AirXService, passphrase: String) {
+    airx.config_mut().group_key = if passphrase.is_empty() {
+        None
+    } else {
+        Some(GroupKey::from_passphrase(&passphrase))
+    };
+    info!("lib: Data channel encryption {}.",
+        if passphrase.is_empty() { "disabled" } else { "enabled" });
+}
+
+pub fn shared_airx_set_accept_unsigned_discovery(airx: &mut AirXService, accept: bool) {
+    airx.config_mut().accept_unsigned_discovery = accept;
+    info!("lib: Unsigned discovery packets {}.", if accept { "accepted" } else { "rejected" });
+}
+
+pub fn shared_airx_set_discovery_mode(airx: &mut AirXService, mode: u32) -> Result<(), AirXError> {
+    let mode = DiscoveryMode::from(mode)
+        .ok_or_else(|| AirXError::InvalidArgument(format!("Unknown discovery mode {}.", mode)))?;
+    airx.config_mut().discovery_mode = mode;
+    info!("lib: Discovery mode set to {:?}.", mode);
+    Ok(())
+}
+
+/// Sets the IPv4 or IPv6 group depending on the address family of `group`.
+pub fn shared_airx_set_multicast_group(airx: &mut AirXService, group: String, ttl: u32) -> Result<(), AirXError> {
+    let multicast = &mut airx.config_mut().multicast;
+    match group.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) if ip.is_multicast() => multicast.group_v4 = ip,
+        Ok(IpAddr::V6(ip)) if ip.is_multicast() => multicast.group_v6 = ip,
+        _ => return Err(AirXError::InvalidArgument(format!("{} is not a multicast address.", group))),
+    }
+    multicast.ttl = ttl;
+    info!("lib: Multicast group set to {} (ttl={}).", group, ttl);
+    Ok(())
+}
+
+pub fn shared_airx_set_mdns_enabled(airx: &mut AirXService, enabled: bool) {
+    airx.config_mut().mdns_enabled = enabled;
+    info!("lib: mDNS advertisement {}.", if enabled { "enabled" } else { "disabled" });
+}
+
+pub fn shared_airx_set_peer_liveness(airx: &mut AirXService, heartbeat_interval_millis: u64, peer_ttl_millis: u64) -> Result<(), AirXError> {
+    if heartbeat_interval_millis == 0 || peer_ttl_millis <= heartbeat_interval_millis {
+        return Err(AirXError::InvalidArgument(format!(
+            "Peer TTL ({}ms) must exceed the heartbeat interval ({}ms).",
+            peer_ttl_millis, heartbeat_interval_millis)));
+    }
+    let config = airx.config_mut();
+    config.heartbeat_interval_millis = heartbeat_interval_millis;
+    config.peer_ttl_millis = peer_ttl_millis;
+    info!("lib: Heartbeat every {}ms, peer TTL {}ms.", heartbeat_interval_millis, peer_ttl_millis);
+    Ok(())
+}
+
+/// Name and type announced to peers, the hostname when the name is empty.
+pub fn shared_airx_set_device_info(airx: &mut AirXService, display_name: String, device_type: u32) -> Result<(), AirXError> {
+    let device_type = DeviceType::from(device_type)
+        .ok_or_else(|| AirXError::InvalidArgument(format!("Unknown device type {}.", device_type)))?;
+    let config = airx.config_mut();
+    config.display_name = Some(display_name).filter(|name| !name.is_empty());
+    config.device_type = device_type;
+    info!("lib: Device info set (name={:?},type={:?}).", config.display_name, device_type);
+    Ok(())
+}
+
+/// Loads the device ID, identity key and trust store from `path`, creating them on first use.
+pub fn shared_airx_set_data_directory(airx: &mut AirXService, path: String) -> Result<(), AirXError> {
+    let directory = PathBuf::from(&path);
+    let device_id = DeviceId::load_or_create(&directory)?;
+    let identity = DeviceIdentity::load_or_create(&directory)?;
+    airx.trust_store().attach(&directory)?;
+    airx.transfer_manager().attach_journal(&directory)?;
+
+    info!("lib: Data directory set to {} (device={}).", path, device_id);
+    let config = airx.config_mut();
+    config.device_id = device_id;
+    config.identity = identity;
+    config.data_directory = Some(directory);
+    Ok(())
+}
+
+pub fn shared_airx_set_untrusted_policy(airx: &mut AirXService, policy: u32) -> Result<(), AirXError> {
+    let policy = UntrustedPolicy::from(policy)
+        .ok_or_else(|| AirXError::InvalidArgument(format!("Unknown untrusted policy {}.", policy)))?;
+    airx.config_mut().untrusted_policy = policy;
+    info!("lib: Untrusted policy set to {:?}.", policy);
+    Ok(())
+}
+
+/// A discovered peer with this device ID and the identity key it advertised.
+fn identified_peer(airx: &AirXService, device_id: &str) -> Result<(Peer, IdentityKey), AirXError> {
+    let service_disc = airx.discovery_service();
+    let peers = service_disc.peers();
+    let peers = peers.lock()
+        .map_err(|_| AirXError::Io(std::io::Error::other("Peer set is poisoned.")))?;
+    peers.iter()
+        .filter(|p| p.device_id().map(|id| id.as_str()) == Some(device_id))
+        .find_map(|p| p.identity_key().map(|key| (p.clone(), *key)))
+        .ok_or_else(|| AirXError::NotFound(
+            format!("{} has not been discovered with an identity key.", device_id)))
+}
+
+/// The code to compare with the one shown on the other device.
+pub fn shared_airx_verification_code(airx: &AirXService, device_id: String) -> Result<String, AirXError> {
+    let (_, key) = identified_peer(airx, &device_id)?;
+    let own_key = airx.config().identity.public_key();
+    Ok(verification_code(&own_key, &key))
+}
+
+/// Trust the discovered device. Call after the user confirmed the verification code.
+/// What it sent while unpaired is let out of the quarantine.
+pub fn shared_airx_pair(airx: &AirXService, device_id: String) -> Result<(), AirXError> {
+    let (peer, key) = identified_peer(airx, &device_id)?;
+    airx.trust_store().pair(TrustedDevice::new(&device_id, peer.host_name(), key))?;
+    let released = airx.quarantine().deliver(&key);
+    info!("lib: Paired with {} ({}), {} quarantined packets released.", peer.host_name(), device_id, released);
+    Ok(())
+}
+
+/// Fails with `NotFound` if the device was not paired.
+pub fn shared_airx_unpair(airx: &AirXService, device_id: String) -> Result<(), AirXError> {
+    if !airx.trust_store().unpair(&device_id)? {
+        return Err(AirXError::NotFound(format!("{} is not paired.", device_id)));
+    }
+    info!("lib: Unpaired {}.", device_id);
+    Ok(())
+}
+
+/// Paired devices as "host_name@device_id", comma separated.
+pub fn shared_airx_list_trusted(airx: &AirXService) -> String {
+    airx.trust_store()
+        .list()
+        .iter()
+        .map(|d| format!("{}@{}", d.host_name(), d.device_id()))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// One line per packet held back from unpaired devices, oldest first, with tab separated
+/// fields: sender address, "text" or "file", 1 or 0 for identified, then seconds held.
+pub fn shared_airx_list_quarantined(airx: &AirXService) -> String {
+    airx.quarantine()
+        .list()
+        .iter()
+        .map(|p| format!("{}\t{}\t{}\t{}",
+            p.socket_addr().ip(),
+            if p.magic_number() == MagicNumbers::Text { "text" } else { "file" },
+            p.identity_key().is_some() as u8,
+            p.received_at().elapsed().as_secs()))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Deliver what is held from `host` without pairing it, through the running data service.
+/// Fails with `NotFound` if nothing is held from it or no data service runs.
+pub fn shared_airx_release_quarantined(airx: &AirXService, host: String) -> Result<(), AirXError> {
+    let ip = host.parse::<IpAddr>()
+        .map_err(|_| AirXError::InvalidArgument(format!("Invalid address {}.", host)))?;
+    match airx.quarantine().deliver_from(ip) {
+        0 => Err(AirXError::NotFound(format!("Nothing from {} can be released.", host))),
+        released => {
+            info!("lib: Released {} quarantined packets from {}.", released, host);
+            Ok(())
         }
     }
 }
 
+fn without_control_chars(s: &str) -> String {
+    s.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// One line per discovered peer, with tab separated fields:
+/// peer as in `airx_get_peers`, display name, device ID, device type, platform,
+/// protocol version, feature bits, then 1 or 0 for encryption required, TLS and compatible.
+/// Values a peer did not announce are empty.
+pub fn shared_airx_peer_details(airx: &AirXService) -> Result<String, AirXError> {
+    let service_disc = airx.discovery_service();
+    let peers = service_disc.peers();
+    let peers = peers.lock()
+        .map_err(|_| AirXError::Io(std::io::Error::other("Peer set is poisoned.")))?;
+    Ok(peers.iter()
+        .map(|peer| {
+            let info = peer.device_info();
+            [
+                peer.to_string(),
+                peer.display_name().clone(),
+                peer.device_id().cloned().unwrap_or_default(),
+                info.device_type.value().to_string(),
+                info.platform.clone().unwrap_or_default(),
+                info.protocol_version.map(|v| v.to_string()).unwrap_or_default(),
+                info.features.bits().to_string(),
+                (peer.encryption_required() as u8).to_string(),
+                (info.data_port_tls as u8).to_string(),
+                (peer.is_compatible() as u8).to_string(),
+            ].iter().map(|field| without_control_chars(field)).collect::<Vec<String>>().join("\t")
+        })
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+pub fn shared_airx_set_default_access(airx: &mut AirXService, allow: bool) {
+    let mut policy = airx.config().access_policy;
+    policy.set_default_action(if allow { AccessAction::Allow } else { AccessAction::Deny });
+    airx.set_access_policy(policy);
+    info!("lib: Connections not matching any rule are {}.", if allow { "allowed" } else { "denied" });
+}
+
+/// `rule` is an address, a CIDR range or a device ID.
+pub fn shared_airx_add_access_rule(airx: &mut AirXService, rule: String, allow: bool) -> Result<(), AirXError> {
+    let parsed = AccessRule::parse(&rule)?;
+    let mut policy = airx.config().access_policy;
+    policy.add_rule(if allow { AccessAction::Allow } else { AccessAction::Deny }, parsed);
+    airx.set_access_policy(policy);
+    info!("lib: Access rule added ({} {}).", if allow { "allow" } else { "deny" }, rule);
+    Ok(())
+}
+
+pub fn shared_airx_clear_access_rules(airx: &mut AirXService) {
+    let mut policy = airx.config().access_policy;
+    policy.clear_rules();
+    airx.set_access_policy(policy);
+    info!("lib: Access rules cleared.");
+}
+
+/// Takes effect the next time the data service starts.
+pub fn shared_airx_set_connection_limits(
+    airx: &mut AirXService,
+    worker_pool_size: u32,
+    max_connections: u32,
+    max_connections_per_peer: u32,
+    backlog_policy: u32,
+) -> Result<(), AirXError> {
+    let backlog_policy = BacklogPolicy::from(backlog_policy)
+        .ok_or_else(|| AirXError::InvalidArgument(format!("Unknown backlog policy {}.", backlog_policy)))?;
+    if worker_pool_size == 0 {
+        return Err(AirXError::InvalidArgument(String::from("Worker pool needs at least one thread.")));
+    }
+    let limits = ConnectionLimits {
+        worker_pool_size: worker_pool_size as usize,
+        max_connections: max_connections as usize,
+        max_connections_per_peer: max_connections_per_peer as usize,
+        backlog_policy,
+        idle_timeout: airx.config().connection_limits.idle_timeout,
+    };
+    info!("lib: Connection limits set to {:?}.", limits);
+    airx.config_mut().connection_limits = limits;
+    Ok(())
+}
+
+/// Only text and file part frames are configurable, the others are protocol sized.
+/// Takes effect the next time the data service starts.
+pub fn shared_airx_set_frame_limits(
+    airx: &mut AirXService,
+    max_text_frame_size: u32,
+    max_file_part_frame_size: u32,
+    oversized_frame_penalty_millis: u64,
+) -> Result<(), AirXError> {
+    if max_text_frame_size == 0 {
+        return Err(AirXError::InvalidArgument(String::from("Text frame limit must not be 0.")));
+    }
+    if (max_file_part_frame_size as usize) < DEFAULT_FILE_PART_FRAME_LIMIT {
+        return Err(AirXError::InvalidArgument(format!(
+            "File part frame limit must hold a whole chunk ({} bytes).", DEFAULT_FILE_PART_FRAME_LIMIT)));
+    }
+    let config = airx.config_mut();
+    config.frame_limits.text = max_text_frame_size as usize;
+    config.frame_limits.file_part = max_file_part_frame_size as usize;
+    config.oversized_frame_penalty_millis = oversized_frame_penalty_millis;
+    info!("lib: Frame limits set to {:?}, penalty {}ms.", config.frame_limits, oversized_frame_penalty_millis);
+    Ok(())
+}
+
+pub fn shared_airx_stop(airx: &AirXService) {
+    info!("lib: Stopping services.");
+    airx.stop();
+    info!("lib: Services stopped.");
+}
+
 pub fn shared_airx_init() {
     // Init logger.
     if let Ok(logger_config) = Config::builder()
@@ -0,0 +1,74 @@
+use crate::packet::hello_packet::{MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use crate::packet::protocol::features::ProtocolFeatures;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DeviceType {
+    #[default]
+    Unknown,
+    Phone,
+    Tablet,
+    Desktop,
+}
+
+impl DeviceType {
+    pub fn value(&self) -> u32 {
+        match self {
+            DeviceType::Unknown => 0,
+            DeviceType::Phone => 1,
+            DeviceType::Tablet => 2,
+            DeviceType::Desktop => 3,
+        }
+    }
+
+    pub fn from(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(DeviceType::Unknown),
+            1 => Some(DeviceType::Phone),
+            2 => Some(DeviceType::Tablet),
+            3 => Some(DeviceType::Desktop),
+            _ => None,
+        }
+    }
+}
+
+/// What a device announces about itself besides its address.
+/// Peers predating these fields leave everything unset.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct DeviceInfo {
+    pub protocol_version: Option<u32>,
+    /// Oldest protocol version the device still talks to.
+    pub min_protocol_version: Option<u32>,
+    /// Operating system, as in `std::env::consts::OS`.
+    pub platform: Option<String>,
+    pub device_type: DeviceType,
+    pub features: ProtocolFeatures,
+    /// Name chosen by the user, shown instead of the hostname when set.
+    pub display_name: Option<String>,
+    /// Whether the data port is served over TLS.
+    pub data_port_tls: bool,
+}
+
+impl DeviceInfo {
+    /// Info of this build.
+    pub fn local(display_name: Option<String>, device_type: DeviceType, encrypted: bool) -> Self {
+        Self {
+            protocol_version: Some(PROTOCOL_VERSION),
+            min_protocol_version: Some(MIN_PROTOCOL_VERSION),
+            platform: Some(std::env::consts::OS.to_string()),
+            device_type,
+            features: ProtocolFeatures::local(encrypted),
+            display_name,
+            data_port_tls: false,
+        }
+    }
+
+    /// Whether this build can open a data connection to the device.
+    /// Devices not announcing a version predate the hello and hang up on it.
+    pub fn is_compatible(&self) -> bool {
+        match self.protocol_version {
+            Some(version) => version >= MIN_PROTOCOL_VERSION
+                && self.min_protocol_version.unwrap_or(version) <= PROTOCOL_VERSION,
+            None => false,
+        }
+    }
+}
@@ -1,2 +1,3 @@
+pub mod device_info;
 pub mod peer;
 pub mod tcp_server;
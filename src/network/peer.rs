@@ -1,6 +1,10 @@
+use std::collections::HashMap;
 use std::hash::Hash;
-use std::net::{Ipv4Addr};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV6};
 use std::string::ToString;
+use std::time::{Duration, Instant};
+use crate::network::device_info::DeviceInfo;
+use crate::security::identity::IdentityKey;
 
 const DEFAULT_HOSTNAME: &str = "<empty>";
 
@@ -9,27 +13,32 @@ pub struct Peer {
     host: String,
     port: u16,
     host_name: String,
+    encryption_required: bool,
+    last_seen: Instant,
+    device_id: Option<String>,
+    authenticated: bool,
+    addresses: HashMap<String, Instant>,
+    identity_key: Option<IdentityKey>,
+    device_info: DeviceInfo,
 }
 
 impl Default for Peer {
     fn default() -> Self {
-        Self {
-            host: String::from("0.0.0.0"),
-            port: 0,
-            host_name: DEFAULT_HOSTNAME.to_string(),
-        }
+        Self::new(&String::from("0.0.0.0"), 0, None)
     }
 }
 
 impl Hash for Peer {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.host.hash(state);
+        self.key().hash(state);
     }
 }
 
+/// Peers with a device ID are the same device on any address.
+/// Legacy peers without one are told apart by host.
 impl PartialEq for Peer {
     fn eq(&self, other: &Self) -> bool {
-        self.host == other.host
+        self.key() == other.key()
     }
 
     fn ne(&self, other: &Self) -> bool {
@@ -39,6 +48,10 @@ impl PartialEq for Peer {
 
 impl ToString for Peer {
     fn to_string(&self) -> String {
+        // IPv6 hosts are bracketed so the port stays unambiguous.
+        if self.host.contains(':') {
+            return format!("{}@[{}]:{}", &self.host_name, self.host, self.port);
+        }
         format!(
             "{}@{}:{}",
             &self.host_name,
@@ -48,16 +61,29 @@ impl ToString for Peer {
     }
 }
 
+/// Plain IP, or IPv6 with a scope id as in "fe80::1%3".
+fn parse_host(host: &str, port: u16) -> Option<SocketAddr> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Some(SocketAddr::new(ip, port));
+    }
+    format!("[{}]:{}", host, port)
+        .parse::<SocketAddrV6>()
+        .ok()
+        .map(SocketAddr::V6)
+}
+
 impl Peer {
     pub fn from(socket_addr: &Ipv4Addr, port: u16, host_name: Option<&String>) -> Self {
-        Self {
-            host: socket_addr.to_string(),
-            port,
-            host_name: match host_name {
-                Some(name) => name.clone(),
-                None => DEFAULT_HOSTNAME.to_string(),
-            },
-        }
+        Self::new(&socket_addr.to_string(), port, host_name)
+    }
+
+    /// IPv6 link-local peers keep the scope id of the interface they were seen on.
+    pub fn from_socket_addr(socket_addr: &SocketAddr, port: u16, host_name: Option<&String>) -> Self {
+        let host = match socket_addr {
+            SocketAddr::V6(addr) if addr.scope_id() != 0 => format!("{}%{}", addr.ip(), addr.scope_id()),
+            _ => socket_addr.ip().to_string(),
+        };
+        Self::new(&host, port, host_name)
     }
 
     pub fn new(host: &String, port: u16, host_name: Option<&String>) -> Self {
@@ -68,9 +94,24 @@ impl Peer {
                 Some(name) => name.clone(),
                 None => DEFAULT_HOSTNAME.to_string(),
             },
+            encryption_required: false,
+            last_seen: Instant::now(),
+            device_id: None,
+            authenticated: false,
+            addresses: HashMap::from([(host.clone(), Instant::now())]),
+            identity_key: None,
+            device_info: DeviceInfo::default(),
         }
     }
 
+    fn key(&self) -> (bool, &String) {
+        match self.device_id {
+            Some(ref id) => (true, id),
+            None => (false, &self.host),
+        }
+    }
+
+    /// The address this peer was most recently seen on.
     pub fn host(&self) -> &String {
         &self.host
     }
@@ -79,7 +120,106 @@ impl Peer {
         self.port
     }
 
+    /// Socket address of the peer on `port`, including the IPv6 scope id if any.
+    pub fn socket_addr(&self, port: u16) -> Option<SocketAddr> {
+        parse_host(&self.host, port)
+    }
+
+    pub fn ip_addr(&self) -> Option<IpAddr> {
+        self.socket_addr(0).map(|addr| addr.ip())
+    }
+
     pub fn host_name(&self) -> &String {
         &self.host_name
     }
+
+    /// Whether the peer only accepts encrypted data connections.
+    pub fn encryption_required(&self) -> bool {
+        self.encryption_required
+    }
+
+    pub fn set_encryption_required(&mut self, encryption_required: bool) {
+        self.encryption_required = encryption_required;
+    }
+
+    /// When the peer was last heard from, the creation time unless touched.
+    pub fn last_seen(&self) -> Instant {
+        self.last_seen
+    }
+
+    pub fn set_last_seen(&mut self, last_seen: Instant) {
+        self.last_seen = last_seen;
+    }
+
+    pub fn is_expired(&self, ttl: Duration) -> bool {
+        self.last_seen.elapsed() > ttl
+    }
+
+    pub fn device_id(&self) -> Option<&String> {
+        self.device_id.as_ref()
+    }
+
+    pub fn set_device_id(&mut self, device_id: Option<String>) {
+        self.device_id = device_id;
+    }
+
+    /// Whether the announcement was signed with the group key, so that
+    /// its device ID can be believed. Anyone may claim any ID otherwise.
+    pub fn authenticated(&self) -> bool {
+        self.authenticated
+    }
+
+    pub fn set_authenticated(&mut self, authenticated: bool) {
+        self.authenticated = authenticated;
+    }
+
+    /// Identity key the peer advertises. Unverified until a data connection proves it.
+    pub fn identity_key(&self) -> Option<&IdentityKey> {
+        self.identity_key.as_ref()
+    }
+
+    pub fn set_identity_key(&mut self, identity_key: Option<IdentityKey>) {
+        self.identity_key = identity_key;
+    }
+
+    /// Version, platform and features the peer announced, unset for older peers.
+    pub fn device_info(&self) -> &DeviceInfo {
+        &self.device_info
+    }
+
+    pub fn set_device_info(&mut self, device_info: DeviceInfo) {
+        self.device_info = device_info;
+    }
+
+    /// The name chosen by the user, or the hostname.
+    pub fn display_name(&self) -> &String {
+        self.device_info.display_name.as_ref().unwrap_or(&self.host_name)
+    }
+
+    pub fn is_compatible(&self) -> bool {
+        self.device_info.is_compatible()
+    }
+
+    /// Every host this device has been seen on recently, including `host`.
+    pub fn addresses(&self) -> Vec<&String> {
+        self.addresses.keys().collect()
+    }
+
+    /// Take the addresses `other` was seen on, then forget those not seen within `max_age`,
+    /// they may belong to another device by now. An authenticated peer ignores
+    /// the addresses of an unauthenticated one, which may be anyone's.
+    pub fn merge_addresses(&mut self, other: &Peer, max_age: Duration) {
+        if !self.authenticated || other.authenticated {
+            for (host, seen) in &other.addresses {
+                let latest = self.addresses.entry(host.clone()).or_insert(*seen);
+                *latest = (*latest).max(*seen);
+            }
+        }
+        let host = &self.host;
+        self.addresses.retain(|h, seen| h == host || seen.elapsed() <= max_age);
+    }
+
+    pub fn has_ip(&self, ip: &IpAddr) -> bool {
+        self.addresses.keys().any(|host| parse_host(host, 0).map(|a| a.ip()) == Some(*ip))
+    }
 }
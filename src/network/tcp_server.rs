@@ -1,20 +1,54 @@
 use std::io;
-use std::net::{Incoming, TcpListener};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use log::warn;
+use socket2::{Domain, Protocol, Socket, Type};
 
 pub struct TcpServer {
-    listener: TcpListener,
+    listeners: Vec<TcpListener>,
+}
+
+fn listen_v6_only(port: u16) -> Result<TcpListener, io::Error> {
+    let socket = Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_only_v6(true)?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), port).into())?;
+    socket.listen(128)?;
+    Ok(socket.into())
 }
 
 impl TcpServer {
     /// Create a new TcpServer with non-blocking mode.
-    pub fn create_and_listen(host: &String, port: u16) -> Result<Self, io::Error> {
-        let addr = format!("{}:{}", host, port);
-        let listener = TcpListener::bind(addr)?;
-        listener.set_nonblocking(true)?;
-        Ok(Self { listener })
+    /// Listening on 0.0.0.0 also listens on [::] when IPv6 is available.
+    pub fn create_and_listen(host: &str, port: u16) -> Result<Self, io::Error> {
+        let mut listeners = vec![TcpListener::bind((host, port))?];
+
+        if host.parse::<Ipv4Addr>().map(|ip| ip.is_unspecified()).unwrap_or(false) {
+            match listen_v6_only(port) {
+                Ok(l) => listeners.push(l),
+                Err(e) => warn!("IPv6 is unavailable, listening on IPv4 only ({}).", e),
+            }
+        }
+
+        for listener in &listeners {
+            listener.set_nonblocking(true)?;
+        }
+        Ok(Self { listeners })
+    }
+
+    /// Accept a connection from any of the listeners, or WouldBlock if none is pending.
+    pub fn accept(&self) -> Result<(TcpStream, SocketAddr), io::Error> {
+        for listener in &self.listeners {
+            match listener.accept() {
+                Ok(pair) => return Ok(pair),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(io::Error::from(io::ErrorKind::WouldBlock))
     }
 
-    pub fn incoming(&self) -> Incoming<'_> {
-        self.listener.incoming()
+    /// The non-blocking listeners, for handing over to an async runtime.
+    pub fn into_listeners(self) -> Vec<TcpListener> {
+        self.listeners
     }
 }
@@ -0,0 +1,305 @@
+use std::io;
+use std::mem::size_of;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use crate::compatibility::unified_endian::UnifiedEndian;
+use crate::packet::data::transfer_id::TransferIdFormat;
+use crate::packet::frame_limits::{check_size, FrameLimits};
+use crate::packet::hello_packet::{HandshakeError, HelloPacket, is_hello_packet, Negotiated};
+use crate::packet::protocol::serialize::Serialize;
+use crate::security::group_key::GroupKey;
+use crate::security::identity::{deserialize_identity_hello, DeviceIdentity, identity_transcript, IdentityKey, random_nonce, serialize_identity_hello};
+use crate::security::secure_channel::{deserialize_hello, HandshakeRole, random_hello, SEAL_OVERHEAD, SecureChannel, serialize_hello};
+
+const SIZE_SIZE: usize = size_of::<u32>();
+const FRAME_HEAD_SIZE: usize = size_of::<u16>();
+
+/// Async counterpart of `DataTransmit`, speaking the same framing on any `AsyncRead + AsyncWrite`.
+/// The runtime waits for readiness, so there is no retrying on WouldBlock.
+pub struct AsyncDataTransmit<S = TcpStream> {
+    stream: S,
+    channel: Option<SecureChannel>,
+    peer_identity: Option<IdentityKey>,
+    frame_limits: FrameLimits,
+    negotiated: Option<Negotiated>,
+    pending_frame: Option<Vec<u8>>,
+}
+
+impl<S> AsyncDataTransmit<S> where S: AsyncRead + AsyncWrite + Unpin {
+    pub fn from(stream: S) -> Self {
+        Self {
+            stream,
+            channel: None,
+            peer_identity: None,
+            frame_limits: FrameLimits::default(),
+            negotiated: None,
+            pending_frame: None,
+        }
+    }
+
+    pub async fn close(&mut self) -> Result<(), io::Error> {
+        self.stream.shutdown().await
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.channel.is_some()
+    }
+
+    /// Largest frames accepted from the peer, the defaults unless set.
+    pub fn set_frame_limits(&mut self, frame_limits: FrameLimits) {
+        self.frame_limits = frame_limits;
+    }
+
+    pub fn frame_limits(&self) -> &FrameLimits {
+        &self.frame_limits
+    }
+
+    /// Public key the peer proved to own, if it identified itself.
+    pub fn peer_identity(&self) -> Option<&IdentityKey> {
+        self.peer_identity.as_ref()
+    }
+
+    pub fn stream(&self) -> &S {
+        &self.stream
+    }
+
+    /// Version and features settled in the hello, `None` for peers older than the hello.
+    pub fn negotiated(&self) -> Option<&Negotiated> {
+        self.negotiated.as_ref()
+    }
+
+    /// How transfer ids are written on this connection.
+    pub fn transfer_id_format(&self) -> TransferIdFormat {
+        TransferIdFormat::of(self.negotiated.map(|n| n.version))
+    }
+
+    /// Exchange hellos as the connecting side, before anything else is sent.
+    pub async fn negotiate_as_initiator(&mut self, hello: &HelloPacket) -> Result<Negotiated, io::Error> {
+        self.send_frame(&hello.serialize()).await?;
+
+        // Older peers cannot parse the hello and hang up.
+        let reply = match self.read_frame(false).await {
+            Ok(r) => r,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(HandshakeError::NoHello.into()),
+            Err(e) => return Err(e),
+        };
+        let peer_hello = HelloPacket::deserialize(&reply).map_err(|_| HandshakeError::NoHello)?;
+
+        let negotiated = hello.negotiate(&peer_hello)?;
+        self.negotiated = Some(negotiated);
+        Ok(negotiated)
+    }
+
+    /// Exchange hellos as the listening side, like `DataTransmit::negotiate_as_responder`.
+    pub async fn negotiate_as_responder(&mut self, hello: &HelloPacket) -> Result<Option<Negotiated>, io::Error> {
+        let frame = self.read_frame(false).await?;
+        if !is_hello_packet(&frame) {
+            self.pending_frame = Some(frame);
+            return Ok(None);
+        }
+        let peer_hello = HelloPacket::deserialize(&frame)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.send_frame(&hello.serialize()).await?;
+
+        let negotiated = hello.negotiate(&peer_hello)?;
+        self.negotiated = Some(negotiated);
+        Ok(Some(negotiated))
+    }
+
+    /// Discard whatever the peer still sends until it closes or `timeout` passes,
+    /// so that closing does not reset the connection before it read our last frame.
+    pub async fn drain(&mut self, timeout: Duration) {
+        let mut buf = [0u8; 64 * 1024];
+        let _ = tokio::time::timeout(timeout, async {
+            while let Ok(n) = self.stream.read(&mut buf).await {
+                if n == 0 {
+                    break;
+                }
+            }
+        }).await;
+    }
+
+    /// Start an encrypted session as the connecting side.
+    pub async fn handshake_as_initiator(&mut self, group_key: &GroupKey) -> Result<(), io::Error> {
+        let initiator_random = random_hello();
+        self.send_frame(&serialize_hello(&initiator_random)).await?;
+
+        let reply = self.read_frame(false).await?;
+        let responder_random = match deserialize_hello(&reply) {
+            Some(r) => r,
+            None => return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "Peer did not answer the encryption handshake.")),
+        };
+
+        self.channel = Some(SecureChannel::new(
+            group_key, HandshakeRole::Initiator, &initiator_random, &responder_random));
+        Ok(())
+    }
+
+    /// Accept an encrypted session as the listening side.
+    /// Peers that start talking in plaintext are refused.
+    pub async fn handshake_as_responder(&mut self, group_key: &GroupKey) -> Result<(), io::Error> {
+        let hello = self.read_frame(false).await?;
+        let initiator_random = match deserialize_hello(&hello) {
+            Some(r) => r,
+            None => return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied, "Peer did not start an encrypted session.")),
+        };
+
+        let responder_random = random_hello();
+        self.send_frame(&serialize_hello(&responder_random)).await?;
+
+        self.channel = Some(SecureChannel::new(
+            group_key, HandshakeRole::Responder, &initiator_random, &responder_random));
+        Ok(())
+    }
+
+    /// What identity proofs are bound to, empty on a plain connection.
+    fn channel_binding(&self) -> Vec<u8> {
+        self.channel.as_ref().map(|c| c.binding().to_vec()).unwrap_or_default()
+    }
+
+    /// Prove our identity to the listening side and have it prove its own.
+    /// Runs after the encryption handshake, if any.
+    pub async fn identify_as_initiator(&mut self, identity: &DeviceIdentity) -> Result<IdentityKey, io::Error> {
+        let binding = self.channel_binding();
+        let own_key = identity.public_key();
+        let own_nonce = random_nonce();
+        self.send_data(&serialize_identity_hello(&own_key, &own_nonce)).await?;
+
+        let reply = self.read_data().await?;
+        let (peer_key, peer_nonce, signature) = match deserialize_identity_hello(&reply) {
+            Some(r) => r,
+            None => return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "Peer did not answer the identity exchange.")),
+        };
+        let transcript = identity_transcript(false, &own_nonce, &peer_nonce, &own_key, &binding);
+        if !DeviceIdentity::verify(&peer_key, &transcript, signature) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied, "Peer failed to prove its identity."));
+        }
+
+        if let Some(negotiated) = &self.negotiated {
+            negotiated.check_identity(&peer_key)?;
+        }
+
+        let transcript = identity_transcript(true, &peer_nonce, &own_nonce, &peer_key, &binding);
+        self.send_data(&identity.sign(&transcript)).await?;
+
+        self.peer_identity = Some(peer_key);
+        Ok(peer_key)
+    }
+
+    /// Answer an identity hello already read from the connection.
+    pub async fn identify_as_responder(&mut self, identity: &DeviceIdentity, hello: &[u8]) -> Result<IdentityKey, io::Error> {
+        let (peer_key, peer_nonce, _) = match deserialize_identity_hello(hello) {
+            Some(r) => r,
+            None => return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "Invalid identity hello.")),
+        };
+
+        let binding = self.channel_binding();
+        let own_key = identity.public_key();
+        let own_nonce = random_nonce();
+        let transcript = identity_transcript(false, &peer_nonce, &own_nonce, &peer_key, &binding);
+        let mut reply = serialize_identity_hello(&own_key, &own_nonce);
+        reply.extend_from_slice(&identity.sign(&transcript));
+        self.send_data(&reply).await?;
+
+        let signature = self.read_data().await?;
+        let transcript = identity_transcript(true, &own_nonce, &peer_nonce, &own_key, &binding);
+        if !DeviceIdentity::verify(&peer_key, &transcript, &signature) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied, "Peer failed to prove its identity."));
+        }
+        if let Some(negotiated) = &self.negotiated {
+            negotiated.check_identity(&peer_key)?;
+        }
+
+        self.peer_identity = Some(peer_key);
+        Ok(peer_key)
+    }
+
+    /// Send data as one frame, encrypted if the session is.
+    pub async fn send_data(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        match self.channel.as_mut() {
+            Some(channel) => {
+                let sealed = channel.seal(data)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                self.send_frame(&sealed).await
+            }
+            None => self.send_frame(data).await,
+        }
+    }
+
+    /// Read one frame, decrypting it if the session is encrypted.
+    pub async fn read_data(&mut self) -> Result<Vec<u8>, io::Error> {
+        let sealed = self.channel.is_some();
+        let frame = self.read_frame(sealed).await?;
+        match self.channel.as_mut() {
+            Some(channel) => {
+                let data = channel.open(&frame)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                self.frame_limits.check(&data, data.len())?;
+                Ok(data)
+            }
+            None => Ok(frame),
+        }
+    }
+
+    async fn send_frame(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        let data_len = data.len() as u32;
+        let mut buf = vec![0u8; SIZE_SIZE + data.len()];
+        buf[0..SIZE_SIZE].copy_from_slice(&data_len.to_bytes());
+        buf[SIZE_SIZE..].copy_from_slice(data);
+        self.stream.write_all(&buf).await?;
+        self.stream.flush().await
+    }
+
+    /// Read size N and read N bytes of data, checking N against the frame limits
+    /// the same way `DataTransmit` does before allocating.
+    async fn read_frame(&mut self, sealed: bool) -> Result<Vec<u8>, io::Error> {
+        if let Some(frame) = self.pending_frame.take() {
+            return Ok(frame);
+        }
+
+        let mut size_buf = [0u8; SIZE_SIZE];
+        self.stream.read_exact(&mut size_buf).await?;
+        let packet_size = u32::from_bytes(size_buf) as usize;
+        if packet_size == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Empty frame."));
+        }
+
+        let mut head = [0u8; FRAME_HEAD_SIZE];
+        let head_len = FRAME_HEAD_SIZE.min(packet_size);
+        if sealed {
+            check_size(packet_size, self.frame_limits.max_frame_size() + SEAL_OVERHEAD)?;
+        } else {
+            check_size(packet_size, self.frame_limits.max_frame_size())?;
+            self.stream.read_exact(&mut head[..head_len]).await?;
+            self.frame_limits.check(&head[..head_len], packet_size)?;
+        }
+
+        let mut data_buf = vec![0u8; packet_size];
+        let from = if sealed { 0 } else { head_len };
+        data_buf[..from].copy_from_slice(&head[..from]);
+        self.stream.read_exact(&mut data_buf[from..]).await?;
+        Ok(data_buf)
+    }
+}
+
+impl AsyncDataTransmit<TcpStream> {
+    /// Wait up to `timeout` for the peer to send something, or close the connection.
+    /// Returns whether it did.
+    pub async fn wait_for_data(&mut self, timeout: Duration) -> Result<bool, io::Error> {
+        if self.pending_frame.is_some() {
+            return Ok(true);
+        }
+        let mut buf = [0u8; 1];
+        match tokio::time::timeout(timeout, self.stream.peek(&mut buf)).await {
+            Ok(result) => result.map(|_| true),
+            Err(_) => Ok(false),
+        }
+    }
+}
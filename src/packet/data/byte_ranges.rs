@@ -0,0 +1,122 @@
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use crate::compatibility::unified_endian::UnifiedEndian;
+use crate::packet::protocol::reader::{PacketReader, ReadError};
+
+/// Most ranges sent or accepted in one packet. Received files are mostly
+/// contiguous, and leaving ranges out only costs sending them again.
+pub const MAX_BYTE_RANGES: usize = 1024;
+/// Size of `MAX_BYTE_RANGES` ranges as written by `ByteRanges::write`.
+pub const MAX_BYTE_RANGES_SIZE: usize = 4 + MAX_BYTE_RANGES * 16;
+
+/// Byte ranges of a file, `start..end`, kept sorted and merged.
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct ByteRanges {
+    ranges: Vec<(u64, u64)>,
+}
+
+impl ByteRanges {
+    pub fn new() -> Self {
+        Self { ranges: Vec::new() }
+    }
+
+    pub fn ranges(&self) -> &[(u64, u64)] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Add `start..end`, merging it with the ranges it touches.
+    pub fn insert(&mut self, start: u64, end: u64) {
+        if start >= end {
+            return;
+        }
+        let first = self.ranges.partition_point(|(_, e)| *e < start);
+        let last = self.ranges.partition_point(|(s, _)| *s <= end);
+        let merged = match first < last {
+            true => (start.min(self.ranges[first].0), end.max(self.ranges[last - 1].1)),
+            false => (start, end),
+        };
+        self.ranges.splice(first..last, [merged]);
+    }
+
+    /// Bytes covered.
+    pub fn len(&self) -> u64 {
+        self.ranges.iter().map(|(s, e)| e - s).sum()
+    }
+
+    /// Ranges of a file of `size` bytes not covered yet.
+    pub fn missing(&self, size: u64) -> Vec<(u64, u64)> {
+        let mut missing = Vec::new();
+        let mut position = 0;
+        for &(start, end) in &self.ranges {
+            if start >= size {
+                break;
+            }
+            if start > position {
+                missing.push((position, start));
+            }
+            position = position.max(end);
+        }
+        if position < size {
+            missing.push((position, size));
+        }
+        missing
+    }
+
+    pub fn covers(&self, size: u64) -> bool {
+        self.missing(size).is_empty()
+    }
+
+    /// Serialized as a u32 count, then start and end of each range as u64.
+    /// Only the first `MAX_BYTE_RANGES` are written.
+    pub fn write(&self, bytes: &mut Vec<u8>) {
+        let ranges = &self.ranges[..self.ranges.len().min(MAX_BYTE_RANGES)];
+        bytes.extend_from_slice(&(ranges.len() as u32).to_bytes());
+        for (start, end) in ranges {
+            bytes.extend_from_slice(&start.to_bytes());
+            bytes.extend_from_slice(&end.to_bytes());
+        }
+    }
+
+    pub fn read(reader: &mut PacketReader) -> Result<Self, ReadError> {
+        let count: u32 = reader.read()?;
+        let mut ranges = Self::new();
+        for i in 0..count as usize {
+            let start: u64 = reader.read()?;
+            let end: u64 = reader.read()?;
+            // Past the limit, the ranges are skipped and will be sent again.
+            if i < MAX_BYTE_RANGES {
+                ranges.insert(start, end);
+            }
+        }
+        Ok(ranges)
+    }
+
+    /// Parse the form given by `to_string`, such as `0-1024,4096-8192`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut ranges = Self::new();
+        for range in text.split(',').filter(|r| !r.is_empty()) {
+            let (start, end) = range.split_once('-')?;
+            ranges.insert(start.parse().ok()?, end.parse().ok()?);
+        }
+        Some(ranges)
+    }
+}
+
+impl Display for ByteRanges {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let ranges = self.ranges.iter()
+            .map(|(start, end)| format!("{}-{}", start, end))
+            .collect::<Vec<String>>();
+        write!(f, "{}", ranges.join(","))
+    }
+}
+
+impl Debug for ByteRanges {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
@@ -2,35 +2,78 @@ use std::error::Error;
 use std::fmt;
 use std::fmt::{Debug, Display, Formatter};
 use crate::compatibility::unified_endian::UnifiedEndian;
+use crate::packet::data::transfer_id::{TransferId, TransferIdFormat};
 use crate::packet::protocol::hash::Hash;
+use crate::packet::protocol::reader::{PacketReader, ReadError};
 use crate::packet::protocol::serialize::Serialize;
 
 // Serialized as:
+// 16 bytes: transfer id, absent in the legacy format
 // 8 bytes: file size in bytes
 // 4 bytes: file name length (UTF-8)
 // N bytes: file name (UTF-8)
 // 2 bytes: hash of (file_size,file_name_length)
-// 14 + N bytes in total
-const BASE_PACKET_SIZE: usize = 12;
+// 30 + N bytes in total, 14 + N in the legacy format
+// Then, if there is metadata (never in the legacy format):
+// 1 byte: metadata flags, see below
+// 2 + M bytes: MIME type length and MIME type (UTF-8), if flagged
+// 8 bytes: modification time in seconds since the Unix epoch, if flagged
+// 4 bytes: Unix permission bits, if flagged
+// Senders without metadata stop after the hash, and older receivers ignore what follows it.
+const METADATA_MIME_TYPE: u8 = 0x1;
+const METADATA_MODIFIED_AT: u8 = 0x2;
+const METADATA_PERMISSIONS: u8 = 0x4;
 
+/// Optional details of an offered file, for the receiver to show or restore.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct FileMetadata {
+    pub mime_type: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub modified_at: Option<u64>,
+    /// Unix permission bits.
+    pub permissions: Option<u32>,
+}
+
+impl FileMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.mime_type.is_none() && self.modified_at.is_none() && self.permissions.is_none()
+    }
+}
+
+/// Offer of a file. The name is the file name only, never a path.
 pub struct FileComingPacket {
+    transfer_id: TransferId,
     file_size: u64,
     file_name_length: u32,
     file_name: String,
+    metadata: FileMetadata,
 }
 
 impl FileComingPacket {
     pub fn new(
+        transfer_id: TransferId,
         file_size: u64,
         file_name: String,
     ) -> FileComingPacket {
         FileComingPacket {
+            transfer_id,
             file_size,
             file_name_length: file_name.len() as u32,
             file_name,
+            metadata: FileMetadata::default(),
         }
     }
 
+    pub fn with_metadata(mut self, metadata: FileMetadata) -> FileComingPacket {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Picked by the sender. Legacy senders announce none, theirs reads as the default id.
+    pub fn transfer_id(&self) -> TransferId {
+        self.transfer_id
+    }
+
     pub fn file_size(&self) -> u64 {
         self.file_size
     }
@@ -38,21 +81,29 @@ impl FileComingPacket {
     pub fn file_name(&self) -> &String {
         &self.file_name
     }
+
+    pub fn metadata(&self) -> &FileMetadata {
+        &self.metadata
+    }
 }
 
 impl Debug for FileComingPacket {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("FileComingPacket")
+            .field("transfer_id", &self.transfer_id)
             .field("file_size", &self.file_size)
             .field("file_name", &self.file_name)
+            .field("metadata", &self.metadata)
             .finish()
     }
 }
 
 impl PartialEq for FileComingPacket {
     fn eq(&self, other: &Self) -> bool {
-        self.file_size == other.file_size
+        self.transfer_id == other.transfer_id
+            && self.file_size == other.file_size
             && self.file_name == other.file_name
+            && self.metadata == other.metadata
     }
 
     fn ne(&self, other: &Self) -> bool {
@@ -68,34 +119,40 @@ pub enum FileComingPacketError {
 }
 
 impl Debug for FileComingPacketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for FileComingPacketError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         fmt::write(
             f,
             format_args!(
-                "DiscoveryPacketError: {}",
+                "{}",
                 match self {
-                    FileComingPacketError::InvalidHash => "Invalid hash",
-                    FileComingPacketError::FileNameTooLong => "File name too long",
-                    FileComingPacketError::FileTooLarge => "File too large",
-                    FileComingPacketError::CorruptedPacket => "Corrupted packet",
+                    FileComingPacketError::InvalidHash => "Invalid hash.",
+                    FileComingPacketError::FileNameTooLong => "File name too long.",
+                    FileComingPacketError::FileTooLarge => "File too large.",
+                    FileComingPacketError::CorruptedPacket => "Corrupted packet.",
                 }
             ),
         )
     }
 }
 
-impl Display for FileComingPacketError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        Debug::fmt(self, f)
+impl Error for FileComingPacketError {}
+
+impl From<ReadError> for FileComingPacketError {
+    fn from(_: ReadError) -> Self {
+        FileComingPacketError::CorruptedPacket
     }
 }
 
-impl Error for FileComingPacketError {}
-
 // 没有150年功力的人，不要轻易使用这个哈希算法，极易遭到反噬！
 // 怎么IDEA连注释都算重复啊，IDEA他不懂编程
 fn packet_hash(packet: &FileComingPacket) -> u16 {
-    (packet.file_size as u32 + packet.file_name_length) as u16
+    (packet.file_size as u32).wrapping_add(packet.file_name_length) as u16
 }
 
 impl Hash<u16> for FileComingPacket {
@@ -104,34 +161,78 @@ impl Hash<u16> for FileComingPacket {
     }
 }
 
-impl Serialize<Vec<u8>, FileComingPacketError> for FileComingPacket {
-    fn serialize(&self) -> Vec<u8> {
+fn write_metadata(metadata: &FileMetadata, bytes: &mut Vec<u8>) {
+    let mut flags = 0u8;
+    if metadata.mime_type.is_some() {
+        flags |= METADATA_MIME_TYPE;
+    }
+    if metadata.modified_at.is_some() {
+        flags |= METADATA_MODIFIED_AT;
+    }
+    if metadata.permissions.is_some() {
+        flags |= METADATA_PERMISSIONS;
+    }
+    bytes.push(flags);
+    if let Some(mime_type) = &metadata.mime_type {
+        let mime_type = &mime_type.as_bytes()[..mime_type.len().min(u16::MAX as usize)];
+        bytes.extend_from_slice(&(mime_type.len() as u16).to_bytes());
+        bytes.extend_from_slice(mime_type);
+    }
+    if let Some(modified_at) = metadata.modified_at {
+        bytes.extend_from_slice(&modified_at.to_bytes());
+    }
+    if let Some(permissions) = metadata.permissions {
+        bytes.extend_from_slice(&permissions.to_bytes());
+    }
+}
+
+/// Flags added later follow the known fields and are skipped.
+fn read_metadata(reader: &mut PacketReader) -> Result<FileMetadata, FileComingPacketError> {
+    let flags = reader.read_u8()?;
+    let mut metadata = FileMetadata::default();
+    if flags & METADATA_MIME_TYPE != 0 {
+        let length: u16 = reader.read()?;
+        metadata.mime_type = Some(String::from_utf8_lossy(reader.read_bytes(length)?).to_string());
+    }
+    if flags & METADATA_MODIFIED_AT != 0 {
+        metadata.modified_at = Some(reader.read()?);
+    }
+    if flags & METADATA_PERMISSIONS != 0 {
+        metadata.permissions = Some(reader.read()?);
+    }
+    Ok(metadata)
+}
+
+impl FileComingPacket {
+    pub fn serialize_as(&self, format: TransferIdFormat) -> Vec<u8> {
         let mut bytes = Vec::<u8>::new();
+        if format == TransferIdFormat::Wide {
+            format.write(&self.transfer_id, &mut bytes);
+        }
         bytes.extend_from_slice(&self.file_size.to_bytes());
         bytes.extend_from_slice(&self.file_name_length.to_bytes());
         bytes.extend_from_slice(self.file_name.as_bytes());
         bytes.extend_from_slice(&packet_hash(self).to_bytes());
+        if format == TransferIdFormat::Wide && !self.metadata.is_empty() {
+            write_metadata(&self.metadata, &mut bytes);
+        }
         bytes
     }
 
-    fn deserialize(data: &Vec<u8>) -> Result<Self, FileComingPacketError> {
-        if data.len() < BASE_PACKET_SIZE {
-            return Err(FileComingPacketError::CorruptedPacket);
-        }
-
-        let file_size = u64::from_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]]);
-        let file_name_length = u32::from_bytes([data[8], data[9], data[10], data[11]]);
-        let file_name_length = file_name_length as usize;
-        let file_name = String::from_utf8(
-            data[12..(12 + file_name_length)].to_vec()
-        ).map_err(|_| FileComingPacketError::FileNameTooLong)?;
+    pub fn deserialize_as(data: &[u8], format: TransferIdFormat) -> Result<Self, FileComingPacketError> {
+        let mut reader = PacketReader::new(data);
+        let transfer_id = match format {
+            TransferIdFormat::Wide => format.read(&mut reader)?,
+            TransferIdFormat::Legacy => TransferId::default(),
+        };
+        let file_size: u64 = reader.read()?;
+        let file_name_length: u32 = reader.read()?;
+        let file_name = String::from_utf8(reader.read_bytes(file_name_length)?.to_vec())
+            .map_err(|_| FileComingPacketError::FileNameTooLong)?;
+        let hash: u16 = reader.read()?;
 
-        let hash = u16::from_bytes([
-            data[12 + file_name_length],
-            data[12 + file_name_length + 1],
-        ]);
-
-        let ret = FileComingPacket::new(
+        let mut ret = FileComingPacket::new(
+            transfer_id,
             file_size,
             file_name,
         );
@@ -140,6 +241,21 @@ impl Serialize<Vec<u8>, FileComingPacketError> for FileComingPacket {
             return Err(FileComingPacketError::InvalidHash);
         }
 
+        if format == TransferIdFormat::Wide && reader.remaining() > 0 {
+            ret.metadata = read_metadata(&mut reader)?;
+        }
+        reader.finish()?;
+
         Ok(ret)
     }
 }
+
+impl Serialize<Vec<u8>, FileComingPacketError> for FileComingPacket {
+    fn serialize(&self) -> Vec<u8> {
+        self.serialize_as(TransferIdFormat::Wide)
+    }
+
+    fn deserialize(data: &Vec<u8>) -> Result<Self, FileComingPacketError> {
+        Self::deserialize_as(data, TransferIdFormat::Wide)
+    }
+}
@@ -1,40 +1,43 @@
+use std::error::Error;
 use std::fmt;
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use crate::compatibility::unified_endian::UnifiedEndian;
+use crate::packet::data::transfer_id::{TransferId, TransferIdFormat};
+use crate::packet::protocol::reader::{PacketReader, ReadError};
 use crate::packet::protocol::serialize::Serialize;
 
 pub struct FilePartPacket {
-    file_id: u8,
+    transfer_id: TransferId,
     offset: u64,
     length: u64,
     data: Vec<u8>,
 }
 
 // Serialized as:
-// 1 byte: file id
+// 16 bytes: transfer id (1 byte in the legacy format)
 // 8 bytes: offset
 // 8 bytes: length
 // N bytes: data
-// 17 + N bytes in total
-const BASE_PACKET_SIZE: usize = 17;
+// 32 + N bytes in total, 17 + N in the legacy format
+const BASE_PACKET_SIZE: usize = 16; // without the transfer id
 
 impl FilePartPacket {
     pub fn new(
-        file_id: u8,
+        transfer_id: TransferId,
         offset: u64,
         length: u64,
         data: Vec<u8>,
     ) -> FilePartPacket {
         FilePartPacket {
-            file_id,
+            transfer_id,
             offset,
             length,
             data,
         }
     }
 
-    pub fn file_id(&self) -> u8 {
-        self.file_id
+    pub fn transfer_id(&self) -> TransferId {
+        self.transfer_id
     }
 
     pub fn offset(&self) -> u64 {
@@ -53,7 +56,7 @@ impl FilePartPacket {
 impl Debug for FilePartPacket {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("FilePartPacket")
-            .field("file_id", &self.file_id)
+            .field("transfer_id", &self.transfer_id)
             .field("offset", &self.offset)
             .field("length", &self.length)
             .field("data", &self.data)
@@ -63,7 +66,7 @@ impl Debug for FilePartPacket {
 
 impl PartialEq for FilePartPacket {
     fn eq(&self, other: &Self) -> bool {
-        self.file_id == other.file_id
+        self.transfer_id == other.transfer_id
             && self.offset == other.offset
             && self.length == other.length
             && self.data == other.data
@@ -79,67 +82,71 @@ pub enum FilePartPacketError {
 }
 
 impl Debug for FilePartPacketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for FilePartPacketError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         fmt::write(
             f,
             format_args!(
-                "DiscoveryPacketError: {}",
+                "{}",
                 match self {
-                    FilePartPacketError::CorruptedData => "Corrupted packet",
+                    FilePartPacketError::CorruptedData => "Corrupted packet.",
                 }
             ),
         )
     }
 }
 
-impl Serialize<Vec<u8>, FilePartPacketError> for FilePartPacket {
-    fn serialize(&self) -> Vec<u8> {
-        let mut serialized = Vec::with_capacity(BASE_PACKET_SIZE + self.data.len());
-        serialized.push(self.file_id);
+impl Error for FilePartPacketError {}
+
+impl From<ReadError> for FilePartPacketError {
+    fn from(_: ReadError) -> Self {
+        FilePartPacketError::CorruptedData
+    }
+}
+
+impl FilePartPacket {
+    pub fn serialize_as(&self, format: TransferIdFormat) -> Vec<u8> {
+        let mut serialized = Vec::with_capacity(format.size() + BASE_PACKET_SIZE + self.data.len());
+        format.write(&self.transfer_id, &mut serialized);
         serialized.extend_from_slice(&self.offset.to_bytes());
         serialized.extend_from_slice(&self.length.to_bytes());
         serialized.extend_from_slice(&self.data);
         serialized
     }
 
-    fn deserialize(serialized: &Vec<u8>) -> Result<FilePartPacket, FilePartPacketError> {
-        if serialized.len() < BASE_PACKET_SIZE {
-            return Err(FilePartPacketError::CorruptedData);
-        }
-
-        let file_id = serialized[0];
-        let offset = u64::from_bytes([
-            serialized[1],
-            serialized[2],
-            serialized[3],
-            serialized[4],
-            serialized[5],
-            serialized[6],
-            serialized[7],
-            serialized[8],
-        ]);
-        let length = u64::from_bytes([
-            serialized[9],
-            serialized[10],
-            serialized[11],
-            serialized[12],
-            serialized[13],
-            serialized[14],
-            serialized[15],
-            serialized[16],
-        ]);
-
-        if serialized.len() != BASE_PACKET_SIZE + length as usize {
-            return Err(FilePartPacketError::CorruptedData);
-        }
-
-        let data = serialized[BASE_PACKET_SIZE..(BASE_PACKET_SIZE + length as usize)].to_vec();
+    pub fn deserialize_as(serialized: &[u8], format: TransferIdFormat) -> Result<FilePartPacket, FilePartPacketError> {
+        let mut reader = PacketReader::new(serialized);
+        let transfer_id = format.read(&mut reader)?;
+        let offset: u64 = reader.read()?;
+        let length: u64 = reader.read()?;
+        let data = reader.read_bytes(length)?.to_vec();
+        reader.finish()?;
 
         Ok(FilePartPacket::new(
-            file_id,
+            transfer_id,
             offset,
             length,
             data,
         ))
     }
+
+    /// Transfer id of a serialized packet, without copying its data.
+    pub fn peek_transfer_id(serialized: &[u8], format: TransferIdFormat) -> Option<TransferId> {
+        format.read(&mut PacketReader::new(serialized)).ok()
+    }
+}
+
+impl Serialize<Vec<u8>, FilePartPacketError> for FilePartPacket {
+    fn serialize(&self) -> Vec<u8> {
+        self.serialize_as(TransferIdFormat::Wide)
+    }
+
+    fn deserialize(serialized: &Vec<u8>) -> Result<FilePartPacket, FilePartPacketError> {
+        Self::deserialize_as(serialized, TransferIdFormat::Wide)
+    }
 }
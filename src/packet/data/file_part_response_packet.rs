@@ -1,51 +1,65 @@
+use std::error::Error;
 use std::fmt;
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
+use crate::packet::data::transfer_id::{TransferId, TransferIdFormat};
+use crate::packet::protocol::reader::{PacketReader, ReadError};
 use crate::packet::protocol::serialize::Serialize;
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum ResponseKind {
     StopSending = 0x1,
     StopReceiving = 0x2,
 }
 
+impl ResponseKind {
+    pub fn value(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from(value: u8) -> Option<Self> {
+        match value {
+            0x1 => Some(ResponseKind::StopSending),
+            0x2 => Some(ResponseKind::StopReceiving),
+            _ => None,
+        }
+    }
+}
+
 pub struct FilePartResponsePacket {
-    file_id: u8,
-    response_kind: u8,
+    transfer_id: TransferId,
+    response_kind: ResponseKind,
 }
 
 // Serialized as:
-// 1 byte: file id
+// 16 bytes: transfer id (1 byte in the legacy format)
 // 1 byte: response kind
-// 2 bytes in total
-const BASE_PACKET_SIZE: usize = 2;
+// 17 bytes in total, 2 in the legacy format
+const BASE_PACKET_SIZE: usize = 1; // without the transfer id
 
 impl FilePartResponsePacket {
     pub fn new(
-        file_id: u8,
+        transfer_id: TransferId,
         response_kind: ResponseKind,
     ) -> FilePartResponsePacket {
         FilePartResponsePacket {
-            file_id,
-            response_kind: response_kind as u8,
+            transfer_id,
+            response_kind,
         }
     }
 
-    pub fn file_id(&self) -> u8 {
-        self.file_id
+    pub fn transfer_id(&self) -> TransferId {
+        self.transfer_id
     }
 
     pub fn response_kind(&self) -> ResponseKind {
-        match self.response_kind {
-            0x1 => ResponseKind::StopSending,
-            0x2 => ResponseKind::StopReceiving,
-            _ => panic!("Invalid response kind"),
-        }
+        self.response_kind
     }
 }
 
 impl Debug for FilePartResponsePacket {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("FilePartResponsePacket")
-            .field("file_id", &self.file_id)
+            .field("transfer_id", &self.transfer_id)
             .field("response_kind", &self.response_kind)
             .finish()
     }
@@ -53,40 +67,72 @@ impl Debug for FilePartResponsePacket {
 
 impl PartialEq for FilePartResponsePacket {
     fn eq(&self, other: &Self) -> bool {
-        self.file_id == other.file_id && self.response_kind == other.response_kind
+        self.transfer_id == other.transfer_id && self.response_kind == other.response_kind
     }
 }
 
 pub enum FilePartResponsePacketError {
-    CorruptedData
+    CorruptedData,
+    UnknownResponseKind,
 }
 
 impl Debug for FilePartResponsePacketError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.debug_struct("FilePartResponsePacketError")
-            .finish()
+        Display::fmt(self, f)
     }
 }
 
-impl Serialize<Vec<u8>, FilePartResponsePacketError> for FilePartResponsePacket {
-    fn serialize(&self) -> Vec<u8> {
-        let mut data = Vec::with_capacity(BASE_PACKET_SIZE);
-        data.push(self.file_id);
-        data.push(self.response_kind);
-        data
+impl Display for FilePartResponsePacketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fmt::write(
+            f,
+            format_args!(
+                "{}",
+                match self {
+                    FilePartResponsePacketError::CorruptedData => "Corrupted packet.",
+                    FilePartResponsePacketError::UnknownResponseKind => "Unknown response kind.",
+                }
+            ),
+        )
     }
+}
 
-    fn deserialize(data: &Vec<u8>) -> Result<FilePartResponsePacket, FilePartResponsePacketError> {
-        if data.len() != BASE_PACKET_SIZE {
-            return Err(FilePartResponsePacketError::CorruptedData);
-        }
+impl Error for FilePartResponsePacketError {}
 
-        let file_id = data[0];
-        let response_kind = data[1];
+impl From<ReadError> for FilePartResponsePacketError {
+    fn from(_: ReadError) -> Self {
+        FilePartResponsePacketError::CorruptedData
+    }
+}
+
+impl FilePartResponsePacket {
+    pub fn serialize_as(&self, format: TransferIdFormat) -> Vec<u8> {
+        let mut data = Vec::with_capacity(format.size() + BASE_PACKET_SIZE);
+        format.write(&self.transfer_id, &mut data);
+        data.push(self.response_kind.value());
+        data
+    }
+
+    pub fn deserialize_as(data: &[u8], format: TransferIdFormat) -> Result<FilePartResponsePacket, FilePartResponsePacketError> {
+        let mut reader = PacketReader::new(data);
+        let transfer_id = format.read(&mut reader)?;
+        let response_kind = ResponseKind::from(reader.read_u8()?)
+            .ok_or(FilePartResponsePacketError::UnknownResponseKind)?;
+        reader.finish()?;
 
         Ok(FilePartResponsePacket {
-            file_id,
+            transfer_id,
             response_kind,
         })
     }
 }
+
+impl Serialize<Vec<u8>, FilePartResponsePacketError> for FilePartResponsePacket {
+    fn serialize(&self) -> Vec<u8> {
+        self.serialize_as(TransferIdFormat::Wide)
+    }
+
+    fn deserialize(data: &Vec<u8>) -> Result<FilePartResponsePacket, FilePartResponsePacketError> {
+        Self::deserialize_as(data, TransferIdFormat::Wide)
+    }
+}
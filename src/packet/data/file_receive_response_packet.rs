@@ -1,43 +1,58 @@
+use std::error::Error;
 use std::fmt;
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use crate::compatibility::unified_endian::UnifiedEndian;
+use crate::packet::data::byte_ranges::ByteRanges;
+use crate::packet::data::transfer_id::{TransferId, TransferIdFormat};
+use crate::packet::protocol::reader::{PacketReader, ReadError};
 use crate::packet::protocol::serialize::Serialize;
 
 pub struct FileReceiveResponsePacket {
-    file_id: u8,
+    transfer_id: TransferId,
     file_size: u64,
     file_name_length: u32,
     file_name: String,
     accepted: bool,
+    received: ByteRanges,
 }
 
 // Serialized as:
-// 1 byte: file id
+// 16 bytes: transfer id (1 byte in the legacy format)
 // 8 bytes: file size in bytes
 // 4 bytes: file name length (UTF-8)
 // N bytes: file name (UTF-8)
 // 1 byte: accepted
-// 14 + N bytes in total
-const BASE_PACKET_SIZE: usize = 14;
+// 29 + N bytes in total, 14 + N in the legacy format
+// Then, when resuming (never in the legacy format):
+// the byte ranges the receiver already has, see `ByteRanges::write`
+// Older senders ignore them and send the whole file again.
+const BASE_PACKET_SIZE: usize = 13; // without the transfer id
 
 impl FileReceiveResponsePacket {
     pub fn new(
-        file_id: u8,
+        transfer_id: TransferId,
         file_size: u64,
         file_name: String,
         accepted: bool,
     ) -> FileReceiveResponsePacket {
         FileReceiveResponsePacket {
-            file_id,
+            transfer_id,
             file_size,
             file_name_length: file_name.len() as u32,
             file_name,
             accepted,
+            received: ByteRanges::new(),
         }
     }
 
-    pub fn file_id(&self) -> u8 {
-        self.file_id
+    /// Ask the sender to resume, sending only what is not in `received`.
+    pub fn with_received(mut self, received: ByteRanges) -> FileReceiveResponsePacket {
+        self.received = received;
+        self
+    }
+
+    pub fn transfer_id(&self) -> TransferId {
+        self.transfer_id
     }
 
     pub fn file_size(&self) -> u64 {
@@ -51,25 +66,32 @@ impl FileReceiveResponsePacket {
     pub fn accepted(&self) -> bool {
         self.accepted
     }
+
+    /// Byte ranges the receiver already has, empty unless resuming.
+    pub fn received(&self) -> &ByteRanges {
+        &self.received
+    }
 }
 
 impl Debug for FileReceiveResponsePacket {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("FileReceiveResponsePacket")
-            .field("file_id", &self.file_id)
+            .field("transfer_id", &self.transfer_id)
             .field("file_size", &self.file_size)
             .field("file_name", &self.file_name)
             .field("accepted", &self.accepted)
+            .field("received", &self.received)
             .finish()
     }
 }
 
 impl PartialEq for FileReceiveResponsePacket {
     fn eq(&self, other: &Self) -> bool {
-        self.file_id == other.file_id
+        self.transfer_id == other.transfer_id
             && self.file_size == other.file_size
             && self.file_name == other.file_name
             && self.accepted == other.accepted
+            && self.received == other.received
     }
 
     fn ne(&self, other: &Self) -> bool {
@@ -82,48 +104,76 @@ pub enum FileReceiveResponsePacketError {
 }
 
 impl Debug for FileReceiveResponsePacketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for FileReceiveResponsePacketError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         fmt::write(
             f,
             format_args!(
-                "DiscoveryPacketError: {}",
+                "{}",
                 match self {
-                    FileReceiveResponsePacketError::CorruptedData => "Corrupted packet",
+                    FileReceiveResponsePacketError::CorruptedData => "Corrupted packet.",
                 }
             ),
         )
     }
 }
 
-impl Serialize<Vec<u8>, FileReceiveResponsePacketError> for FileReceiveResponsePacket {
-    fn serialize(&self) -> Vec<u8> {
-        let mut data = Vec::with_capacity(BASE_PACKET_SIZE + self.file_name_length as usize);
-        data.push(self.file_id);
+impl Error for FileReceiveResponsePacketError {}
+
+impl From<ReadError> for FileReceiveResponsePacketError {
+    fn from(_: ReadError) -> Self {
+        FileReceiveResponsePacketError::CorruptedData
+    }
+}
+
+impl FileReceiveResponsePacket {
+    pub fn serialize_as(&self, format: TransferIdFormat) -> Vec<u8> {
+        let mut data = Vec::with_capacity(format.size() + BASE_PACKET_SIZE + self.file_name_length as usize);
+        format.write(&self.transfer_id, &mut data);
         data.extend_from_slice(&self.file_size.to_bytes());
         data.extend_from_slice(&self.file_name_length.to_bytes());
         data.extend_from_slice(self.file_name.as_bytes());
         data.push(self.accepted as u8);
+        if format == TransferIdFormat::Wide && !self.received.is_empty() {
+            self.received.write(&mut data);
+        }
         data
     }
 
-    fn deserialize(data: &Vec<u8>) -> Result<Self, FileReceiveResponsePacketError> where Self: Sized {
-        if data.len() < BASE_PACKET_SIZE {
-            return Err(FileReceiveResponsePacketError::CorruptedData);
-        }
-        let file_id = data[0];
-        let file_size = u64::from_bytes([data[1], data[2], data[3], data[4], data[5], data[6], data[7], data[8]]);
-        let file_name_length = u32::from_bytes([data[9], data[10], data[11], data[12]]);
-        if data.len() < BASE_PACKET_SIZE + file_name_length as usize {
-            return Err(FileReceiveResponsePacketError::CorruptedData);
-        }
-        let file_name = String::from_utf8_lossy(&data[13..13 + file_name_length as usize]).to_string();
-        let accepted = data[13 + file_name_length as usize] != 0;
+    pub fn deserialize_as(data: &[u8], format: TransferIdFormat) -> Result<Self, FileReceiveResponsePacketError> {
+        let mut reader = PacketReader::new(data);
+        let transfer_id = format.read(&mut reader)?;
+        let file_size: u64 = reader.read()?;
+        let file_name_length: u32 = reader.read()?;
+        let file_name = String::from_utf8_lossy(reader.read_bytes(file_name_length)?).to_string();
+        let accepted = reader.read_u8()? != 0;
+        let received = match format == TransferIdFormat::Wide && reader.remaining() > 0 {
+            true => ByteRanges::read(&mut reader)?,
+            false => ByteRanges::new(),
+        };
+        reader.finish()?;
         Ok(FileReceiveResponsePacket {
-            file_id,
+            transfer_id,
             file_size,
             file_name_length,
             file_name,
             accepted,
+            received,
         })
     }
 }
+
+impl Serialize<Vec<u8>, FileReceiveResponsePacketError> for FileReceiveResponsePacket {
+    fn serialize(&self) -> Vec<u8> {
+        self.serialize_as(TransferIdFormat::Wide)
+    }
+
+    fn deserialize(data: &Vec<u8>) -> Result<Self, FileReceiveResponsePacketError> where Self: Sized {
+        Self::deserialize_as(data, TransferIdFormat::Wide)
+    }
+}
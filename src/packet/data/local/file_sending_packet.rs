@@ -1,5 +1,7 @@
+use std::error::Error;
 use std::fmt;
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
+use crate::packet::data::transfer_id::TransferId;
 
 pub enum FileSendingStatus {
     Requested,
@@ -33,10 +35,26 @@ pub enum FileSendingStatusError {
 
 impl Debug for FileSendingStatusError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "Invalid status.")
+        Display::fmt(self, f)
     }
 }
 
+impl Display for FileSendingStatusError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fmt::write(
+            f,
+            format_args!(
+                "{}",
+                match self {
+                    FileSendingStatusError::InvalidStatus => "Invalid status.",
+                }
+            ),
+        )
+    }
+}
+
+impl Error for FileSendingStatusError {}
+
 impl FileSendingStatus {
     pub fn to_u8(&self) -> u8 {
         match self {
@@ -67,7 +85,7 @@ impl FileSendingStatus {
 }
 
 pub struct FileSendingPacket {
-    file_id: u8,
+    transfer_id: TransferId,
     progress: u64,
     total: u64,
     status: FileSendingStatus,
@@ -75,21 +93,21 @@ pub struct FileSendingPacket {
 
 impl FileSendingPacket {
     pub fn new(
-        file_id: u8,
+        transfer_id: TransferId,
         progress: u64,
         total: u64,
         status: FileSendingStatus,
     ) -> FileSendingPacket {
         FileSendingPacket {
-            file_id,
+            transfer_id,
             progress,
             total,
             status,
         }
     }
 
-    pub fn file_id(&self) -> u8 {
-        self.file_id
+    pub fn transfer_id(&self) -> TransferId {
+        self.transfer_id
     }
 
     pub fn progress(&self) -> u64 {
@@ -1,3 +1,4 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum MagicNumbers {
     FileComing, Text, FileReceiveResponse, FilePart, FilePartResponse,
 }
@@ -5,3 +5,5 @@ pub mod file_part_packet;
 pub mod file_receive_response_packet;
 pub mod file_part_response_packet;
 pub mod local;
+pub mod transfer_id;
+pub mod byte_ranges;
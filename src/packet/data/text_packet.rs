@@ -3,6 +3,7 @@ use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
 use crate::compatibility::unified_endian::UnifiedEndian;
 use crate::packet::protocol::hash::Hash;
+use crate::packet::protocol::reader::{PacketReader, ReadError};
 use crate::packet::protocol::serialize::Serialize;
 
 const STRING_LENGTH_MAX: usize = 0xffff;
@@ -49,12 +50,18 @@ impl Display for TextPacketError {
 
 impl Error for TextPacketError {}
 
+impl From<ReadError> for TextPacketError {
+    fn from(_: ReadError) -> Self {
+        TextPacketError::InvalidData
+    }
+}
+
 type HashType = u16;
 
 fn text_hash(text: &String) -> HashType {
     let mut ret: HashType = HashType::MAX ^ 0x12 ^ 0x13 ^ 0x8;
     for (i, c) in text.chars().enumerate() {
-        ret = ret.wrapping_add((i * c as usize) as HashType);
+        ret = ret.wrapping_add(i.wrapping_mul(c as usize) as HashType);
     }
     ret
 }
@@ -76,19 +83,15 @@ impl Serialize<Vec<u8>, TextPacketError> for TextPacket {
     }
 
     fn deserialize(data: &Vec<u8>) -> Result<Self, TextPacketError> {
-        let data_len = data.len();
-
-        if data_len < BASE_PACKET_SIZE {
-            return Err(TextPacketError::InvalidData);
-        }
-
-        let text_len = u32::from_bytes([data[0], data[1], data[2], data[3]]) as usize;
-        let text = String::from_utf8(data[4..4 + text_len].to_vec())
+        let mut reader = PacketReader::new(data);
+        let text_len: u32 = reader.read()?;
+        let text = String::from_utf8(reader.read_bytes(text_len)?.to_vec())
             .map_err(|_| TextPacketError::InvalidData)?;
-        let hash = u16::from_bytes([data[4 + text_len], data[4 + text_len + 1]]);
+        let hash: u16 = reader.read()?;
+        reader.finish()?;
 
         if text_hash(&text) == hash {
-            match TextPacket::new(text.clone()) {
+            match TextPacket::new(text) {
                 Ok(x) => Ok(x),
                 Err(_) => Err(TextPacketError::InvalidData),
             }
@@ -0,0 +1,109 @@
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use rand::RngCore;
+use crate::packet::protocol::reader::{PacketReader, ReadError};
+
+pub const TRANSFER_ID_SIZE: usize = 16;
+/// First protocol version with the full transfer id.
+pub const WIDE_TRANSFER_ID_VERSION: u32 = 8;
+
+/// Identifies one file transfer, picked at random by the sender.
+/// Shown as 32 lowercase hex digits.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TransferId([u8; TRANSFER_ID_SIZE]);
+
+impl TransferId {
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; TRANSFER_ID_SIZE];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(bytes)
+    }
+
+    pub fn from_bytes(bytes: [u8; TRANSFER_ID_SIZE]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn bytes(&self) -> &[u8; TRANSFER_ID_SIZE] {
+        &self.0
+    }
+
+    /// The one byte id of older peers, kept in the last byte.
+    pub fn from_legacy(file_id: u8) -> Self {
+        let mut bytes = [0u8; TRANSFER_ID_SIZE];
+        bytes[TRANSFER_ID_SIZE - 1] = file_id;
+        Self(bytes)
+    }
+
+    pub fn legacy_id(&self) -> u8 {
+        self.0[TRANSFER_ID_SIZE - 1]
+    }
+
+    /// Parse the hex form, as given by `to_string`.
+    pub fn parse(hex: &str) -> Option<Self> {
+        if hex.len() != TRANSFER_ID_SIZE * 2 || !hex.is_ascii() {
+            return None;
+        }
+        let mut bytes = [0u8; TRANSFER_ID_SIZE];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+        }
+        Some(Self(bytes))
+    }
+}
+
+impl Display for TransferId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for TransferId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+/// How file packets carry the transfer id on a connection.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransferIdFormat {
+    /// One byte picked by the receiver, spoken by peers older than `WIDE_TRANSFER_ID_VERSION`.
+    /// `FileComingPacket` carries no id at all.
+    Legacy,
+    /// The full id.
+    Wide,
+}
+
+impl TransferIdFormat {
+    /// Format of a connection, given the protocol version negotiated by its hello.
+    pub fn of(negotiated_version: Option<u32>) -> Self {
+        match negotiated_version {
+            Some(version) if version >= WIDE_TRANSFER_ID_VERSION => TransferIdFormat::Wide,
+            _ => TransferIdFormat::Legacy,
+        }
+    }
+
+    pub fn write(&self, transfer_id: &TransferId, bytes: &mut Vec<u8>) {
+        match self {
+            TransferIdFormat::Legacy => bytes.push(transfer_id.legacy_id()),
+            TransferIdFormat::Wide => bytes.extend_from_slice(transfer_id.bytes()),
+        }
+    }
+
+    pub fn read(&self, reader: &mut PacketReader) -> Result<TransferId, ReadError> {
+        match self {
+            TransferIdFormat::Legacy => Ok(TransferId::from_legacy(reader.read_u8()?)),
+            TransferIdFormat::Wide => Ok(TransferId::from_bytes(reader.read_array()?)),
+        }
+    }
+
+    /// Size of the id on the wire.
+    pub fn size(&self) -> usize {
+        match self {
+            TransferIdFormat::Legacy => 1,
+            TransferIdFormat::Wide => TRANSFER_ID_SIZE,
+        }
+    }
+}
@@ -1,20 +1,37 @@
+use std::error::Error;
 use std::fmt;
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use crate::compatibility::unified_endian::UnifiedEndian;
+use crate::packet::protocol::reader::{PacketReader, ReadError};
 use crate::packet::protocol::serialize::Serialize;
 
 /**
-* Serialized as:
+* Serialized as (version 2):
+   * 2 bytes: magic number
+   * 1 byte: packet version
+   * 4 bytes: data length in bytes
+   * N bytes: data
+   * 4 bytes: CRC32 of (magic_number, version, data_length, data)
+   * 11 + N bytes in total
+ */
+const BASE_PACKET_SIZE: usize = 11;
+
+/**
+* Legacy serialization (version 1), still accepted from older peers:
    * 2 bytes: magic number
    * 4 bytes: data length in bytes
    * N bytes: data
    * 2 bytes: hash of (data_length)
    * 8 + N bytes in total
  */
-const BASE_PACKET_SIZE: usize = 8;
+const LEGACY_BASE_PACKET_SIZE: usize = 8;
+
+pub const DATA_PACKET_VERSION: u8 = 2;
+pub const LEGACY_DATA_PACKET_VERSION: u8 = 1;
 
 pub struct DataPacket {
     magic_number: u16,
+    version: u8,
     data: Vec<u8>,
 }
 
@@ -22,24 +39,40 @@ pub enum DataPacketError {
     InvalidMagicNumber,
     InvalidHash,
     CorruptedData,
+    ChecksumMismatch,
 }
 
 impl Debug for DataPacketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for DataPacketError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         fmt::write(
             f,
             format_args!(
-                "DataPacketError::{}",
+                "{}",
                 match self {
-                    DataPacketError::InvalidMagicNumber => "Invalid magic number",
-                    DataPacketError::InvalidHash => "Invalid hash",
-                    DataPacketError::CorruptedData => "Corrupted data",
-                },
+                    DataPacketError::InvalidMagicNumber => "Invalid magic number.",
+                    DataPacketError::InvalidHash => "Invalid hash.",
+                    DataPacketError::CorruptedData => "Corrupted data.",
+                    DataPacketError::ChecksumMismatch => "Checksum mismatch.",
+                }
             ),
         )
     }
 }
 
+impl Error for DataPacketError {}
+
+impl From<ReadError> for DataPacketError {
+    fn from(_: ReadError) -> Self {
+        DataPacketError::CorruptedData
+    }
+}
+
 impl DataPacket {
     pub fn new(
         magic_number: u16,
@@ -47,6 +80,7 @@ impl DataPacket {
     ) -> DataPacket {
         DataPacket {
             magic_number,
+            version: DATA_PACKET_VERSION,
             data: data.clone(),
         }
     }
@@ -55,13 +89,67 @@ impl DataPacket {
         self.magic_number
     }
 
+    /// Wire format version this packet was received with.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
     pub fn data(&self) -> &Vec<u8> {
         &self.data
     }
 }
 
-fn packet_hash(packet: &DataPacket) -> u16 {
-    (packet.data().len() / 2) as u16
+fn legacy_packet_hash(data_len: usize) -> u16 {
+    (data_len / 2) as u16
+}
+
+fn packet_checksum(header: &[u8], data: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(header);
+    hasher.update(data);
+    hasher.finalize()
+}
+
+fn deserialize_current(data: &[u8]) -> Result<DataPacket, DataPacketError> {
+    let mut reader = PacketReader::new(data);
+    let magic_number: u16 = reader.read()?;
+    if reader.read_u8()? != DATA_PACKET_VERSION {
+        return Err(DataPacketError::CorruptedData);
+    }
+    let actual_data_len: u32 = reader.read()?;
+    let header = &data[..reader.position()];
+    let wrapping_data = reader.read_bytes(actual_data_len)?;
+    let checksum: u32 = reader.read()?;
+    reader.finish()?;
+
+    if checksum != packet_checksum(header, wrapping_data) {
+        return Err(DataPacketError::ChecksumMismatch);
+    }
+
+    Ok(DataPacket {
+        magic_number,
+        version: DATA_PACKET_VERSION,
+        data: wrapping_data.to_vec(),
+    })
+}
+
+fn deserialize_legacy(data: &[u8]) -> Result<DataPacket, DataPacketError> {
+    let mut reader = PacketReader::new(data);
+    let magic_number: u16 = reader.read()?;
+    let actual_data_len: u32 = reader.read()?;
+    let wrapping_data = reader.read_bytes(actual_data_len)?.to_vec();
+    let hash: u16 = reader.read()?;
+    reader.finish()?;
+
+    if hash != legacy_packet_hash(wrapping_data.len()) {
+        return Err(DataPacketError::InvalidHash);
+    }
+
+    Ok(DataPacket {
+        magic_number,
+        version: LEGACY_DATA_PACKET_VERSION,
+        data: wrapping_data,
+    })
 }
 
 impl Serialize<Vec<u8>, DataPacketError> for DataPacket {
@@ -69,36 +157,24 @@ impl Serialize<Vec<u8>, DataPacketError> for DataPacket {
         let data_len = self.data.len();
         let mut bytes = Vec::with_capacity(BASE_PACKET_SIZE + data_len);
         bytes.extend_from_slice(&self.magic_number.to_bytes());
+        bytes.push(DATA_PACKET_VERSION);
         bytes.extend_from_slice(&(data_len as u32).to_bytes());
         bytes.extend_from_slice(&self.data);
-        bytes.extend_from_slice(&packet_hash(self).to_bytes());
+        let checksum = packet_checksum(&bytes[..7], &self.data);
+        bytes.extend_from_slice(&checksum.to_bytes());
         bytes
     }
 
     fn deserialize(data: &Vec<u8>) -> Result<Self, DataPacketError> where Self: Sized {
-        let data_len = data.len();
-        if data_len < BASE_PACKET_SIZE {
+        if data.len() < LEGACY_BASE_PACKET_SIZE {
             return Err(DataPacketError::InvalidMagicNumber);
         }
 
-        let magic_number = u16::from_bytes([data[0], data[1]]);
-        let actual_data_len = u32::from_bytes([data[2], data[3], data[4], data[5]]) as usize;
-
-        if data_len != BASE_PACKET_SIZE + actual_data_len {
-            return Err(DataPacketError::CorruptedData);
+        // Version 2 carries its version byte right after the magic number.
+        // Otherwise it may be a packet from an older peer.
+        match deserialize_current(data) {
+            Err(DataPacketError::CorruptedData) => deserialize_legacy(data),
+            result => result,
         }
-
-        let wrapping_data = data[6..6 + actual_data_len].to_vec();
-        let hash = u16::from_bytes([data[6 + actual_data_len], data[6 + actual_data_len + 1]]);
-
-        let ret = DataPacket::new(
-            magic_number,
-            &wrapping_data,
-        );
-        if hash != packet_hash(&ret) {
-            return Err(DataPacketError::InvalidHash);
-        }
-
-        Ok(ret)
     }
 }
@@ -3,30 +3,289 @@ use std::io::{Read, Write};
 use std::mem::size_of;
 use std::net::TcpStream;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use log::warn;
 use crate::compatibility::unified_endian::UnifiedEndian;
+use crate::packet::data::transfer_id::TransferIdFormat;
+use crate::packet::frame_limits::{check_size, FrameLimits};
+use crate::packet::hello_packet::{HandshakeError, HelloPacket, is_hello_packet, Negotiated};
+use crate::packet::protocol::serialize::Serialize;
+use crate::security::group_key::GroupKey;
+use crate::security::identity::{deserialize_identity_hello, DeviceIdentity, identity_transcript, IdentityKey, random_nonce, serialize_identity_hello};
+use crate::security::secure_channel::{deserialize_hello, HandshakeRole, random_hello, SEAL_OVERHEAD, SecureChannel, serialize_hello};
 
 const PACKET_TRY_TIMES: u64 = 3;
 const TCP_ACCEPT_TRY_WAIT_MILLISECONDS: u64 = 100;
 
 pub struct DataTransmit {
     stream: TcpStream,
+    channel: Option<SecureChannel>,
+    peer_identity: Option<IdentityKey>,
+    frame_limits: FrameLimits,
+    negotiated: Option<Negotiated>,
+    // First frame of a peer without hello, read again by the next read.
+    pending_frame: Option<Vec<u8>>,
 }
 
 impl DataTransmit {
     pub fn from(stream: TcpStream) -> Self {
-        Self { stream }
+        Self {
+            stream,
+            channel: None,
+            peer_identity: None,
+            frame_limits: FrameLimits::default(),
+            negotiated: None,
+            pending_frame: None,
+        }
     }
     pub fn close(&mut self) -> Result<(), io::Error> {
         self.stream.shutdown(std::net::Shutdown::Both)
     }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.channel.is_some()
+    }
+
+    /// Whether the peer sent something, or closed the connection, without blocking.
+    pub fn has_pending_data(&self) -> Result<bool, io::Error> {
+        let mut buf = [0u8; 1];
+        self.stream.set_nonblocking(true)?;
+        let result = self.stream.peek(&mut buf);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Wait up to `timeout` for the peer to send something, or close the connection.
+    /// Returns whether it did.
+    pub fn wait_for_data(&mut self, timeout: Duration) -> Result<bool, io::Error> {
+        if self.pending_frame.is_some() {
+            return Ok(true);
+        }
+        let mut buf = [0u8; 1];
+        self.stream.set_read_timeout(Some(timeout))?;
+        let result = self.stream.peek(&mut buf);
+        self.stream.set_read_timeout(None)?;
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Discard whatever the peer still sends until it closes or `timeout` passes,
+    /// so that closing does not reset the connection before it read our last frame.
+    pub fn drain(&mut self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0u8; 64 * 1024];
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            if remaining.is_zero() || self.stream.set_read_timeout(Some(remaining)).is_err() {
+                break;
+            }
+            match self.stream.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => (),
+            }
+        }
+    }
+
+    /// Largest frames accepted from the peer, the defaults unless set.
+    pub fn set_frame_limits(&mut self, frame_limits: FrameLimits) {
+        self.frame_limits = frame_limits;
+    }
+
+    pub fn frame_limits(&self) -> &FrameLimits {
+        &self.frame_limits
+    }
+
+    /// Public key the peer proved to own, if it identified itself.
+    pub fn peer_identity(&self) -> Option<&IdentityKey> {
+        self.peer_identity.as_ref()
+    }
+
+    /// Version and features settled in the hello, `None` for peers older than the hello.
+    pub fn negotiated(&self) -> Option<&Negotiated> {
+        self.negotiated.as_ref()
+    }
+
+    /// How transfer ids are written on this connection.
+    pub fn transfer_id_format(&self) -> TransferIdFormat {
+        TransferIdFormat::of(self.negotiated.map(|n| n.version))
+    }
+
+    /// Exchange hellos as the connecting side, before anything else is sent.
+    pub fn negotiate_as_initiator(&mut self, hello: &HelloPacket) -> Result<Negotiated, io::Error> {
+        self.send_frame_progress_with_retry(&hello.serialize(), |_| ())?;
+
+        // Older peers cannot parse the hello and hang up.
+        let reply = match self.read_frame_progress_with_retry(false, |_| ()) {
+            Ok(r) => r,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(HandshakeError::NoHello.into()),
+            Err(e) => return Err(e),
+        };
+        let peer_hello = HelloPacket::deserialize(&reply).map_err(|_| HandshakeError::NoHello)?;
+
+        let negotiated = hello.negotiate(&peer_hello)?;
+        self.negotiated = Some(negotiated);
+        Ok(negotiated)
+    }
+
+    /// Exchange hellos as the listening side. Our hello is sent even when refusing the peer,
+    /// so that it can tell why. Peers older than the hello start with another frame,
+    /// which is left for the next read, and get `None`.
+    pub fn negotiate_as_responder(&mut self, hello: &HelloPacket) -> Result<Option<Negotiated>, io::Error> {
+        let frame = self.read_frame_progress_with_retry(false, |_| ())?;
+        if !is_hello_packet(&frame) {
+            self.pending_frame = Some(frame);
+            return Ok(None);
+        }
+        let peer_hello = HelloPacket::deserialize(&frame)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.send_frame_progress_with_retry(&hello.serialize(), |_| ())?;
+
+        let negotiated = hello.negotiate(&peer_hello)?;
+        self.negotiated = Some(negotiated);
+        Ok(Some(negotiated))
+    }
+
+    /// Start an encrypted session as the connecting side.
+    pub fn handshake_as_initiator(&mut self, group_key: &GroupKey) -> Result<(), io::Error> {
+        let initiator_random = random_hello();
+        self.send_frame_progress_with_retry(&serialize_hello(&initiator_random), |_| ())?;
+
+        let reply = self.read_frame_progress_with_retry(false, |_| ())?;
+        let responder_random = match deserialize_hello(&reply) {
+            Some(r) => r,
+            None => return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "Peer did not answer the encryption handshake.")),
+        };
+
+        self.channel = Some(SecureChannel::new(
+            group_key, HandshakeRole::Initiator, &initiator_random, &responder_random));
+        Ok(())
+    }
+
+    /// Accept an encrypted session as the listening side.
+    /// Peers that start talking in plaintext are refused.
+    pub fn handshake_as_responder(&mut self, group_key: &GroupKey) -> Result<(), io::Error> {
+        let hello = self.read_frame_progress_with_retry(false, |_| ())?;
+        let initiator_random = match deserialize_hello(&hello) {
+            Some(r) => r,
+            None => return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied, "Peer did not start an encrypted session.")),
+        };
+
+        let responder_random = random_hello();
+        self.send_frame_progress_with_retry(&serialize_hello(&responder_random), |_| ())?;
+
+        self.channel = Some(SecureChannel::new(
+            group_key, HandshakeRole::Responder, &initiator_random, &responder_random));
+        Ok(())
+    }
+
+    /// What identity proofs are bound to, empty on a plain connection.
+    fn channel_binding(&self) -> Vec<u8> {
+        self.channel.as_ref().map(|c| c.binding().to_vec()).unwrap_or_default()
+    }
+
+    /// Prove our identity to the listening side and have it prove its own.
+    /// Runs after the encryption handshake, if any.
+    pub fn identify_as_initiator(&mut self, identity: &DeviceIdentity) -> Result<IdentityKey, io::Error> {
+        let binding = self.channel_binding();
+        let own_key = identity.public_key();
+        let own_nonce = random_nonce();
+        self.send_data_progress_with_retry(&serialize_identity_hello(&own_key, &own_nonce), |_| ())?;
+
+        let reply = self.read_data_progress_with_retry(|_| ())?;
+        let (peer_key, peer_nonce, signature) = match deserialize_identity_hello(&reply) {
+            Some(r) => r,
+            None => return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "Peer did not answer the identity exchange.")),
+        };
+        let transcript = identity_transcript(false, &own_nonce, &peer_nonce, &own_key, &binding);
+        if !DeviceIdentity::verify(&peer_key, &transcript, signature) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied, "Peer failed to prove its identity."));
+        }
+
+        if let Some(negotiated) = &self.negotiated {
+            negotiated.check_identity(&peer_key)?;
+        }
+
+        let transcript = identity_transcript(true, &peer_nonce, &own_nonce, &peer_key, &binding);
+        self.send_data_progress_with_retry(&identity.sign(&transcript).to_vec(), |_| ())?;
+
+        self.peer_identity = Some(peer_key);
+        Ok(peer_key)
+    }
+
+    /// Answer an identity hello already read from the connection.
+    pub fn identify_as_responder(&mut self, identity: &DeviceIdentity, hello: &[u8]) -> Result<IdentityKey, io::Error> {
+        let (peer_key, peer_nonce, _) = match deserialize_identity_hello(hello) {
+            Some(r) => r,
+            None => return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "Invalid identity hello.")),
+        };
+
+        let binding = self.channel_binding();
+        let own_key = identity.public_key();
+        let own_nonce = random_nonce();
+        let transcript = identity_transcript(false, &peer_nonce, &own_nonce, &peer_key, &binding);
+        let mut reply = serialize_identity_hello(&own_key, &own_nonce);
+        reply.extend_from_slice(&identity.sign(&transcript));
+        self.send_data_progress_with_retry(&reply, |_| ())?;
+
+        let signature = self.read_data_progress_with_retry(|_| ())?;
+        let transcript = identity_transcript(true, &own_nonce, &peer_nonce, &own_key, &binding);
+        if !DeviceIdentity::verify(&peer_key, &transcript, &signature) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied, "Peer failed to prove its identity."));
+        }
+        if let Some(negotiated) = &self.negotiated {
+            negotiated.check_identity(&peer_key)?;
+        }
+
+        self.peer_identity = Some(peer_key);
+        Ok(peer_key)
+    }
 }
 
 const SIZE_SIZE: usize = size_of::<u32>();
+// Enough to tell the kind of a plaintext frame.
+const FRAME_HEAD_SIZE: usize = size_of::<u16>();
 
 impl DataTransmit {
-    pub fn send_data_progress_with_retry<F>(&mut self, data: &Vec<u8>, mut on_progress: F) -> Result<(), io::Error> where F: FnMut(u64) {
+    /// Send data as one frame, encrypted if the session is.
+    pub fn send_data_progress_with_retry<F>(&mut self, data: &Vec<u8>, on_progress: F) -> Result<(), io::Error> where F: FnMut(u64) {
+        match self.channel.as_mut() {
+            Some(channel) => {
+                let sealed = channel.seal(data)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                self.send_frame_progress_with_retry(&sealed, on_progress)
+            }
+            None => self.send_frame_progress_with_retry(data, on_progress),
+        }
+    }
+
+    /// Read one frame, decrypting it if the session is encrypted.
+    pub fn read_data_progress_with_retry<F>(&mut self, on_progress: F) -> Result<Vec<u8>, io::Error> where F: Fn(f32) {
+        let sealed = self.channel.is_some();
+        let frame = self.read_frame_progress_with_retry(sealed, on_progress)?;
+        match self.channel.as_mut() {
+            Some(channel) => {
+                let data = channel.open(&frame)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                self.frame_limits.check(&data, data.len())?;
+                Ok(data)
+            }
+            None => Ok(frame),
+        }
+    }
+
+    fn send_frame_progress_with_retry<F>(&mut self, data: &[u8], mut on_progress: F) -> Result<(), io::Error> where F: FnMut(u64) {
         // Strings are already utf8 encoded.
         let data_len = data.len() as u32;
         let mut buf = vec![0u8; SIZE_SIZE + data_len as usize];
@@ -61,48 +320,81 @@ impl DataTransmit {
         Err(error)
     }
 
-    /// Read size N and read N bytes of data with retry and progress reporting.
-    pub fn read_data_progress_with_retry<F>(&mut self, on_progress: F) -> Result<Vec<u8>, io::Error> where F: Fn(f32) {
+    /// Read exactly `buf.len()` bytes with retry.
+    fn read_exact_with_retry(&mut self, buf: &mut [u8]) -> Result<(), io::Error> {
         let mut remaining_tries = PACKET_TRY_TIMES;
         let mut last_error: io::Error = io::Error::new(io::ErrorKind::Other, "Failed to read data.");
-        let mut size_buf: [u8; SIZE_SIZE] = [0u8; SIZE_SIZE];
-        let mut packet_size = 0;
-        let mut bytes_read_total = 0;
 
-        // Read size.
         while remaining_tries > 0 {
-            packet_size = match self.stream.read_exact(&mut size_buf) {
-                Ok(_) => u32::from_bytes(size_buf),
+            match self.stream.read_exact(buf) {
+                Ok(_) => return Ok(()),
                 Err(e) => {
                     if e.kind() != io::ErrorKind::WouldBlock {
                         last_error = e;
                         remaining_tries -= 1;
                     }
                     sleep(Duration::from_millis(TCP_ACCEPT_TRY_WAIT_MILLISECONDS));
-                    continue;
                 }
-            };
-            break;
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Read size N and read N bytes of data with retry and progress reporting.
+    /// N is checked against the frame limits before anything is allocated for it.
+    /// Plaintext frames are held to the limit of their kind, known from the first two bytes,
+    /// sealed ones only to the largest limit until they are opened.
+    fn read_frame_progress_with_retry<F>(&mut self, sealed: bool, on_progress: F) -> Result<Vec<u8>, io::Error> where F: Fn(f32) {
+        if let Some(frame) = self.pending_frame.take() {
+            return Ok(frame);
         }
 
-        // Failed to read size?
+        let mut size_buf: [u8; SIZE_SIZE] = [0u8; SIZE_SIZE];
+
+        // Read size.
+        self.read_exact_with_retry(&mut size_buf)?;
+        let packet_size = u32::from_bytes(size_buf) as usize;
         if packet_size == 0 {
-            return Err(last_error);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Empty frame."));
+        }
+
+        // Check size.
+        let mut head = [0u8; FRAME_HEAD_SIZE];
+        let head_len = FRAME_HEAD_SIZE.min(packet_size);
+        if sealed {
+            check_size(packet_size, self.frame_limits.max_frame_size() + SEAL_OVERHEAD)?;
+        } else {
+            check_size(packet_size, self.frame_limits.max_frame_size())?;
+            self.read_exact_with_retry(&mut head[..head_len])?;
+            self.frame_limits.check(&head[..head_len], packet_size)?;
         }
 
         // Allocate buffer.
-        remaining_tries = PACKET_TRY_TIMES;
-        let mut data_buf = vec![0u8; packet_size as usize];
+        let mut remaining_tries = PACKET_TRY_TIMES;
+        let mut data_buf = vec![0u8; packet_size];
+        let mut bytes_read_total = 0;
+        if !sealed {
+            data_buf[..head_len].copy_from_slice(&head[..head_len]);
+            bytes_read_total = head_len;
+        }
 
         // Read data.
-        while remaining_tries > 0 {
+        loop {
+            if bytes_read_total >= packet_size {
+                return Ok(data_buf);
+            }
+
             let bytes_read = match self.stream.read(&mut data_buf[bytes_read_total..]) {
+                Ok(0) => return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof, "Connection closed in the middle of a frame.")),
                 Ok(n) => n,
                 Err(e) => {
                     if e.kind() != io::ErrorKind::WouldBlock {
-                        last_error = e;
                         remaining_tries -= 1;
-                        warn!("Failed to read payload data ({}), remaining tries: {}.", last_error, remaining_tries);
+                        warn!("Failed to read payload data ({}), remaining tries: {}.", e, remaining_tries);
+                        if remaining_tries == 0 {
+                            return Err(e);
+                        }
                     }
                     sleep(Duration::from_millis(TCP_ACCEPT_TRY_WAIT_MILLISECONDS));
                     continue;
@@ -113,13 +405,6 @@ impl DataTransmit {
 
             // Report progress.
             on_progress(bytes_read_total as f32 / packet_size as f32);
-
-            // Read everything?
-            if bytes_read_total >= packet_size as usize {
-                return Ok(data_buf);
-            }
         }
-
-        Err(last_error)
     }
 }
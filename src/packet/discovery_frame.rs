@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use crate::compatibility::unified_endian::UnifiedEndian;
+
+/**
+* Discovery datagram, self-contained so that datagrams of different senders cannot mix:
+   * 4 bytes: magic "AXDG"
+   * 1 byte: framing version
+   * the sealed protobuf `DiscoveryPacket`
+* Older peers send a datagram with the size as u32, then one with the protobuf packet.
+ */
+const DISCOVERY_FRAME_MAGIC: [u8; 4] = *b"AXDG";
+const HEADER_SIZE: usize = 5;
+pub const DISCOVERY_FRAME_VERSION: u8 = 1;
+
+/// Largest UDP payload, enough for any datagram we may receive.
+pub const MAX_DISCOVERY_DATAGRAM_SIZE: usize = 65507;
+
+/// How long the size datagram of a legacy sender waits for its payload.
+const LEGACY_PAIRING_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_LEGACY_PENDING: usize = 256;
+
+pub fn frame_discovery_packet(body: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + body.len());
+    bytes.extend_from_slice(&DISCOVERY_FRAME_MAGIC);
+    bytes.push(DISCOVERY_FRAME_VERSION);
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum DiscoveryDatagram<'a> {
+    /// Body of a framed packet.
+    Packet(&'a [u8]),
+    /// Framed, but by a newer framing version.
+    UnsupportedVersion(u8),
+    /// Size datagram of the legacy form.
+    LegacySize(usize),
+    /// Anything else, possibly the payload of the legacy form.
+    Unframed(&'a [u8]),
+}
+
+pub fn parse_discovery_datagram(datagram: &[u8]) -> DiscoveryDatagram<'_> {
+    if datagram.len() >= HEADER_SIZE && datagram[0..4] == DISCOVERY_FRAME_MAGIC {
+        return match datagram[4] {
+            DISCOVERY_FRAME_VERSION => DiscoveryDatagram::Packet(&datagram[HEADER_SIZE..]),
+            version => DiscoveryDatagram::UnsupportedVersion(version),
+        };
+    }
+    if datagram.len() == 4 {
+        let size = u32::from_bytes([datagram[0], datagram[1], datagram[2], datagram[3]]);
+        return DiscoveryDatagram::LegacySize(size as usize);
+    }
+    DiscoveryDatagram::Unframed(datagram)
+}
+
+/// Pairs the two datagrams of legacy senders by source address.
+pub struct LegacyDiscoveryPairing {
+    pending: HashMap<SocketAddr, (usize, Instant)>,
+}
+
+impl Default for LegacyDiscoveryPairing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LegacyDiscoveryPairing {
+    pub fn new() -> Self {
+        Self { pending: HashMap::new() }
+    }
+
+    /// Remember that `source` announced a payload of `size` bytes.
+    pub fn expect(&mut self, source: SocketAddr, size: usize) {
+        let now = Instant::now();
+        self.pending.retain(|_, (_, since)| now.duration_since(*since) < LEGACY_PAIRING_TIMEOUT);
+        if self.pending.len() < MAX_LEGACY_PENDING || self.pending.contains_key(&source) {
+            self.pending.insert(source, (size, now));
+        }
+    }
+
+    /// Whether `payload` is what `source` announced last.
+    pub fn take(&mut self, source: &SocketAddr, payload: &[u8]) -> bool {
+        match self.pending.remove(source) {
+            Some((size, since)) => size == payload.len() && since.elapsed() < LEGACY_PAIRING_TIMEOUT,
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::io;
+use crate::compatibility::unified_endian::UnifiedEndian;
+use crate::packet::data::byte_ranges::MAX_BYTE_RANGES_SIZE;
+use crate::packet::data::magic_numbers::MagicNumbers;
+
+/// File content carried by one file part.
+pub const FILE_PART_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Room for the data packet and file part headers around a chunk.
+const FILE_PART_HEADER_ALLOWANCE: usize = 1024;
+
+pub const DEFAULT_CONTROL_FRAME_LIMIT: usize = 4 * 1024;
+pub const DEFAULT_TEXT_FRAME_LIMIT: usize = 4 * 1024 * 1024;
+pub const DEFAULT_FILE_COMING_FRAME_LIMIT: usize = 64 * 1024;
+pub const DEFAULT_RESPONSE_FRAME_LIMIT: usize = 4 * 1024;
+/// A file receive response may carry the ranges the receiver already has.
+pub const DEFAULT_FILE_RECEIVE_RESPONSE_FRAME_LIMIT: usize = DEFAULT_RESPONSE_FRAME_LIMIT + MAX_BYTE_RANGES_SIZE;
+pub const DEFAULT_FILE_PART_FRAME_LIMIT: usize = FILE_PART_CHUNK_SIZE + FILE_PART_HEADER_ALLOWANCE;
+
+/// Largest frame accepted from a peer, by what it carries.
+/// Sizes are of the plaintext frame, so the data packet header is included.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FrameLimits {
+    /// Handshake and identity frames, and anything that is not a data packet.
+    pub control: usize,
+    pub text: usize,
+    pub file_coming: usize,
+    pub file_receive_response: usize,
+    pub file_part: usize,
+    pub file_part_response: usize,
+}
+
+impl Default for FrameLimits {
+    fn default() -> Self {
+        Self {
+            control: DEFAULT_CONTROL_FRAME_LIMIT,
+            text: DEFAULT_TEXT_FRAME_LIMIT,
+            file_coming: DEFAULT_FILE_COMING_FRAME_LIMIT,
+            file_receive_response: DEFAULT_FILE_RECEIVE_RESPONSE_FRAME_LIMIT,
+            file_part: DEFAULT_FILE_PART_FRAME_LIMIT,
+            file_part_response: DEFAULT_RESPONSE_FRAME_LIMIT,
+        }
+    }
+}
+
+impl FrameLimits {
+    pub fn limit_for(&self, magic_number: MagicNumbers) -> usize {
+        match magic_number {
+            MagicNumbers::FileComing => self.file_coming,
+            MagicNumbers::Text => self.text,
+            MagicNumbers::FileReceiveResponse => self.file_receive_response,
+            MagicNumbers::FilePart => self.file_part,
+            MagicNumbers::FilePartResponse => self.file_part_response,
+        }
+    }
+
+    /// Limit for a frame starting with `head`, judged by the data packet magic number.
+    pub fn limit_of(&self, head: &[u8]) -> usize {
+        match head {
+            [a, b, ..] => match MagicNumbers::from(u16::from_bytes([*a, *b])) {
+                Some(magic_number) => self.limit_for(magic_number),
+                None => self.control,
+            },
+            _ => self.control,
+        }
+    }
+
+    /// The largest frame of any kind.
+    pub fn max_frame_size(&self) -> usize {
+        [
+            self.control,
+            self.text,
+            self.file_coming,
+            self.file_receive_response,
+            self.file_part,
+            self.file_part_response,
+        ].into_iter().max().unwrap_or(self.control)
+    }
+
+    /// Fail with `FrameError::TooLarge` if a frame of `size` starting with `head` is over its limit.
+    pub fn check(&self, head: &[u8], size: usize) -> Result<(), io::Error> {
+        check_size(size, self.limit_of(head))
+    }
+}
+
+pub(crate) fn check_size(size: usize, limit: usize) -> Result<(), io::Error> {
+    if size > limit {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, FrameError::TooLarge { size, limit }));
+    }
+    Ok(())
+}
+
+pub enum FrameError {
+    TooLarge { size: usize, limit: usize },
+}
+
+impl FrameError {
+    /// The frame error behind an I/O error returned by a data transmit, if any.
+    pub fn of(error: &io::Error) -> Option<&FrameError> {
+        error.get_ref().and_then(|e| e.downcast_ref::<FrameError>())
+    }
+}
+
+impl Debug for FrameError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for FrameError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::TooLarge { size, limit } =>
+                write!(f, "Frame of {} bytes exceeds the limit of {} bytes.", size, limit),
+        }
+    }
+}
+
+impl Error for FrameError {}
@@ -0,0 +1,240 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::io;
+use crate::compatibility::unified_endian::UnifiedEndian;
+use crate::lib_util::AIRX_COMPATIBLE_NUMBER;
+use crate::packet::protocol::features::ProtocolFeatures;
+use crate::packet::protocol::reader::{PacketReader, ReadError};
+use crate::packet::protocol::serialize::Serialize;
+use crate::security::identity::IdentityKey;
+
+/// Protocol version spoken by this build.
+pub const PROTOCOL_VERSION: u32 = AIRX_COMPATIBLE_NUMBER as u32;
+/// Oldest protocol version this build talks to, the first one with a hello.
+/// Peers without one still get a session, with the legacy file packets.
+pub const MIN_PROTOCOL_VERSION: u32 = 7;
+
+// Hello packet, the first frame of a data connection from either side, in plaintext:
+// 4 bytes: magic "AXHL"
+// 4 bytes: protocol version
+// 4 bytes: oldest protocol version understood
+// 4 bytes: feature bits
+// 1 byte: 1 if an identity key follows, else 0
+// 32 bytes: identity key, if any
+// 17 or 49 bytes in total
+const HELLO_MAGIC: [u8; 4] = *b"AXHL";
+const BASE_PACKET_SIZE: usize = 17;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HelloPacket {
+    version: u32,
+    min_version: u32,
+    features: ProtocolFeatures,
+    identity_key: Option<IdentityKey>,
+}
+
+pub enum HelloPacketError {
+    InvalidMagicNumber,
+    InvalidData,
+}
+
+impl Debug for HelloPacketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for HelloPacketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fmt::write(
+            f,
+            format_args!(
+                "{}",
+                match self {
+                    HelloPacketError::InvalidMagicNumber => "Invalid magic number.",
+                    HelloPacketError::InvalidData => "Invalid data.",
+                }
+            ),
+        )
+    }
+}
+
+impl Error for HelloPacketError {}
+
+impl From<ReadError> for HelloPacketError {
+    fn from(_: ReadError) -> Self {
+        HelloPacketError::InvalidData
+    }
+}
+
+/// Why a data connection was refused during the hello.
+pub enum HandshakeError {
+    /// The peer answered with something else, so it predates the hello.
+    NoHello,
+    Incompatible { peer_version: u32, peer_min_version: u32 },
+    /// Exactly one side has a group key.
+    EncryptionMismatch { peer_encrypted: bool },
+    /// The key proven in the identity exchange is not the one announced in the hello.
+    IdentityMismatch,
+}
+
+impl HandshakeError {
+    /// The handshake error behind an I/O error returned by a data transmit, if any.
+    pub fn of(error: &io::Error) -> Option<&HandshakeError> {
+        error.get_ref().and_then(|e| e.downcast_ref::<HandshakeError>())
+    }
+}
+
+impl From<HandshakeError> for io::Error {
+    fn from(e: HandshakeError) -> Self {
+        io::Error::new(io::ErrorKind::PermissionDenied, e)
+    }
+}
+
+impl Debug for HandshakeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for HandshakeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::NoHello => write!(
+                f, "Peer did not answer the hello, it speaks a protocol older than version {}.",
+                MIN_PROTOCOL_VERSION),
+            HandshakeError::Incompatible { peer_version, peer_min_version } => write!(
+                f, "Peer speaks protocol version {} (understands {} and later), this device {} (understands {} and later).",
+                peer_version, peer_min_version, PROTOCOL_VERSION, MIN_PROTOCOL_VERSION),
+            HandshakeError::EncryptionMismatch { peer_encrypted: true } =>
+                write!(f, "Peer requires encryption but no group key is set."),
+            HandshakeError::EncryptionMismatch { peer_encrypted: false } =>
+                write!(f, "Peer does not use encryption but a group key is set."),
+            HandshakeError::IdentityMismatch =>
+                write!(f, "Peer proved an identity other than the one in its hello."),
+        }
+    }
+}
+
+impl Error for HandshakeError {}
+
+/// What both sides settled on in the hello.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Negotiated {
+    pub version: u32,
+    pub features: ProtocolFeatures,
+    /// Identity key the peer announced, proven only once the identity exchange ran.
+    pub peer_identity_key: Option<IdentityKey>,
+}
+
+impl Negotiated {
+    /// Fail if the key a peer proved is not the one it announced.
+    pub fn check_identity(&self, proven: &IdentityKey) -> Result<(), HandshakeError> {
+        match &self.peer_identity_key {
+            Some(announced) if announced != proven => Err(HandshakeError::IdentityMismatch),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl HelloPacket {
+    /// Hello of this build.
+    pub fn new(features: ProtocolFeatures, identity_key: Option<IdentityKey>) -> Self {
+        Self::with_versions(PROTOCOL_VERSION, MIN_PROTOCOL_VERSION, features, identity_key)
+    }
+
+    pub fn with_versions(
+        version: u32,
+        min_version: u32,
+        features: ProtocolFeatures,
+        identity_key: Option<IdentityKey>,
+    ) -> Self {
+        Self { version, min_version, features, identity_key }
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn min_version(&self) -> u32 {
+        self.min_version
+    }
+
+    pub fn features(&self) -> ProtocolFeatures {
+        self.features
+    }
+
+    pub fn identity_key(&self) -> Option<&IdentityKey> {
+        self.identity_key.as_ref()
+    }
+
+    /// Settle on the highest common version and features with `peer`.
+    /// Both sides reach the same result from the two hellos, so neither has to announce it.
+    pub fn negotiate(&self, peer: &HelloPacket) -> Result<Negotiated, HandshakeError> {
+        if peer.version < self.min_version || self.version < peer.min_version {
+            return Err(HandshakeError::Incompatible {
+                peer_version: peer.version,
+                peer_min_version: peer.min_version,
+            });
+        }
+
+        // Falling back to plaintext would defeat the group key.
+        let encrypted = self.features.contains(ProtocolFeatures::ENCRYPTION);
+        let peer_encrypted = peer.features.contains(ProtocolFeatures::ENCRYPTION);
+        if encrypted != peer_encrypted {
+            return Err(HandshakeError::EncryptionMismatch { peer_encrypted });
+        }
+
+        Ok(Negotiated {
+            version: self.version.min(peer.version),
+            features: self.features.common(peer.features),
+            peer_identity_key: peer.identity_key,
+        })
+    }
+}
+
+pub fn is_hello_packet(frame: &[u8]) -> bool {
+    frame.len() >= BASE_PACKET_SIZE && frame[0..4] == HELLO_MAGIC
+}
+
+impl Serialize<Vec<u8>, HelloPacketError> for HelloPacket {
+    fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(BASE_PACKET_SIZE + self.identity_key.map_or(0, |k| k.len()));
+        bytes.extend_from_slice(&HELLO_MAGIC);
+        bytes.extend_from_slice(&self.version.to_bytes());
+        bytes.extend_from_slice(&self.min_version.to_bytes());
+        bytes.extend_from_slice(&self.features.bits().to_bytes());
+        match &self.identity_key {
+            Some(key) => {
+                bytes.push(1);
+                bytes.extend_from_slice(key);
+            }
+            None => bytes.push(0),
+        }
+        bytes
+    }
+
+    fn deserialize(data: &Vec<u8>) -> Result<Self, HelloPacketError> where Self: Sized {
+        let mut reader = PacketReader::new(data);
+        if reader.read_array::<4>()? != HELLO_MAGIC {
+            return Err(HelloPacketError::InvalidMagicNumber);
+        }
+        let version: u32 = reader.read()?;
+        let min_version: u32 = reader.read()?;
+        let features: u32 = reader.read()?;
+        let identity_key = match reader.read_u8()? {
+            0 => None,
+            1 => Some(reader.read_array()?),
+            _ => return Err(HelloPacketError::InvalidData),
+        };
+        reader.finish()?;
+
+        Ok(Self {
+            version,
+            min_version,
+            features: ProtocolFeatures::from_bits(features),
+            identity_key,
+        })
+    }
+}
@@ -0,0 +1,355 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use crate::packet::protocol::serialize::Serialize;
+
+/**
+* Minimal DNS message (RFC 1035) as used by mDNS / DNS-SD (RFC 6762, 6763).
+   * 12 bytes: header (id, flags, question/answer/authority/additional counts)
+   * questions, then resource records
+* Names are written uncompressed and may be compressed when read.
+ */
+const HEADER_SIZE: usize = 12;
+const FLAG_RESPONSE: u16 = 0x8400;
+const CLASS_IN: u16 = 0x0001;
+const CLASS_UNIQUE_BIT: u16 = 0x8000;
+const MAX_NAME_JUMPS: usize = 16;
+const MAX_NAME_LENGTH: usize = 255;
+
+pub const RECORD_TYPE_A: u16 = 1;
+pub const RECORD_TYPE_PTR: u16 = 12;
+pub const RECORD_TYPE_TXT: u16 = 16;
+pub const RECORD_TYPE_AAAA: u16 = 28;
+pub const RECORD_TYPE_SRV: u16 = 33;
+
+pub enum MdnsPacketError {
+    Truncated,
+    InvalidName,
+}
+
+impl Debug for MdnsPacketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for MdnsPacketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fmt::write(
+            f,
+            format_args!(
+                "{}",
+                match self {
+                    MdnsPacketError::Truncated => "Truncated packet.",
+                    MdnsPacketError::InvalidName => "Invalid name.",
+                }
+            ),
+        )
+    }
+}
+
+impl Error for MdnsPacketError {}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MdnsRecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Ptr(String),
+    Srv { port: u16, target: String },
+    Txt(Vec<String>),
+    Other(u16),
+}
+
+impl MdnsRecordData {
+    fn record_type(&self) -> u16 {
+        match self {
+            MdnsRecordData::A(_) => RECORD_TYPE_A,
+            MdnsRecordData::Aaaa(_) => RECORD_TYPE_AAAA,
+            MdnsRecordData::Ptr(_) => RECORD_TYPE_PTR,
+            MdnsRecordData::Srv { .. } => RECORD_TYPE_SRV,
+            MdnsRecordData::Txt(_) => RECORD_TYPE_TXT,
+            MdnsRecordData::Other(t) => *t,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MdnsQuestion {
+    name: String,
+    record_type: u16,
+}
+
+impl MdnsQuestion {
+    pub fn new(name: &str, record_type: u16) -> Self {
+        Self { name: name.to_string(), record_type }
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn record_type(&self) -> u16 {
+        self.record_type
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MdnsRecord {
+    name: String,
+    ttl: u32,
+    data: MdnsRecordData,
+}
+
+impl MdnsRecord {
+    pub fn new(name: &str, ttl: u32, data: MdnsRecordData) -> Self {
+        Self { name: name.to_string(), ttl, data }
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn ttl(&self) -> u32 {
+        self.ttl
+    }
+
+    pub fn data(&self) -> &MdnsRecordData {
+        &self.data
+    }
+}
+
+pub struct MdnsPacket {
+    is_response: bool,
+    questions: Vec<MdnsQuestion>,
+    records: Vec<MdnsRecord>,
+}
+
+impl MdnsPacket {
+    pub fn query(questions: Vec<MdnsQuestion>) -> Self {
+        Self { is_response: false, questions, records: Vec::new() }
+    }
+
+    pub fn response(records: Vec<MdnsRecord>) -> Self {
+        Self { is_response: true, questions: Vec::new(), records }
+    }
+
+    pub fn is_response(&self) -> bool {
+        self.is_response
+    }
+
+    pub fn questions(&self) -> &Vec<MdnsQuestion> {
+        &self.questions
+    }
+
+    /// Answer, authority and additional records alike.
+    pub fn records(&self) -> &Vec<MdnsRecord> {
+        &self.records
+    }
+}
+
+fn write_name(buf: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|l| !l.is_empty()) {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label);
+    }
+    buf.push(0);
+}
+
+fn write_record(buf: &mut Vec<u8>, record: &MdnsRecord) {
+    write_name(buf, &record.name);
+    buf.extend_from_slice(&record.data.record_type().to_be_bytes());
+    // Shared records (PTR) must not set the cache-flush bit.
+    let class = match record.data {
+        MdnsRecordData::Ptr(_) => CLASS_IN,
+        _ => CLASS_IN | CLASS_UNIQUE_BIT,
+    };
+    buf.extend_from_slice(&class.to_be_bytes());
+    buf.extend_from_slice(&record.ttl.to_be_bytes());
+
+    let mut rdata = Vec::new();
+    match &record.data {
+        MdnsRecordData::A(ip) => rdata.extend_from_slice(&ip.octets()),
+        MdnsRecordData::Aaaa(ip) => rdata.extend_from_slice(&ip.octets()),
+        MdnsRecordData::Ptr(name) => write_name(&mut rdata, name),
+        MdnsRecordData::Srv { port, target } => {
+            rdata.extend_from_slice(&[0, 0, 0, 0]); // Priority and weight.
+            rdata.extend_from_slice(&port.to_be_bytes());
+            write_name(&mut rdata, target);
+        }
+        MdnsRecordData::Txt(entries) => {
+            for entry in entries {
+                let entry = &entry.as_bytes()[..entry.len().min(255)];
+                rdata.push(entry.len() as u8);
+                rdata.extend_from_slice(entry);
+            }
+        }
+        MdnsRecordData::Other(_) => {}
+    }
+    buf.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    buf.extend_from_slice(&rdata);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MdnsPacketError> {
+        let end = self.position.checked_add(len).ok_or(MdnsPacketError::Truncated)?;
+        let bytes = self.data.get(self.position..end).ok_or(MdnsPacketError::Truncated)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, MdnsPacketError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, MdnsPacketError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Read a possibly compressed name, following at most `MAX_NAME_JUMPS` pointers.
+    fn name(&mut self) -> Result<String, MdnsPacketError> {
+        let mut labels: Vec<String> = Vec::new();
+        let mut position = self.position;
+        let mut resume_at = None;
+        let mut jumps = 0;
+        let mut length = 0;
+
+        loop {
+            let len = *self.data.get(position).ok_or(MdnsPacketError::Truncated)? as usize;
+            if len == 0 {
+                position += 1;
+                break;
+            }
+            if len & 0xC0 == 0xC0 {
+                let low = *self.data.get(position + 1).ok_or(MdnsPacketError::Truncated)? as usize;
+                jumps += 1;
+                if jumps > MAX_NAME_JUMPS {
+                    return Err(MdnsPacketError::InvalidName);
+                }
+                resume_at.get_or_insert(position + 2);
+                position = ((len & 0x3F) << 8) | low;
+                continue;
+            }
+            if len & 0xC0 != 0 {
+                return Err(MdnsPacketError::InvalidName);
+            }
+            let label = self.data
+                .get(position + 1..position + 1 + len)
+                .ok_or(MdnsPacketError::Truncated)?;
+            length += len + 1;
+            if length > MAX_NAME_LENGTH {
+                return Err(MdnsPacketError::InvalidName);
+            }
+            labels.push(String::from_utf8_lossy(label).to_string());
+            position += 1 + len;
+        }
+
+        self.position = resume_at.unwrap_or(position);
+        Ok(labels.join("."))
+    }
+
+    fn record(&mut self) -> Result<MdnsRecord, MdnsPacketError> {
+        let name = self.name()?;
+        let record_type = self.u16()?;
+        let _class = self.u16()?;
+        let ttl = self.u32()?;
+        let rdata_len = self.u16()? as usize;
+        let rdata_end = self.position.checked_add(rdata_len).ok_or(MdnsPacketError::Truncated)?;
+        if rdata_end > self.data.len() {
+            return Err(MdnsPacketError::Truncated);
+        }
+
+        let data = match record_type {
+            RECORD_TYPE_A if rdata_len == 4 => {
+                let b = self.take(4)?;
+                MdnsRecordData::A(Ipv4Addr::new(b[0], b[1], b[2], b[3]))
+            }
+            RECORD_TYPE_AAAA if rdata_len == 16 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(self.take(16)?);
+                MdnsRecordData::Aaaa(Ipv6Addr::from(octets))
+            }
+            RECORD_TYPE_PTR => MdnsRecordData::Ptr(self.name()?),
+            RECORD_TYPE_SRV => {
+                let _priority = self.u16()?;
+                let _weight = self.u16()?;
+                let port = self.u16()?;
+                MdnsRecordData::Srv { port, target: self.name()? }
+            }
+            RECORD_TYPE_TXT => {
+                let mut entries = Vec::new();
+                while self.position < rdata_end {
+                    let len = self.take(1)?[0] as usize;
+                    entries.push(String::from_utf8_lossy(self.take(len)?).to_string());
+                }
+                MdnsRecordData::Txt(entries)
+            }
+            other => MdnsRecordData::Other(other),
+        };
+
+        // Skip whatever was not understood, and never read past the record.
+        if self.position > rdata_end {
+            return Err(MdnsPacketError::Truncated);
+        }
+        self.position = rdata_end;
+        Ok(MdnsRecord { name, ttl, data })
+    }
+}
+
+impl Serialize<Vec<u8>, MdnsPacketError> for MdnsPacket {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_SIZE);
+        buf.extend_from_slice(&0u16.to_be_bytes()); // mDNS ids are zero.
+        let flags = if self.is_response { FLAG_RESPONSE } else { 0 };
+        buf.extend_from_slice(&flags.to_be_bytes());
+        buf.extend_from_slice(&(self.questions.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&(self.records.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&0u16.to_be_bytes());
+        buf.extend_from_slice(&0u16.to_be_bytes());
+
+        for question in &self.questions {
+            write_name(&mut buf, &question.name);
+            buf.extend_from_slice(&question.record_type.to_be_bytes());
+            buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+        }
+        for record in &self.records {
+            write_record(&mut buf, record);
+        }
+        buf
+    }
+
+    fn deserialize(data: &Vec<u8>) -> Result<Self, MdnsPacketError> {
+        let mut reader = Reader { data, position: 0 };
+        let _id = reader.u16()?;
+        let flags = reader.u16()?;
+        let question_count = reader.u16()?;
+        let record_count = reader.u16()? as usize + reader.u16()? as usize + reader.u16()? as usize;
+
+        let mut questions = Vec::new();
+        for _ in 0..question_count {
+            let name = reader.name()?;
+            let record_type = reader.u16()?;
+            let _class = reader.u16()?;
+            questions.push(MdnsQuestion { name, record_type });
+        }
+
+        let mut records = Vec::new();
+        for _ in 0..record_count {
+            records.push(reader.record()?);
+        }
+
+        Ok(MdnsPacket {
+            is_response: flags & 0x8000 != 0,
+            questions,
+            records,
+        })
+    }
+}
@@ -1,4 +1,10 @@
 pub mod protocol;
 pub mod data_packet;
+pub mod mdns_packet;
+pub mod discovery_frame;
 pub mod data_transmission;
+pub mod frame_limits;
+pub mod hello_packet;
+#[cfg(feature = "tokio")]
+pub mod async_data_transmission;
 pub mod data;
@@ -0,0 +1,68 @@
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+
+/// Optional protocol features a peer supports, as bits so that unknown ones pass through.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProtocolFeatures(u32);
+
+impl ProtocolFeatures {
+    pub const NONE: ProtocolFeatures = ProtocolFeatures(0);
+    pub const COMPRESSION: ProtocolFeatures = ProtocolFeatures(1 << 0);
+    /// Data channel sealed with the group key.
+    pub const ENCRYPTION: ProtocolFeatures = ProtocolFeatures(1 << 1);
+    /// Data packets checked by CRC32 instead of the legacy length hash.
+    pub const CRC32_CHECKSUM: ProtocolFeatures = ProtocolFeatures(1 << 2);
+    /// Interrupted file transfers continue where they stopped, see `TransferJournal`.
+    pub const RESUMABLE_TRANSFER: ProtocolFeatures = ProtocolFeatures(1 << 3);
+
+    /// What this build implements, with encryption only when a group key is set.
+    pub fn local(encrypted: bool) -> Self {
+        let features = Self::CRC32_CHECKSUM.with(Self::RESUMABLE_TRANSFER);
+        if encrypted {
+            features.with(Self::ENCRYPTION)
+        } else {
+            features
+        }
+    }
+
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub fn contains(&self, other: ProtocolFeatures) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn with(&self, other: ProtocolFeatures) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub fn without(&self, other: ProtocolFeatures) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    /// Features both sides support.
+    pub fn common(&self, other: ProtocolFeatures) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+impl Debug for ProtocolFeatures {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let names = [
+            (Self::COMPRESSION, "compression"),
+            (Self::ENCRYPTION, "encryption"),
+            (Self::CRC32_CHECKSUM, "crc32"),
+            (Self::RESUMABLE_TRANSFER, "resumable"),
+        ];
+        let known = names.iter()
+            .filter(|(feature, _)| self.contains(*feature))
+            .map(|(_, name)| *name)
+            .collect::<Vec<&str>>();
+        write!(f, "[{}]", known.join(","))
+    }
+}
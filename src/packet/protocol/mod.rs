@@ -1,2 +1,4 @@
 pub mod hash;
 pub mod serialize;
+pub mod reader;
+pub mod features;
@@ -0,0 +1,96 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use crate::compatibility::unified_endian::UnifiedEndian;
+
+pub enum ReadError {
+    /// Fewer bytes left than the field needs.
+    UnexpectedEnd,
+    /// Bytes left over after the last field.
+    TrailingData,
+}
+
+impl Debug for ReadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fmt::write(
+            f,
+            format_args!(
+                "{}",
+                match self {
+                    ReadError::UnexpectedEnd => "Unexpected end of packet.",
+                    ReadError::TrailingData => "Trailing data after packet.",
+                }
+            ),
+        )
+    }
+}
+
+impl Error for ReadError {}
+
+/// Cursor over a serialized packet. Every read checks the remaining length,
+/// so malformed input ends in an error instead of a panic.
+pub struct PacketReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PacketReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    /// Read a number in the unified byte order.
+    pub fn read<T, const SIZE: usize>(&mut self) -> Result<T, ReadError> where T: UnifiedEndian<SIZE> {
+        Ok(T::from_bytes(self.read_array()?))
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, ReadError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub fn read_array<const SIZE: usize>(&mut self) -> Result<[u8; SIZE], ReadError> {
+        let mut array = [0u8; SIZE];
+        array.copy_from_slice(self.read_bytes(SIZE)?);
+        Ok(array)
+    }
+
+    /// Read `len` bytes, `len` being any integer read from the packet, so it needs no cast.
+    pub fn read_bytes<L>(&mut self, len: L) -> Result<&'a [u8], ReadError> where L: TryInto<usize> {
+        let len = len.try_into().map_err(|_| ReadError::UnexpectedEnd)?;
+        if len > self.remaining() {
+            return Err(ReadError::UnexpectedEnd);
+        }
+        let bytes = &self.data[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    /// Everything not read yet.
+    pub fn read_rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.position..];
+        self.position = self.data.len();
+        rest
+    }
+
+    /// Fail unless every byte was read.
+    pub fn finish(&self) -> Result<(), ReadError> {
+        match self.remaining() {
+            0 => Ok(()),
+            _ => Err(ReadError::TrailingData),
+        }
+    }
+}
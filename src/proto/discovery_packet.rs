@@ -1,5 +1,5 @@
-// This file is generated by rust-protobuf 3.2.0. Do not edit
-// .proto file is parsed by protoc --rust-out=...
+// This file is generated by rust-protobuf 3.3.0. Do not edit
+// .proto file is parsed by pure
 // @generated
 
 // https://github.com/rust-lang/rust-clippy/issues/702
@@ -25,8 +25,8 @@
 /// of protobuf runtime.
 const _PROTOBUF_VERSION_CHECK: () = ::protobuf::VERSION_3_3_0;
 
-#[derive(PartialEq,Clone,Default,Debug)]
 // @@protoc_insertion_point(message:airx.DiscoveryPacket)
+#[derive(PartialEq,Clone,Default,Debug)]
 pub struct DiscoveryPacket {
     // message fields
     // @@protoc_insertion_point(field:airx.DiscoveryPacket.address)
@@ -39,6 +39,36 @@ pub struct DiscoveryPacket {
     pub need_response: ::std::option::Option<bool>,
     // @@protoc_insertion_point(field:airx.DiscoveryPacket.host_name)
     pub host_name: ::std::option::Option<::std::string::String>,
+    // @@protoc_insertion_point(field:airx.DiscoveryPacket.encryption_required)
+    pub encryption_required: ::std::option::Option<bool>,
+    // @@protoc_insertion_point(field:airx.DiscoveryPacket.address_v6)
+    pub address_v6: ::std::option::Option<::std::vec::Vec<u8>>,
+    // @@protoc_insertion_point(field:airx.DiscoveryPacket.goodbye)
+    pub goodbye: ::std::option::Option<bool>,
+    // @@protoc_insertion_point(field:airx.DiscoveryPacket.device_id)
+    pub device_id: ::std::option::Option<::std::string::String>,
+    // @@protoc_insertion_point(field:airx.DiscoveryPacket.identity_key)
+    pub identity_key: ::std::option::Option<::std::vec::Vec<u8>>,
+    // @@protoc_insertion_point(field:airx.DiscoveryPacket.protocol_version)
+    pub protocol_version: ::std::option::Option<u32>,
+    // @@protoc_insertion_point(field:airx.DiscoveryPacket.min_protocol_version)
+    pub min_protocol_version: ::std::option::Option<u32>,
+    // @@protoc_insertion_point(field:airx.DiscoveryPacket.platform)
+    pub platform: ::std::option::Option<::std::string::String>,
+    // @@protoc_insertion_point(field:airx.DiscoveryPacket.device_type)
+    pub device_type: ::std::option::Option<u32>,
+    // @@protoc_insertion_point(field:airx.DiscoveryPacket.features)
+    pub features: ::std::option::Option<u32>,
+    // @@protoc_insertion_point(field:airx.DiscoveryPacket.display_name)
+    pub display_name: ::std::option::Option<::std::string::String>,
+    // @@protoc_insertion_point(field:airx.DiscoveryPacket.data_port_tls)
+    pub data_port_tls: ::std::option::Option<bool>,
+    // @@protoc_insertion_point(field:airx.DiscoveryPacket.timestamp)
+    pub timestamp: ::std::option::Option<u64>,
+    // @@protoc_insertion_point(field:airx.DiscoveryPacket.nonce)
+    pub nonce: ::std::option::Option<::std::vec::Vec<u8>>,
+    // @@protoc_insertion_point(field:airx.DiscoveryPacket.signature)
+    pub signature: ::std::option::Option<::std::vec::Vec<u8>>,
     // special fields
     // @@protoc_insertion_point(special_field:airx.DiscoveryPacket.special_fields)
     pub special_fields: ::protobuf::SpecialFields,
@@ -167,8 +197,412 @@ impl DiscoveryPacket {
         self.host_name.take().unwrap_or_else(|| ::std::string::String::new())
     }
 
+    // optional bool encryption_required = 6;
+
+    pub fn encryption_required(&self) -> bool {
+        self.encryption_required.unwrap_or(false)
+    }
+
+    pub fn clear_encryption_required(&mut self) {
+        self.encryption_required = ::std::option::Option::None;
+    }
+
+    pub fn has_encryption_required(&self) -> bool {
+        self.encryption_required.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_encryption_required(&mut self, v: bool) {
+        self.encryption_required = ::std::option::Option::Some(v);
+    }
+
+    // optional bytes address_v6 = 10;
+
+    pub fn address_v6(&self) -> &[u8] {
+        match self.address_v6.as_ref() {
+            Some(v) => v,
+            None => &[],
+        }
+    }
+
+    pub fn clear_address_v6(&mut self) {
+        self.address_v6 = ::std::option::Option::None;
+    }
+
+    pub fn has_address_v6(&self) -> bool {
+        self.address_v6.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_address_v6(&mut self, v: ::std::vec::Vec<u8>) {
+        self.address_v6 = ::std::option::Option::Some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_address_v6(&mut self) -> &mut ::std::vec::Vec<u8> {
+        if self.address_v6.is_none() {
+            self.address_v6 = ::std::option::Option::Some(::std::vec::Vec::new());
+        }
+        self.address_v6.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_address_v6(&mut self) -> ::std::vec::Vec<u8> {
+        self.address_v6.take().unwrap_or_else(|| ::std::vec::Vec::new())
+    }
+
+    // optional bool goodbye = 11;
+
+    pub fn goodbye(&self) -> bool {
+        self.goodbye.unwrap_or(false)
+    }
+
+    pub fn clear_goodbye(&mut self) {
+        self.goodbye = ::std::option::Option::None;
+    }
+
+    pub fn has_goodbye(&self) -> bool {
+        self.goodbye.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_goodbye(&mut self, v: bool) {
+        self.goodbye = ::std::option::Option::Some(v);
+    }
+
+    // optional string device_id = 12;
+
+    pub fn device_id(&self) -> &str {
+        match self.device_id.as_ref() {
+            Some(v) => v,
+            None => "",
+        }
+    }
+
+    pub fn clear_device_id(&mut self) {
+        self.device_id = ::std::option::Option::None;
+    }
+
+    pub fn has_device_id(&self) -> bool {
+        self.device_id.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_device_id(&mut self, v: ::std::string::String) {
+        self.device_id = ::std::option::Option::Some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_device_id(&mut self) -> &mut ::std::string::String {
+        if self.device_id.is_none() {
+            self.device_id = ::std::option::Option::Some(::std::string::String::new());
+        }
+        self.device_id.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_device_id(&mut self) -> ::std::string::String {
+        self.device_id.take().unwrap_or_else(|| ::std::string::String::new())
+    }
+
+    // optional bytes identity_key = 13;
+
+    pub fn identity_key(&self) -> &[u8] {
+        match self.identity_key.as_ref() {
+            Some(v) => v,
+            None => &[],
+        }
+    }
+
+    pub fn clear_identity_key(&mut self) {
+        self.identity_key = ::std::option::Option::None;
+    }
+
+    pub fn has_identity_key(&self) -> bool {
+        self.identity_key.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_identity_key(&mut self, v: ::std::vec::Vec<u8>) {
+        self.identity_key = ::std::option::Option::Some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_identity_key(&mut self) -> &mut ::std::vec::Vec<u8> {
+        if self.identity_key.is_none() {
+            self.identity_key = ::std::option::Option::Some(::std::vec::Vec::new());
+        }
+        self.identity_key.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_identity_key(&mut self) -> ::std::vec::Vec<u8> {
+        self.identity_key.take().unwrap_or_else(|| ::std::vec::Vec::new())
+    }
+
+    // optional uint32 protocol_version = 14;
+
+    pub fn protocol_version(&self) -> u32 {
+        self.protocol_version.unwrap_or(0)
+    }
+
+    pub fn clear_protocol_version(&mut self) {
+        self.protocol_version = ::std::option::Option::None;
+    }
+
+    pub fn has_protocol_version(&self) -> bool {
+        self.protocol_version.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_protocol_version(&mut self, v: u32) {
+        self.protocol_version = ::std::option::Option::Some(v);
+    }
+
+    // optional uint32 min_protocol_version = 15;
+
+    pub fn min_protocol_version(&self) -> u32 {
+        self.min_protocol_version.unwrap_or(0)
+    }
+
+    pub fn clear_min_protocol_version(&mut self) {
+        self.min_protocol_version = ::std::option::Option::None;
+    }
+
+    pub fn has_min_protocol_version(&self) -> bool {
+        self.min_protocol_version.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_min_protocol_version(&mut self, v: u32) {
+        self.min_protocol_version = ::std::option::Option::Some(v);
+    }
+
+    // optional string platform = 16;
+
+    pub fn platform(&self) -> &str {
+        match self.platform.as_ref() {
+            Some(v) => v,
+            None => "",
+        }
+    }
+
+    pub fn clear_platform(&mut self) {
+        self.platform = ::std::option::Option::None;
+    }
+
+    pub fn has_platform(&self) -> bool {
+        self.platform.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_platform(&mut self, v: ::std::string::String) {
+        self.platform = ::std::option::Option::Some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_platform(&mut self) -> &mut ::std::string::String {
+        if self.platform.is_none() {
+            self.platform = ::std::option::Option::Some(::std::string::String::new());
+        }
+        self.platform.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_platform(&mut self) -> ::std::string::String {
+        self.platform.take().unwrap_or_else(|| ::std::string::String::new())
+    }
+
+    // optional uint32 device_type = 17;
+
+    pub fn device_type(&self) -> u32 {
+        self.device_type.unwrap_or(0)
+    }
+
+    pub fn clear_device_type(&mut self) {
+        self.device_type = ::std::option::Option::None;
+    }
+
+    pub fn has_device_type(&self) -> bool {
+        self.device_type.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_device_type(&mut self, v: u32) {
+        self.device_type = ::std::option::Option::Some(v);
+    }
+
+    // optional uint32 features = 18;
+
+    pub fn features(&self) -> u32 {
+        self.features.unwrap_or(0)
+    }
+
+    pub fn clear_features(&mut self) {
+        self.features = ::std::option::Option::None;
+    }
+
+    pub fn has_features(&self) -> bool {
+        self.features.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_features(&mut self, v: u32) {
+        self.features = ::std::option::Option::Some(v);
+    }
+
+    // optional string display_name = 19;
+
+    pub fn display_name(&self) -> &str {
+        match self.display_name.as_ref() {
+            Some(v) => v,
+            None => "",
+        }
+    }
+
+    pub fn clear_display_name(&mut self) {
+        self.display_name = ::std::option::Option::None;
+    }
+
+    pub fn has_display_name(&self) -> bool {
+        self.display_name.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_display_name(&mut self, v: ::std::string::String) {
+        self.display_name = ::std::option::Option::Some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_display_name(&mut self) -> &mut ::std::string::String {
+        if self.display_name.is_none() {
+            self.display_name = ::std::option::Option::Some(::std::string::String::new());
+        }
+        self.display_name.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_display_name(&mut self) -> ::std::string::String {
+        self.display_name.take().unwrap_or_else(|| ::std::string::String::new())
+    }
+
+    // optional bool data_port_tls = 20;
+
+    pub fn data_port_tls(&self) -> bool {
+        self.data_port_tls.unwrap_or(false)
+    }
+
+    pub fn clear_data_port_tls(&mut self) {
+        self.data_port_tls = ::std::option::Option::None;
+    }
+
+    pub fn has_data_port_tls(&self) -> bool {
+        self.data_port_tls.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_data_port_tls(&mut self, v: bool) {
+        self.data_port_tls = ::std::option::Option::Some(v);
+    }
+
+    // optional uint64 timestamp = 7;
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp.unwrap_or(0)
+    }
+
+    pub fn clear_timestamp(&mut self) {
+        self.timestamp = ::std::option::Option::None;
+    }
+
+    pub fn has_timestamp(&self) -> bool {
+        self.timestamp.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_timestamp(&mut self, v: u64) {
+        self.timestamp = ::std::option::Option::Some(v);
+    }
+
+    // optional bytes nonce = 8;
+
+    pub fn nonce(&self) -> &[u8] {
+        match self.nonce.as_ref() {
+            Some(v) => v,
+            None => &[],
+        }
+    }
+
+    pub fn clear_nonce(&mut self) {
+        self.nonce = ::std::option::Option::None;
+    }
+
+    pub fn has_nonce(&self) -> bool {
+        self.nonce.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_nonce(&mut self, v: ::std::vec::Vec<u8>) {
+        self.nonce = ::std::option::Option::Some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_nonce(&mut self) -> &mut ::std::vec::Vec<u8> {
+        if self.nonce.is_none() {
+            self.nonce = ::std::option::Option::Some(::std::vec::Vec::new());
+        }
+        self.nonce.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_nonce(&mut self) -> ::std::vec::Vec<u8> {
+        self.nonce.take().unwrap_or_else(|| ::std::vec::Vec::new())
+    }
+
+    // optional bytes signature = 9;
+
+    pub fn signature(&self) -> &[u8] {
+        match self.signature.as_ref() {
+            Some(v) => v,
+            None => &[],
+        }
+    }
+
+    pub fn clear_signature(&mut self) {
+        self.signature = ::std::option::Option::None;
+    }
+
+    pub fn has_signature(&self) -> bool {
+        self.signature.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_signature(&mut self, v: ::std::vec::Vec<u8>) {
+        self.signature = ::std::option::Option::Some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_signature(&mut self) -> &mut ::std::vec::Vec<u8> {
+        if self.signature.is_none() {
+            self.signature = ::std::option::Option::Some(::std::vec::Vec::new());
+        }
+        self.signature.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_signature(&mut self) -> ::std::vec::Vec<u8> {
+        self.signature.take().unwrap_or_else(|| ::std::vec::Vec::new())
+    }
+
     fn generated_message_descriptor_data() -> ::protobuf::reflect::GeneratedMessageDescriptorData {
-        let mut fields = ::std::vec::Vec::with_capacity(5);
+        let mut fields = ::std::vec::Vec::with_capacity(20);
         let mut oneofs = ::std::vec::Vec::with_capacity(0);
         fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
             "address",
@@ -195,6 +629,81 @@ impl DiscoveryPacket {
             |m: &DiscoveryPacket| { &m.host_name },
             |m: &mut DiscoveryPacket| { &mut m.host_name },
         ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "encryption_required",
+            |m: &DiscoveryPacket| { &m.encryption_required },
+            |m: &mut DiscoveryPacket| { &mut m.encryption_required },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "address_v6",
+            |m: &DiscoveryPacket| { &m.address_v6 },
+            |m: &mut DiscoveryPacket| { &mut m.address_v6 },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "goodbye",
+            |m: &DiscoveryPacket| { &m.goodbye },
+            |m: &mut DiscoveryPacket| { &mut m.goodbye },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "device_id",
+            |m: &DiscoveryPacket| { &m.device_id },
+            |m: &mut DiscoveryPacket| { &mut m.device_id },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "identity_key",
+            |m: &DiscoveryPacket| { &m.identity_key },
+            |m: &mut DiscoveryPacket| { &mut m.identity_key },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "protocol_version",
+            |m: &DiscoveryPacket| { &m.protocol_version },
+            |m: &mut DiscoveryPacket| { &mut m.protocol_version },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "min_protocol_version",
+            |m: &DiscoveryPacket| { &m.min_protocol_version },
+            |m: &mut DiscoveryPacket| { &mut m.min_protocol_version },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "platform",
+            |m: &DiscoveryPacket| { &m.platform },
+            |m: &mut DiscoveryPacket| { &mut m.platform },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "device_type",
+            |m: &DiscoveryPacket| { &m.device_type },
+            |m: &mut DiscoveryPacket| { &mut m.device_type },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "features",
+            |m: &DiscoveryPacket| { &m.features },
+            |m: &mut DiscoveryPacket| { &mut m.features },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "display_name",
+            |m: &DiscoveryPacket| { &m.display_name },
+            |m: &mut DiscoveryPacket| { &mut m.display_name },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "data_port_tls",
+            |m: &DiscoveryPacket| { &m.data_port_tls },
+            |m: &mut DiscoveryPacket| { &mut m.data_port_tls },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "timestamp",
+            |m: &DiscoveryPacket| { &m.timestamp },
+            |m: &mut DiscoveryPacket| { &mut m.timestamp },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "nonce",
+            |m: &DiscoveryPacket| { &m.nonce },
+            |m: &mut DiscoveryPacket| { &mut m.nonce },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "signature",
+            |m: &DiscoveryPacket| { &m.signature },
+            |m: &mut DiscoveryPacket| { &mut m.signature },
+        ));
         ::protobuf::reflect::GeneratedMessageDescriptorData::new_2::<DiscoveryPacket>(
             "DiscoveryPacket",
             fields,
@@ -243,6 +752,51 @@ impl ::protobuf::Message for DiscoveryPacket {
                 42 => {
                     self.host_name = ::std::option::Option::Some(is.read_string()?);
                 },
+                48 => {
+                    self.encryption_required = ::std::option::Option::Some(is.read_bool()?);
+                },
+                82 => {
+                    self.address_v6 = ::std::option::Option::Some(is.read_bytes()?);
+                },
+                88 => {
+                    self.goodbye = ::std::option::Option::Some(is.read_bool()?);
+                },
+                98 => {
+                    self.device_id = ::std::option::Option::Some(is.read_string()?);
+                },
+                106 => {
+                    self.identity_key = ::std::option::Option::Some(is.read_bytes()?);
+                },
+                112 => {
+                    self.protocol_version = ::std::option::Option::Some(is.read_uint32()?);
+                },
+                120 => {
+                    self.min_protocol_version = ::std::option::Option::Some(is.read_uint32()?);
+                },
+                130 => {
+                    self.platform = ::std::option::Option::Some(is.read_string()?);
+                },
+                136 => {
+                    self.device_type = ::std::option::Option::Some(is.read_uint32()?);
+                },
+                144 => {
+                    self.features = ::std::option::Option::Some(is.read_uint32()?);
+                },
+                154 => {
+                    self.display_name = ::std::option::Option::Some(is.read_string()?);
+                },
+                160 => {
+                    self.data_port_tls = ::std::option::Option::Some(is.read_bool()?);
+                },
+                56 => {
+                    self.timestamp = ::std::option::Option::Some(is.read_uint64()?);
+                },
+                66 => {
+                    self.nonce = ::std::option::Option::Some(is.read_bytes()?);
+                },
+                74 => {
+                    self.signature = ::std::option::Option::Some(is.read_bytes()?);
+                },
                 tag => {
                     ::protobuf::rt::read_unknown_or_skip_group(tag, is, self.special_fields.mut_unknown_fields())?;
                 },
@@ -270,6 +824,51 @@ impl ::protobuf::Message for DiscoveryPacket {
         if let Some(v) = self.host_name.as_ref() {
             my_size += ::protobuf::rt::string_size(5, &v);
         }
+        if let Some(v) = self.encryption_required {
+            my_size += 1 + 1;
+        }
+        if let Some(v) = self.address_v6.as_ref() {
+            my_size += ::protobuf::rt::bytes_size(10, &v);
+        }
+        if let Some(v) = self.goodbye {
+            my_size += 1 + 1;
+        }
+        if let Some(v) = self.device_id.as_ref() {
+            my_size += ::protobuf::rt::string_size(12, &v);
+        }
+        if let Some(v) = self.identity_key.as_ref() {
+            my_size += ::protobuf::rt::bytes_size(13, &v);
+        }
+        if let Some(v) = self.protocol_version {
+            my_size += ::protobuf::rt::uint32_size(14, v);
+        }
+        if let Some(v) = self.min_protocol_version {
+            my_size += ::protobuf::rt::uint32_size(15, v);
+        }
+        if let Some(v) = self.platform.as_ref() {
+            my_size += ::protobuf::rt::string_size(16, &v);
+        }
+        if let Some(v) = self.device_type {
+            my_size += ::protobuf::rt::uint32_size(17, v);
+        }
+        if let Some(v) = self.features {
+            my_size += ::protobuf::rt::uint32_size(18, v);
+        }
+        if let Some(v) = self.display_name.as_ref() {
+            my_size += ::protobuf::rt::string_size(19, &v);
+        }
+        if let Some(v) = self.data_port_tls {
+            my_size += 2 + 1;
+        }
+        if let Some(v) = self.timestamp {
+            my_size += ::protobuf::rt::uint64_size(7, v);
+        }
+        if let Some(v) = self.nonce.as_ref() {
+            my_size += ::protobuf::rt::bytes_size(8, &v);
+        }
+        if let Some(v) = self.signature.as_ref() {
+            my_size += ::protobuf::rt::bytes_size(9, &v);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.special_fields.unknown_fields());
         self.special_fields.cached_size().set(my_size as u32);
         my_size
@@ -291,6 +890,51 @@ impl ::protobuf::Message for DiscoveryPacket {
         if let Some(v) = self.host_name.as_ref() {
             os.write_string(5, v)?;
         }
+        if let Some(v) = self.encryption_required {
+            os.write_bool(6, v)?;
+        }
+        if let Some(v) = self.address_v6.as_ref() {
+            os.write_bytes(10, v)?;
+        }
+        if let Some(v) = self.goodbye {
+            os.write_bool(11, v)?;
+        }
+        if let Some(v) = self.device_id.as_ref() {
+            os.write_string(12, v)?;
+        }
+        if let Some(v) = self.identity_key.as_ref() {
+            os.write_bytes(13, v)?;
+        }
+        if let Some(v) = self.protocol_version {
+            os.write_uint32(14, v)?;
+        }
+        if let Some(v) = self.min_protocol_version {
+            os.write_uint32(15, v)?;
+        }
+        if let Some(v) = self.platform.as_ref() {
+            os.write_string(16, v)?;
+        }
+        if let Some(v) = self.device_type {
+            os.write_uint32(17, v)?;
+        }
+        if let Some(v) = self.features {
+            os.write_uint32(18, v)?;
+        }
+        if let Some(v) = self.display_name.as_ref() {
+            os.write_string(19, v)?;
+        }
+        if let Some(v) = self.data_port_tls {
+            os.write_bool(20, v)?;
+        }
+        if let Some(v) = self.timestamp {
+            os.write_uint64(7, v)?;
+        }
+        if let Some(v) = self.nonce.as_ref() {
+            os.write_bytes(8, v)?;
+        }
+        if let Some(v) = self.signature.as_ref() {
+            os.write_bytes(9, v)?;
+        }
         os.write_unknown_fields(self.special_fields.unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -313,6 +957,21 @@ impl ::protobuf::Message for DiscoveryPacket {
         self.group_identifier = ::std::option::Option::None;
         self.need_response = ::std::option::Option::None;
         self.host_name = ::std::option::Option::None;
+        self.encryption_required = ::std::option::Option::None;
+        self.address_v6 = ::std::option::Option::None;
+        self.goodbye = ::std::option::Option::None;
+        self.device_id = ::std::option::Option::None;
+        self.identity_key = ::std::option::Option::None;
+        self.protocol_version = ::std::option::Option::None;
+        self.min_protocol_version = ::std::option::Option::None;
+        self.platform = ::std::option::Option::None;
+        self.device_type = ::std::option::Option::None;
+        self.features = ::std::option::Option::None;
+        self.display_name = ::std::option::Option::None;
+        self.data_port_tls = ::std::option::Option::None;
+        self.timestamp = ::std::option::Option::None;
+        self.nonce = ::std::option::Option::None;
+        self.signature = ::std::option::Option::None;
         self.special_fields.clear();
     }
 
@@ -323,6 +982,21 @@ impl ::protobuf::Message for DiscoveryPacket {
             group_identifier: ::std::option::Option::None,
             need_response: ::std::option::Option::None,
             host_name: ::std::option::Option::None,
+            encryption_required: ::std::option::Option::None,
+            address_v6: ::std::option::Option::None,
+            goodbye: ::std::option::Option::None,
+            device_id: ::std::option::Option::None,
+            identity_key: ::std::option::Option::None,
+            protocol_version: ::std::option::Option::None,
+            min_protocol_version: ::std::option::Option::None,
+            platform: ::std::option::Option::None,
+            device_type: ::std::option::Option::None,
+            features: ::std::option::Option::None,
+            display_name: ::std::option::Option::None,
+            data_port_tls: ::std::option::Option::None,
+            timestamp: ::std::option::Option::None,
+            nonce: ::std::option::Option::None,
+            signature: ::std::option::Option::None,
             special_fields: ::protobuf::SpecialFields::new(),
         };
         &instance
@@ -347,31 +1021,24 @@ impl ::protobuf::reflect::ProtobufValue for DiscoveryPacket {
 }
 
 static file_descriptor_proto_data: &'static [u8] = b"\
-    \n\x1cproto/discovery_packet.proto\x12\x04airx\"\xb9\x01\n\x0fDiscoveryP\
+    \n\x1cproto/discovery_packet.proto\x12\x04airx\"\xb2\x05\n\x0fDiscoveryP\
     acket\x12\x18\n\x07address\x18\x01\x20\x02(\rR\x07address\x12\x1f\n\x0bs\
     erver_port\x18\x02\x20\x02(\rR\nserverPort\x12)\n\x10group_identifier\
     \x18\x03\x20\x02(\rR\x0fgroupIdentifier\x12#\n\rneed_response\x18\x04\
     \x20\x02(\x08R\x0cneedResponse\x12\x1b\n\thost_name\x18\x05\x20\x02(\tR\
-    \x08hostNameJ\x8d\x03\n\x06\x12\x04\0\0\n\x01\n\x08\n\x01\x0c\x12\x03\0\
-    \0\x12\n\x08\n\x01\x02\x12\x03\x02\0\r\n\n\n\x02\x04\0\x12\x04\x04\0\n\
-    \x01\n\n\n\x03\x04\0\x01\x12\x03\x04\x08\x17\n\x0b\n\x04\x04\0\x02\0\x12\
-    \x03\x05\x02\x1e\n\x0c\n\x05\x04\0\x02\0\x04\x12\x03\x05\x02\n\n\x0c\n\
-    \x05\x04\0\x02\0\x05\x12\x03\x05\x0b\x11\n\x0c\n\x05\x04\0\x02\0\x01\x12\
-    \x03\x05\x12\x19\n\x0c\n\x05\x04\0\x02\0\x03\x12\x03\x05\x1c\x1d\n\x0b\n\
-    \x04\x04\0\x02\x01\x12\x03\x06\x02\"\n\x0c\n\x05\x04\0\x02\x01\x04\x12\
-    \x03\x06\x02\n\n\x0c\n\x05\x04\0\x02\x01\x05\x12\x03\x06\x0b\x11\n\x0c\n\
-    \x05\x04\0\x02\x01\x01\x12\x03\x06\x12\x1d\n\x0c\n\x05\x04\0\x02\x01\x03\
-    \x12\x03\x06\x20!\n\x0b\n\x04\x04\0\x02\x02\x12\x03\x07\x02'\n\x0c\n\x05\
-    \x04\0\x02\x02\x04\x12\x03\x07\x02\n\n\x0c\n\x05\x04\0\x02\x02\x05\x12\
-    \x03\x07\x0b\x11\n\x0c\n\x05\x04\0\x02\x02\x01\x12\x03\x07\x12\"\n\x0c\n\
-    \x05\x04\0\x02\x02\x03\x12\x03\x07%&\n\x0b\n\x04\x04\0\x02\x03\x12\x03\
-    \x08\x02\"\n\x0c\n\x05\x04\0\x02\x03\x04\x12\x03\x08\x02\n\n\x0c\n\x05\
-    \x04\0\x02\x03\x05\x12\x03\x08\x0b\x0f\n\x0c\n\x05\x04\0\x02\x03\x01\x12\
-    \x03\x08\x10\x1d\n\x0c\n\x05\x04\0\x02\x03\x03\x12\x03\x08\x20!\n\x0b\n\
-    \x04\x04\0\x02\x04\x12\x03\t\x02\x20\n\x0c\n\x05\x04\0\x02\x04\x04\x12\
-    \x03\t\x02\n\n\x0c\n\x05\x04\0\x02\x04\x05\x12\x03\t\x0b\x11\n\x0c\n\x05\
-    \x04\0\x02\x04\x01\x12\x03\t\x12\x1b\n\x0c\n\x05\x04\0\x02\x04\x03\x12\
-    \x03\t\x1e\x1f\
+    \x08hostName\x12/\n\x13encryption_required\x18\x06\x20\x01(\x08R\x12encr\
+    yptionRequired\x12\x1d\n\naddress_v6\x18\n\x20\x01(\x0cR\taddressV6\x12\
+    \x18\n\x07goodbye\x18\x0b\x20\x01(\x08R\x07goodbye\x12\x1b\n\tdevice_id\
+    \x18\x0c\x20\x01(\tR\x08deviceId\x12!\n\x0cidentity_key\x18\r\x20\x01(\
+    \x0cR\x0bidentityKey\x12)\n\x10protocol_version\x18\x0e\x20\x01(\rR\x0fp\
+    rotocolVersion\x120\n\x14min_protocol_version\x18\x0f\x20\x01(\rR\x12min\
+    ProtocolVersion\x12\x1a\n\x08platform\x18\x10\x20\x01(\tR\x08platform\
+    \x12\x1f\n\x0bdevice_type\x18\x11\x20\x01(\rR\ndeviceType\x12\x1a\n\x08f\
+    eatures\x18\x12\x20\x01(\rR\x08features\x12!\n\x0cdisplay_name\x18\x13\
+    \x20\x01(\tR\x0bdisplayName\x12\"\n\rdata_port_tls\x18\x14\x20\x01(\x08R\
+    \x0bdataPortTls\x12\x1c\n\ttimestamp\x18\x07\x20\x01(\x04R\ttimestamp\
+    \x12\x14\n\x05nonce\x18\x08\x20\x01(\x0cR\x05nonce\x12\x1c\n\tsignature\
+    \x18\t\x20\x01(\x0cR\tsignatureb\x06proto2\
 ";
 
 /// `FileDescriptorProto` object which was a source for this generated file
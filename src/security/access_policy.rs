@@ -0,0 +1,262 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use crate::network::peer::Peer;
+
+/// Called with the source address, and the discovered peer if known, of every refused connection.
+pub type OnConnectionRejectedFunctionType = Arc<Box<dyn Fn(&SocketAddr, Option<&Peer>) + Send + Sync>>;
+
+pub enum AccessPolicyError {
+    InvalidRule,
+}
+
+impl Debug for AccessPolicyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for AccessPolicyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fmt::write(
+            f,
+            format_args!(
+                "{}",
+                match self {
+                    AccessPolicyError::InvalidRule => "Rule is neither an address, a CIDR range nor a device ID.",
+                }
+            ),
+        )
+    }
+}
+
+impl Error for AccessPolicyError {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AccessAction {
+    Allow,
+    Deny,
+}
+
+/// An address range such as "192.168.1.0/24" or "fd00::/8".
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// A bare address is a range of one.
+    pub fn parse(s: &str) -> Result<Self, AccessPolicyError> {
+        let (address, prefix_len) = match s.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (s, None),
+        };
+        let network = address.parse::<IpAddr>().map_err(|_| AccessPolicyError::InvalidRule)?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => len.parse::<u8>().map_err(|_| AccessPolicyError::InvalidRule)?,
+            None => max_len,
+        };
+        if prefix_len > max_len {
+            return Err(AccessPolicyError::InvalidRule);
+        }
+        Ok(Self { network, prefix_len })
+    }
+
+    pub fn network(&self) -> IpAddr {
+        self.network
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// IPv4-mapped IPv6 addresses, as seen on dual-stack listeners, match IPv4 ranges.
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) =>
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix_len),
+            (IpAddr::V6(network), IpAddr::V6(ip)) =>
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix_len),
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(network: &[u8], ip: &[u8], prefix_len: u8) -> bool {
+    let full_bytes = prefix_len as usize / 8;
+    let remaining_bits = prefix_len % 8;
+    if network[..full_bytes] != ip[..full_bytes] {
+        return false;
+    }
+    if remaining_bits == 0 {
+        return true;
+    }
+    let mask = 0xFFu8 << (8 - remaining_bits);
+    network[full_bytes] & mask == ip[full_bytes] & mask
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum AccessRule {
+    Cidr(Cidr),
+    DeviceId(String),
+}
+
+impl AccessRule {
+    /// Addresses and CIDR ranges are taken as such, anything else as a device ID.
+    pub fn parse(s: &str) -> Result<Self, AccessPolicyError> {
+        let s = s.trim();
+        if s.is_empty() || s.contains(char::is_whitespace) {
+            return Err(AccessPolicyError::InvalidRule);
+        }
+        if s.contains('/') || s.parse::<IpAddr>().is_ok() {
+            return Cidr::parse(s).map(AccessRule::Cidr);
+        }
+        Ok(AccessRule::DeviceId(s.to_string()))
+    }
+
+    pub fn matches(&self, ip: &IpAddr, device_id: Option<&String>) -> bool {
+        match self {
+            AccessRule::Cidr(cidr) => cidr.contains(ip),
+            AccessRule::DeviceId(id) => device_id == Some(id),
+        }
+    }
+}
+
+/// Which peers the data service accepts connections from.
+/// Deny rules win over allow rules, and the default action applies when neither matches.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AccessPolicy {
+    default_action: AccessAction,
+    allow: Vec<AccessRule>,
+    deny: Vec<AccessRule>,
+}
+
+impl Default for AccessPolicy {
+    /// Accept everyone, as before access policies existed.
+    fn default() -> Self {
+        Self::new(AccessAction::Allow)
+    }
+}
+
+impl AccessPolicy {
+    pub fn new(default_action: AccessAction) -> Self {
+        Self {
+            default_action,
+            allow: Vec::new(),
+            deny: Vec::new(),
+        }
+    }
+
+    pub fn default_action(&self) -> AccessAction {
+        self.default_action
+    }
+
+    pub fn set_default_action(&mut self, default_action: AccessAction) {
+        self.default_action = default_action;
+    }
+
+    pub fn allow_rules(&self) -> &Vec<AccessRule> {
+        &self.allow
+    }
+
+    pub fn deny_rules(&self) -> &Vec<AccessRule> {
+        &self.deny
+    }
+
+    pub fn add_rule(&mut self, action: AccessAction, rule: AccessRule) {
+        let rules = match action {
+            AccessAction::Allow => &mut self.allow,
+            AccessAction::Deny => &mut self.deny,
+        };
+        if !rules.contains(&rule) {
+            rules.push(rule);
+        }
+    }
+
+    pub fn allow(mut self, rule: &str) -> Result<Self, AccessPolicyError> {
+        self.add_rule(AccessAction::Allow, AccessRule::parse(rule)?);
+        Ok(self)
+    }
+
+    pub fn deny(mut self, rule: &str) -> Result<Self, AccessPolicyError> {
+        self.add_rule(AccessAction::Deny, AccessRule::parse(rule)?);
+        Ok(self)
+    }
+
+    pub fn clear_rules(&mut self) {
+        self.allow.clear();
+        self.deny.clear();
+    }
+
+    pub fn evaluate(&self, ip: &IpAddr, device_id: Option<&String>) -> AccessAction {
+        if self.deny.iter().any(|r| r.matches(ip, device_id)) {
+            return AccessAction::Deny;
+        }
+        if self.allow.iter().any(|r| r.matches(ip, device_id)) {
+            return AccessAction::Allow;
+        }
+        self.default_action
+    }
+}
+
+/// The live policy of a running data service, replaceable at any time,
+/// plus addresses denied for a while after misbehaving.
+#[derive(Default)]
+pub struct AccessControl {
+    policy: RwLock<AccessPolicy>,
+    penalties: Mutex<HashMap<IpAddr, Instant>>,
+}
+
+impl AccessControl {
+    pub fn new(policy: AccessPolicy) -> Self {
+        Self { policy: RwLock::new(policy), penalties: Mutex::new(HashMap::new()) }
+    }
+
+    /// Deny `ip` for `duration`, whatever the policy says.
+    pub fn penalize(&self, ip: &IpAddr, duration: Duration) {
+        if let Ok(mut penalties) = self.penalties.lock() {
+            let now = Instant::now();
+            penalties.retain(|_, until| *until > now);
+            penalties.insert(ip.to_canonical(), now + duration);
+        }
+    }
+
+    pub fn is_penalized(&self, ip: &IpAddr) -> bool {
+        match self.penalties.lock() {
+            Ok(penalties) => penalties
+                .get(&ip.to_canonical())
+                .is_some_and(|until| *until > Instant::now()),
+            Err(_) => true,
+        }
+    }
+
+    pub fn policy(&self) -> AccessPolicy {
+        match self.policy.read() {
+            Ok(policy) => policy.clone(),
+            Err(_) => AccessPolicy::new(AccessAction::Deny),
+        }
+    }
+
+    pub fn set_policy(&self, policy: AccessPolicy) {
+        if let Ok(mut locked) = self.policy.write() {
+            *locked = policy;
+        }
+    }
+
+    /// Fails closed if the lock is poisoned.
+    pub fn evaluate(&self, ip: &IpAddr, device_id: Option<&String>) -> AccessAction {
+        if self.is_penalized(ip) {
+            return AccessAction::Deny;
+        }
+        match self.policy.read() {
+            Ok(policy) => policy.evaluate(ip, device_id),
+            Err(_) => AccessAction::Deny,
+        }
+    }
+}
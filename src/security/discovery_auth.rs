@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use hmac::{Hmac, Mac};
+use protobuf::Message;
+use rand::RngCore;
+use sha2::Sha256;
+use crate::proto::discovery_packet::DiscoveryPacket;
+use crate::security::group_key::{GROUP_KEY_SIZE, GroupKey};
+
+const DISCOVERY_KEY_LABEL: &[u8] = b"airx discovery";
+
+/// Signed packets older or newer than this are rejected.
+pub const SIGNATURE_WINDOW_MILLIS: u64 = 30_000;
+const NONCE_SIZE: usize = 16;
+const SIGNATURE_SIZE: usize = 32;
+
+// Field 9 (signature), wire type 2 (length-delimited), followed by its length.
+const SIGNATURE_FIELD_PREFIX: [u8; 2] = [(9 << 3) | 2, SIGNATURE_SIZE as u8];
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub enum DiscoveryAuthError {
+    Malformed,
+    MissingSignature,
+    InvalidSignature,
+    Expired,
+    Replayed,
+}
+
+impl Debug for DiscoveryAuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for DiscoveryAuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fmt::write(
+            f,
+            format_args!(
+                "{}",
+                match self {
+                    DiscoveryAuthError::Malformed => "Malformed packet.",
+                    DiscoveryAuthError::MissingSignature => "Missing signature.",
+                    DiscoveryAuthError::InvalidSignature => "Invalid signature.",
+                    DiscoveryAuthError::Expired => "Timestamp out of window.",
+                    DiscoveryAuthError::Replayed => "Replayed packet.",
+                }
+            ),
+        )
+    }
+}
+
+impl Error for DiscoveryAuthError {}
+
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn discovery_key(group_key: &GroupKey) -> [u8; GROUP_KEY_SIZE] {
+    group_key.derive(&[], DISCOVERY_KEY_LABEL)
+}
+
+fn signature_of(key: &[u8], signed_bytes: &[u8]) -> HmacSha256 {
+    // HMAC accepts keys of any length.
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC key");
+    mac.update(signed_bytes);
+    mac
+}
+
+/// Serialize a discovery packet, signing it when a group key is given.
+pub fn seal_discovery_packet(
+    packet: &mut DiscoveryPacket,
+    group_key: Option<&GroupKey>,
+) -> Result<Vec<u8>, protobuf::Error> {
+    let group_key = match group_key {
+        Some(k) => k,
+        None => return packet.write_to_bytes(),
+    };
+
+    let mut nonce = vec![0u8; NONCE_SIZE];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    packet.set_timestamp(unix_millis());
+    packet.set_nonce(nonce);
+    packet.clear_signature();
+
+    let mut bytes = packet.write_to_bytes()?;
+    let signature = signature_of(&discovery_key(group_key), &bytes).finalize().into_bytes();
+    bytes.extend_from_slice(&SIGNATURE_FIELD_PREFIX);
+    bytes.extend_from_slice(&signature);
+    Ok(bytes)
+}
+
+/// Verifies incoming discovery packets and remembers recent nonces to stop replays.
+pub struct DiscoveryVerifier {
+    key: Option<[u8; GROUP_KEY_SIZE]>,
+    accept_unsigned: bool,
+    seen_nonces: Mutex<HashMap<Vec<u8>, u64>>,
+}
+
+impl DiscoveryVerifier {
+    /// Without a group key nothing can be verified and every packet is accepted.
+    pub fn new(group_key: Option<&GroupKey>, accept_unsigned: bool) -> Self {
+        Self {
+            key: group_key.map(discovery_key),
+            accept_unsigned,
+            seen_nonces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn open(&self, bytes: &[u8]) -> Result<DiscoveryPacket, DiscoveryAuthError> {
+        self.open_at(bytes, unix_millis())
+    }
+
+    pub fn open_at(&self, bytes: &[u8], now_millis: u64) -> Result<DiscoveryPacket, DiscoveryAuthError> {
+        let packet = DiscoveryPacket::parse_from_bytes(bytes)
+            .map_err(|_| DiscoveryAuthError::Malformed)?;
+
+        let key = match self.key {
+            Some(ref k) => k,
+            None => return Ok(packet),
+        };
+
+        if !packet.has_signature() {
+            return if self.accept_unsigned {
+                Ok(packet)
+            } else {
+                Err(DiscoveryAuthError::MissingSignature)
+            };
+        }
+
+        // The signature covers everything serialized before it.
+        let suffix_len = SIGNATURE_FIELD_PREFIX.len() + SIGNATURE_SIZE;
+        if bytes.len() < suffix_len
+            || packet.signature().len() != SIGNATURE_SIZE
+            || bytes[bytes.len() - suffix_len..bytes.len() - SIGNATURE_SIZE] != SIGNATURE_FIELD_PREFIX
+            || bytes[bytes.len() - SIGNATURE_SIZE..] != *packet.signature() {
+            return Err(DiscoveryAuthError::InvalidSignature);
+        }
+        signature_of(key, &bytes[..bytes.len() - suffix_len])
+            .verify_slice(packet.signature())
+            .map_err(|_| DiscoveryAuthError::InvalidSignature)?;
+
+        if now_millis.abs_diff(packet.timestamp()) > SIGNATURE_WINDOW_MILLIS {
+            return Err(DiscoveryAuthError::Expired);
+        }
+
+        if let Ok(mut seen) = self.seen_nonces.lock() {
+            seen.retain(|_, t| now_millis.abs_diff(*t) <= SIGNATURE_WINDOW_MILLIS);
+            if seen.insert(packet.nonce().to_vec(), packet.timestamp()).is_some() {
+                return Err(DiscoveryAuthError::Replayed);
+            }
+        }
+
+        Ok(packet)
+    }
+}
@@ -0,0 +1,43 @@
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+pub const GROUP_KEY_SIZE: usize = 32;
+
+const PASSPHRASE_SALT: &[u8] = b"airx-group-key-v1";
+const PASSPHRASE_ROUNDS: u32 = 100_000;
+
+/// Pre-shared secret of an AirX group, derived from the group passphrase.
+/// Every purpose (data channel, discovery, ...) derives its own sub key from it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct GroupKey {
+    key: [u8; GROUP_KEY_SIZE],
+}
+
+impl GroupKey {
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        let mut key = [0u8; GROUP_KEY_SIZE];
+        pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), PASSPHRASE_SALT, PASSPHRASE_ROUNDS, &mut key);
+        Self { key }
+    }
+
+    pub fn from_bytes(key: [u8; GROUP_KEY_SIZE]) -> Self {
+        Self { key }
+    }
+
+    /// Derive a sub key bound to `label` and `salt`.
+    pub fn derive(&self, salt: &[u8], label: &[u8]) -> [u8; GROUP_KEY_SIZE] {
+        let mut out = [0u8; GROUP_KEY_SIZE];
+        // Expanding 32 bytes out of SHA-256 HKDF never fails.
+        let _ = Hkdf::<Sha256>::new(Some(salt), &self.key).expand(label, &mut out);
+        out
+    }
+}
+
+impl Debug for GroupKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // Never print the key itself.
+        f.write_str("GroupKey(..)")
+    }
+}
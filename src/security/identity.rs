@@ -0,0 +1,183 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use crate::util::os::OSUtil;
+
+const IDENTITY_KEY_FILE_NAME: &str = "identity_key";
+
+pub const IDENTITY_KEY_SIZE: usize = 32;
+pub const IDENTITY_SIGNATURE_SIZE: usize = 64;
+pub const IDENTITY_NONCE_SIZE: usize = 32;
+
+// Identity hello, the first data frame of an identified connection:
+// 4 bytes: magic "AXID"
+// 1 byte: version
+// 32 bytes: public key
+// 32 bytes: nonce
+// 69 bytes in total
+// The responder appends a signature over the initiator nonce (133 bytes),
+// and the initiator then answers with its own signature as a bare frame.
+// Since version 2 the signatures cover the binding of the encrypted channel.
+const HELLO_MAGIC: [u8; 4] = *b"AXID";
+const HELLO_VERSION: u8 = 2;
+const HELLO_SIZE: usize = 69;
+
+const INITIATOR_LABEL: &[u8] = b"airx identity initiator";
+const RESPONDER_LABEL: &[u8] = b"airx identity responder";
+
+pub type IdentityKey = [u8; IDENTITY_KEY_SIZE];
+pub type IdentityNonce = [u8; IDENTITY_NONCE_SIZE];
+
+/// Long-term Ed25519 key pair of this device.
+/// Peers pin the public key when paired, see `TrustStore`.
+#[derive(Clone)]
+pub struct DeviceIdentity {
+    signing_key: SigningKey,
+}
+
+impl DeviceIdentity {
+    pub fn generate() -> Self {
+        let mut seed = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut seed);
+        Self::from_seed(&seed)
+    }
+
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        Self { signing_key: SigningKey::from_bytes(seed) }
+    }
+
+    /// Read the key stored in `directory`, creating it on first use.
+    /// A key that can't be read fails instead of being replaced, which would break every pairing.
+    pub fn load_or_create(directory: &Path) -> Result<Self, io::Error> {
+        let path = directory.join(IDENTITY_KEY_FILE_NAME);
+        match fs::read(&path) {
+            Ok(seed) => {
+                return match <[u8; 32]>::try_from(seed.as_slice()) {
+                    Ok(seed) => Ok(Self::from_seed(&seed)),
+                    Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData,
+                        format!("Identity key {} is corrupted.", path.display()))),
+                };
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        fs::create_dir_all(directory)?;
+        let identity = Self::generate();
+        OSUtil::write_private(&path, &identity.signing_key.to_bytes())?;
+        Ok(identity)
+    }
+
+    pub fn public_key(&self) -> IdentityKey {
+        self.signing_key.verifying_key().to_bytes()
+    }
+
+    pub fn sign(&self, message: &[u8]) -> [u8; IDENTITY_SIGNATURE_SIZE] {
+        self.signing_key.sign(message).to_bytes()
+    }
+
+    pub fn verify(public_key: &IdentityKey, message: &[u8], signature: &[u8]) -> bool {
+        let key = match VerifyingKey::from_bytes(public_key) {
+            Ok(k) => k,
+            Err(_) => return false,
+        };
+        let signature = match Signature::from_slice(signature) {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+        key.verify(message, &signature).is_ok()
+    }
+}
+
+pub fn encode_identity_key(public_key: &IdentityKey) -> String {
+    public_key.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn decode_identity_key(hex: &str) -> Option<IdentityKey> {
+    if hex.len() != IDENTITY_KEY_SIZE * 2 || !hex.is_ascii() {
+        return None;
+    }
+    let mut public_key = [0u8; IDENTITY_KEY_SIZE];
+    for (i, byte) in public_key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(public_key)
+}
+
+/// Short hex form of a public key for display, e.g. "3f2a-91c0-5be7-04d8".
+pub fn fingerprint(public_key: &IdentityKey) -> String {
+    let digest = Sha256::digest(public_key);
+    digest[..8]
+        .chunks(2)
+        .map(|pair| format!("{:02x}{:02x}", pair[0], pair[1]))
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+/// Six digit code both devices show while pairing.
+/// It is the same on both sides, whichever key is passed first.
+pub fn verification_code(a: &IdentityKey, b: &IdentityKey) -> String {
+    let (first, second) = if a <= b { (a, b) } else { (b, a) };
+    let mut hasher = Sha256::new();
+    hasher.update(b"airx pairing code");
+    hasher.update(first);
+    hasher.update(second);
+    let digest = hasher.finalize();
+    let value = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+    format!("{:06}", value % 1_000_000)
+}
+
+pub fn random_nonce() -> IdentityNonce {
+    let mut nonce = [0u8; IDENTITY_NONCE_SIZE];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    nonce
+}
+
+pub fn is_identity_hello(frame: &[u8]) -> bool {
+    frame.len() >= HELLO_SIZE && frame[0..4] == HELLO_MAGIC
+}
+
+pub fn serialize_identity_hello(public_key: &IdentityKey, nonce: &IdentityNonce) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HELLO_SIZE + IDENTITY_SIGNATURE_SIZE);
+    bytes.extend_from_slice(&HELLO_MAGIC);
+    bytes.push(HELLO_VERSION);
+    bytes.extend_from_slice(public_key);
+    bytes.extend_from_slice(nonce);
+    bytes
+}
+
+/// Public key, nonce and whatever follows the hello.
+pub fn deserialize_identity_hello(frame: &[u8]) -> Option<(IdentityKey, IdentityNonce, &[u8])> {
+    if !is_identity_hello(frame) || frame[4] != HELLO_VERSION {
+        return None;
+    }
+    let mut public_key = [0u8; IDENTITY_KEY_SIZE];
+    let mut nonce = [0u8; IDENTITY_NONCE_SIZE];
+    public_key.copy_from_slice(&frame[5..37]);
+    nonce.copy_from_slice(&frame[37..HELLO_SIZE]);
+    Some((public_key, nonce, &frame[HELLO_SIZE..]))
+}
+
+/// What a side signs: its role, both nonces (its peer's first), the peer's key
+/// and the binding of the encrypted channel, if any. A proof relayed by someone
+/// in the middle then fails, its two channels have different bindings.
+pub fn identity_transcript(
+    initiator: bool,
+    peer_nonce: &IdentityNonce,
+    own_nonce: &IdentityNonce,
+    peer_key: &IdentityKey,
+    channel_binding: &[u8],
+) -> Vec<u8> {
+    let label = if initiator { INITIATOR_LABEL } else { RESPONDER_LABEL };
+    let mut transcript = Vec::with_capacity(
+        label.len() + 2 * IDENTITY_NONCE_SIZE + IDENTITY_KEY_SIZE + channel_binding.len());
+    transcript.extend_from_slice(label);
+    transcript.extend_from_slice(peer_nonce);
+    transcript.extend_from_slice(own_nonce);
+    transcript.extend_from_slice(peer_key);
+    transcript.extend_from_slice(channel_binding);
+    transcript
+}
@@ -0,0 +1,6 @@
+pub mod group_key;
+pub mod secure_channel;
+pub mod discovery_auth;
+pub mod identity;
+pub mod trust_store;
+pub mod access_policy;
@@ -0,0 +1,151 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use chacha20poly1305::aead::Aead;
+use rand::RngCore;
+use crate::compatibility::unified_endian::UnifiedEndian;
+use crate::security::group_key::GroupKey;
+
+// Hello frame, sent in plaintext by both sides before anything else:
+// 4 bytes: magic "AXSC"
+// 1 byte: channel version
+// 32 bytes: random
+// 37 bytes in total
+const HELLO_MAGIC: [u8; 4] = *b"AXSC";
+const HELLO_VERSION: u8 = 1;
+pub const HELLO_RANDOM_SIZE: usize = 32;
+const HELLO_SIZE: usize = 37;
+
+/// Bytes a sealed frame is longer than its plaintext, the Poly1305 tag.
+pub const SEAL_OVERHEAD: usize = 16;
+
+const INITIATOR_LABEL: &[u8] = b"airx data channel initiator";
+const RESPONDER_LABEL: &[u8] = b"airx data channel responder";
+const BINDING_LABEL: &[u8] = b"airx data channel binding";
+
+pub type HelloRandom = [u8; HELLO_RANDOM_SIZE];
+
+pub enum HandshakeRole {
+    Initiator,
+    Responder,
+}
+
+pub enum SecureChannelError {
+    EncryptionFailed,
+    DecryptionFailed,
+    NonceExhausted,
+}
+
+impl Debug for SecureChannelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for SecureChannelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fmt::write(
+            f,
+            format_args!(
+                "{}",
+                match self {
+                    SecureChannelError::EncryptionFailed => "Encryption failed.",
+                    SecureChannelError::DecryptionFailed => "Decryption failed.",
+                    SecureChannelError::NonceExhausted => "Nonce exhausted.",
+                }
+            ),
+        )
+    }
+}
+
+impl Error for SecureChannelError {}
+
+/// ChaCha20-Poly1305 protected channel.
+/// Each direction has its own key derived from the group key and both hello randoms,
+/// and every frame uses a fresh counter nonce.
+pub struct SecureChannel {
+    seal_cipher: ChaCha20Poly1305,
+    open_cipher: ChaCha20Poly1305,
+    seal_counter: u64,
+    open_counter: u64,
+    binding: [u8; 32],
+}
+
+pub fn random_hello() -> HelloRandom {
+    let mut random = [0u8; HELLO_RANDOM_SIZE];
+    rand::thread_rng().fill_bytes(&mut random);
+    random
+}
+
+pub fn serialize_hello(random: &HelloRandom) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HELLO_SIZE);
+    bytes.extend_from_slice(&HELLO_MAGIC);
+    bytes.push(HELLO_VERSION);
+    bytes.extend_from_slice(random);
+    bytes
+}
+
+pub fn deserialize_hello(data: &[u8]) -> Option<HelloRandom> {
+    if data.len() != HELLO_SIZE || data[0..4] != HELLO_MAGIC || data[4] != HELLO_VERSION {
+        return None;
+    }
+    let mut random = [0u8; HELLO_RANDOM_SIZE];
+    random.copy_from_slice(&data[5..]);
+    Some(random)
+}
+
+fn nonce_of(counter: u64) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&counter.to_bytes());
+    Nonce::from(nonce)
+}
+
+impl SecureChannel {
+    pub fn new(
+        group_key: &GroupKey,
+        role: HandshakeRole,
+        initiator_random: &HelloRandom,
+        responder_random: &HelloRandom,
+    ) -> Self {
+        let mut salt = Vec::with_capacity(HELLO_RANDOM_SIZE * 2);
+        salt.extend_from_slice(initiator_random);
+        salt.extend_from_slice(responder_random);
+
+        let initiator_key = group_key.derive(&salt, INITIATOR_LABEL);
+        let responder_key = group_key.derive(&salt, RESPONDER_LABEL);
+        let (seal_key, open_key) = match role {
+            HandshakeRole::Initiator => (initiator_key, responder_key),
+            HandshakeRole::Responder => (responder_key, initiator_key),
+        };
+
+        Self {
+            seal_cipher: ChaCha20Poly1305::new(Key::from_slice(&seal_key)),
+            open_cipher: ChaCha20Poly1305::new(Key::from_slice(&open_key)),
+            seal_counter: 0,
+            open_counter: 0,
+            binding: group_key.derive(&salt, BINDING_LABEL),
+        }
+    }
+
+    /// Value unique to this channel, the same on both ends, from the same key material.
+    pub fn binding(&self) -> &[u8; 32] {
+        &self.binding
+    }
+
+    pub fn seal(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, SecureChannelError> {
+        let nonce = nonce_of(self.seal_counter);
+        self.seal_counter = self.seal_counter.checked_add(1)
+            .ok_or(SecureChannelError::NonceExhausted)?;
+        self.seal_cipher.encrypt(&nonce, plaintext)
+            .map_err(|_| SecureChannelError::EncryptionFailed)
+    }
+
+    pub fn open(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, SecureChannelError> {
+        let nonce = nonce_of(self.open_counter);
+        self.open_counter = self.open_counter.checked_add(1)
+            .ok_or(SecureChannelError::NonceExhausted)?;
+        self.open_cipher.decrypt(&nonce, ciphertext)
+            .map_err(|_| SecureChannelError::DecryptionFailed)
+    }
+}
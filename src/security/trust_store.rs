@@ -0,0 +1,195 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use crate::security::identity::{decode_identity_key, encode_identity_key, IdentityKey};
+
+const TRUST_STORE_FILE_NAME: &str = "trusted_devices";
+
+/// What to do with text and file offers from peers that are not paired.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UntrustedPolicy {
+    /// Deliver as before pairing existed.
+    Allow,
+    /// Discard silently.
+    Drop,
+    /// Hold back texts and offers in the quarantine until the sender is paired or released.
+    Quarantine,
+}
+
+impl UntrustedPolicy {
+    pub fn value(&self) -> u32 {
+        match self {
+            UntrustedPolicy::Allow => 0,
+            UntrustedPolicy::Drop => 1,
+            UntrustedPolicy::Quarantine => 2,
+        }
+    }
+
+    pub fn from(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(UntrustedPolicy::Allow),
+            1 => Some(UntrustedPolicy::Drop),
+            2 => Some(UntrustedPolicy::Quarantine),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TrustedDevice {
+    device_id: String,
+    host_name: String,
+    identity_key: IdentityKey,
+}
+
+impl TrustedDevice {
+    pub fn new(device_id: &str, host_name: &str, identity_key: IdentityKey) -> Self {
+        Self {
+            device_id: device_id.to_string(),
+            host_name: host_name.to_string(),
+            identity_key,
+        }
+    }
+
+    pub fn device_id(&self) -> &String {
+        &self.device_id
+    }
+
+    pub fn host_name(&self) -> &String {
+        &self.host_name
+    }
+
+    pub fn identity_key(&self) -> &IdentityKey {
+        &self.identity_key
+    }
+
+    /// One line: device ID, hex key and host name, tab separated.
+    fn to_line(&self) -> String {
+        let host_name: String = self.host_name.chars().filter(|c| !c.is_control()).collect();
+        format!("{}\t{}\t{}", self.device_id, encode_identity_key(&self.identity_key), host_name)
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(3, '\t');
+        let device_id = fields.next()?;
+        let identity_key = decode_identity_key(fields.next()?)?;
+        let host_name = fields.next().unwrap_or("");
+        if device_id.is_empty() {
+            return None;
+        }
+        Some(Self::new(device_id, host_name, identity_key))
+    }
+}
+
+/// Devices the user approved, pinned by their identity key.
+/// Kept in memory until a directory is attached, then written on every change.
+pub struct TrustStore {
+    path: Mutex<Option<PathBuf>>,
+    devices: Mutex<Vec<TrustedDevice>>,
+}
+
+impl Default for TrustStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TrustStore {
+    pub fn new() -> Self {
+        Self {
+            path: Mutex::new(None),
+            devices: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn open(directory: &Path) -> Result<Self, io::Error> {
+        let store = Self::new();
+        store.attach(directory)?;
+        Ok(store)
+    }
+
+    /// Load the store kept in `directory` and persist there from now on.
+    /// Devices paired before are kept.
+    pub fn attach(&self, directory: &Path) -> Result<(), io::Error> {
+        let path = directory.join(TRUST_STORE_FILE_NAME);
+        let loaded = match fs::read_to_string(&path) {
+            Ok(content) => content.lines().filter_map(TrustedDevice::from_line).collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        fs::create_dir_all(directory)?;
+        if let Ok(mut devices) = self.devices.lock() {
+            let paired_before = std::mem::replace(&mut *devices, loaded);
+            for device in paired_before {
+                devices.retain(|d| d.device_id != device.device_id);
+                devices.push(device);
+            }
+        }
+        if let Ok(mut locked) = self.path.lock() {
+            *locked = Some(path);
+        }
+        self.save()
+    }
+
+    /// Trust `device`, replacing an earlier entry with the same device ID.
+    pub fn pair(&self, device: TrustedDevice) -> Result<(), io::Error> {
+        if let Ok(mut devices) = self.devices.lock() {
+            devices.retain(|d| d.device_id != device.device_id);
+            devices.push(device);
+        }
+        self.save()
+    }
+
+    /// Returns whether the device was paired.
+    pub fn unpair(&self, device_id: &str) -> Result<bool, io::Error> {
+        let removed = match self.devices.lock() {
+            Ok(mut devices) => {
+                let count = devices.len();
+                devices.retain(|d| d.device_id != device_id);
+                devices.len() != count
+            }
+            Err(_) => false,
+        };
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    pub fn list(&self) -> Vec<TrustedDevice> {
+        match self.devices.lock() {
+            Ok(devices) => devices.clone(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    pub fn find(&self, device_id: &str) -> Option<TrustedDevice> {
+        self.list().into_iter().find(|d| d.device_id == device_id)
+    }
+
+    pub fn is_trusted(&self, identity_key: &IdentityKey) -> bool {
+        match self.devices.lock() {
+            Ok(devices) => devices.iter().any(|d| &d.identity_key == identity_key),
+            Err(_) => false,
+        }
+    }
+
+    fn save(&self) -> Result<(), io::Error> {
+        let path = match self.path.lock() {
+            Ok(path) => path.clone(),
+            Err(_) => None,
+        };
+        let path = match path {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        let content: String = self.list().iter().map(|d| d.to_line() + "\n").collect();
+        // Write aside and rename, so a crash never leaves half a store behind.
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, content)?;
+        fs::rename(&temp_path, &path)
+    }
+}
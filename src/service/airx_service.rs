@@ -1,7 +1,22 @@
+use crate::network::device_info::{DeviceInfo, DeviceType};
 use crate::service::discovery_service::DiscoveryService;
 use crate::service::data_service::DataService;
+use crate::service::metrics::ServiceMetrics;
+use crate::security::access_policy::{AccessControl, AccessPolicy};
+use crate::security::group_key::GroupKey;
+use crate::security::identity::DeviceIdentity;
+use crate::security::trust_store::{TrustStore, UntrustedPolicy};
+use crate::packet::frame_limits::FrameLimits;
+use crate::service::context::data_service_context::ConnectionLimits;
+use crate::service::context::discovery_service_context::{DiscoveryMode, DiscoveryServiceContext, MulticastConfig};
+use crate::service::mdns_service::MdnsConfig;
+use crate::service::quarantine::Quarantine;
+use crate::service::transfer_manager::TransferManager;
+use crate::service::shutdown::ShutdownHandle;
 use std::io;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub struct AirXServiceConfig {
     pub discovery_service_server_port: u16,
@@ -9,6 +24,37 @@ pub struct AirXServiceConfig {
     pub text_service_listen_addr: String,
     pub data_service_listen_port: u16,
     pub group_identifier: u32,
+    /// Encrypt every data connection with this key when set.
+    pub group_key: Option<GroupKey>,
+    /// Accept unsigned discovery packets from older peers even when a group key is set.
+    pub accept_unsigned_discovery: bool,
+    pub discovery_mode: DiscoveryMode,
+    /// Groups and TTL used by the multicast and hybrid discovery modes.
+    pub multicast: MulticastConfig,
+    /// Advertise and browse `_airx._tcp.local` alongside the AirX discovery packets.
+    pub mdns_enabled: bool,
+    pub heartbeat_interval_millis: u64,
+    /// Peers not heard from for this long are evicted.
+    pub peer_ttl_millis: u64,
+    /// Persistent identity of this instance, see `DeviceId`.
+    pub device_id: String,
+    /// Where the device ID and other persistent state are kept, if anywhere.
+    pub data_directory: Option<PathBuf>,
+    /// Key pair proving who we are on data connections, see `DeviceIdentity`.
+    pub identity: DeviceIdentity,
+    /// What happens to text and file offers from devices that are not paired.
+    pub untrusted_policy: UntrustedPolicy,
+    /// Which peers the data service accepts connections from.
+    pub access_policy: AccessPolicy,
+    /// Worker threads and connection caps of the data service.
+    pub connection_limits: ConnectionLimits,
+    /// Largest frames the data service accepts.
+    pub frame_limits: FrameLimits,
+    /// How long peers sending oversized frames are denied, 0 for not at all.
+    pub oversized_frame_penalty_millis: u64,
+    /// Announced instead of the hostname when set.
+    pub display_name: Option<String>,
+    pub device_type: DeviceType,
 }
 
 impl Clone for AirXServiceConfig {
@@ -19,6 +65,56 @@ impl Clone for AirXServiceConfig {
             text_service_listen_addr: self.text_service_listen_addr.clone(),
             data_service_listen_port: self.data_service_listen_port,
             group_identifier: self.group_identifier,
+            group_key: self.group_key.clone(),
+            accept_unsigned_discovery: self.accept_unsigned_discovery,
+            discovery_mode: self.discovery_mode,
+            multicast: self.multicast,
+            mdns_enabled: self.mdns_enabled,
+            heartbeat_interval_millis: self.heartbeat_interval_millis,
+            peer_ttl_millis: self.peer_ttl_millis,
+            device_id: self.device_id.clone(),
+            data_directory: self.data_directory.clone(),
+            identity: self.identity.clone(),
+            untrusted_policy: self.untrusted_policy,
+            access_policy: self.access_policy.clone(),
+            connection_limits: self.connection_limits,
+            frame_limits: self.frame_limits,
+            oversized_frame_penalty_millis: self.oversized_frame_penalty_millis,
+            display_name: self.display_name.clone(),
+            device_type: self.device_type,
+        }
+    }
+}
+
+impl AirXServiceConfig {
+    pub fn discovery_service_context(&self) -> DiscoveryServiceContext {
+        let mut context = DiscoveryServiceContext::new(
+            self.discovery_service_client_port,
+            self.discovery_service_server_port,
+            self.group_identifier,
+            self.group_key.clone(),
+            self.accept_unsigned_discovery,
+            self.discovery_mode,
+            self.multicast,
+        );
+        context.set_liveness(
+            Duration::from_millis(self.heartbeat_interval_millis),
+            Duration::from_millis(self.peer_ttl_millis),
+        );
+        context.set_device_id(Some(self.device_id.clone()));
+        context.set_identity_key(Some(self.identity.public_key()));
+        context.set_device_info(DeviceInfo::local(
+            self.display_name.clone(), self.device_type, self.group_key.is_some()));
+        if self.mdns_enabled {
+            context.set_mdns(Some(MdnsConfig::new(self.data_service_listen_port)));
+        }
+        context
+    }
+
+    pub fn oversized_frame_penalty(&self) -> Option<Duration> {
+        match self.oversized_frame_penalty_millis {
+            0 => None,
+            millis => Some(Duration::from_millis(millis)),
         }
     }
 }
@@ -28,6 +124,12 @@ pub struct AirXService {
     config: AirXServiceConfig,
     text_service: Arc<DataService>,
     discovery_service: Arc<DiscoveryService>,
+    metrics: Arc<ServiceMetrics>,
+    trust_store: Arc<TrustStore>,
+    quarantine: Arc<Quarantine>,
+    transfer_manager: Arc<TransferManager>,
+    access_control: Arc<AccessControl>,
+    shutdown: ShutdownHandle,
 }
 
 #[allow(dead_code)]
@@ -41,6 +143,12 @@ impl AirXService {
             config: config.clone(),
             text_service: Arc::new(text_service),
             discovery_service: Arc::new(discovery_service),
+            metrics: Arc::new(ServiceMetrics::new()),
+            trust_store: Arc::new(TrustStore::new()),
+            quarantine: Arc::new(Quarantine::new()),
+            transfer_manager: Arc::new(TransferManager::new()),
+            access_control: Arc::new(AccessControl::new(config.access_policy.clone())),
+            shutdown: ShutdownHandle::new(),
         })
     } // run
 
@@ -52,7 +160,51 @@ impl AirXService {
         self.discovery_service.clone()
     }
 
+    pub fn metrics(&self) -> Arc<ServiceMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Paired devices, shared with the running data service.
+    pub fn trust_store(&self) -> Arc<TrustStore> {
+        self.trust_store.clone()
+    }
+
+    /// Offers held back from unpaired devices under `UntrustedPolicy::Quarantine`.
+    pub fn quarantine(&self) -> Arc<Quarantine> {
+        self.quarantine.clone()
+    }
+
+    /// Files offered by this instance, shared with the running data service.
+    pub fn transfer_manager(&self) -> Arc<TransferManager> {
+        self.transfer_manager.clone()
+    }
+
+    /// The access policy in effect, shared with the running data service.
+    pub fn access_control(&self) -> Arc<AccessControl> {
+        self.access_control.clone()
+    }
+
+    /// Takes effect immediately, also for a data service that is already running.
+    pub fn set_access_policy(&mut self, policy: AccessPolicy) {
+        self.access_control.set_policy(policy.clone());
+        self.config.access_policy = policy;
+    }
+
+    /// Stops the services started for this instance, see `stop`.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Wake and stop every service, abort transfers in flight and wait for all threads.
+    pub fn stop(&self) {
+        self.shutdown.stop();
+    }
+
     pub fn config(&self) -> AirXServiceConfig {
         self.config.clone()
     }
+
+    pub fn config_mut(&mut self) -> &mut AirXServiceConfig {
+        &mut self.config
+    }
 }
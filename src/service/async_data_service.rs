@@ -0,0 +1,314 @@
+use std::future::poll_fn;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+use log::{info, warn};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use crate::error::AirXError;
+use crate::network::peer::Peer;
+use crate::network::tcp_server::TcpServer;
+use crate::packet::async_data_transmission::AsyncDataTransmit;
+use crate::packet::data::magic_numbers::MagicNumbers;
+use crate::packet::data_packet::DataPacket;
+use crate::packet::hello_packet::{HandshakeError, HelloPacket};
+use crate::packet::protocol::features::ProtocolFeatures;
+use crate::packet::protocol::serialize::Serialize;
+use crate::security::group_key::GroupKey;
+use crate::security::identity::DeviceIdentity;
+use crate::service::connection_limiter::ConnectionLimiter;
+use crate::service::context::data_service_context::DataServiceContext;
+use crate::service::data_service::DataService;
+use crate::service::handler::context::ConnectionControl;
+use crate::service::peer_session::{Frame, PeerSession};
+
+const FILE_CANCEL_GRACE: Duration = Duration::from_secs(2);
+const OFFER_EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Async counterpart of `DataService` for embedders running a tokio runtime.
+/// Connections are tasks instead of threads. Handlers run on the blocking pool
+/// since they call back into the embedder and may send whole files.
+pub struct AsyncDataService {}
+
+impl Default for AsyncDataService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncDataService {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub async fn send_once(
+        peer: &Peer,
+        port: u16,
+        magic_number: MagicNumbers,
+        data: &[u8],
+        connect_timeout: Duration,
+        group_key: Option<&GroupKey>,
+        identity: Option<&DeviceIdentity>,
+    ) -> Result<(), io::Error> {
+        let mut dt = open_transmit(peer, port, connect_timeout, group_key, identity).await?;
+        info!("Connection established with {}.", peer.to_string());
+
+        let data_packet = DataPacket::new(magic_number.value(), &data.to_vec());
+        let result = dt.send_data(&data_packet.serialize()).await;
+        let _ = dt.close().await;
+        result
+    }
+
+    /// Serve until the context's shutdown handle is triggered, then wait for the sessions.
+    pub async fn run(context: DataServiceContext) -> Result<(), AirXError> {
+        let listeners = TcpServer::create_and_listen(context.host(), context.port())?
+            .into_listeners()
+            .into_iter()
+            .map(TcpListener::from_std)
+            .collect::<Result<Vec<TcpListener>, io::Error>>()?;
+        let shutdown = context.shutdown().clone();
+        let _running = shutdown.enter_service();
+        let limiter = ConnectionLimiter::new(&context.connection_limits());
+        DataService::attach_quarantine(&context);
+        let context = Arc::new(context);
+        let mut sessions = JoinSet::new();
+
+        let stopped = shutdown.wait();
+        tokio::pin!(stopped);
+        let mut expiry = tokio::time::interval(OFFER_EXPIRY_INTERVAL);
+
+        info!("Async data service online and ready for connections.");
+
+        loop {
+            let accepted = tokio::select! {
+                _ = &mut stopped => {
+                    info!("Async data service is shut down.");
+                    break;
+                }
+                _ = expiry.tick() => {
+                    DataService::expire_offers(&context);
+                    continue;
+                }
+                accepted = accept_any(&listeners) => accepted,
+            };
+            match accepted {
+                Ok((stream, socket_addr)) => {
+                    // There is no worker pool to wait for here, only the connection limits apply.
+                    let permit = match limiter.try_acquire(socket_addr.ip()) {
+                        Some(p) => p,
+                        None => {
+                            DataService::count_over_limit(&socket_addr, &context, "connection limit reached");
+                            continue;
+                        }
+                    };
+                    while sessions.try_join_next().is_some() {}
+                    let session_context = context.clone();
+                    sessions.spawn(async move {
+                        let _permit = permit;
+                        Self::handle_peer(stream, socket_addr, session_context).await;
+                    });
+                }
+                Err(e) => {
+                    warn!("Failed to accept connection ({}).", e);
+                    break;
+                }
+            }
+        }
+
+        while sessions.join_next().await.is_some() {}
+        DataService::detach_quarantine(&context);
+        DataService::flush_journal(&context);
+        Ok(())
+    }
+
+    async fn handle_peer(stream: TcpStream, socket_addr: SocketAddr, context: Arc<DataServiceContext>) {
+        if !DataService::admit_connection(&socket_addr, &context) {
+            return;
+        }
+
+        let stopped = context.shutdown().wait();
+        tokio::pin!(stopped);
+        let mut tt = AsyncDataTransmit::from(stream);
+        tt.set_frame_limits(context.frame_limits());
+
+        let hello = DataService::local_hello(&context);
+        let negotiation = tokio::select! {
+            _ = &mut stopped => return,
+            r = async {
+                wait_for_peer(&mut tt, &context).await?;
+                tt.negotiate_as_responder(&hello).await
+            } => r,
+        };
+        if let Err(e) = negotiation {
+            PeerSession::refuse(&e, &socket_addr, &context);
+            let _ = tt.close().await;
+            return;
+        }
+
+        if let Some(group_key) = context.group_key() {
+            let handshake = tokio::select! {
+                _ = &mut stopped => return,
+                r = async {
+                    wait_for_peer(&mut tt, &context).await?;
+                    tt.handshake_as_responder(group_key).await
+                } => r,
+            };
+            if let Err(e) = handshake {
+                PeerSession::refuse(&e, &socket_addr, &context);
+                let _ = tt.close().await;
+                return;
+            }
+        }
+
+        let mut session = PeerSession::new(socket_addr, tt.negotiated());
+        loop {
+            tokio::select! {
+                _ = &mut stopped => break,
+                r = wait_for_peer(&mut tt, &context) => if let Err(e) = r {
+                    info!("Closing connection from {} ({}).", socket_addr, e);
+                    break;
+                },
+            };
+            let raw_data = tokio::select! {
+                _ = &mut stopped => break,
+                r = tt.read_data() => match r {
+                    Ok(d) => d,
+                    Err(e) => {
+                        DataService::check_oversized_frame(&e, &socket_addr, &context);
+                        break;
+                    }
+                },
+            };
+
+            let data_packet = match session.read_frame(&raw_data, tt.peer_identity().is_some(), &context) {
+                Some(Frame::IdentityHello) => {
+                    if let Err(e) = tt.identify_as_responder(context.identity(), &raw_data).await {
+                        warn!("Identity exchange with {} failed ({}).", socket_addr, e);
+                        break;
+                    }
+                    continue;
+                }
+                Some(Frame::Data(p)) => p,
+                None => break,
+            };
+
+            let peer_identity = tt.peer_identity().copied();
+            let handler_session = session.clone();
+            let handler_context = context.clone();
+            let control = tokio::task::spawn_blocking(move || {
+                handler_session.dispatch(peer_identity, &data_packet, &handler_context)
+            }).await;
+            match control {
+                Ok(ConnectionControl::Default) => (),
+                _ => break,
+            }
+        }
+
+        if let Some(frame) = session.stop_receiving_frame(&context) {
+            if tt.send_data(&frame).await.is_ok() {
+                tt.drain(FILE_CANCEL_GRACE).await;
+            }
+        }
+        let _ = tt.close().await;
+
+        info!("Session with {} is ended.", socket_addr);
+    }
+}
+
+/// Wait for the peer's next frame, failing once it was idle for the idle timeout.
+async fn wait_for_peer(tt: &mut AsyncDataTransmit, context: &DataServiceContext) -> Result<(), io::Error> {
+    let idle_timeout = context.connection_limits().idle_timeout;
+    if idle_timeout.is_zero() || tt.wait_for_data(idle_timeout).await? {
+        return Ok(());
+    }
+    Err(io::Error::new(io::ErrorKind::TimedOut, "Peer was idle."))
+}
+
+/// Accept from whichever listener has a connection pending.
+async fn accept_any(listeners: &[TcpListener]) -> Result<(TcpStream, SocketAddr), io::Error> {
+    poll_fn(|cx| {
+        for listener in listeners {
+            if let Poll::Ready(accepted) = listener.poll_accept(cx) {
+                return Poll::Ready(accepted);
+            }
+        }
+        Poll::Pending
+    }).await
+}
+
+async fn open_transmit(
+    peer: &Peer,
+    port: u16,
+    timeout: Duration,
+    group_key: Option<&GroupKey>,
+    identity: Option<&DeviceIdentity>,
+) -> Result<AsyncDataTransmit, io::Error> {
+    if group_key.is_none() && peer.encryption_required() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("Peer {} requires encryption but no group key is set.", peer.to_string()),
+        ));
+    }
+
+    let mut dt = AsyncDataTransmit::from(connect(peer, port, timeout).await?);
+    let hello = HelloPacket::new(ProtocolFeatures::local(group_key.is_some()), identity.map(|i| i.public_key()));
+    match dt.negotiate_as_initiator(&hello).await {
+        Ok(_) => {}
+        // Peers older than the hello hang up on it, they get a legacy session instead.
+        Err(e) if matches!(HandshakeError::of(&e), Some(HandshakeError::NoHello)) => {
+            let _ = dt.close().await;
+            info!("Peer {} predates the hello, falling back to a legacy session.", peer.to_string());
+            return open_legacy_transmit(peer, port, timeout, group_key).await;
+        }
+        Err(e) => {
+            let _ = dt.close().await;
+            return Err(e);
+        }
+    }
+    if let Some(group_key) = group_key {
+        if let Err(e) = dt.handshake_as_initiator(group_key).await {
+            let _ = dt.close().await;
+            return Err(e);
+        }
+    }
+    if let Some(identity) = identity {
+        if let Err(e) = dt.identify_as_initiator(identity).await {
+            let _ = dt.close().await;
+            return Err(e);
+        }
+    }
+    Ok(dt)
+}
+
+/// See `open_legacy_transmit` of `DataService`.
+async fn open_legacy_transmit(
+    peer: &Peer,
+    port: u16,
+    timeout: Duration,
+    group_key: Option<&GroupKey>,
+) -> Result<AsyncDataTransmit, io::Error> {
+    let mut dt = AsyncDataTransmit::from(connect(peer, port, timeout).await?);
+    if let Some(group_key) = group_key {
+        if let Err(e) = dt.handshake_as_initiator(group_key).await {
+            let _ = dt.close().await;
+            return Err(e);
+        }
+    }
+    Ok(dt)
+}
+
+async fn connect(peer: &Peer, port: u16, timeout: Duration) -> Result<TcpStream, io::Error> {
+    let socket_addr = match peer.socket_addr(port) {
+        Some(addr) => addr,
+        None => return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid peer address {}.", peer.host()),
+        )),
+    };
+    match tokio::time::timeout(timeout, TcpStream::connect(socket_addr)).await {
+        Ok(s) => s,
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "Connection timed out.")),
+    }
+}
@@ -0,0 +1,170 @@
+use std::io;
+use std::net::UdpSocket as StdUdpSocket;
+use std::sync::Arc;
+use std::time::Duration;
+use log::{error, info, warn};
+use tokio::net::UdpSocket;
+use tokio::task::JoinSet;
+use crate::error::AirXError;
+use crate::packet::discovery_frame::{LegacyDiscoveryPairing, MAX_DISCOVERY_DATAGRAM_SIZE};
+use crate::security::discovery_auth::DiscoveryVerifier;
+use crate::service::context::discovery_service_context::DiscoveryServiceContext;
+use crate::service::discovery_service::{DiscoveryService, open_discovery_datagram, PeerCollectionType, scan_local_addresses};
+use crate::service::mdns_service::MdnsService;
+use crate::service::ShouldInterruptFunctionType;
+
+const MIN_HEARTBEAT_MILLIS: u64 = 100;
+
+/// Async counterpart of `DiscoveryService` for embedders running a tokio runtime.
+/// Receiving and heartbeats are tasks, announcing and peer updates run on the blocking pool
+/// since they scan interfaces and call back into the embedder. mDNS, when enabled,
+/// still takes a blocking thread.
+pub struct AsyncDiscoveryService {}
+
+/// State shared by the receiving tasks.
+struct AsyncDiscoverySession {
+    context: DiscoveryServiceContext,
+    peer_set_ptr: PeerCollectionType,
+    verifier: DiscoveryVerifier,
+}
+
+impl Default for AsyncDiscoveryService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncDiscoveryService {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Discover until the context's shutdown handle is triggered, then say goodbye.
+    pub async fn run(context: DiscoveryServiceContext, peer_set_ptr: PeerCollectionType) -> Result<(), AirXError> {
+        let server_socket = DiscoveryService::create_broadcast_socket(context.server_port())?;
+        if context.mode().uses_multicast() {
+            DiscoveryService::join_multicast_v4(&server_socket, &context.multicast().group_v4)?;
+        }
+        let server_socket_v6 = match DiscoveryService::create_multicast_socket_v6(
+            context.server_port(), &context.ipv6_groups()) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                warn!("IPv6 discovery is unavailable ({})", e);
+                None
+            }
+        };
+        let shutdown = context.shutdown().clone();
+        let _running = shutdown.enter_service();
+        let session = Arc::new(AsyncDiscoverySession {
+            verifier: DiscoveryVerifier::new(context.group_key(), context.accept_unsigned_discovery()),
+            context,
+            peer_set_ptr,
+        });
+
+        // Broadcast discovery request twice to ensure that we are discovered.
+        let announce_session = session.clone();
+        let _ = tokio::task::spawn_blocking(move || {
+            for _ in 0..2 {
+                let _ = DiscoveryService::broadcast_discovery_request(&announce_session.context);
+            }
+        }).await;
+
+        info!("Async discovery service online and ready for connections ({:?} mode).", session.context.mode());
+
+        let mut tasks = JoinSet::new();
+        tasks.spawn(Self::receive_loop(into_async(server_socket)?, session.clone()));
+        if let Some(socket_v6) = server_socket_v6 {
+            tasks.spawn(Self::receive_loop(into_async(socket_v6)?, session.clone()));
+        }
+        tasks.spawn(Self::liveness_loop(session.clone()));
+        if session.context.mdns().is_some() {
+            let mdns_session = session.clone();
+            tasks.spawn_blocking(move || {
+                let should_interrupt: ShouldInterruptFunctionType = Box::new(|| false);
+                if let Some(mdns) = mdns_session.context.mdns() {
+                    if let Err(e) = MdnsService::run(
+                        &mdns_session.context, mdns, mdns_session.peer_set_ptr.clone(), &should_interrupt) {
+                        warn!("mDNS is unavailable ({})", e);
+                    }
+                }
+            });
+        }
+        while tasks.join_next().await.is_some() {}
+
+        let goodbye_session = session.clone();
+        let _ = tokio::task::spawn_blocking(move || {
+            DiscoveryService::broadcast_goodbye(&goodbye_session.context)
+        }).await;
+        Ok(())
+    }
+
+    /// Send heartbeats and evict stale peers until shut down.
+    async fn liveness_loop(session: Arc<AsyncDiscoverySession>) {
+        let stopped = session.context.shutdown().wait();
+        tokio::pin!(stopped);
+        let mut heartbeat = tokio::time::interval(
+            session.context.heartbeat_interval().max(Duration::from_millis(MIN_HEARTBEAT_MILLIS)));
+        // The first tick is immediate, and we have just announced ourselves.
+        heartbeat.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = &mut stopped => break,
+                _ = heartbeat.tick() => (),
+            }
+            let heartbeat_session = session.clone();
+            let _ = tokio::task::spawn_blocking(move || {
+                let _ = DiscoveryService::broadcast_heartbeat(&heartbeat_session.context);
+                DiscoveryService::evict_expired_peers(&heartbeat_session.peer_set_ptr, &heartbeat_session.context);
+            }).await;
+        }
+    }
+
+    async fn receive_loop((server_socket, responder): (UdpSocket, Arc<StdUdpSocket>), session: Arc<AsyncDiscoverySession>) {
+        let stopped = session.context.shutdown().wait();
+        tokio::pin!(stopped);
+        let mut buf = vec![0u8; MAX_DISCOVERY_DATAGRAM_SIZE];
+        let mut pairing = LegacyDiscoveryPairing::new();
+
+        loop {
+            let received = tokio::select! {
+                _ = &mut stopped => break,
+                r = server_socket.recv_from(&mut buf) => r,
+            };
+            let (n, source) = match received {
+                Ok(x) => x,
+                Err(e) => {
+                    error!("Failed to receive packet ({})", e);
+                    continue;
+                }
+            };
+            let packet = match open_discovery_datagram(&buf[..n], &source, &mut pairing, &session.verifier) {
+                Some(x) => x,
+                None => continue,
+            };
+
+            let peer_session = session.clone();
+            let peer_responder = responder.clone();
+            let _ = tokio::task::spawn_blocking(move || {
+                if let Ok(local_addresses) = scan_local_addresses() {
+                    let _ = DiscoveryService::handle_new_peer(
+                        local_addresses,
+                        &peer_responder,
+                        peer_session.peer_set_ptr.clone(),
+                        packet,
+                        source,
+                        &peer_session.context,
+                    );
+                }
+            }).await;
+        }
+        info!("Async discovery service is shut down.");
+    }
+}
+
+/// Hand `socket` to the runtime, keeping a blocking handle for sending responses.
+fn into_async(socket: StdUdpSocket) -> Result<(UdpSocket, Arc<StdUdpSocket>), io::Error> {
+    let responder = Arc::new(socket.try_clone()?);
+    socket.set_nonblocking(true)?;
+    Ok((UdpSocket::from_std(socket)?, responder))
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use crate::service::context::data_service_context::ConnectionLimits;
+
+#[derive(Default)]
+struct LimiterState {
+    total: usize,
+    per_peer: HashMap<IpAddr, usize>,
+}
+
+/// Counts open connections, in total and per source address.
+pub struct ConnectionLimiter {
+    max_connections: usize,
+    max_connections_per_peer: usize,
+    state: Arc<Mutex<LimiterState>>,
+}
+
+/// One admitted connection, released when dropped.
+pub struct ConnectionPermit {
+    ip: IpAddr,
+    state: Arc<Mutex<LimiterState>>,
+}
+
+fn lock(state: &Mutex<LimiterState>) -> MutexGuard<'_, LimiterState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl ConnectionLimiter {
+    pub fn new(limits: &ConnectionLimits) -> Self {
+        Self {
+            max_connections: limits.max_connections,
+            max_connections_per_peer: limits.max_connections_per_peer,
+            state: Arc::new(Mutex::new(LimiterState::default())),
+        }
+    }
+
+    /// Admit a connection from `ip`, or None if a limit is reached.
+    /// IPv4-mapped addresses count as their IPv4 address.
+    pub fn try_acquire(&self, ip: IpAddr) -> Option<ConnectionPermit> {
+        let ip = ip.to_canonical();
+        let mut state = lock(&self.state);
+        let from_peer = state.per_peer.get(&ip).copied().unwrap_or(0);
+        if (self.max_connections > 0 && state.total >= self.max_connections)
+            || (self.max_connections_per_peer > 0 && from_peer >= self.max_connections_per_peer) {
+            return None;
+        }
+        state.total += 1;
+        state.per_peer.insert(ip, from_peer + 1);
+        Some(ConnectionPermit { ip, state: self.state.clone() })
+    }
+
+    pub fn active(&self) -> usize {
+        lock(&self.state).total
+    }
+
+    pub fn active_from(&self, ip: &IpAddr) -> usize {
+        lock(&self.state).per_peer.get(&ip.to_canonical()).copied().unwrap_or(0)
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut state = lock(&self.state);
+        state.total -= 1;
+        if let Some(count) = state.per_peer.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                state.per_peer.remove(&self.ip);
+            }
+        }
+    }
+}
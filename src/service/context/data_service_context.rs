@@ -1,10 +1,76 @@
 use std::sync::Arc;
+use std::time::Duration;
 use crate::packet::data::file_coming_packet::FileComingPacket;
 use crate::packet::data::file_part_packet::FilePartPacket;
 use crate::packet::data::local::file_sending_packet::FileSendingPacket;
 use crate::packet::data::text_packet::TextPacket;
+use crate::packet::frame_limits::FrameLimits;
 use crate::service::data_service::OnPacketReceivedFunctionType;
 use crate::service::discovery_service::DiscoveryService;
+use crate::service::metrics::ServiceMetrics;
+use crate::security::access_policy::{AccessControl, OnConnectionRejectedFunctionType};
+use crate::security::group_key::GroupKey;
+use crate::security::identity::DeviceIdentity;
+use crate::security::trust_store::{TrustStore, UntrustedPolicy};
+use crate::service::quarantine::Quarantine;
+use crate::service::transfer_manager::TransferManager;
+use crate::service::shutdown::ShutdownHandle;
+
+pub const DEFAULT_WORKER_POOL_SIZE: usize = 8;
+pub const DEFAULT_MAX_CONNECTIONS: usize = 32;
+/// Below the pool size, so that one peer can't take every worker.
+pub const DEFAULT_MAX_CONNECTIONS_PER_PEER: usize = 4;
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BacklogPolicy {
+    /// Close new connections while every worker is busy.
+    Reject,
+    /// Let new connections wait for a worker, up to the connection limit.
+    Queue,
+}
+
+impl BacklogPolicy {
+    pub fn value(&self) -> u32 {
+        match self {
+            BacklogPolicy::Reject => 0,
+            BacklogPolicy::Queue => 1,
+        }
+    }
+
+    pub fn from(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(BacklogPolicy::Reject),
+            1 => Some(BacklogPolicy::Queue),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ConnectionLimits {
+    /// Threads serving connections, at least one.
+    pub worker_pool_size: usize,
+    /// Connections being served or waiting at once, 0 for no limit.
+    pub max_connections: usize,
+    /// Connections from one address being served or waiting at once, 0 for no limit.
+    pub max_connections_per_peer: usize,
+    pub backlog_policy: BacklogPolicy,
+    /// How long a session may wait for the peer's next frame before it is closed, zero for ever.
+    pub idle_timeout: Duration,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        Self {
+            worker_pool_size: DEFAULT_WORKER_POOL_SIZE,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            max_connections_per_peer: DEFAULT_MAX_CONNECTIONS_PER_PEER,
+            backlog_policy: BacklogPolicy::Queue,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+        }
+    }
+}
 
 pub struct DataServiceContext {
     host: String,
@@ -14,9 +80,23 @@ pub struct DataServiceContext {
     file_sending_callback: OnPacketReceivedFunctionType<FileSendingPacket, ()>,
     file_part_callback: OnPacketReceivedFunctionType<FilePartPacket, bool>,
     discovery_service: Arc<DiscoveryService>,
+    metrics: Arc<ServiceMetrics>,
+    group_key: Option<GroupKey>,
+    identity: DeviceIdentity,
+    trust_store: Arc<TrustStore>,
+    untrusted_policy: UntrustedPolicy,
+    quarantine: Arc<Quarantine>,
+    transfer_manager: Arc<TransferManager>,
+    access_control: Arc<AccessControl>,
+    connection_rejected_callback: Option<OnConnectionRejectedFunctionType>,
+    shutdown: ShutdownHandle,
+    connection_limits: ConnectionLimits,
+    frame_limits: FrameLimits,
+    oversized_frame_penalty: Option<Duration>,
 }
 
 impl DataServiceContext {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         host: String,
         port: u16,
@@ -25,7 +105,11 @@ impl DataServiceContext {
         file_sending_callback: OnPacketReceivedFunctionType<FileSendingPacket, ()>,
         file_part_callback: OnPacketReceivedFunctionType<FilePartPacket, bool>,
         discovery_service: Arc<DiscoveryService>,
+        metrics: Arc<ServiceMetrics>,
+        group_key: Option<GroupKey>,
     ) -> Self {
+        let transfer_manager = Arc::new(TransferManager::new());
+        transfer_manager.set_listener(file_sending_callback.clone());
         Self {
             host,
             port,
@@ -34,6 +118,19 @@ impl DataServiceContext {
             file_sending_callback,
             file_part_callback,
             discovery_service,
+            metrics,
+            group_key,
+            identity: DeviceIdentity::generate(),
+            trust_store: Arc::new(TrustStore::new()),
+            untrusted_policy: UntrustedPolicy::Allow,
+            quarantine: Arc::new(Quarantine::new()),
+            transfer_manager,
+            access_control: Arc::new(AccessControl::default()),
+            connection_rejected_callback: None,
+            shutdown: ShutdownHandle::new(),
+            connection_limits: ConnectionLimits::default(),
+            frame_limits: FrameLimits::default(),
+            oversized_frame_penalty: None,
         }
     }
 
@@ -64,6 +161,101 @@ impl DataServiceContext {
     pub fn discovery_service(&self) -> Arc<DiscoveryService> {
         self.discovery_service.clone()
     }
+
+    pub fn metrics(&self) -> Arc<ServiceMetrics> {
+        self.metrics.clone()
+    }
+
+    pub fn group_key(&self) -> Option<&GroupKey> {
+        self.group_key.as_ref()
+    }
+
+    /// Key used to prove who we are to identifying peers.
+    pub fn set_identity(&mut self, identity: DeviceIdentity) {
+        self.identity = identity;
+    }
+
+    pub fn identity(&self) -> &DeviceIdentity {
+        &self.identity
+    }
+
+    /// Paired devices, and what happens to offers from everyone else.
+    pub fn set_trust(&mut self, trust_store: Arc<TrustStore>, untrusted_policy: UntrustedPolicy, quarantine: Arc<Quarantine>) {
+        self.trust_store = trust_store;
+        self.untrusted_policy = untrusted_policy;
+        self.quarantine = quarantine;
+    }
+
+    pub fn trust_store(&self) -> Arc<TrustStore> {
+        self.trust_store.clone()
+    }
+
+    pub fn untrusted_policy(&self) -> UntrustedPolicy {
+        self.untrusted_policy
+    }
+
+    pub fn quarantine(&self) -> Arc<Quarantine> {
+        self.quarantine.clone()
+    }
+
+    /// Share the sender's transfers, their states are reported to the file sending callback.
+    pub fn set_transfer_manager(&mut self, transfer_manager: Arc<TransferManager>) {
+        transfer_manager.set_listener(self.file_sending_callback.clone());
+        self.transfer_manager = transfer_manager;
+    }
+
+    pub fn transfer_manager(&self) -> Arc<TransferManager> {
+        self.transfer_manager.clone()
+    }
+
+    /// Which peers may connect, and who to tell about the ones that may not.
+    pub fn set_access_control(
+        &mut self,
+        access_control: Arc<AccessControl>,
+        connection_rejected_callback: Option<OnConnectionRejectedFunctionType>,
+    ) {
+        self.access_control = access_control;
+        self.connection_rejected_callback = connection_rejected_callback;
+    }
+
+    pub fn access_control(&self) -> Arc<AccessControl> {
+        self.access_control.clone()
+    }
+
+    pub fn connection_rejected_callback(&self) -> Option<OnConnectionRejectedFunctionType> {
+        self.connection_rejected_callback.clone()
+    }
+
+    /// Stops the service, its sessions and file transfers when triggered.
+    pub fn set_shutdown(&mut self, shutdown: ShutdownHandle) {
+        self.shutdown = shutdown;
+    }
+
+    pub fn shutdown(&self) -> &ShutdownHandle {
+        &self.shutdown
+    }
+
+    pub fn set_connection_limits(&mut self, connection_limits: ConnectionLimits) {
+        self.connection_limits = connection_limits;
+    }
+
+    pub fn connection_limits(&self) -> ConnectionLimits {
+        self.connection_limits
+    }
+
+    /// Largest frames accepted, and how long to deny peers that send larger ones, if at all.
+    pub fn set_frame_limits(&mut self, frame_limits: FrameLimits, oversized_frame_penalty: Option<Duration>) {
+        self.frame_limits = frame_limits;
+        self.oversized_frame_penalty = oversized_frame_penalty;
+    }
+
+    pub fn frame_limits(&self) -> FrameLimits {
+        self.frame_limits
+    }
+
+    pub fn oversized_frame_penalty(&self) -> Option<Duration> {
+        self.oversized_frame_penalty
+    }
 }
 
 impl Clone for DataServiceContext {
@@ -76,6 +268,19 @@ impl Clone for DataServiceContext {
             file_sending_callback: self.file_sending_callback.clone(),
             file_part_callback: self.file_part_callback.clone(),
             discovery_service: self.discovery_service.clone(),
+            metrics: self.metrics.clone(),
+            group_key: self.group_key.clone(),
+            identity: self.identity.clone(),
+            trust_store: self.trust_store.clone(),
+            untrusted_policy: self.untrusted_policy,
+            quarantine: self.quarantine.clone(),
+            transfer_manager: self.transfer_manager.clone(),
+            access_control: self.access_control.clone(),
+            connection_rejected_callback: self.connection_rejected_callback.clone(),
+            shutdown: self.shutdown.clone(),
+            connection_limits: self.connection_limits,
+            frame_limits: self.frame_limits,
+            oversized_frame_penalty: self.oversized_frame_penalty,
         }
     }
 }
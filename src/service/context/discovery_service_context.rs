@@ -0,0 +1,234 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use crate::network::device_info::{DeviceInfo, DeviceType};
+use crate::security::group_key::GroupKey;
+use crate::security::identity::IdentityKey;
+use crate::service::discovery_service::DISCOVERY_MULTICAST_V6;
+use crate::service::mdns_service::MdnsConfig;
+use crate::service::peer_event::OnPeerEventFunctionType;
+use crate::service::shutdown::ShutdownHandle;
+
+/// Default administratively-scoped IPv4 group ("AX" = 65.88).
+pub const DEFAULT_MULTICAST_GROUP_V4: Ipv4Addr = Ipv4Addr::new(239, 255, 65, 88);
+
+/// Default site-local IPv6 group.
+pub const DEFAULT_MULTICAST_GROUP_V6: Ipv6Addr = Ipv6Addr::new(0xff05, 0, 0, 0, 0, 0, 0, 0x4158);
+
+pub const DEFAULT_MULTICAST_TTL: u32 = 4;
+
+pub const DEFAULT_HEARTBEAT_INTERVAL_MILLIS: u64 = 10_000;
+
+/// Peers not heard from for this long are dropped, a few missed heartbeats.
+pub const DEFAULT_PEER_TTL_MILLIS: u64 = 35_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DiscoveryMode {
+    /// IPv4 subnet broadcast and IPv6 link-local multicast.
+    Broadcast,
+    /// Configured IPv4 and IPv6 multicast groups, which can cross routers.
+    Multicast,
+    /// Both of the above, to reach peers that have not migrated yet.
+    Hybrid,
+}
+
+impl DiscoveryMode {
+    pub fn value(&self) -> u32 {
+        match self {
+            DiscoveryMode::Broadcast => 0,
+            DiscoveryMode::Multicast => 1,
+            DiscoveryMode::Hybrid => 2,
+        }
+    }
+
+    pub fn from(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(DiscoveryMode::Broadcast),
+            1 => Some(DiscoveryMode::Multicast),
+            2 => Some(DiscoveryMode::Hybrid),
+            _ => None,
+        }
+    }
+
+    pub fn uses_broadcast(&self) -> bool {
+        *self != DiscoveryMode::Multicast
+    }
+
+    pub fn uses_multicast(&self) -> bool {
+        *self != DiscoveryMode::Broadcast
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MulticastConfig {
+    pub group_v4: Ipv4Addr,
+    pub group_v6: Ipv6Addr,
+    /// IPv4 TTL and IPv6 hop limit of outgoing discovery packets.
+    pub ttl: u32,
+}
+
+impl Default for MulticastConfig {
+    fn default() -> Self {
+        Self {
+            group_v4: DEFAULT_MULTICAST_GROUP_V4,
+            group_v6: DEFAULT_MULTICAST_GROUP_V6,
+            ttl: DEFAULT_MULTICAST_TTL,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DiscoveryServiceContext {
+    client_port: u16,
+    server_port: u16,
+    group_identifier: u32,
+    group_key: Option<GroupKey>,
+    accept_unsigned_discovery: bool,
+    mode: DiscoveryMode,
+    multicast: MulticastConfig,
+    mdns: Option<MdnsConfig>,
+    heartbeat_interval: Duration,
+    peer_ttl: Duration,
+    peer_event_callback: Option<OnPeerEventFunctionType>,
+    device_id: Option<String>,
+    identity_key: Option<IdentityKey>,
+    device_info: DeviceInfo,
+    shutdown: ShutdownHandle,
+}
+
+impl DiscoveryServiceContext {
+    pub fn new(
+        client_port: u16,
+        server_port: u16,
+        group_identifier: u32,
+        group_key: Option<GroupKey>,
+        accept_unsigned_discovery: bool,
+        mode: DiscoveryMode,
+        multicast: MulticastConfig,
+    ) -> Self {
+        let device_info = DeviceInfo::local(None, DeviceType::Unknown, group_key.is_some());
+        Self {
+            client_port,
+            server_port,
+            group_identifier,
+            group_key,
+            accept_unsigned_discovery,
+            mode,
+            multicast,
+            mdns: None,
+            heartbeat_interval: Duration::from_millis(DEFAULT_HEARTBEAT_INTERVAL_MILLIS),
+            peer_ttl: Duration::from_millis(DEFAULT_PEER_TTL_MILLIS),
+            peer_event_callback: None,
+            device_id: None,
+            identity_key: None,
+            device_info,
+            shutdown: ShutdownHandle::new(),
+        }
+    }
+
+    pub fn client_port(&self) -> u16 {
+        self.client_port
+    }
+
+    pub fn server_port(&self) -> u16 {
+        self.server_port
+    }
+
+    pub fn group_identifier(&self) -> u32 {
+        self.group_identifier
+    }
+
+    pub fn group_key(&self) -> Option<&GroupKey> {
+        self.group_key.as_ref()
+    }
+
+    pub fn accept_unsigned_discovery(&self) -> bool {
+        self.accept_unsigned_discovery
+    }
+
+    pub fn mode(&self) -> DiscoveryMode {
+        self.mode
+    }
+
+    pub fn multicast(&self) -> &MulticastConfig {
+        &self.multicast
+    }
+
+    /// Also advertise and browse `_airx._tcp.local` when set.
+    pub fn set_mdns(&mut self, mdns: Option<MdnsConfig>) {
+        self.mdns = mdns;
+    }
+
+    pub fn mdns(&self) -> Option<&MdnsConfig> {
+        self.mdns.as_ref()
+    }
+
+    pub fn set_liveness(&mut self, heartbeat_interval: Duration, peer_ttl: Duration) {
+        self.heartbeat_interval = heartbeat_interval;
+        self.peer_ttl = peer_ttl;
+    }
+
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.heartbeat_interval
+    }
+
+    pub fn peer_ttl(&self) -> Duration {
+        self.peer_ttl
+    }
+
+    /// Called whenever a peer is added, removed or updated.
+    pub fn set_peer_event_callback(&mut self, callback: Option<OnPeerEventFunctionType>) {
+        self.peer_event_callback = callback;
+    }
+
+    pub fn peer_event_callback(&self) -> Option<OnPeerEventFunctionType> {
+        self.peer_event_callback.clone()
+    }
+
+    /// Our persistent device ID, advertised so peers recognize us on any address.
+    pub fn set_device_id(&mut self, device_id: Option<String>) {
+        self.device_id = device_id;
+    }
+
+    pub fn device_id(&self) -> Option<&String> {
+        self.device_id.as_ref()
+    }
+
+    /// Our public identity key, advertised so peers can pair with us.
+    pub fn set_identity_key(&mut self, identity_key: Option<IdentityKey>) {
+        self.identity_key = identity_key;
+    }
+
+    pub fn identity_key(&self) -> Option<&IdentityKey> {
+        self.identity_key.as_ref()
+    }
+
+    /// What we announce about ourselves.
+    pub fn set_device_info(&mut self, device_info: DeviceInfo) {
+        self.device_info = device_info;
+    }
+
+    pub fn device_info(&self) -> &DeviceInfo {
+        &self.device_info
+    }
+
+    /// Stops the service when triggered, without waiting for the next read timeout.
+    pub fn set_shutdown(&mut self, shutdown: ShutdownHandle) {
+        self.shutdown = shutdown;
+    }
+
+    pub fn shutdown(&self) -> &ShutdownHandle {
+        &self.shutdown
+    }
+
+    /// IPv6 groups to send to and listen on in the current mode.
+    pub fn ipv6_groups(&self) -> Vec<Ipv6Addr> {
+        let mut groups = Vec::new();
+        if self.mode.uses_broadcast() {
+            groups.push(DISCOVERY_MULTICAST_V6);
+        }
+        if self.mode.uses_multicast() && !groups.contains(&self.multicast.group_v6) {
+            groups.push(self.multicast.group_v6);
+        }
+        groups
+    }
+}
@@ -1 +1,2 @@
-pub mod data_service_context;
\ No newline at end of file
+pub mod data_service_context;
+pub mod discovery_service_context;
\ No newline at end of file
@@ -1,26 +1,40 @@
+use crate::error::AirXError;
 use crate::network::peer::Peer;
 use crate::network::tcp_server::TcpServer;
 use crate::packet::data_transmission::DataTransmit;
 use std::io;
 use std::io::ErrorKind::{TimedOut, WouldBlock};
-use std::net::{SocketAddr, TcpStream};
-use std::sync::Arc;
-use std::thread::sleep;
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 use log::{info, trace, warn};
 use crate::packet::data::magic_numbers::MagicNumbers;
+use crate::packet::data::transfer_id::TransferIdFormat;
 use crate::packet::data_packet::DataPacket;
+use crate::packet::frame_limits::FrameError;
+use crate::packet::hello_packet::{HandshakeError, HelloPacket};
+use crate::packet::protocol::features::ProtocolFeatures;
 use crate::packet::protocol::serialize::Serialize;
-use crate::service::context::data_service_context::DataServiceContext;
+use crate::service::connection_limiter::ConnectionLimiter;
+use crate::service::context::data_service_context::{BacklogPolicy, DataServiceContext};
 use crate::service::handler::{file_coming_packet_handler, file_part_packet_handler, file_receive_response_packet_handler, text_packet_handler, file_part_response_packet_handler};
 use crate::service::handler::context::{HandlerContext, ConnectionControl};
+use crate::service::quarantine::QuarantinedPacket;
+use crate::service::peer_session::{Frame, PeerSession};
 use crate::service::ShouldInterruptFunctionType;
+use crate::service::shutdown::ShutdownHookGuard;
+use crate::service::worker_pool::{Job, WorkerPool};
+use crate::security::access_policy::AccessAction;
+use crate::security::group_key::GroupKey;
+use crate::security::identity::{DeviceIdentity, IdentityKey};
 
 pub type OnPacketReceivedFunctionType<T, R> = Arc<Box<dyn (Fn(&T, Option<&Peer>) -> R) + Send + Sync>>;
 
 
 const TCP_ACCEPT_WAIT_MILLIS: u64 = 10;
 const TCP_ACCEPT_TIMEOUT_COUNT: u64 = 100;
+const FILE_CANCEL_GRACE: Duration = Duration::from_secs(2);
 
 pub struct DataService {}
 
@@ -33,16 +47,29 @@ impl DataService {
         peer: &Peer,
         port: u16,
         magic_number: MagicNumbers,
-        data: &Vec<u8>,
+        data: &[u8],
         connect_timeout: Duration,
+        group_key: Option<&GroupKey>,
+        identity: Option<&DeviceIdentity>,
     ) -> Result<(), io::Error> {
-        let stream = connect(peer, port, connect_timeout)?;
-        info!("Connection established with {}.", peer.to_string());
+        Self::send_packet_with_retry(peer, port, magic_number, &|_| data.to_vec(), connect_timeout, group_key, identity)
+    }
 
-        let mut dt = DataTransmit::from(stream);
+    /// Send a packet carrying a transfer id, serialized as the connection writes transfer ids.
+    pub fn send_packet_with_retry(
+        peer: &Peer,
+        port: u16,
+        magic_number: MagicNumbers,
+        serialize: &dyn Fn(TransferIdFormat) -> Vec<u8>,
+        connect_timeout: Duration,
+        group_key: Option<&GroupKey>,
+        identity: Option<&DeviceIdentity>,
+    ) -> Result<(), io::Error> {
+        let mut dt = open_transmit(peer, port, connect_timeout, group_key, identity)?;
+        info!("Connection established with {}.", peer.to_string());
 
         // Wrap with data packet.
-        let data_packet = DataPacket::new(magic_number.value(), data);
+        let data_packet = DataPacket::new(magic_number.value(), &serialize(dt.transfer_id_format()));
         let result = dt.send_data_progress_with_retry(&data_packet.serialize(), |_| ());
         let _ = dt.close();
 
@@ -52,6 +79,7 @@ impl DataService {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn data_session<State, F>(
         peer: &Peer,
         port: u16,
@@ -59,19 +87,24 @@ impl DataService {
         session: &mut F,
         reconnect_try_count: u32,
         mut state: State,
+        group_key: Option<&GroupKey>,
+        identity: Option<&DeviceIdentity>,
     ) -> Result<(), io::Error> where F: FnMut(&mut DataTransmit, &mut State) -> Result<(), io::Error> {
         let mut tries = 0;
         while tries < reconnect_try_count {
-            let stream = connect(peer, port, connect_timeout)?;
+            let mut dt = open_transmit(peer, port, connect_timeout, group_key, identity)?;
             info!("Data session established with {}.", peer.to_string());
 
-            let mut dt = DataTransmit::from(stream);
-
             match session(&mut dt, &mut state) {
                 Ok(_) => {
                     let _ = dt.close();
                     return Ok(());
                 }
+                // Cancelled on purpose, retrying would undo that.
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    let _ = dt.close();
+                    return Err(e);
+                }
                 Err(e) => {
                     warn!("Data session error: {:?}. Retrying...", e);
                     tries += 1;
@@ -81,14 +114,19 @@ impl DataService {
         Err(io::Error::new(io::ErrorKind::Other, "Failed to establish data session."))
     }
 
-    fn dispatch_data_packet(
-        tt: &mut DataTransmit,
+    pub(crate) fn dispatch_data_packet(
+        peer_identity: Option<IdentityKey>,
         packet: &DataPacket,
         socket_addr: SocketAddr,
+        transfer_id_format: TransferIdFormat,
+        features: ProtocolFeatures,
         data_service_context: &DataServiceContext,
     ) -> ConnectionControl {
-        let context = HandlerContext::new(tt, packet, socket_addr, data_service_context);
-        match MagicNumbers::from(packet.magic_number()) {
+        Self::dispatch(HandlerContext::new(peer_identity, packet, socket_addr, transfer_id_format, features, data_service_context))
+    }
+
+    fn dispatch(context: HandlerContext) -> ConnectionControl {
+        match MagicNumbers::from(context.packet().magic_number()) {
             Some(MagicNumbers::Text) => text_packet_handler::handle(context),
             Some(MagicNumbers::FileComing) => file_coming_packet_handler::handle(context),
             Some(MagicNumbers::FileReceiveResponse) => file_receive_response_packet_handler::handle(context),
@@ -109,55 +147,257 @@ impl DataService {
                 return;
             }
         };
+
+        if !Self::admit_connection(&socket_addr, &context) {
+            let _ = stream.shutdown(Shutdown::Both);
+            return;
+        }
+
+        let receiving_file = Arc::new(AtomicBool::new(false));
+        let _shutdown_watch = Self::watch_session(&stream, &context, receiving_file.clone());
         let mut tt = DataTransmit::from(stream);
+        tt.set_frame_limits(context.frame_limits());
+
+        let negotiation = Self::wait_for_peer(&mut tt, &context)
+            .and_then(|_| tt.negotiate_as_responder(&Self::local_hello(&context)));
+        if let Err(e) = negotiation {
+            PeerSession::refuse(&e, &socket_addr, &context);
+            let _ = tt.close();
+            return;
+        }
 
+        if let Some(group_key) = context.group_key() {
+            if let Err(e) = Self::wait_for_peer(&mut tt, &context).and_then(|_| tt.handshake_as_responder(group_key)) {
+                PeerSession::refuse(&e, &socket_addr, &context);
+                let _ = tt.close();
+                return;
+            }
+        }
+
+        let mut session = PeerSession::new(socket_addr, tt.negotiated());
         loop {
+            if context.shutdown().is_shutdown() {
+                break;
+            }
+            if let Err(e) = Self::wait_for_peer(&mut tt, &context) {
+                info!("Closing connection from {} ({}).", socket_addr, e);
+                break;
+            }
+
             let raw_data = match tt.read_data_progress_with_retry(|portion| {
                 trace!("Received data {:.2}% from {}.", portion * 100.0, socket_addr);
             }) {
                 Ok(s) => s,
-                Err(_) => break,
-            };
-
-            let data_packet = match DataPacket::deserialize(&raw_data) {
-                Ok(p) => p,
                 Err(e) => {
-                    warn!("Failed to deserialize data ({:?}).", e);
+                    Self::check_oversized_frame(&e, &socket_addr, &context);
                     break;
                 }
             };
 
-            trace!("Received data packet from {}, magic_nubmer={}.", socket_addr, data_packet.magic_number());
-            match Self::dispatch_data_packet(&mut tt, &data_packet, socket_addr, &context) {
+            let data_packet = match session.read_frame(&raw_data, tt.peer_identity().is_some(), &context) {
+                Some(Frame::IdentityHello) => {
+                    if let Err(e) = tt.identify_as_responder(context.identity(), &raw_data) {
+                        warn!("Identity exchange with {} failed ({}).", socket_addr, e);
+                        break;
+                    }
+                    continue;
+                }
+                Some(Frame::Data(p)) => p,
+                None => break,
+            };
+
+            if session.is_receiving_file() {
+                receiving_file.store(true, Ordering::SeqCst);
+            }
+            match session.dispatch(tt.peer_identity().copied(), &data_packet, &context) {
                 ConnectionControl::CloseConnection => break,
                 ConnectionControl::Default => (),
             }
         }
 
+        if let Some(frame) = session.stop_receiving_frame(&context) {
+            if tt.send_data_progress_with_retry(&frame, |_| ()).is_ok() {
+                tt.drain(FILE_CANCEL_GRACE);
+            }
+        }
+        let _ = tt.close();
+
         info!("Session with {} is ended.", socket_addr);
     }
 
-    pub fn run(context: DataServiceContext, should_interrupt: ShouldInterruptFunctionType) -> Result<(), io::Error> {
+    /// Wait for the peer's next frame, failing once it was idle for the idle timeout.
+    /// Idle peers would hold a worker for as long as they like otherwise.
+    fn wait_for_peer(tt: &mut DataTransmit, context: &DataServiceContext) -> Result<(), io::Error> {
+        let idle_timeout = context.connection_limits().idle_timeout;
+        if idle_timeout.is_zero() || tt.wait_for_data(idle_timeout)? {
+            return Ok(());
+        }
+        Err(io::Error::new(TimedOut, "Peer was idle."))
+    }
+
+    /// Hello answered to connecting peers.
+    pub(crate) fn local_hello(context: &DataServiceContext) -> HelloPacket {
+        HelloPacket::new(
+            ProtocolFeatures::local(context.group_key().is_some()),
+            Some(context.identity().public_key()),
+        )
+    }
+
+    /// Wake the session on shutdown. Mid-file, the sender first gets a moment to reach
+    /// its next part so that it can be told to stop. That wait is joined on shutdown,
+    /// and cut short by dropping the returned sender when the session ends.
+    fn watch_session(
+        stream: &TcpStream,
+        context: &DataServiceContext,
+        receiving_file: Arc<AtomicBool>,
+    ) -> Option<(ShutdownHookGuard, mpsc::Sender<()>)> {
+        let stream = stream.try_clone().ok()?;
+        let (session_alive, session_ended) = mpsc::channel::<()>();
+        let session_ended = Mutex::new(Some(session_ended));
+        let shutdown = context.shutdown().clone();
+        let guard = context.shutdown().on_shutdown(Box::new(move || {
+            if !receiving_file.load(Ordering::SeqCst) {
+                let _ = stream.shutdown(Shutdown::Read);
+                return;
+            }
+            let session_ended = session_ended.lock().ok().and_then(|mut r| r.take());
+            if let (Ok(stream), Some(session_ended)) = (stream.try_clone(), session_ended) {
+                shutdown.spawn(move || {
+                    let _ = session_ended.recv_timeout(FILE_CANCEL_GRACE);
+                    let _ = stream.shutdown(Shutdown::Read);
+                });
+            }
+        }));
+        Some((guard, session_alive))
+    }
+
+    /// Check the source against the access policy before reading anything from it.
+    /// Device IDs are known from discovery and believed only from authenticated announcements,
+    /// so other peers only match address rules.
+    pub(crate) fn admit_connection(socket_addr: &SocketAddr, context: &DataServiceContext) -> bool {
+        let peer = context.discovery_service().peer_lookup(socket_addr);
+        let device_id = peer.as_ref().filter(|p| p.authenticated()).and_then(|p| p.device_id());
+        if context.access_control().evaluate(&socket_addr.ip(), device_id) == AccessAction::Allow {
+            return true;
+        }
+
+        warn!("Rejected connection from {} by access policy.", socket_addr);
+        context.metrics().record_rejected_connection();
+        if let Some(callback) = context.connection_rejected_callback() {
+            callback(socket_addr, peer.as_ref());
+        }
+        false
+    }
+
+    /// Count, and penalize if configured, a peer whose read failed on an oversized frame.
+    pub(crate) fn check_oversized_frame(error: &io::Error, socket_addr: &SocketAddr, context: &DataServiceContext) {
+        let frame_error = match FrameError::of(error) {
+            Some(e) => e,
+            None => return,
+        };
+        warn!("Closing connection from {} ({}).", socket_addr, frame_error);
+        context.metrics().record_oversized_frame();
+        if let Some(penalty) = context.oversized_frame_penalty() {
+            context.access_control().penalize(&socket_addr.ip(), penalty);
+        }
+    }
+
+    fn refuse_over_limit(stream: TcpStream, socket_addr: &SocketAddr, context: &DataServiceContext, reason: &str) {
+        Self::count_over_limit(socket_addr, context, reason);
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+
+    pub(crate) fn count_over_limit(socket_addr: &SocketAddr, context: &DataServiceContext, reason: &str) {
+        warn!("Refused connection from {} ({}).", socket_addr, reason);
+        context.metrics().record_limited_connection();
+    }
+
+    /// Fail the offers the receivers never answered.
+    pub(crate) fn expire_offers(context: &DataServiceContext) {
+        for transfer in context.transfer_manager().expire_offers() {
+            info!("Offer of {} to {} expired (transfer_id={}).",
+                transfer.file_path(), transfer.host(), transfer.id());
+        }
+    }
+
+    /// Keep the latest progress of received files for when we start again.
+    /// Deliver packets let out of the quarantine as if they just arrived, until detached.
+    pub(crate) fn attach_quarantine(context: &DataServiceContext) {
+        let replay_context = context.clone();
+        context.quarantine().set_listener(Some(Arc::new(Box::new(move |packet: &QuarantinedPacket| {
+            info!("Delivering packet from {} released from the quarantine.", packet.socket_addr());
+            let data_packet = DataPacket::new(packet.magic_number().value(), packet.data());
+            let _ = Self::dispatch(HandlerContext::new(
+                packet.identity_key().copied(),
+                &data_packet,
+                packet.socket_addr(),
+                packet.transfer_id_format(),
+                ProtocolFeatures::NONE,
+                &replay_context,
+            ).released());
+        }))));
+    }
+
+    /// Released packets stay held while no data service runs.
+    pub(crate) fn detach_quarantine(context: &DataServiceContext) {
+        context.quarantine().set_listener(None);
+    }
+
+    pub(crate) fn flush_journal(context: &DataServiceContext) {
+        if let Err(e) = context.transfer_manager().journal().flush() {
+            warn!("Failed to save the transfer journal ({}).", e);
+        }
+    }
+
+    pub fn run(context: DataServiceContext, should_interrupt: ShouldInterruptFunctionType) -> Result<(), AirXError> {
         let server_socket = TcpServer::create_and_listen(&context.host(), context.port())?;
+        let shutdown = context.shutdown().clone();
+        let _running = shutdown.enter_service();
+        let limits = context.connection_limits();
+        let limiter = ConnectionLimiter::new(&limits);
+        let workers = WorkerPool::new(limits.worker_pool_size, &shutdown);
         let mut timeout_counter = 0;
+        Self::attach_quarantine(&context);
 
         info!("Data service online and ready for connections.");
 
-        for stream in server_socket.incoming() {
-            match stream {
-                Ok(s) => {
+        loop {
+            match server_socket.accept() {
+                Ok((s, socket_addr)) => {
+                    let permit = match limiter.try_acquire(socket_addr.ip()) {
+                        Some(p) => p,
+                        None => {
+                            Self::refuse_over_limit(s, &socket_addr, &context, "connection limit reached");
+                            continue;
+                        }
+                    };
                     let thread_context = context.clone();
-                    std::thread::spawn(move || {
+                    let job: Job = Box::new(move || {
+                        let _permit = permit;
                         Self::handle_peer(s, thread_context);
                     });
+                    match limits.backlog_policy {
+                        BacklogPolicy::Queue => workers.execute(job),
+                        BacklogPolicy::Reject => {
+                            if let Err(job) = workers.try_execute(job) {
+                                Self::count_over_limit(&socket_addr, &context, "every worker is busy");
+                                // Closes the stream and releases the permit, once counted.
+                                drop(job);
+                            }
+                        }
+                    }
                 }
                 Err(ref e) if e.kind() == WouldBlock || e.kind() == TimedOut => {
-                    // Check if interrupted.
-                    sleep(Duration::from_millis(TCP_ACCEPT_WAIT_MILLIS));
+                    // Wakes up at once on shutdown.
+                    if shutdown.sleep(Duration::from_millis(TCP_ACCEPT_WAIT_MILLIS)) {
+                        info!("Data service is shut down.");
+                        break;
+                    }
 
                     // Check if timeout.
                     if timeout_counter > TCP_ACCEPT_TIMEOUT_COUNT {
                         timeout_counter = 0;
+                        Self::expire_offers(&context);
                         if should_interrupt() {
                             info!("Data service is interrupted by caller.");
                             break;
@@ -173,15 +413,83 @@ impl DataService {
             }
         }
 
+        Self::detach_quarantine(&context);
+        Self::flush_journal(&context);
         Ok(())
     }
 }
 
+/// Connect to peer, exchange hellos and run the encryption handshake when a group key is set,
+/// then the identity exchange when an identity is given.
+fn open_transmit(
+    peer: &Peer,
+    port: u16,
+    timeout: Duration,
+    group_key: Option<&GroupKey>,
+    identity: Option<&DeviceIdentity>,
+) -> Result<DataTransmit, io::Error> {
+    if group_key.is_none() && peer.encryption_required() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("Peer {} requires encryption but no group key is set.", peer.to_string()),
+        ));
+    }
+
+    let mut dt = DataTransmit::from(connect(peer, port, timeout)?);
+    let hello = HelloPacket::new(ProtocolFeatures::local(group_key.is_some()), identity.map(|i| i.public_key()));
+    match dt.negotiate_as_initiator(&hello) {
+        Ok(_) => {}
+        // Peers older than the hello hang up on it, they get a legacy session instead.
+        Err(e) if matches!(HandshakeError::of(&e), Some(HandshakeError::NoHello)) => {
+            let _ = dt.close();
+            info!("Peer {} predates the hello, falling back to a legacy session.", peer.to_string());
+            return open_legacy_transmit(peer, port, timeout, group_key);
+        }
+        Err(e) => {
+            let _ = dt.close();
+            return Err(e);
+        }
+    }
+    if let Some(group_key) = group_key {
+        if let Err(e) = dt.handshake_as_initiator(group_key) {
+            let _ = dt.close();
+            return Err(e);
+        }
+    }
+    if let Some(identity) = identity {
+        if let Err(e) = dt.identify_as_initiator(identity) {
+            let _ = dt.close();
+            return Err(e);
+        }
+    }
+    Ok(dt)
+}
+
+/// A new connection without the hello and the identity exchange, older peers know neither.
+/// Transfer ids go in their one byte form on it.
+fn open_legacy_transmit(
+    peer: &Peer,
+    port: u16,
+    timeout: Duration,
+    group_key: Option<&GroupKey>,
+) -> Result<DataTransmit, io::Error> {
+    let mut dt = DataTransmit::from(connect(peer, port, timeout)?);
+    if let Some(group_key) = group_key {
+        if let Err(e) = dt.handshake_as_initiator(group_key) {
+            let _ = dt.close();
+            return Err(e);
+        }
+    }
+    Ok(dt)
+}
+
 fn connect(peer: &Peer, port: u16, timeout: Duration) -> Result<TcpStream, io::Error> {
-    let addr = format!("{}:{}", peer.host(), port);
-    let socket_addr = match addr.parse::<SocketAddr>() {
-        Ok(addr) => addr,
-        Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
+    let socket_addr = match peer.socket_addr(port) {
+        Some(addr) => addr,
+        None => return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid peer address {}.", peer.host()),
+        )),
     };
     TcpStream::connect_timeout(&socket_addr, timeout)
 }
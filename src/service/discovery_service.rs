@@ -1,19 +1,32 @@
+use crate::error::AirXError;
+use crate::network::device_info::{DeviceInfo, DeviceType};
 use crate::network::peer::Peer;
 use crate::service::ShouldInterruptFunctionType;
 use std::collections::HashSet;
 use std::io;
 use std::io::ErrorKind::{TimedOut, WouldBlock};
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, UdpSocket};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use log::{error, info};
-use protobuf::Message;
-use crate::compatibility::unified_endian::UnifiedEndian;
+use std::time::{Duration, Instant};
+use log::{error, info, warn};
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use crate::packet::protocol::features::ProtocolFeatures;
+use crate::packet::discovery_frame::{DiscoveryDatagram, frame_discovery_packet, LegacyDiscoveryPairing, MAX_DISCOVERY_DATAGRAM_SIZE, parse_discovery_datagram};
 use crate::proto::discovery_packet::DiscoveryPacket;
+use crate::util::network::NetworkUtil;
 use crate::util::os::OSUtil;
 use crate::extension::ip_to_u32::ConvertIpU32;
+use crate::security::discovery_auth::{DiscoveryVerifier, seal_discovery_packet};
+use crate::security::identity::IdentityKey;
+use crate::service::context::discovery_service_context::DiscoveryServiceContext;
+use crate::service::mdns_service::MdnsService;
+use crate::service::peer_event::PeerEvent;
 
 const DISCOVERY_TIMEOUT_MILLIS: u64 = 1000;
+const LIVENESS_POLL_MILLIS: u64 = 100;
+
+/// Link-local multicast group used for discovery over IPv6.
+pub const DISCOVERY_MULTICAST_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0x4158);
 
 pub type PeerCollectionType = Arc<Mutex<HashSet<Peer>>>;
 
@@ -30,16 +43,152 @@ impl ToIpV4Addr for IpAddr {
     }
 }
 
-fn scan_local_addresses() -> Result<HashSet<Ipv4Addr>, local_ip_address::Error> {
+pub(crate) fn scan_local_addresses() -> Result<HashSet<IpAddr>, local_ip_address::Error> {
     Ok(local_ip_address::list_afinet_netifas()?
         .iter()
-        .map(|(_, i)| i)
-        .filter(|i| i.is_ipv4() && !i.is_loopback())
-        .map(|i| i.to_ipv4_addr().unwrap())
-        .collect::<HashSet<Ipv4Addr>>()
+        .map(|(_, i)| *i)
+        .filter(|i| !i.is_loopback())
+        .collect::<HashSet<IpAddr>>()
     )
 }
 
+/// Indices of the interfaces that have an IPv6 address.
+fn scan_ipv6_interfaces() -> Result<HashSet<u32>, local_ip_address::Error> {
+    Ok(local_ip_address::list_afinet_netifas()?
+        .iter()
+        .filter(|(_, i)| i.is_ipv6() && !i.is_loopback())
+        .map(|(name, _)| NetworkUtil::interface_index(name))
+        .collect::<HashSet<u32>>())
+}
+
+fn first_ipv6(addresses: &HashSet<IpAddr>) -> Option<Ipv6Addr> {
+    addresses.iter().find_map(|i| match i {
+        IpAddr::V6(ip) => Some(*ip),
+        IpAddr::V4(_) => None,
+    })
+}
+
+/// Send the packet in one framed datagram, see `frame_discovery_packet`.
+fn send_discovery_packet(socket: &UdpSocket, bytes: &[u8], destination: SocketAddr) -> Result<(), io::Error> {
+    socket.send_to(&frame_discovery_packet(bytes), destination)?;
+    Ok(())
+}
+
+/// The discovery packet in `datagram`, framed or the payload of a legacy sender.
+/// Legacy size datagrams are remembered in `pairing` and give `None`.
+pub(crate) fn open_discovery_datagram(
+    datagram: &[u8],
+    source: &SocketAddr,
+    pairing: &mut LegacyDiscoveryPairing,
+    verifier: &DiscoveryVerifier,
+) -> Option<DiscoveryPacket> {
+    let body = match parse_discovery_datagram(datagram) {
+        DiscoveryDatagram::Packet(body) => body,
+        DiscoveryDatagram::LegacySize(size) => {
+            pairing.expect(*source, size);
+            return None;
+        }
+        DiscoveryDatagram::Unframed(body) if pairing.take(source, body) => body,
+        DiscoveryDatagram::Unframed(_) => {
+            warn!("Dropped unframed discovery datagram from {}.", source);
+            return None;
+        }
+        DiscoveryDatagram::UnsupportedVersion(version) => {
+            warn!("Dropped discovery datagram of framing version {} from {}.", version, source);
+            return None;
+        }
+    };
+    match verifier.open(body) {
+        Ok(x) => Some(x),
+        Err(e) => {
+            warn!("Rejected discovery packet from {} ({})", source, e);
+            None
+        }
+    }
+}
+
+/// State shared by the IPv4 and IPv6 receiving loops.
+struct DiscoverySession {
+    context: DiscoveryServiceContext,
+    peer_set_ptr: PeerCollectionType,
+    should_interrupt: ShouldInterruptFunctionType,
+    verifier: DiscoveryVerifier,
+}
+
+fn emit_peer_event(context: &DiscoveryServiceContext, event: PeerEvent) {
+    if let Some(callback) = context.peer_event_callback() {
+        callback(&event);
+    }
+}
+
+/// Insert or refresh `peer`, reporting it if it is new or its details changed.
+pub fn upsert_peer(peers: &PeerCollectionType, peer: Peer, context: &DiscoveryServiceContext) {
+    let mut peer = peer;
+    let event = match peers.lock() {
+        Ok(mut locked) => {
+            if let Some(known) = locked.get(&peer) {
+                peer.merge_addresses(known, context.peer_ttl());
+            }
+            match locked.replace(peer.clone()) {
+                None => Some(PeerEvent::Added(peer)),
+                Some(previous) if previous.host_name() != peer.host_name()
+                    || previous.port() != peer.port()
+                    || previous.encryption_required() != peer.encryption_required()
+                    || previous.identity_key() != peer.identity_key()
+                    || previous.device_info() != peer.device_info() =>
+                    Some(PeerEvent::Updated { previous, current: peer }),
+                Some(_) => None,
+            }
+        }
+        Err(_) => None,
+    };
+    // Outside the lock, so the callback may read the peer set.
+    if let Some(event) = event {
+        emit_peer_event(context, event);
+    }
+}
+
+fn write_device_info(packet: &mut DiscoveryPacket, info: &DeviceInfo) {
+    if let Some(version) = info.protocol_version {
+        packet.set_protocol_version(version);
+    }
+    if let Some(version) = info.min_protocol_version {
+        packet.set_min_protocol_version(version);
+    }
+    if let Some(platform) = &info.platform {
+        packet.set_platform(platform.clone());
+    }
+    packet.set_device_type(info.device_type.value());
+    packet.set_features(info.features.bits());
+    if let Some(name) = &info.display_name {
+        packet.set_display_name(name.clone());
+    }
+    packet.set_data_port_tls(info.data_port_tls);
+}
+
+fn read_device_info(packet: &DiscoveryPacket) -> DeviceInfo {
+    DeviceInfo {
+        protocol_version: packet.protocol_version,
+        min_protocol_version: packet.min_protocol_version,
+        platform: packet.platform.clone(),
+        // Types added later read as unknown.
+        device_type: DeviceType::from(packet.device_type()).unwrap_or_default(),
+        features: ProtocolFeatures::from_bits(packet.features()),
+        display_name: packet.display_name.clone().filter(|name| !name.is_empty()),
+        data_port_tls: packet.data_port_tls(),
+    }
+}
+
+pub fn remove_peer(peers: &PeerCollectionType, peer: &Peer, context: &DiscoveryServiceContext) {
+    let removed = match peers.lock() {
+        Ok(mut locked) => locked.take(peer),
+        Err(_) => None,
+    };
+    if let Some(removed) = removed {
+        emit_peer_event(context, PeerEvent::Removed(removed));
+    }
+}
+
 fn scan_broadcast_addresses() -> Result<HashSet<Ipv4Addr>, local_ip_address::Error> {
     let fallback = Ipv4Addr::new(255, 255, 255, 255);
     Ok(local_ip_address::list_afinet_netifas()?
@@ -74,6 +223,39 @@ impl DiscoveryService {
         }
     }
 
+    /// Join `group` on every local IPv4 interface of a socket from `create_broadcast_socket`.
+    pub fn join_multicast_v4(socket: &UdpSocket, group: &Ipv4Addr) -> Result<(), io::Error> {
+        let local_addresses = scan_local_addresses()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        for interface in local_addresses.iter().filter_map(|i| i.to_ipv4_addr()) {
+            if let Err(e) = socket.join_multicast_v4(group, &interface) {
+                warn!("Failed to join discovery group {} on {} ({})", group, interface, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// IPv6-only UDP socket, so it can share the port with the IPv4 socket.
+    /// Joins each of `groups` on every IPv6 interface.
+    pub fn create_multicast_socket_v6(port: u16, groups: &[Ipv6Addr]) -> Result<UdpSocket, io::Error> {
+        let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_only_v6(true)?;
+        socket.bind(&SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), port).into())?;
+
+        let socket: UdpSocket = socket.into();
+        socket.set_read_timeout(Some(Duration::from_millis(DISCOVERY_TIMEOUT_MILLIS)))?;
+
+        let interfaces = scan_ipv6_interfaces().unwrap_or_default();
+        for group in groups {
+            for interface in &interfaces {
+                if let Err(e) = socket.join_multicast_v6(group, *interface) {
+                    warn!("Failed to join discovery group {} on interface {} ({})", group, interface, e);
+                }
+            }
+        }
+        Ok(socket)
+    }
+
     pub fn peers(&self) -> PeerCollectionType {
         self.peer_set_ptr.clone()
     }
@@ -81,7 +263,7 @@ impl DiscoveryService {
     pub fn peer_lookup(&self, socker_address: &SocketAddr) -> Option<Peer> {
         if let Ok(locked) = self.peer_set_ptr.lock() {
             for peer in locked.iter() {
-                if *peer.host() == socker_address.ip().to_string() {
+                if peer.has_ip(&socker_address.ip()) {
                     return Some(peer.clone());
                 }
             }
@@ -92,15 +274,29 @@ impl DiscoveryService {
     // Suppress: `std::` can't be omitted but IDEA thinks it can.
     #[allow(unused_qualifications)]
     pub fn handle_new_peer(
-        local_addresses: HashSet<Ipv4Addr>,
+        local_addresses: HashSet<IpAddr>,
         server_socket: &UdpSocket,
         peers: PeerCollectionType,
         packet: DiscoveryPacket,
-        group_identifier: u32,
+        source: SocketAddr,
+        context: &DiscoveryServiceContext,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let sender_address = packet.address();
-        let sender_address_ipv4 = Ipv4Addr::from(sender_address);
-        if local_addresses.contains(&sender_address_ipv4) {
+        let group_identifier = context.group_identifier();
+        let group_key = context.group_key();
+        // The source address, not the one the sender reports, which may be spoofed or stale.
+        // Over IPv6 it also carries the scope id of the link.
+        let sender_address = match source {
+            SocketAddr::V4(addr) => SocketAddr::new(IpAddr::V4(*addr.ip()), packet.server_port() as u16),
+            SocketAddr::V6(addr) => SocketAddr::V6(SocketAddrV6::new(
+                *addr.ip(), packet.server_port() as u16, 0, addr.scope_id())),
+        };
+        let device_id = packet.has_device_id().then(|| packet.device_id().to_string());
+        // Another instance on this host is told apart by its device ID.
+        let from_self = match (device_id.as_ref(), context.device_id()) {
+            (Some(theirs), Some(ours)) => theirs == ours,
+            _ => local_addresses.contains(&sender_address.ip()),
+        };
+        if from_self {
             return Err("Received packet from self".into());
         }
 
@@ -111,21 +307,47 @@ impl DiscoveryService {
             return Err("Group identity mismatch".into());
         }
 
+        if packet.goodbye() {
+            info!("Received goodbye from {} - {}", packet.host_name(), sender_address);
+            let mut peer = Peer::from_socket_addr(&sender_address, packet.server_port() as u16, None);
+            peer.set_device_id(device_id);
+            remove_peer(&peers, &peer, context);
+            return Ok(());
+        }
+
         info!("Received discovery packet from {} - {}", packet.host_name(), sender_address);
 
         if packet.need_response() {
             // Respond to our new friend on behalf of each local address.
             info!("Responding to discovery request from {}", sender_address);
             let self_hostname = OSUtil::hostname();
-            for local_addr_ipv4 in local_addresses {
+            let response_addresses: Vec<IpAddr> = match sender_address {
+                SocketAddr::V4(_) => local_addresses.iter().filter(|i| i.is_ipv4()).copied().collect(),
+                SocketAddr::V6(_) => first_ipv6(&local_addresses).map(IpAddr::V6).into_iter().collect(),
+            };
+            for local_addr in response_addresses.iter() {
                 let mut response_packet = DiscoveryPacket::new();
-                response_packet.set_address(local_addr_ipv4.into());
+                match local_addr {
+                    IpAddr::V4(ip) => response_packet.set_address(ip.to_u32()),
+                    IpAddr::V6(ip) => {
+                        response_packet.set_address(0);
+                        response_packet.set_address_v6(ip.octets().to_vec());
+                    }
+                }
                 response_packet.set_server_port(packet.server_port());
                 response_packet.set_group_identifier(group_identifier);
                 response_packet.set_need_response(false);
                 response_packet.set_host_name(self_hostname.clone());
+                response_packet.set_encryption_required(group_key.is_some());
+                if let Some(id) = context.device_id() {
+                    response_packet.set_device_id(id.clone());
+                }
+                if let Some(key) = context.identity_key() {
+                    response_packet.set_identity_key(key.to_vec());
+                }
+                write_device_info(&mut response_packet, context.device_info());
 
-                let serialized = match response_packet.write_to_bytes() {
+                let serialized = match seal_discovery_packet(&mut response_packet, group_key) {
                     Ok(x) => x,
                     Err(e) => {
                         error!("Failed to serialize response packet: {}", e);
@@ -133,16 +355,7 @@ impl DiscoveryService {
                     }
                 };
 
-                let size = serialized.len() as u32;
-                let _ = server_socket.send_to(
-                    &size.to_bytes(),
-                    SocketAddrV4::new(sender_address_ipv4, packet.server_port() as u16),
-                );
-
-                match server_socket.send_to(
-                    serialized.as_slice(),
-                    SocketAddrV4::new(sender_address_ipv4, packet.server_port() as u16),
-                ) {
+                match send_discovery_packet(server_socket, &serialized, sender_address) {
                     Ok(_) => {
                         info!("Successfully response packet to {}", sender_address);
                     }
@@ -155,30 +368,76 @@ impl DiscoveryService {
         }
 
         info!("Adding peer {} to peer set.", sender_address);
-        if let Ok(mut locked) = peers.lock() {
-            locked.insert(Peer::from(
-                &sender_address_ipv4,
-                packet.server_port() as u16,
-                Some(&packet.host_name().to_string()),
-            ));
-            info!("Added peer {} to peer set.", sender_address);
-        }
+        let mut peer = Peer::from_socket_addr(
+            &sender_address,
+            packet.server_port() as u16,
+            Some(&packet.host_name().to_string()),
+        );
+        peer.set_encryption_required(packet.encryption_required());
+        peer.set_device_id(device_id);
+        // Signed packets reach here only once verified.
+        peer.set_authenticated(group_key.is_some() && packet.has_signature());
+        peer.set_identity_key(IdentityKey::try_from(packet.identity_key()).ok());
+        peer.set_device_info(read_device_info(&packet));
+        upsert_peer(&peers, peer, context);
+        info!("Added peer {} to peer set.", sender_address);
 
         Ok(())
     }
 
-    pub fn broadcast_discovery_request(client_port: u16, server_port: u16, group_identifier: u32) -> Result<(), io::Error> {
-        let client_socket = Self::create_broadcast_socket(client_port)?;
-        let broadcast_addresses = match scan_broadcast_addresses() {
-            Ok(x) => x,
-            Err(e) => {
-                error!("Failed to get broadcast addresses: {}", e);
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("Failed to get broadcast addresses: {}", e),
-                ));
+    pub fn broadcast_discovery_request(context: &DiscoveryServiceContext) -> Result<(), io::Error> {
+        Self::announce(context, true, false)
+    }
+
+    /// Tells others we are still here, without asking for responses.
+    pub fn broadcast_heartbeat(context: &DiscoveryServiceContext) -> Result<(), io::Error> {
+        Self::announce(context, false, false)
+    }
+
+    /// Tells others to drop us right away.
+    pub fn broadcast_goodbye(context: &DiscoveryServiceContext) -> Result<(), io::Error> {
+        Self::announce(context, false, true)
+    }
+
+    /// Remove peers not heard from within the peer TTL, returning them.
+    pub fn evict_expired_peers(peers: &PeerCollectionType, context: &DiscoveryServiceContext) -> Vec<Peer> {
+        let mut evicted = Vec::new();
+        if let Ok(mut locked) = peers.lock() {
+            locked.retain(|peer| {
+                if peer.is_expired(context.peer_ttl()) {
+                    evicted.push(peer.clone());
+                    return false;
+                }
+                true
+            });
+        }
+        for peer in &evicted {
+            info!("Evicted stale peer {}.", peer.to_string());
+            emit_peer_event(context, PeerEvent::Removed(peer.clone()));
+        }
+        evicted
+    }
+
+    fn announce(context: &DiscoveryServiceContext, need_response: bool, goodbye: bool) -> Result<(), io::Error> {
+        let server_port = context.server_port();
+        let group_key = context.group_key();
+        let client_socket = Self::create_broadcast_socket(context.client_port())?;
+        let mut destinations_v4 = Vec::new();
+        if context.mode().uses_broadcast() {
+            match scan_broadcast_addresses() {
+                Ok(x) => destinations_v4.extend(x),
+                Err(e) => {
+                    error!("Failed to get broadcast addresses: {}", e);
+                    return Err(io::Error::other(
+                        format!("Failed to get broadcast addresses: {}", e),
+                    ));
+                }
             }
-        };
+        }
+        if context.mode().uses_multicast() {
+            client_socket.set_multicast_ttl_v4(context.multicast().ttl)?;
+            destinations_v4.push(context.multicast().group_v4);
+        }
         let local_addresses = match scan_local_addresses() {
             Ok(x) => x,
             Err(e) => {
@@ -193,15 +452,26 @@ impl DiscoveryService {
         let self_hostname = OSUtil::hostname();
         let mut broadcast_packet = DiscoveryPacket::new();
         broadcast_packet.set_server_port(server_port as u32);
-        broadcast_packet.set_group_identifier(group_identifier);
-        broadcast_packet.set_need_response(true);
+        broadcast_packet.set_group_identifier(context.group_identifier());
+        broadcast_packet.set_need_response(need_response);
         broadcast_packet.set_host_name(self_hostname.clone());
+        broadcast_packet.set_encryption_required(group_key.is_some());
+        if goodbye {
+            broadcast_packet.set_goodbye(true);
+        }
+        if let Some(id) = context.device_id() {
+            broadcast_packet.set_device_id(id.clone());
+        }
+        if let Some(key) = context.identity_key() {
+            broadcast_packet.set_identity_key(key.to_vec());
+        }
+        write_device_info(&mut broadcast_packet, context.device_info());
 
-        for broadcast_addr_ipv4 in &broadcast_addresses {
-            for local_addr_ipv4 in &local_addresses {
+        for destination_ipv4 in &destinations_v4 {
+            for local_addr_ipv4 in local_addresses.iter().filter_map(|i| i.to_ipv4_addr()) {
                 broadcast_packet.set_address(local_addr_ipv4.clone().to_u32());
 
-                let broadcast_packet_bytes = match broadcast_packet.write_to_bytes() {
+                let broadcast_packet_bytes = match seal_discovery_packet(&mut broadcast_packet, group_key) {
                     Ok(x) => x,
                     Err(e) => {
                         error!("Failed to serialize broadcast packet: {}", e);
@@ -212,96 +482,163 @@ impl DiscoveryService {
                     }
                 };
 
-                let size = broadcast_packet_bytes.len() as u32;
-                let result1 = client_socket.send_to(
-                    &size.to_bytes(),
-                    SocketAddrV4::new(*broadcast_addr_ipv4, server_port),
-                );
-
-                let result2 = client_socket.send_to(
-                    broadcast_packet_bytes.as_slice(),
-                    SocketAddrV4::new(*broadcast_addr_ipv4, server_port),
-                );
+                // Multicast leaves through the interface of the local address we announce.
+                if destination_ipv4.is_multicast() {
+                    let _ = SockRef::from(&client_socket).set_multicast_if_v4(&local_addr_ipv4);
+                }
+                match send_discovery_packet(
+                    &client_socket,
+                    &broadcast_packet_bytes,
+                    SocketAddr::V4(SocketAddrV4::new(*destination_ipv4, server_port)),
+                ) {
+                    Ok(_) => info!("Successfully broadcast discovery packet to {}", destination_ipv4),
+                    Err(_) => error!("Failed to broadcast discovery packet to {}", destination_ipv4),
+                }
+            }
+        }
 
-                if result1.is_ok() && result2.is_ok() {
-                    info!("Successfully broadcast discovery packet to {}", broadcast_addr_ipv4);
-                } else {
-                    error!("Failed to broadcast discovery packet to {}", broadcast_addr_ipv4);
+        // IPv6 has no broadcast, multicast to the link-local group on each interface instead.
+        if let Some(local_addr_ipv6) = first_ipv6(&local_addresses) {
+            match Self::create_multicast_socket_v6(context.client_port(), &[]) {
+                Ok(client_socket_v6) => {
+                    let _ = SockRef::from(&client_socket_v6).set_multicast_hops_v6(context.multicast().ttl);
+                    broadcast_packet.set_address(0);
+                    broadcast_packet.set_address_v6(local_addr_ipv6.octets().to_vec());
+                    for group in context.ipv6_groups() {
+                        for interface in scan_ipv6_interfaces().unwrap_or_default() {
+                            let bytes = match seal_discovery_packet(&mut broadcast_packet, group_key) {
+                                Ok(x) => x,
+                                Err(e) => {
+                                    error!("Failed to serialize multicast packet: {}", e);
+                                    return Ok(());
+                                }
+                            };
+                            let destination = SocketAddr::V6(SocketAddrV6::new(group, server_port, 0, interface));
+                            match send_discovery_packet(&client_socket_v6, &bytes, destination) {
+                                Ok(_) => info!("Successfully multicast discovery packet to {}", destination),
+                                Err(e) => warn!("Failed to multicast discovery packet to {} ({})", destination, e),
+                            }
+                        }
+                    }
                 }
+                Err(e) => warn!("Failed to create IPv6 discovery socket ({})", e),
             }
         }
         Ok(())
     }
 
     pub fn run(
-        client_port: u16,
-        server_port: u16,
+        context: DiscoveryServiceContext,
         peer_set_ptr: PeerCollectionType,
         should_interrupt: ShouldInterruptFunctionType,
-        group_identifier: u32,
-    ) -> Result<(), io::Error> {
-        let server_socket = Self::create_broadcast_socket(server_port)?;
-        let mut size_buffer = [0u8; 4];
+    ) -> Result<(), AirXError> {
+        let server_socket = Self::create_broadcast_socket(context.server_port())?;
+        if context.mode().uses_multicast() {
+            Self::join_multicast_v4(&server_socket, &context.multicast().group_v4)?;
+        }
+        let server_socket_v6 = match Self::create_multicast_socket_v6(context.server_port(), &context.ipv6_groups()) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                warn!("IPv6 discovery is unavailable ({})", e);
+                None
+            }
+        };
+        let shutdown = context.shutdown().clone();
+        let _running = shutdown.enter_service();
+        let _socket_watch = shutdown.watch_udp_socket(&server_socket);
+        let _socket_v6_watch = server_socket_v6.as_ref().and_then(|s| shutdown.watch_udp_socket(s));
+        let session = DiscoverySession {
+            verifier: DiscoveryVerifier::new(context.group_key(), context.accept_unsigned_discovery()),
+            context,
+            peer_set_ptr,
+            should_interrupt: Box::new(move || shutdown.is_shutdown() || should_interrupt()),
+        };
 
         // Broadcast discovery request twice to ensure that we are discovered.
         for _ in 0..2 {
-            let _ = Self::broadcast_discovery_request(client_port, server_port, group_identifier);
+            let _ = Self::broadcast_discovery_request(&session.context);
         }
 
-        info!("Discovery service online and ready for connections.");
+        info!("Discovery service online and ready for connections ({:?} mode).", session.context.mode());
+
+        std::thread::scope(|scope| {
+            if let Some(ref socket_v6) = server_socket_v6 {
+                scope.spawn(|| Self::receive_loop(socket_v6, &session));
+            }
+            scope.spawn(|| Self::liveness_loop(&session));
+            if let Some(mdns) = session.context.mdns() {
+                scope.spawn(|| {
+                    if let Err(e) = MdnsService::run(
+                        &session.context, mdns, session.peer_set_ptr.clone(), &session.should_interrupt) {
+                        warn!("mDNS is unavailable ({})", e);
+                    }
+                });
+            }
+            Self::receive_loop(&server_socket, &session);
+        });
+
+        let _ = Self::broadcast_goodbye(&session.context);
+        Ok(())
+    }
+
+    /// Send heartbeats and evict stale peers until interrupted.
+    fn liveness_loop(session: &DiscoverySession) {
+        let mut last_heartbeat = Instant::now();
+        while !(session.should_interrupt)() {
+            if session.context.shutdown().sleep(Duration::from_millis(LIVENESS_POLL_MILLIS)) {
+                break;
+            }
+            if last_heartbeat.elapsed() < session.context.heartbeat_interval() {
+                continue;
+            }
+            last_heartbeat = Instant::now();
+            let _ = Self::broadcast_heartbeat(&session.context);
+            Self::evict_expired_peers(&session.peer_set_ptr, &session.context);
+        }
+    }
+
+    fn receive_loop(server_socket: &UdpSocket, session: &DiscoverySession) {
+        let mut buf = vec![0u8; MAX_DISCOVERY_DATAGRAM_SIZE];
+        let mut pairing = LegacyDiscoveryPairing::new();
 
         loop {
-            let packet_size = match server_socket.recv(&mut size_buffer) {
-                Ok(_) => u32::from_bytes(size_buffer),
+            let received = server_socket.recv_from(&mut buf);
+            if session.context.shutdown().is_shutdown() {
+                info!("Discovery service is shut down.");
+                break;
+            }
+            let (n, source) = match received {
+                Ok(x) => x,
                 Err(e) if e.kind() == WouldBlock || e.kind() == TimedOut => {
-                    if should_interrupt() {
+                    if (session.should_interrupt)() {
                         info!("Discovery service interrupted by caller.");
                         break;
                     }
                     continue;
                 }
                 Err(e) => {
-                    error!("Failed to receive packet size ({})", e);
+                    error!("Failed to receive packet ({})", e);
 
                     // Broadcast another one to ensure that we are discovered.
-                    let _ = Self::broadcast_discovery_request(client_port, server_port, group_identifier);
+                    let _ = Self::broadcast_discovery_request(&session.context);
                     continue;
                 }
             };
 
-            let mut buf = vec![0u8; packet_size as usize];
-            match server_socket.recv(&mut buf) {
-                Ok(_) => {
-                    if let Ok(local_addresses) = scan_local_addresses() {
-                        let packet = match DiscoveryPacket::parse_from_bytes(buf.as_slice()) {
-                            Ok(x) => x,
-                            Err(e) => {
-                                error!("Failed to parse discovery packet ({})", e);
-                                continue;
-                            }
-                        };
-                        let _ = Self::handle_new_peer(
-                            local_addresses,
-                            &server_socket,
-                            peer_set_ptr.clone(),
-                            packet,
-                            group_identifier,
-                        );
-                    }
-                }
-                Err(ref e) if e.kind() == WouldBlock || e.kind() == TimedOut => {
-                    if should_interrupt() {
-                        info!("Discovery service interrupted by caller.");
-                        break;
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to receive packet ({})", e);
-                    continue;
-                }
+            let packet = match open_discovery_datagram(&buf[..n], &source, &mut pairing, &session.verifier) {
+                Some(x) => x,
+                None => continue,
+            };
+            if let Ok(local_addresses) = scan_local_addresses() {
+                let _ = Self::handle_new_peer(
+                    local_addresses,
+                    server_socket,
+                    session.peer_set_ptr.clone(),
+                    packet,
+                    source,
+                    &session.context,
+                );
             }
         }
-
-        Ok(())
     }
 }
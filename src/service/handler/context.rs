@@ -1,32 +1,53 @@
 use std::net::SocketAddr;
+use log::info;
+use crate::packet::data::magic_numbers::MagicNumbers;
+use crate::packet::data::transfer_id::TransferIdFormat;
 use crate::packet::data_packet::DataPacket;
-use crate::packet::data_transmission::DataTransmit;
+use crate::packet::protocol::features::ProtocolFeatures;
+use crate::security::identity::IdentityKey;
+use crate::security::trust_store::UntrustedPolicy;
 use crate::service::context::data_service_context::DataServiceContext;
+use crate::service::quarantine::QuarantinedPacket;
 
 pub struct HandlerContext<'a> {
-    tt: &'a mut DataTransmit,
+    peer_identity: Option<IdentityKey>,
     packet: &'a DataPacket,
     socket_addr: SocketAddr,
+    transfer_id_format: TransferIdFormat,
+    features: ProtocolFeatures,
     data_service_context: &'a DataServiceContext,
+    released: bool,
 }
 
 impl<'a> HandlerContext<'a> {
     pub fn new(
-        tt: &'a mut DataTransmit,
+        peer_identity: Option<IdentityKey>,
         packet: &'a DataPacket,
         socket_addr: SocketAddr,
+        transfer_id_format: TransferIdFormat,
+        features: ProtocolFeatures,
         data_service_context: &'a DataServiceContext,
     ) -> Self {
         Self {
-            tt,
+            peer_identity,
             packet,
             socket_addr,
+            transfer_id_format,
+            features,
             data_service_context,
+            released: false,
         }
     }
 
-    pub fn tt(&mut self) -> &mut DataTransmit {
-        self.tt
+    /// The packet was let out of the quarantine, the untrusted policy no longer applies.
+    pub fn released(mut self) -> Self {
+        self.released = true;
+        self
+    }
+
+    /// Public key the sender proved to own, if it identified itself.
+    pub fn peer_identity(&self) -> Option<&IdentityKey> {
+        self.peer_identity.as_ref()
     }
 
     pub fn packet(&self) -> &DataPacket {
@@ -37,9 +58,55 @@ impl<'a> HandlerContext<'a> {
         self.socket_addr
     }
 
+    /// How file packets on this connection carry their transfer id.
+    pub fn transfer_id_format(&self) -> TransferIdFormat {
+        self.transfer_id_format
+    }
+
+    /// Features agreed on in the hello, none for peers without one.
+    pub fn features(&self) -> ProtocolFeatures {
+        self.features
+    }
+
     pub fn data_service_context(&self) -> &DataServiceContext {
         self.data_service_context
     }
+
+    /// Whether the sender proved an identity that is paired with us.
+    pub fn is_sender_trusted(&self) -> bool {
+        match self.peer_identity() {
+            Some(key) => self.data_service_context.trust_store().is_trusted(key),
+            None => false,
+        }
+    }
+
+    /// Apply the untrusted policy to the current packet.
+    /// Returns false if the packet was dropped or quarantined instead of delivered.
+    /// Only texts and file offers are quarantined, anything else is dropped.
+    pub fn admit_sender(&self) -> bool {
+        let policy = self.data_service_context.untrusted_policy();
+        if self.released || policy == UntrustedPolicy::Allow || self.is_sender_trusted() {
+            return true;
+        }
+
+        self.data_service_context.metrics().record_untrusted_packet();
+        let held = match MagicNumbers::from(self.packet.magic_number()) {
+            Some(magic_number @ (MagicNumbers::Text | MagicNumbers::FileComing))
+                if policy == UntrustedPolicy::Quarantine => {
+                self.data_service_context.quarantine().hold(QuarantinedPacket::new(
+                    magic_number,
+                    self.packet.data().clone(),
+                    self.socket_addr,
+                    self.peer_identity,
+                    self.transfer_id_format,
+                ));
+                true
+            }
+            _ => false,
+        };
+        info!("{} packet from unpaired {}.", if held { "Quarantined" } else { "Dropped" }, self.socket_addr);
+        false
+    }
 }
 
 pub enum ConnectionControl {
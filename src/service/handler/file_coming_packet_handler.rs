@@ -1,11 +1,11 @@
 use log::{info, warn};
 use crate::network::peer::Peer;
 use crate::packet::data::file_coming_packet::FileComingPacket;
-use crate::packet::protocol::serialize::Serialize;
 use crate::service::handler::context::{ConnectionControl, HandlerContext};
+use crate::util::file_name::FileNameUtil;
 
 pub fn handle(context: HandlerContext) -> ConnectionControl {
-    let packet = match FileComingPacket::deserialize(context.packet().data()) {
+    let packet = match FileComingPacket::deserialize_as(context.packet().data(), context.transfer_id_format()) {
         Ok(p) => p,
         Err(e) => {
             warn!("Failed to deserialize file coming packet ({:?}).", e);
@@ -13,6 +13,21 @@ pub fn handle(context: HandlerContext) -> ConnectionControl {
         },
     };
 
+    if !context.admit_sender() {
+        return ConnectionControl::CloseConnection;
+    }
+
+    // Older senders send their full path, and anyone may send `..` or a device name.
+    let file_name = FileNameUtil::sanitize(packet.file_name());
+    let packet = match &file_name == packet.file_name() {
+        true => packet,
+        false => {
+            info!("Sanitized offered file name {:?} to {:?}.", packet.file_name(), file_name);
+            FileComingPacket::new(packet.transfer_id(), packet.file_size(), file_name)
+                .with_metadata(packet.metadata().clone())
+        }
+    };
+
     let peer = context
         .data_service_context()
         .discovery_service()
@@ -1,10 +1,9 @@
 use log::{info, trace, warn};
 use crate::packet::data::file_part_packet::FilePartPacket;
-use crate::packet::protocol::serialize::Serialize;
 use crate::service::handler::context::{ConnectionControl, HandlerContext};
 
 pub fn handle(context: HandlerContext) -> ConnectionControl {
-    let packet = match FilePartPacket::deserialize(context.packet().data()) {
+    let packet = match FilePartPacket::deserialize_as(context.packet().data(), context.transfer_id_format()) {
         Ok(p) => p,
         Err(e) => {
             warn!("Failed to deserialize file part packet ({:?}).", e);
@@ -12,6 +11,10 @@ pub fn handle(context: HandlerContext) -> ConnectionControl {
         },
     };
 
+    if !context.admit_sender() {
+        return ConnectionControl::CloseConnection;
+    }
+
     trace!("Received file part packet from {} (offset={}, length={}).", context.socket_addr(), packet.offset(), packet.length());
     let should_interrupt = (context.data_service_context().file_part_callback())(&packet, None);
     if should_interrupt {
@@ -19,5 +22,14 @@ pub fn handle(context: HandlerContext) -> ConnectionControl {
         return ConnectionControl::CloseConnection;
     }
 
+    // Delivered parts need not be sent again if the transfer resumes.
+    let journal = context.data_service_context().transfer_manager().journal();
+    let end = packet.offset().saturating_add(packet.length());
+    if let Some(entry) = journal.record_received(&packet.transfer_id(), packet.offset(), end) {
+        if entry.received().covers(entry.file_size()) {
+            info!("File received in full (transfer_id={}).", packet.transfer_id());
+        }
+    }
+
     ConnectionControl::Default
 }
@@ -1,10 +1,9 @@
 use log::{trace, warn};
 use crate::packet::data::file_part_response_packet::{FilePartResponsePacket, ResponseKind};
-use crate::packet::protocol::serialize::Serialize;
 use crate::service::handler::context::{ConnectionControl, HandlerContext};
 
 pub fn handle(context: HandlerContext) -> ConnectionControl {
-    let packet = match FilePartResponsePacket::deserialize(context.packet().data()) {
+    let packet = match FilePartResponsePacket::deserialize_as(context.packet().data(), context.transfer_id_format()) {
         Ok(p) => p,
         Err(e) => {
             warn!("Failed to deserialize file part response packet ({:?}).", e);
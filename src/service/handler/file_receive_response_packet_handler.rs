@@ -1,30 +1,37 @@
 use std::fs::File;
 use std::io;
 use std::io::{Read, Seek};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::time::Duration;
 use log::{error, info, warn};
 use crate::network::peer::Peer;
+use crate::packet::data::byte_ranges::ByteRanges;
 use crate::packet::data::file_part_packet::FilePartPacket;
+use crate::packet::data::file_part_response_packet::{FilePartResponsePacket, ResponseKind};
 use crate::packet::data::file_receive_response_packet::FileReceiveResponsePacket;
-use crate::packet::data::local::file_sending_packet::{FileSendingPacket, FileSendingStatus};
 use crate::packet::data::magic_numbers::MagicNumbers;
+use crate::packet::data::transfer_id::{TransferId, TransferIdFormat};
 use crate::packet::data_packet::DataPacket;
 use crate::packet::data_transmission::DataTransmit;
+use crate::packet::frame_limits::FILE_PART_CHUNK_SIZE;
+use crate::packet::protocol::features::ProtocolFeatures;
 use crate::packet::protocol::serialize::Serialize;
 use crate::service::data_service::DataService;
 use crate::service::handler::context::{ConnectionControl, HandlerContext};
+use crate::service::transfer_manager::{Transfer, TransferSide, TransferState};
+use crate::util::file_name::FileNameUtil;
+use crate::util::os::OSUtil;
 
-const BUFFER_SIZE: usize = 8 * 1024 * 1024;
 const TIMEOUT_MILLIS: u64 = 1000;
 const DATA_SESSION_RECONNECT_TRIES: u32 = 3;
 
 struct TransmissionState {
-    bytes_sent_total: u64,
+    /// Ranges of the file the receiver has, told by it or sent since.
+    delivered: ByteRanges,
 }
 
 pub fn handle(context: HandlerContext) -> ConnectionControl {
-    let packet = match FileReceiveResponsePacket::deserialize(context.packet().data()) {
+    let packet = match FileReceiveResponsePacket::deserialize_as(context.packet().data(), context.transfer_id_format()) {
         Ok(p) => p,
         Err(e) => {
             warn!("Failed to deserialize file receive response packet ({:?}).", e);
@@ -34,131 +41,180 @@ pub fn handle(context: HandlerContext) -> ConnectionControl {
 
     info!("Received file receive response packet from {}.", context.socket_addr());
 
-    let update_status = |status: FileSendingStatus| {
-        (context.data_service_context().file_sending_callback())(&FileSendingPacket::new(
-            packet.file_id(),
-            0,
-            packet.file_size(),
-            status,
-        ), None);
+    // Only files we offered to this peer are served, never the path it names.
+    let transfers = context.data_service_context().transfer_manager();
+    let transfer = match context.transfer_id_format() {
+        TransferIdFormat::Wide => transfers.get(&packet.transfer_id()),
+        // Older peers answer with an id of their own, the offer goes by its name and size.
+        TransferIdFormat::Legacy => transfers.find(|t| t.state() == TransferState::Offered
+            && t.file_size() == packet.file_size()
+            && FileNameUtil::display_name(t.file_path()) == FileNameUtil::display_name(packet.file_name())),
     };
-
-    // Update status!
-    update_status(FileSendingStatus::Requested);
-
-    let ipv4addr = match context.socket_addr() {
-        SocketAddr::V4(addr) => addr.ip().clone(),
-        SocketAddr::V6(_) => {
-            warn!("Received file receive response packet from IPv6 address.");
-            update_status(FileSendingStatus::Error);
-            return ConnectionControl::Default;
+    let transfer = match transfer {
+        Some(t) if is_offered_to(&t, &context.socket_addr()) => t,
+        _ => {
+            warn!("Security: {} answered an offer we never made to it (transfer_id={}, file_name={:?}), refused.",
+                context.socket_addr(), packet.transfer_id(), packet.file_name());
+            context.data_service_context().metrics().record_unsolicited_file_request();
+            return ConnectionControl::CloseConnection;
+        }
+    };
+    let transfer_id = transfer.id();
+    // The id the receiver knows the file by, ours unless it is an older peer.
+    let wire_id = packet.transfer_id();
+    let advance = |state: TransferState, progress: u64| -> bool {
+        match transfers.transition(&transfer_id, state, progress) {
+            Ok(_) => true,
+            Err(e) => {
+                warn!("Transfer {} not updated ({}).", transfer_id, e);
+                false
+            }
         }
     };
 
+    // A paused transfer answered again is the receiver resuming it.
+    let resuming = transfer.state() == TransferState::Paused;
     if !packet.accepted() {
         info!("File receive request rejected by peer.");
-        update_status(FileSendingStatus::Rejected);
+        match resuming {
+            true => advance(TransferState::Cancelled(TransferSide::Receiver), transfer.progress()),
+            false => advance(TransferState::Rejected, 0),
+        };
         return ConnectionControl::Default;
     }
 
-    info!("File receive request accepted by peer.");
-    update_status(FileSendingStatus::Accepted);
-
-    let filename = packet.file_name();
-    let peer = Peer::from(&ipv4addr, context.data_service_context().port(), None);
+    let filename = transfer.file_path();
+    let file_size = transfer.file_size();
+    if resuming {
+        // What the receiver has would not fit a file changed meanwhile.
+        if OSUtil::modified_secs(filename) != transfer.modified_at() {
+            warn!("File {} changed since it was offered, transfer {} cannot resume.", filename, transfer_id);
+            advance(TransferState::Failed, transfer.progress());
+            return ConnectionControl::Default;
+        }
+        info!("File receive request resumed by peer (transfer_id={}, received={}/{}).",
+            transfer_id, packet.received().len(), file_size);
+    } else {
+        info!("File receive request accepted by peer.");
+        if !advance(TransferState::Accepted, 0) {
+            return ConnectionControl::Default;
+        }
+    }
+    let peer = Peer::from_socket_addr(&context.socket_addr(), context.data_service_context().port(), None);
 
     // Connect to peer, start data transmission and close connection.
-    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut buffer = vec![0u8; FILE_PART_CHUNK_SIZE];
 
     // Log on every 10th iteration.
     let mut log_counter = 0;
 
+    // Receivers able to resume may still do so once either side stopped
+    // or the session was given up, for the others the transfer is over.
+    let resumable = context.features().contains(ProtocolFeatures::RESUMABLE_TRANSFER);
+    let stopped_by = |side: TransferSide| match resumable {
+        true => TransferState::Paused,
+        false => TransferState::Cancelled(side),
+    };
+
+    // Errors pause the transfer until `data_session` reconnects or gives up.
+    // Each try sends what the receiver is still missing.
     let mut session = |dt: &mut DataTransmit,
                        state: &mut TransmissionState| -> Result<(), io::Error> {
+        advance(TransferState::Streaming, state.delivered.len());
+
         let mut file = match File::open(filename) {
             Ok(f) => f,
             Err(e) => {
                 warn!("Failed to open file ({}).", e);
-                update_status(FileSendingStatus::Error);
+                advance(TransferState::Paused, state.delivered.len());
                 return Err(e);
             }
         };
-        let mut offset = state.bytes_sent_total;
-        match file.seek(io::SeekFrom::Start(offset)) {
-            Ok(n) => {
-                if n != offset {
-                    let error = io::Error::new(
-                        io::ErrorKind::Other,
-                        "Wrong seek position.",
-                    );
-                    warn!("Failed to seek file ({}).", error);
-                    update_status(FileSendingStatus::Error);
-                    return Err(error);
-                }
-                info!("Seeked file to {}.", offset);
-            }
-            Err(e) => {
-                warn!("Failed to seek file ({}).", e);
-                update_status(FileSendingStatus::Error);
-                return Err(e);
-            }
-        }
 
-        loop {
-            // Read a chunk of data from file.
-            let bytes_read = match file.read(&mut buffer) {
-                Ok(n) => n,
+        for (start, end) in state.delivered.missing(file_size) {
+            match file.seek(io::SeekFrom::Start(start)) {
+                Ok(n) => {
+                    if n != start {
+                        let error = io::Error::other("Wrong seek position.");
+                        warn!("Failed to seek file ({}).", error);
+                        advance(TransferState::Paused, state.delivered.len());
+                        return Err(error);
+                    }
+                    info!("Seeked file to {}.", start);
+                }
                 Err(e) => {
-                    warn!("Failed to read file ({}).", e);
-                    update_status(FileSendingStatus::Error);
+                    warn!("Failed to seek file ({}).", e);
+                    advance(TransferState::Paused, state.delivered.len());
                     return Err(e);
                 }
-            };
-
-            // Read to end?
-            if bytes_read == 0 {
-                break;
             }
 
-            // Create file part packet.
-            let file_part_packet = FilePartPacket::new(
-                packet.file_id(), offset, bytes_read as u64, buffer[..bytes_read].to_vec(),
-            );
+            let mut offset = start;
+            while offset < end {
+                // We are shutting down, tell the receiver before leaving.
+                if context.data_service_context().shutdown().is_shutdown() {
+                    info!("File sending cancelled by shutdown (transfer_id={}).", transfer_id);
+                    let response = FilePartResponsePacket::new(wire_id, ResponseKind::StopSending);
+                    let response = DataPacket::new(MagicNumbers::FilePartResponse.value(), &response.serialize_as(dt.transfer_id_format()));
+                    let _ = dt.send_data_progress_with_retry(&response.serialize(), |_| ());
+                    advance(stopped_by(TransferSide::Sender), state.delivered.len());
+                    return Err(io::Error::new(io::ErrorKind::Interrupted, "Cancelled by sender."));
+                }
+
+                // The receiver only ever talks back to stop us.
+                if dt.has_pending_data()? && receiver_stopped(dt, wire_id)? {
+                    info!("File sending cancelled by receiver (transfer_id={}).", transfer_id);
+                    advance(stopped_by(TransferSide::Receiver), state.delivered.len());
+                    return Err(io::Error::new(io::ErrorKind::Interrupted, "Cancelled by receiver."));
+                }
 
-            // Wrap to generic data packet.
-            let data_packet = DataPacket::new(MagicNumbers::FilePart.value(), &file_part_packet.serialize());
+                // Read a chunk of data from file, up to the end of the range.
+                let chunk_size = buffer.len().min((end - offset) as usize);
+                let bytes_read = match file.read(&mut buffer[..chunk_size]) {
+                    Ok(n) => n,
+                    Err(e) => {
+                        warn!("Failed to read file ({}).", e);
+                        advance(TransferState::Paused, state.delivered.len());
+                        return Err(e);
+                    }
+                };
 
-            // Send.
-            if let Err(e) = dt.send_data_progress_with_retry(&data_packet.serialize(), |bytes_written_total| {
-                state.bytes_sent_total += bytes_written_total;
-            }) {
-                error!("Failed to send file part packet ({}).", e);
-                update_status(FileSendingStatus::Error);
-                return Err(e);
-            }
+                // Read to end?
+                if bytes_read == 0 {
+                    break;
+                }
 
-            // Report on every 10th packet.
-            if log_counter >= 10 {
-                log_counter = 0;
-                info!("File part status: (fid={}, progress={}/{}).", packet.file_id(), offset, packet.file_size());
-
-                // Create local notification packet, update status and notify.
-                let local_packet = FileSendingPacket::new(
-                    packet.file_id(),
-                    offset,
-                    packet.file_size(),
-                    FileSendingStatus::InProgress,
+                // Create file part packet.
+                let file_part_packet = FilePartPacket::new(
+                    wire_id, offset, bytes_read as u64, buffer[..bytes_read].to_vec(),
                 );
-                (context.data_service_context().file_sending_callback())(&local_packet, None);
-            }
 
-            offset += bytes_read as u64;
+                // Wrap to generic data packet.
+                let data_packet = DataPacket::new(MagicNumbers::FilePart.value(), &file_part_packet.serialize_as(dt.transfer_id_format()));
+
+                // Send.
+                if let Err(e) = dt.send_data_progress_with_retry(&data_packet.serialize(), |_| ()) {
+                    error!("Failed to send file part packet ({}).", e);
+                    advance(TransferState::Paused, state.delivered.len());
+                    return Err(e);
+                }
+                state.delivered.insert(offset, offset + bytes_read as u64);
+                offset += bytes_read as u64;
+
+                // Report on every 10th packet.
+                if log_counter >= 10 {
+                    log_counter = 0;
+                    info!("File part status: (transfer_id={}, progress={}/{}).", transfer_id, state.delivered.len(), file_size);
+                    advance(TransferState::Streaming, state.delivered.len());
+                }
+                log_counter += 1;
+            }
         }
         Ok(())
     };
 
     let state = TransmissionState {
-        bytes_sent_total: 0,
+        delivered: packet.received().clone(),
     };
 
     if let Err(e) = DataService::data_session(
@@ -167,12 +223,50 @@ pub fn handle(context: HandlerContext) -> ConnectionControl {
         &mut session,
         DATA_SESSION_RECONNECT_TRIES,
         state,
+        context.data_service_context().group_key(),
+        Some(context.data_service_context().identity()),
     ) {
-        error!("Failed to send file part packet ({}).", e);
-        update_status(FileSendingStatus::Error);
+        // Stopping has already been reported.
+        if e.kind() != io::ErrorKind::Interrupted {
+            error!("Failed to send file part packet ({}).", e);
+            let (state, progress) = transfers.get(&transfer_id)
+                .map(|t| (t.state(), t.progress()))
+                .unwrap_or((TransferState::Failed, 0));
+            if !resumable {
+                advance(TransferState::Failed, progress);
+            } else if state != TransferState::Paused {
+                advance(TransferState::Paused, progress);
+            }
+        }
         return ConnectionControl::Default;
     }
 
-    update_status(FileSendingStatus::Completed);
+    advance(TransferState::Completed, file_size);
     ConnectionControl::Default
 }
+
+/// Whether `transfer` was offered to the peer at `socket_addr`.
+/// Offers made by host name cannot be told apart by address, their id has to do.
+fn is_offered_to(transfer: &Transfer, socket_addr: &SocketAddr) -> bool {
+    match transfer.host().parse::<IpAddr>() {
+        Ok(ip) => ip.to_canonical() == socket_addr.ip().to_canonical(),
+        Err(_) => true,
+    }
+}
+
+/// Read what the receiver sent and whether it asks us to stop `transfer_id`.
+fn receiver_stopped(dt: &mut DataTransmit, transfer_id: TransferId) -> Result<bool, io::Error> {
+    let data = dt.read_data_progress_with_retry(|_| ())?;
+    let packet = match DataPacket::deserialize(&data) {
+        Ok(p) => p,
+        Err(_) => return Ok(false),
+    };
+    if packet.magic_number() != MagicNumbers::FilePartResponse.value() {
+        return Ok(false);
+    }
+    Ok(match FilePartResponsePacket::deserialize_as(packet.data(), dt.transfer_id_format()) {
+        Ok(response) => response.transfer_id() == transfer_id
+            && response.response_kind() == ResponseKind::StopReceiving,
+        Err(_) => false,
+    })
+}
@@ -12,6 +12,10 @@ pub fn handle(context: HandlerContext) -> ConnectionControl {
         },
     };
 
+    if !context.admit_sender() {
+        return ConnectionControl::CloseConnection;
+    }
+
     let peer = context
         .data_service_context()
         .discovery_service()
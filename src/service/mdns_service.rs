@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::ErrorKind::{TimedOut, WouldBlock};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::time::{Duration, Instant};
+use log::{error, info, warn};
+use sha2::{Digest, Sha256};
+use socket2::{Domain, Protocol, Socket, Type};
+use crate::error::AirXError;
+use crate::lib_util::AIRX_COMPATIBLE_NUMBER;
+use crate::network::peer::Peer;
+use crate::packet::mdns_packet::{MdnsPacket, MdnsQuestion, MdnsRecord, MdnsRecordData, RECORD_TYPE_PTR};
+use crate::packet::protocol::serialize::Serialize;
+use crate::security::identity::{decode_identity_key, encode_identity_key, IdentityKey};
+use crate::service::context::discovery_service_context::DiscoveryServiceContext;
+use crate::service::discovery_service::{PeerCollectionType, scan_local_addresses, upsert_peer};
+use crate::service::ShouldInterruptFunctionType;
+use crate::util::os::OSUtil;
+
+pub const MDNS_SERVICE_TYPE: &str = "_airx._tcp.local";
+pub const MDNS_GROUP_V4: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub const MDNS_PORT: u16 = 5353;
+
+const MDNS_RECORD_TTL_SECS: u32 = 120;
+const MDNS_QUERY_INTERVAL_MILLIS: u64 = 30_000;
+const MDNS_TIMEOUT_MILLIS: u64 = 1000;
+const MDNS_MAX_PACKET_SIZE: usize = 9000;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MdnsConfig {
+    /// Where queries and announcements are sent, the mDNS group unless testing.
+    pub endpoint: SocketAddrV4,
+    pub bind_port: u16,
+    /// Advertised in the SRV record.
+    pub data_port: u16,
+}
+
+impl MdnsConfig {
+    pub fn new(data_port: u16) -> Self {
+        Self {
+            endpoint: SocketAddrV4::new(MDNS_GROUP_V4, MDNS_PORT),
+            bind_port: MDNS_PORT,
+            data_port,
+        }
+    }
+}
+
+/// Hash of the group identifier, so the identifier itself is not advertised.
+pub fn group_hash(group_identifier: u32) -> String {
+    let digest = Sha256::digest(group_identifier.to_be_bytes());
+    digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// An `_airx._tcp` service instance, as advertised or browsed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AirXServiceInstance {
+    instance_name: String,
+    host_name: String,
+    port: u16,
+    group_hash: String,
+    protocol_version: i32,
+    encryption_required: bool,
+    addresses: Vec<IpAddr>,
+    device_id: Option<String>,
+    identity_key: Option<IdentityKey>,
+}
+
+impl AirXServiceInstance {
+    pub fn new(
+        host_name: &str,
+        port: u16,
+        group_hash: String,
+        encryption_required: bool,
+        addresses: Vec<IpAddr>,
+    ) -> Self {
+        Self {
+            instance_name: format!("{}.{}", host_name.replace('.', "-"), MDNS_SERVICE_TYPE),
+            host_name: host_name.to_string(),
+            port,
+            group_hash,
+            protocol_version: AIRX_COMPATIBLE_NUMBER,
+            encryption_required,
+            addresses,
+            device_id: None,
+            identity_key: None,
+        }
+    }
+
+    pub fn instance_name(&self) -> &String {
+        &self.instance_name
+    }
+
+    pub fn host_name(&self) -> &String {
+        &self.host_name
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn group_hash(&self) -> &String {
+        &self.group_hash
+    }
+
+    pub fn protocol_version(&self) -> i32 {
+        self.protocol_version
+    }
+
+    pub fn encryption_required(&self) -> bool {
+        self.encryption_required
+    }
+
+    pub fn addresses(&self) -> &Vec<IpAddr> {
+        &self.addresses
+    }
+
+    pub fn device_id(&self) -> Option<&String> {
+        self.device_id.as_ref()
+    }
+
+    pub fn set_device_id(&mut self, device_id: Option<String>) {
+        self.device_id = device_id;
+    }
+
+    pub fn identity_key(&self) -> Option<&IdentityKey> {
+        self.identity_key.as_ref()
+    }
+
+    pub fn set_identity_key(&mut self, identity_key: Option<IdentityKey>) {
+        self.identity_key = identity_key;
+    }
+
+    fn target(&self) -> String {
+        format!("{}.local", self.host_name.replace('.', "-"))
+    }
+
+    /// PTR, SRV, TXT and address records describing this instance.
+    pub fn to_records(&self) -> Vec<MdnsRecord> {
+        let target = self.target();
+        let mut txt = vec![
+            format!("gid={}", self.group_hash),
+            format!("host={}", self.host_name),
+            format!("port={}", self.port),
+            format!("ver={}", self.protocol_version),
+            format!("enc={}", if self.encryption_required { 1 } else { 0 }),
+        ];
+        if let Some(ref id) = self.device_id {
+            txt.push(format!("id={}", id));
+        }
+        if let Some(ref key) = self.identity_key {
+            txt.push(format!("ik={}", encode_identity_key(key)));
+        }
+        let mut records = vec![
+            MdnsRecord::new(MDNS_SERVICE_TYPE, MDNS_RECORD_TTL_SECS,
+                            MdnsRecordData::Ptr(self.instance_name.clone())),
+            MdnsRecord::new(&self.instance_name, MDNS_RECORD_TTL_SECS,
+                            MdnsRecordData::Srv { port: self.port, target: target.clone() }),
+            MdnsRecord::new(&self.instance_name, MDNS_RECORD_TTL_SECS, MdnsRecordData::Txt(txt)),
+        ];
+        for address in &self.addresses {
+            let data = match address {
+                IpAddr::V4(ip) => MdnsRecordData::A(*ip),
+                IpAddr::V6(ip) => MdnsRecordData::Aaaa(*ip),
+            };
+            records.push(MdnsRecord::new(&target, MDNS_RECORD_TTL_SECS, data));
+        }
+        records
+    }
+
+    /// Instances announced in `records`. Instances without SRV or TXT are skipped.
+    pub fn from_records(records: &[MdnsRecord]) -> Vec<Self> {
+        let instance_names = records.iter().filter_map(|r| match r.data() {
+            MdnsRecordData::Ptr(name) if r.name().eq_ignore_ascii_case(MDNS_SERVICE_TYPE) => Some(name),
+            _ => None,
+        });
+
+        let mut instances = Vec::new();
+        for instance_name in instance_names {
+            let srv = records.iter().find_map(|r| match r.data() {
+                MdnsRecordData::Srv { port, target } if r.name().eq_ignore_ascii_case(instance_name) =>
+                    Some((*port, target)),
+                _ => None,
+            });
+            let txt = records.iter().find_map(|r| match r.data() {
+                MdnsRecordData::Txt(entries) if r.name().eq_ignore_ascii_case(instance_name) => Some(
+                    entries.iter()
+                        .filter_map(|e| e.split_once('='))
+                        .collect::<HashMap<&str, &str>>()),
+                _ => None,
+            });
+            let ((port, target), txt) = match (srv, txt) {
+                (Some(srv), Some(txt)) => (srv, txt),
+                _ => continue,
+            };
+
+            let addresses = records.iter().filter_map(|r| match r.data() {
+                MdnsRecordData::A(ip) if r.name().eq_ignore_ascii_case(target) => Some(IpAddr::V4(*ip)),
+                MdnsRecordData::Aaaa(ip) if r.name().eq_ignore_ascii_case(target) => Some(IpAddr::V6(*ip)),
+                _ => None,
+            }).collect();
+
+            instances.push(Self {
+                instance_name: instance_name.clone(),
+                host_name: txt.get("host").unwrap_or(&"").to_string(),
+                port,
+                group_hash: txt.get("gid").unwrap_or(&"").to_string(),
+                protocol_version: txt.get("ver").and_then(|v| v.parse().ok()).unwrap_or(0),
+                encryption_required: txt.get("enc") == Some(&"1"),
+                addresses,
+                device_id: txt.get("id").map(|id| id.to_string()),
+                identity_key: txt.get("ik").and_then(|key| decode_identity_key(key)),
+            });
+        }
+        instances
+    }
+}
+
+pub struct MdnsService;
+
+impl MdnsService {
+    pub fn create_socket(config: &MdnsConfig) -> Result<UdpSocket, io::Error> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        // Share port 5353 with the system responder.
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        socket.bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), config.bind_port).into())?;
+
+        let socket: UdpSocket = socket.into();
+        socket.set_read_timeout(Some(Duration::from_millis(MDNS_TIMEOUT_MILLIS)))?;
+
+        let group = config.endpoint.ip();
+        if group.is_multicast() {
+            for interface in scan_local_addresses().unwrap_or_default().iter() {
+                if let IpAddr::V4(interface) = interface {
+                    if let Err(e) = socket.join_multicast_v4(group, interface) {
+                        warn!("Failed to join mDNS group on {} ({})", interface, e);
+                    }
+                }
+            }
+        }
+        Ok(socket)
+    }
+
+    fn local_instance(context: &DiscoveryServiceContext, config: &MdnsConfig) -> AirXServiceInstance {
+        let addresses = scan_local_addresses()
+            .map(|a| a.into_iter().collect())
+            .unwrap_or_default();
+        let mut instance = AirXServiceInstance::new(
+            &OSUtil::hostname(),
+            config.data_port,
+            group_hash(context.group_identifier()),
+            context.group_key().is_some(),
+            addresses,
+        );
+        instance.set_device_id(context.device_id().cloned());
+        instance.set_identity_key(context.identity_key().copied());
+        instance
+    }
+
+    /// Advertise this device and merge browsed AirX instances into `peers`.
+    /// mDNS records are not signed, so they are ignored when signed discovery is enforced.
+    pub fn run(
+        context: &DiscoveryServiceContext,
+        config: &MdnsConfig,
+        peers: PeerCollectionType,
+        should_interrupt: &ShouldInterruptFunctionType,
+    ) -> Result<(), AirXError> {
+        let socket = Self::create_socket(config)?;
+        let _socket_watch = context.shutdown().watch_udp_socket(&socket);
+        let local_port = socket.local_addr()?.port();
+        let local_instance = Self::local_instance(context, config);
+        let query = MdnsPacket::query(vec![MdnsQuestion::new(MDNS_SERVICE_TYPE, RECORD_TYPE_PTR)]).serialize();
+        let announcement = MdnsPacket::response(local_instance.to_records()).serialize();
+
+        info!("mDNS service advertising {} via {}", local_instance.instance_name(), config.endpoint);
+
+        let mut last_query: Option<Instant> = None;
+        let mut buf = vec![0u8; MDNS_MAX_PACKET_SIZE];
+        loop {
+            if last_query.is_none_or(|t| t.elapsed() >= Duration::from_millis(MDNS_QUERY_INTERVAL_MILLIS)) {
+                let _ = socket.send_to(&announcement, config.endpoint);
+                if let Err(e) = socket.send_to(&query, config.endpoint) {
+                    error!("Failed to send mDNS query ({})", e);
+                }
+                last_query = Some(Instant::now());
+            }
+
+            let received = socket.recv_from(&mut buf);
+            if context.shutdown().is_shutdown() {
+                info!("mDNS service is shut down.");
+                break;
+            }
+            let (n, source) = match received {
+                Ok(x) => x,
+                Err(e) if e.kind() == WouldBlock || e.kind() == TimedOut => {
+                    if should_interrupt() {
+                        info!("mDNS service interrupted by caller.");
+                        break;
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    error!("Failed to receive mDNS packet ({})", e);
+                    continue;
+                }
+            };
+
+            let packet = match MdnsPacket::deserialize(&buf[..n].to_vec()) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+
+            if !packet.is_response() {
+                let asks_for_us = packet.questions().iter().any(|q| {
+                    q.name().eq_ignore_ascii_case(MDNS_SERVICE_TYPE)
+                        || q.name().eq_ignore_ascii_case(local_instance.instance_name())
+                });
+                let from_self = source.port() == local_port
+                    && local_instance.addresses().contains(&source.ip());
+                if asks_for_us && !from_self {
+                    // Queries not from port 5353 are legacy unicast and get a unicast reply.
+                    let destination = if source.port() == MDNS_PORT {
+                        SocketAddr::V4(config.endpoint)
+                    } else {
+                        source
+                    };
+                    let _ = socket.send_to(&announcement, destination);
+                }
+                continue;
+            }
+
+            for instance in AirXServiceInstance::from_records(packet.records()) {
+                Self::handle_instance(context, &local_instance, &peers, instance, source);
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_instance(
+        context: &DiscoveryServiceContext,
+        local_instance: &AirXServiceInstance,
+        peers: &PeerCollectionType,
+        instance: AirXServiceInstance,
+        source: SocketAddr,
+    ) {
+        if instance.instance_name().eq_ignore_ascii_case(local_instance.instance_name())
+            || (instance.device_id().is_some() && instance.device_id() == local_instance.device_id()) {
+            return;
+        }
+        if *instance.group_hash() != group_hash(context.group_identifier()) {
+            info!("Dropped mDNS instance {} from different group.", instance.instance_name());
+            return;
+        }
+        if context.group_key().is_some() && !context.accept_unsigned_discovery() {
+            info!("Ignored unsigned mDNS instance {}.", instance.instance_name());
+            return;
+        }
+
+        let address = instance.addresses().iter()
+            .find(|a| a.is_ipv4())
+            .or_else(|| instance.addresses().first())
+            .copied()
+            .unwrap_or(source.ip());
+        if local_instance.addresses().contains(&address) {
+            return;
+        }
+
+        let mut peer = Peer::new(&address.to_string(), instance.port(), Some(instance.host_name()));
+        peer.set_encryption_required(instance.encryption_required());
+        peer.set_device_id(instance.device_id().cloned());
+        peer.set_identity_key(instance.identity_key().copied());
+        info!("Adding mDNS peer {} to peer set.", peer.to_string());
+        upsert_peer(peers, peer, context);
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters shared by the services of one AirX instance.
+#[derive(Default)]
+pub struct ServiceMetrics {
+    corrupted_packets: AtomicU64,
+    untrusted_packets: AtomicU64,
+    rejected_connections: AtomicU64,
+    limited_connections: AtomicU64,
+    oversized_frames: AtomicU64,
+    unsolicited_file_requests: AtomicU64,
+}
+
+impl ServiceMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Data packets dropped because their checksum did not match.
+    pub fn corrupted_packets(&self) -> u64 {
+        self.corrupted_packets.load(Ordering::Relaxed)
+    }
+
+    pub fn record_corrupted_packet(&self) {
+        self.corrupted_packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Text and file offers from unpaired peers that were dropped or quarantined.
+    pub fn untrusted_packets(&self) -> u64 {
+        self.untrusted_packets.load(Ordering::Relaxed)
+    }
+
+    pub fn record_untrusted_packet(&self) {
+        self.untrusted_packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Connections closed because the access policy denied the peer.
+    pub fn rejected_connections(&self) -> u64 {
+        self.rejected_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn record_rejected_connection(&self) {
+        self.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Connections closed because a connection limit was reached or every worker was busy.
+    pub fn limited_connections(&self) -> u64 {
+        self.limited_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn record_limited_connection(&self) {
+        self.limited_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Connections closed because the peer announced a frame over the frame limits.
+    pub fn oversized_frames(&self) -> u64 {
+        self.oversized_frames.load(Ordering::Relaxed)
+    }
+
+    pub fn record_oversized_frame(&self) {
+        self.oversized_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Accepts for files we never offered to the peer, refused instead of served.
+    pub fn unsolicited_file_requests(&self) -> u64 {
+        self.unsolicited_file_requests.load(Ordering::Relaxed)
+    }
+
+    pub fn record_unsolicited_file_request(&self) {
+        self.unsolicited_file_requests.fetch_add(1, Ordering::Relaxed);
+    }
+}
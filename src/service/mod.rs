@@ -1,7 +1,21 @@
 pub mod airx_service;
 pub mod discovery_service;
 pub mod data_service;
+pub mod mdns_service;
 pub mod context;
 pub mod handler;
+pub mod metrics;
+pub mod peer_event;
+pub mod quarantine;
+pub mod shutdown;
+pub mod worker_pool;
+pub mod connection_limiter;
+pub mod transfer_manager;
+pub mod transfer_journal;
+pub mod peer_session;
+#[cfg(feature = "tokio")]
+pub mod async_data_service;
+#[cfg(feature = "tokio")]
+pub mod async_discovery_service;
 
 pub type ShouldInterruptFunctionType = Box<dyn (Fn() -> bool) + Send + Sync>;
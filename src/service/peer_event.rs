@@ -0,0 +1,54 @@
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver};
+use crate::network::peer::Peer;
+
+pub type OnPeerEventFunctionType = Arc<Box<dyn Fn(&PeerEvent) + Send + Sync>>;
+
+/// A change of the discovered peer set.
+#[derive(Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum PeerEvent {
+    Added(Peer),
+    Removed(Peer),
+    /// Same host, but the hostname, port, encryption requirement or announced device info changed.
+    Updated { previous: Peer, current: Peer },
+}
+
+impl PeerEvent {
+    /// Stable value for the FFI layers.
+    pub fn value(&self) -> u8 {
+        match self {
+            PeerEvent::Added(_) => 1,
+            PeerEvent::Removed(_) => 2,
+            PeerEvent::Updated { .. } => 3,
+        }
+    }
+
+    /// The peer as it is now, or as it was when removed.
+    pub fn peer(&self) -> &Peer {
+        match self {
+            PeerEvent::Added(peer) => peer,
+            PeerEvent::Removed(peer) => peer,
+            PeerEvent::Updated { current, .. } => current,
+        }
+    }
+
+    pub fn previous(&self) -> Option<&Peer> {
+        match self {
+            PeerEvent::Updated { previous, .. } => Some(previous),
+            _ => None,
+        }
+    }
+}
+
+/// Callback that forwards every event into a channel.
+pub fn peer_event_channel() -> (OnPeerEventFunctionType, Receiver<PeerEvent>) {
+    let (sender, receiver) = channel();
+    let sender = Mutex::new(sender);
+    let callback: OnPeerEventFunctionType = Arc::new(Box::new(move |event: &PeerEvent| {
+        if let Ok(sender) = sender.lock() {
+            let _ = sender.send(event.clone());
+        }
+    }));
+    (callback, receiver)
+}
@@ -0,0 +1,97 @@
+use std::io;
+use std::net::SocketAddr;
+use log::{trace, warn};
+use crate::packet::data::file_part_packet::FilePartPacket;
+use crate::packet::data::file_part_response_packet::{FilePartResponsePacket, ResponseKind};
+use crate::packet::data::magic_numbers::MagicNumbers;
+use crate::packet::data::transfer_id::{TransferId, TransferIdFormat};
+use crate::packet::data_packet::{DataPacket, DataPacketError};
+use crate::packet::hello_packet::Negotiated;
+use crate::packet::protocol::features::ProtocolFeatures;
+use crate::packet::protocol::serialize::Serialize;
+use crate::security::identity::{IdentityKey, is_identity_hello};
+use crate::service::context::data_service_context::DataServiceContext;
+use crate::service::data_service::DataService;
+use crate::service::handler::context::ConnectionControl;
+
+/// What a frame read from a peer turned out to be.
+pub(crate) enum Frame {
+    /// To be answered with `identify_as_responder`.
+    IdentityHello,
+    Data(DataPacket),
+}
+
+/// The steps of serving an inbound connection that do not depend on the transport,
+/// shared by `DataService` and `AsyncDataService`.
+#[derive(Clone)]
+pub(crate) struct PeerSession {
+    socket_addr: SocketAddr,
+    transfer_id_format: TransferIdFormat,
+    features: ProtocolFeatures,
+    /// File being received on this connection, told to stop if we shut down.
+    receiving_transfer_id: Option<TransferId>,
+}
+
+impl PeerSession {
+    /// Why the hello or the handshake failed, counted when it was an oversized frame.
+    pub(crate) fn refuse(error: &io::Error, socket_addr: &SocketAddr, context: &DataServiceContext) {
+        warn!("Refused connection from {} ({}).", socket_addr, error);
+        DataService::check_oversized_frame(error, socket_addr, context);
+    }
+
+    /// Start once the hello and the handshake are done.
+    pub(crate) fn new(socket_addr: SocketAddr, negotiated: Option<&Negotiated>) -> Self {
+        Self {
+            socket_addr,
+            transfer_id_format: TransferIdFormat::of(negotiated.map(|n| n.version)),
+            features: negotiated.map(|n| n.features).unwrap_or_default(),
+            receiving_transfer_id: None,
+        }
+    }
+
+    /// Make sense of a frame, None if the connection is to be closed.
+    /// Identified peers open with an identity hello, older ones go straight to data.
+    pub(crate) fn read_frame(&mut self, raw_data: &Vec<u8>, identified: bool, context: &DataServiceContext) -> Option<Frame> {
+        if !identified && is_identity_hello(raw_data) {
+            return Some(Frame::IdentityHello);
+        }
+
+        let data_packet = match DataPacket::deserialize(raw_data) {
+            Ok(p) => p,
+            Err(DataPacketError::ChecksumMismatch) => {
+                warn!("Dropped corrupted data packet from {}.", self.socket_addr);
+                context.metrics().record_corrupted_packet();
+                return None;
+            }
+            Err(e) => {
+                warn!("Failed to deserialize data ({:?}).", e);
+                return None;
+            }
+        };
+
+        trace!("Received data packet from {}, magic_number={}.", self.socket_addr, data_packet.magic_number());
+        if data_packet.magic_number() == MagicNumbers::FilePart.value() {
+            self.receiving_transfer_id = FilePartPacket::peek_transfer_id(data_packet.data(), self.transfer_id_format);
+        }
+        Some(Frame::Data(data_packet))
+    }
+
+    pub(crate) fn is_receiving_file(&self) -> bool {
+        self.receiving_transfer_id.is_some()
+    }
+
+    pub(crate) fn dispatch(&self, peer_identity: Option<IdentityKey>, packet: &DataPacket, context: &DataServiceContext) -> ConnectionControl {
+        DataService::dispatch_data_packet(peer_identity, packet, self.socket_addr, self.transfer_id_format, self.features, context)
+    }
+
+    /// The frame telling the sender to stop, when we shut down in the middle of a file.
+    pub(crate) fn stop_receiving_frame(&self, context: &DataServiceContext) -> Option<Vec<u8>> {
+        if !context.shutdown().is_shutdown() {
+            return None;
+        }
+        let transfer_id = self.receiving_transfer_id?;
+        let response = FilePartResponsePacket::new(transfer_id, ResponseKind::StopReceiving);
+        let packet = DataPacket::new(MagicNumbers::FilePartResponse.value(), &response.serialize_as(self.transfer_id_format));
+        Some(packet.serialize())
+    }
+}
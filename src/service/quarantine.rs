@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use crate::packet::data::magic_numbers::MagicNumbers;
+use crate::packet::data::transfer_id::TransferIdFormat;
+use crate::security::identity::IdentityKey;
+
+/// Called with each packet let out of the quarantine, to deliver it.
+pub type OnReleasedFunctionType = Arc<Box<dyn Fn(&QuarantinedPacket) + Send + Sync>>;
+
+/// Oldest packets are dropped once this many are held.
+pub const QUARANTINE_CAPACITY: usize = 64;
+
+/// A text or file offer held back because its sender is not paired.
+#[derive(Clone)]
+pub struct QuarantinedPacket {
+    magic_number: MagicNumbers,
+    data: Vec<u8>,
+    socket_addr: SocketAddr,
+    identity_key: Option<IdentityKey>,
+    transfer_id_format: TransferIdFormat,
+    received_at: Instant,
+}
+
+impl QuarantinedPacket {
+    pub fn new(
+        magic_number: MagicNumbers,
+        data: Vec<u8>,
+        socket_addr: SocketAddr,
+        identity_key: Option<IdentityKey>,
+        transfer_id_format: TransferIdFormat,
+    ) -> Self {
+        Self {
+            magic_number,
+            data,
+            socket_addr,
+            identity_key,
+            transfer_id_format,
+            received_at: Instant::now(),
+        }
+    }
+
+    pub fn magic_number(&self) -> MagicNumbers {
+        self.magic_number
+    }
+
+    /// The serialized text or file coming packet.
+    pub fn data(&self) -> &Vec<u8> {
+        &self.data
+    }
+
+    pub fn socket_addr(&self) -> SocketAddr {
+        self.socket_addr
+    }
+
+    /// Key the sender proved to own, none for senders that did not identify.
+    pub fn identity_key(&self) -> Option<&IdentityKey> {
+        self.identity_key.as_ref()
+    }
+
+    /// How the file coming packet carries its transfer id, as on the connection it came from.
+    pub fn transfer_id_format(&self) -> TransferIdFormat {
+        self.transfer_id_format
+    }
+
+    pub fn received_at(&self) -> Instant {
+        self.received_at
+    }
+}
+
+#[derive(Default)]
+pub struct Quarantine {
+    packets: Mutex<VecDeque<QuarantinedPacket>>,
+    listener: Mutex<Option<OnReleasedFunctionType>>,
+}
+
+impl Quarantine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn hold(&self, packet: QuarantinedPacket) {
+        if let Ok(mut packets) = self.packets.lock() {
+            if packets.len() >= QUARANTINE_CAPACITY {
+                packets.pop_front();
+            }
+            packets.push_back(packet);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.packets.lock().map(|p| p.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// What is held, oldest first.
+    pub fn list(&self) -> Vec<QuarantinedPacket> {
+        match self.packets.lock() {
+            Ok(packets) => packets.iter().cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Where released packets go, set by the data service while it runs.
+    pub fn set_listener(&self, listener: Option<OnReleasedFunctionType>) {
+        if let Ok(mut current) = self.listener.lock() {
+            *current = listener;
+        }
+    }
+
+    /// Take everything held, oldest first.
+    pub fn drain(&self) -> Vec<QuarantinedPacket> {
+        match self.packets.lock() {
+            Ok(mut packets) => packets.drain(..).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Take what was held from the device with `identity_key`, e.g. right after pairing it.
+    pub fn release(&self, identity_key: &IdentityKey) -> Vec<QuarantinedPacket> {
+        self.take_where(|p| p.identity_key.as_ref() == Some(identity_key))
+    }
+
+    /// Deliver what was held from the device with `identity_key` to the listener.
+    /// Returns how many packets were delivered, none while no listener is set.
+    pub fn deliver(&self, identity_key: &IdentityKey) -> usize {
+        self.deliver_where(|p| p.identity_key.as_ref() == Some(identity_key))
+    }
+
+    /// Deliver what was held from `ip`, identified or not, to the listener.
+    pub fn deliver_from(&self, ip: IpAddr) -> usize {
+        self.deliver_where(|p| p.socket_addr.ip() == ip)
+    }
+
+    fn take_where<P>(&self, predicate: P) -> Vec<QuarantinedPacket> where P: Fn(&QuarantinedPacket) -> bool {
+        match self.packets.lock() {
+            Ok(mut packets) => {
+                let (taken, kept) = packets.drain(..).partition(predicate);
+                *packets = kept;
+                taken.into()
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn deliver_where<P>(&self, predicate: P) -> usize where P: Fn(&QuarantinedPacket) -> bool {
+        let listener = match self.listener.lock().ok().and_then(|l| l.clone()) {
+            Some(listener) => listener,
+            None => return 0,
+        };
+        let released = self.take_where(predicate);
+        for packet in &released {
+            listener(packet);
+        }
+        released.len()
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::net::{Shutdown, TcpStream, UdpSocket};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use socket2::SockRef;
+
+type ShutdownHook = Box<dyn Fn() + Send + Sync>;
+
+#[derive(Default)]
+struct ShutdownState {
+    hooks: HashMap<u64, ShutdownHook>,
+    next_hook_id: u64,
+    threads: Vec<JoinHandle<()>>,
+    running_services: usize,
+}
+
+#[derive(Default)]
+struct ShutdownInner {
+    stopped: AtomicBool,
+    state: Mutex<ShutdownState>,
+    condvar: Condvar,
+}
+
+/// Stops the services of one AirX instance.
+/// Services sleep on it instead of polling, register their sockets so blocked reads wake up,
+/// and spawn their connection threads through it so they can be joined.
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    inner: Arc<ShutdownInner>,
+}
+
+/// Unregisters a shutdown hook when dropped.
+pub struct ShutdownHookGuard {
+    handle: ShutdownHandle,
+    id: u64,
+}
+
+impl Drop for ShutdownHookGuard {
+    fn drop(&mut self) {
+        self.handle.lock().hooks.remove(&self.id);
+    }
+}
+
+/// Marks a service loop as running until dropped, see `ShutdownHandle::join`.
+pub struct RunningServiceGuard {
+    handle: ShutdownHandle,
+}
+
+impl Drop for RunningServiceGuard {
+    fn drop(&mut self) {
+        self.handle.lock().running_services -= 1;
+        self.handle.inner.condvar.notify_all();
+    }
+}
+
+impl ShutdownHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ShutdownState> {
+        // Hooks never panic while holding the lock, so poisoning can be ignored.
+        self.inner.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Signal every service and session. Returns at once, see `join` to wait for them.
+    pub fn shutdown(&self) {
+        let hooks = {
+            let mut state = self.lock();
+            self.inner.stopped.store(true, Ordering::SeqCst);
+            std::mem::take(&mut state.hooks)
+        };
+        self.inner.condvar.notify_all();
+        for hook in hooks.values() {
+            hook();
+        }
+    }
+
+    /// Sleep for `duration` or until shut down. Returns whether shut down.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        let mut state = self.lock();
+        while !self.is_shutdown() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            state = match self.inner.condvar.wait_timeout(state, deadline - now) {
+                Ok((s, _)) => s,
+                Err(e) => e.into_inner().0,
+            };
+        }
+        self.is_shutdown()
+    }
+
+    /// Resolve once shut down, for the async services.
+    #[cfg(feature = "tokio")]
+    pub async fn wait(&self) {
+        let notify = Arc::new(tokio::sync::Notify::new());
+        let hook_notify = notify.clone();
+        let _guard = self.on_shutdown(Box::new(move || hook_notify.notify_one()));
+        notify.notified().await;
+    }
+
+    /// Run `hook` on shutdown, or right away if already shut down.
+    pub fn on_shutdown(&self, hook: ShutdownHook) -> ShutdownHookGuard {
+        let mut state = self.lock();
+        let id = state.next_hook_id;
+        state.next_hook_id += 1;
+        if self.is_shutdown() {
+            drop(state);
+            hook();
+        } else {
+            state.hooks.insert(id, hook);
+        }
+        ShutdownHookGuard { handle: self.clone(), id }
+    }
+
+    /// Wake reads blocked on `stream` on shutdown. Writing stays possible for a last word.
+    pub fn watch_tcp_stream(&self, stream: &TcpStream) -> Option<ShutdownHookGuard> {
+        let stream = stream.try_clone().ok()?;
+        Some(self.on_shutdown(Box::new(move || {
+            let _ = stream.shutdown(Shutdown::Read);
+        })))
+    }
+
+    /// Wake reads blocked on `socket` on shutdown where the platform supports it.
+    /// Elsewhere the socket read timeout bounds the delay.
+    pub fn watch_udp_socket(&self, socket: &UdpSocket) -> Option<ShutdownHookGuard> {
+        let socket = socket.try_clone().ok()?;
+        Some(self.on_shutdown(Box::new(move || {
+            let _ = SockRef::from(&socket).shutdown(Shutdown::Read);
+        })))
+    }
+
+    /// Spawn a thread that `join` waits for.
+    pub fn spawn<F>(&self, f: F) where F: FnOnce() + Send + 'static {
+        let thread = std::thread::spawn(f);
+        let mut state = self.lock();
+        state.threads.retain(|t| !t.is_finished());
+        state.threads.push(thread);
+    }
+
+    pub fn enter_service(&self) -> RunningServiceGuard {
+        self.lock().running_services += 1;
+        RunningServiceGuard { handle: self.clone() }
+    }
+
+    /// Wait for every running service loop to return and every spawned thread to end.
+    /// Must not be called from one of those threads.
+    pub fn join(&self) {
+        let mut state = self.lock();
+        while state.running_services > 0 {
+            state = match self.inner.condvar.wait(state) {
+                Ok(s) => s,
+                Err(e) => e.into_inner(),
+            };
+        }
+        drop(state);
+
+        // Threads may spawn more threads while being joined.
+        loop {
+            let threads = std::mem::take(&mut self.lock().threads);
+            if threads.is_empty() {
+                break;
+            }
+            for thread in threads {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    /// `shutdown` followed by `join`.
+    pub fn stop(&self) {
+        self.shutdown();
+        self.join();
+    }
+}
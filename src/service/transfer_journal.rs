@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use log::warn;
+use crate::packet::data::byte_ranges::ByteRanges;
+use crate::packet::data::transfer_id::TransferId;
+use crate::service::transfer_manager::TransferSide;
+use crate::util::os::OSUtil;
+
+const TRANSFER_JOURNAL_FILE_NAME: &str = "transfer_journal";
+/// Progress of received files is written at most this often, so a crash
+/// forgets a little of it and those bytes are simply sent again.
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+/// Interrupted transfers not resumed within this time are given up.
+pub const RESUME_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// What it takes to resume a transfer, on either side.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct JournalEntry {
+    id: TransferId,
+    side: TransferSide,
+    host: String,
+    file: String,
+    file_size: u64,
+    modified_at: Option<u64>,
+    received: ByteRanges,
+    updated_at: u64,
+}
+
+impl JournalEntry {
+    /// A file we offered to `host`, as it was when offered.
+    pub fn sending(id: TransferId, host: String, file_path: String, file_size: u64, modified_at: Option<u64>) -> Self {
+        Self {
+            id,
+            side: TransferSide::Sender,
+            host,
+            file: file_path,
+            file_size,
+            modified_at,
+            received: ByteRanges::new(),
+            updated_at: now_secs(),
+        }
+    }
+
+    /// A file `host` offered to us and we accepted.
+    pub fn receiving(id: TransferId, host: String, file_name: String, file_size: u64) -> Self {
+        Self {
+            id,
+            side: TransferSide::Receiver,
+            host,
+            file: file_name,
+            file_size,
+            modified_at: None,
+            received: ByteRanges::new(),
+            updated_at: now_secs(),
+        }
+    }
+
+    pub fn id(&self) -> TransferId {
+        self.id
+    }
+
+    pub fn side(&self) -> TransferSide {
+        self.side
+    }
+
+    /// Host on the other side of the transfer.
+    pub fn host(&self) -> &String {
+        &self.host
+    }
+
+    /// Path of the file we send, or name of the file we receive.
+    pub fn file(&self) -> &String {
+        &self.file
+    }
+
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    /// Modification time of the file we send, in seconds since the UNIX epoch.
+    pub fn modified_at(&self) -> Option<u64> {
+        self.modified_at
+    }
+
+    /// Ranges of the file we received, empty when sending.
+    pub fn received(&self) -> &ByteRanges {
+        &self.received
+    }
+
+    fn is_stale(&self, now: u64) -> bool {
+        now.saturating_sub(self.updated_at) >= RESUME_TIMEOUT.as_secs()
+    }
+
+    /// One line: side, id, size, modification and update times, received ranges,
+    /// host and file, tab separated. The file goes last, it may contain tabs.
+    fn to_line(&self) -> Option<String> {
+        if self.file.contains(['\n', '\r']) {
+            return None;
+        }
+        let host: String = self.host.chars().filter(|c| !c.is_control()).collect();
+        let side = match self.side {
+            TransferSide::Sender => "send",
+            TransferSide::Receiver => "receive",
+        };
+        Some(format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            side, self.id, self.file_size, self.modified_at.unwrap_or(0), self.updated_at,
+            self.received, host, self.file))
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(8, '\t');
+        let side = match fields.next()? {
+            "send" => TransferSide::Sender,
+            "receive" => TransferSide::Receiver,
+            _ => return None,
+        };
+        let id = TransferId::parse(fields.next()?)?;
+        let file_size = fields.next()?.parse().ok()?;
+        let modified_at = Some(fields.next()?.parse().ok()?).filter(|t| *t != 0);
+        let updated_at = fields.next()?.parse().ok()?;
+        let received = ByteRanges::parse(fields.next()?)?;
+        let host = fields.next()?.to_string();
+        let file = fields.next()?.to_string();
+        Some(Self { id, side, host, file, file_size, modified_at, received, updated_at })
+    }
+}
+
+/// Transfers that can still be resumed, so that they survive a restart.
+/// Kept in memory until a directory is attached, then written on every change.
+pub struct TransferJournal {
+    path: Mutex<Option<PathBuf>>,
+    entries: Mutex<HashMap<(TransferId, TransferSide), JournalEntry>>,
+    last_saved: Mutex<Option<Instant>>,
+}
+
+impl Default for TransferJournal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TransferJournal {
+    pub fn new() -> Self {
+        Self {
+            path: Mutex::new(None),
+            entries: Mutex::new(HashMap::new()),
+            last_saved: Mutex::new(None),
+        }
+    }
+
+    /// Load the journal kept in `directory` and persist there from now on.
+    /// Entries recorded before are kept, stale ones are dropped.
+    pub fn attach(&self, directory: &Path) -> Result<(), io::Error> {
+        let path = directory.join(TRANSFER_JOURNAL_FILE_NAME);
+        let now = now_secs();
+        let loaded = match fs::read_to_string(&path) {
+            Ok(content) => content.lines()
+                .filter_map(JournalEntry::from_line)
+                .filter(|e| !e.is_stale(now))
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        fs::create_dir_all(directory)?;
+        if let Ok(mut entries) = self.entries.lock() {
+            for entry in loaded {
+                entries.entry((entry.id, entry.side)).or_insert(entry);
+            }
+        }
+        if let Ok(mut locked) = self.path.lock() {
+            *locked = Some(path);
+        }
+        self.save()
+    }
+
+    /// Add or replace the entry of `entry.id()` on its side.
+    pub fn record(&self, mut entry: JournalEntry) -> Result<(), io::Error> {
+        entry.updated_at = now_secs();
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert((entry.id, entry.side), entry);
+        }
+        self.save()
+    }
+
+    /// Returns whether there was an entry.
+    pub fn remove(&self, id: &TransferId, side: TransferSide) -> Result<bool, io::Error> {
+        let removed = match self.entries.lock() {
+            Ok(mut entries) => entries.remove(&(*id, side)).is_some(),
+            Err(_) => false,
+        };
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    pub fn get(&self, id: &TransferId, side: TransferSide) -> Option<JournalEntry> {
+        self.entries.lock().ok()?.get(&(*id, side)).cloned()
+    }
+
+    pub fn entries(&self, side: TransferSide) -> Vec<JournalEntry> {
+        match self.entries.lock() {
+            Ok(entries) => entries.values().filter(|e| e.side == side).cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Note that bytes `start..end` of a file we receive arrived.
+    /// A file received in full leaves the journal. Returns the updated entry,
+    /// None if the transfer is not journaled.
+    pub fn record_received(&self, id: &TransferId, start: u64, end: u64) -> Option<JournalEntry> {
+        let entry = {
+            let mut entries = self.entries.lock().ok()?;
+            let entry = entries.get_mut(&(*id, TransferSide::Receiver))?;
+            entry.received.insert(start, end);
+            entry.updated_at = now_secs();
+            let entry = entry.clone();
+            if entry.received.covers(entry.file_size) {
+                entries.remove(&(*id, TransferSide::Receiver));
+            }
+            entry
+        };
+
+        let due = match self.last_saved.lock() {
+            Ok(last_saved) => last_saved.map(|t| t.elapsed() >= SAVE_INTERVAL).unwrap_or(true),
+            Err(_) => true,
+        };
+        if due || entry.received.covers(entry.file_size) {
+            if let Err(e) = self.save() {
+                warn!("Failed to save the transfer journal ({}).", e);
+            }
+        }
+        Some(entry)
+    }
+
+    /// Write what `record_received` has not written yet.
+    pub fn flush(&self) -> Result<(), io::Error> {
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), io::Error> {
+        // Held until written, saves of concurrent transfers would mix otherwise.
+        let path = match self.path.lock() {
+            Ok(path) => path,
+            Err(_) => return Ok(()),
+        };
+        let path = match path.as_ref() {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        let content: String = match self.entries.lock() {
+            Ok(entries) => entries.values()
+                .filter_map(JournalEntry::to_line)
+                .map(|line| line + "\n")
+                .collect(),
+            Err(_) => return Ok(()),
+        };
+        if let Ok(mut last_saved) = self.last_saved.lock() {
+            *last_saved = Some(Instant::now());
+        }
+        // Private, it tells which files are exchanged with whom.
+        OSUtil::write_private(path, content.as_bytes())
+    }
+}
@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use log::warn;
+use crate::packet::data::local::file_sending_packet::{FileSendingPacket, FileSendingStatus};
+use crate::packet::data::transfer_id::TransferId;
+use crate::service::data_service::OnPacketReceivedFunctionType;
+use crate::service::transfer_journal::{JournalEntry, RESUME_TIMEOUT, TransferJournal};
+use crate::util::os::OSUtil;
+
+/// Offers the receiver has not answered within this time fail.
+pub const DEFAULT_OFFER_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// Finished transfers kept for lookups, the oldest are forgotten first.
+const MAX_FINISHED_TRANSFERS: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TransferSide {
+    Sender,
+    Receiver,
+}
+
+/// Life of a file we send:
+/// Offered -> Accepted | Rejected, Accepted -> Streaming <-> Paused -> Completed,
+/// and Failed or Cancelled from anywhere before that.
+/// Paused transfers wait for the receiver to resume them, see `TransferJournal`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransferState {
+    Offered,
+    Accepted,
+    Rejected,
+    Streaming,
+    /// The connection dropped, waiting to reconnect.
+    Paused,
+    Completed,
+    Failed,
+    Cancelled(TransferSide),
+}
+
+impl TransferState {
+    pub fn is_finished(&self) -> bool {
+        matches!(self,
+            TransferState::Rejected
+            | TransferState::Completed
+            | TransferState::Failed
+            | TransferState::Cancelled(_))
+    }
+
+    /// Whether a transfer in this state may move to `next`.
+    /// Streaming may repeat to report progress.
+    pub fn can_become(&self, next: TransferState) -> bool {
+        use TransferState::*;
+        matches!((self, next),
+            (Offered, Accepted | Rejected | Failed | Cancelled(_))
+            | (Accepted, Paused)
+            | (Accepted | Paused, Streaming | Failed | Cancelled(_))
+            | (Streaming, Streaming | Paused | Completed | Failed | Cancelled(_)))
+    }
+
+    /// Status reported to the file sending callback.
+    pub fn sending_status(&self) -> FileSendingStatus {
+        match self {
+            TransferState::Offered => FileSendingStatus::Requested,
+            TransferState::Accepted => FileSendingStatus::Accepted,
+            TransferState::Rejected => FileSendingStatus::Rejected,
+            TransferState::Streaming | TransferState::Paused => FileSendingStatus::InProgress,
+            TransferState::Completed => FileSendingStatus::Completed,
+            TransferState::Failed => FileSendingStatus::Error,
+            TransferState::Cancelled(TransferSide::Sender) => FileSendingStatus::CancelledBySender,
+            TransferState::Cancelled(TransferSide::Receiver) => FileSendingStatus::CancelledByReceiver,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Transfer {
+    id: TransferId,
+    host: String,
+    file_path: String,
+    file_size: u64,
+    modified_at: Option<u64>,
+    progress: u64,
+    state: TransferState,
+    offered_at: Instant,
+    updated_at: Instant,
+}
+
+impl Transfer {
+    pub fn id(&self) -> TransferId {
+        self.id
+    }
+
+    /// Host the file was offered to.
+    pub fn host(&self) -> &String {
+        &self.host
+    }
+
+    pub fn file_path(&self) -> &String {
+        &self.file_path
+    }
+
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    /// Modification time of the file when offered, in seconds since the UNIX epoch.
+    pub fn modified_at(&self) -> Option<u64> {
+        self.modified_at
+    }
+
+    /// Bytes the receiver has so far.
+    pub fn progress(&self) -> u64 {
+        self.progress
+    }
+
+    pub fn state(&self) -> TransferState {
+        self.state
+    }
+
+    pub fn offered_at(&self) -> Instant {
+        self.offered_at
+    }
+
+    pub fn sending_packet(&self) -> FileSendingPacket {
+        FileSendingPacket::new(self.id, self.progress, self.file_size, self.state.sending_status())
+    }
+}
+
+pub enum TransferError {
+    UnknownTransfer(TransferId),
+    InvalidTransition {
+        from: TransferState,
+        to: TransferState,
+    },
+    OfferExpired,
+}
+
+impl Debug for TransferError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for TransferError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TransferError::UnknownTransfer(id) => write!(f, "Unknown transfer {}.", id),
+            TransferError::InvalidTransition { from, to } =>
+                write!(f, "Transfer cannot go from {:?} to {:?}.", from, to),
+            TransferError::OfferExpired => write!(f, "The offer expired before it was answered."),
+        }
+    }
+}
+
+impl Error for TransferError {}
+
+/// Tracks the files we offered, from the offer to the end of their transfer.
+/// Every change of state is reported to the listener as a `FileSendingPacket`.
+/// Unfinished transfers are kept in the journal, along with the files we receive.
+pub struct TransferManager {
+    transfers: Mutex<HashMap<TransferId, Transfer>>,
+    listener: Mutex<Option<OnPacketReceivedFunctionType<FileSendingPacket, ()>>>,
+    offer_timeout: Duration,
+    journal: Arc<TransferJournal>,
+}
+
+impl Default for TransferManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TransferManager {
+    pub fn new() -> Self {
+        Self::with_offer_timeout(DEFAULT_OFFER_TIMEOUT)
+    }
+
+    pub fn with_offer_timeout(offer_timeout: Duration) -> Self {
+        Self {
+            transfers: Mutex::new(HashMap::new()),
+            listener: Mutex::new(None),
+            offer_timeout,
+            journal: Arc::new(TransferJournal::new()),
+        }
+    }
+
+    pub fn journal(&self) -> Arc<TransferJournal> {
+        self.journal.clone()
+    }
+
+    /// Persist the journal in `directory`. Files we were sending before
+    /// a restart come back paused, waiting for their receiver to resume them.
+    pub fn attach_journal(&self, directory: &Path) -> Result<(), io::Error> {
+        self.journal.attach(directory)?;
+        let now = Instant::now();
+        if let Ok(mut transfers) = self.transfers.lock() {
+            for entry in self.journal.entries(TransferSide::Sender) {
+                transfers.entry(entry.id()).or_insert_with(|| Transfer {
+                    id: entry.id(),
+                    host: entry.host().clone(),
+                    file_path: entry.file().clone(),
+                    file_size: entry.file_size(),
+                    modified_at: entry.modified_at(),
+                    progress: 0,
+                    state: TransferState::Paused,
+                    offered_at: now,
+                    updated_at: now,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Keep the journal in step with `transfer`: unfinished ones stay resumable.
+    fn journal_transfer(&self, transfer: &Transfer) {
+        let result = match transfer.state {
+            TransferState::Offered | TransferState::Paused => self.journal.record(JournalEntry::sending(
+                transfer.id,
+                transfer.host.clone(),
+                transfer.file_path.clone(),
+                transfer.file_size,
+                transfer.modified_at,
+            )),
+            state if state.is_finished() => self.journal.remove(&transfer.id, TransferSide::Sender).map(|_| ()),
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            warn!("Failed to journal transfer {} ({}).", transfer.id, e);
+        }
+    }
+
+    pub fn set_listener(&self, listener: OnPacketReceivedFunctionType<FileSendingPacket, ()>) {
+        if let Ok(mut current) = self.listener.lock() {
+            *current = Some(listener);
+        }
+    }
+
+    fn notify(&self, transfer: &Transfer) {
+        let listener = self.listener.lock().ok().and_then(|l| l.clone());
+        if let Some(listener) = listener {
+            listener(&transfer.sending_packet(), None);
+        }
+    }
+
+    /// Start tracking a file offered to `host`.
+    pub fn offer(&self, id: TransferId, host: String, file_path: String, file_size: u64) {
+        let now = Instant::now();
+        let transfer = Transfer {
+            id,
+            host,
+            modified_at: OSUtil::modified_secs(&file_path),
+            file_path,
+            file_size,
+            progress: 0,
+            state: TransferState::Offered,
+            offered_at: now,
+            updated_at: now,
+        };
+        if let Ok(mut transfers) = self.transfers.lock() {
+            Self::forget_finished(&mut transfers);
+            transfers.insert(id, transfer.clone());
+        }
+        self.journal_transfer(&transfer);
+        self.notify(&transfer);
+    }
+
+    pub fn get(&self, id: &TransferId) -> Option<Transfer> {
+        self.transfers.lock().ok()?.get(id).cloned()
+    }
+
+    /// First transfer matching `predicate`, for answers that do not carry our id.
+    pub fn find<P>(&self, predicate: P) -> Option<Transfer> where P: Fn(&Transfer) -> bool {
+        self.transfers.lock().ok()?.values().find(|t| predicate(t)).cloned()
+    }
+
+    /// Move a transfer to `state` with `progress` bytes sent.
+    /// An offer answered too late fails instead, which is reported as well.
+    pub fn transition(&self, id: &TransferId, state: TransferState, progress: u64) -> Result<Transfer, TransferError> {
+        let mut expired = false;
+        let result = match self.transfers.lock() {
+            Ok(mut transfers) => match transfers.get_mut(id) {
+                Some(transfer) if transfer.state == TransferState::Offered
+                    && transfer.offered_at.elapsed() >= self.offer_timeout => {
+                    transfer.state = TransferState::Failed;
+                    transfer.updated_at = Instant::now();
+                    expired = true;
+                    Ok(transfer.clone())
+                }
+                Some(transfer) if transfer.state.can_become(state) => {
+                    transfer.state = state;
+                    transfer.progress = progress;
+                    transfer.updated_at = Instant::now();
+                    Ok(transfer.clone())
+                }
+                Some(transfer) => Err(TransferError::InvalidTransition { from: transfer.state, to: state }),
+                None => Err(TransferError::UnknownTransfer(*id)),
+            },
+            Err(_) => Err(TransferError::UnknownTransfer(*id)),
+        };
+
+        let transfer = result?;
+        if transfer.state != TransferState::Streaming {
+            self.journal_transfer(&transfer);
+        }
+        self.notify(&transfer);
+        match expired {
+            true => Err(TransferError::OfferExpired),
+            false => Ok(transfer),
+        }
+    }
+
+    /// Fail the offers left unanswered and the paused transfers left unresumed for too long.
+    pub fn expire_offers(&self) -> Vec<Transfer> {
+        let now = Instant::now();
+        let expired = match self.transfers.lock() {
+            Ok(mut transfers) => transfers
+                .values_mut()
+                .filter(|t| match t.state {
+                    TransferState::Offered => now.duration_since(t.offered_at) >= self.offer_timeout,
+                    TransferState::Paused => now.duration_since(t.updated_at) >= RESUME_TIMEOUT,
+                    _ => false,
+                })
+                .map(|t| {
+                    t.state = TransferState::Failed;
+                    t.updated_at = now;
+                    t.clone()
+                })
+                .collect::<Vec<Transfer>>(),
+            Err(_) => Vec::new(),
+        };
+        for transfer in &expired {
+            self.journal_transfer(transfer);
+            self.notify(transfer);
+        }
+        expired
+    }
+
+    fn forget_finished(transfers: &mut HashMap<TransferId, Transfer>) {
+        let mut finished = transfers
+            .values()
+            .filter(|t| t.state.is_finished())
+            .map(|t| (t.updated_at, t.id))
+            .collect::<Vec<(Instant, TransferId)>>();
+        if finished.len() < MAX_FINISHED_TRANSFERS {
+            return;
+        }
+        finished.sort_by_key(|(updated_at, _)| *updated_at);
+        for (_, id) in &finished[..=finished.len() - MAX_FINISHED_TRANSFERS] {
+            transfers.remove(id);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use log::error;
+use crate::service::shutdown::{ShutdownHandle, ShutdownHookGuard};
+
+pub type Job = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct PoolState {
+    jobs: VecDeque<Job>,
+    busy: usize,
+    closed: bool,
+}
+
+struct PoolInner {
+    size: usize,
+    state: Mutex<PoolState>,
+    condvar: Condvar,
+}
+
+impl PoolInner {
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        // Jobs run outside the lock, so poisoning can be ignored.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Fixed set of threads serving jobs in order.
+/// Workers are spawned through the shutdown handle, so `ShutdownHandle::join` waits for them.
+/// On shutdown queued jobs are dropped, otherwise they are served before the workers leave.
+pub struct WorkerPool {
+    inner: Arc<PoolInner>,
+    _shutdown_hook: ShutdownHookGuard,
+}
+
+impl WorkerPool {
+    pub fn new(size: usize, shutdown: &ShutdownHandle) -> Self {
+        let inner = Arc::new(PoolInner {
+            size: size.max(1),
+            state: Mutex::new(PoolState::default()),
+            condvar: Condvar::new(),
+        });
+
+        let hook_inner = inner.clone();
+        let shutdown_hook = shutdown.on_shutdown(Box::new(move || {
+            let _state = hook_inner.lock();
+            hook_inner.condvar.notify_all();
+        }));
+
+        for _ in 0..inner.size {
+            let worker_inner = inner.clone();
+            let worker_shutdown = shutdown.clone();
+            shutdown.spawn(move || Self::work(&worker_inner, &worker_shutdown));
+        }
+        Self { inner, _shutdown_hook: shutdown_hook }
+    }
+
+    pub fn size(&self) -> usize {
+        self.inner.size
+    }
+
+    /// Workers running a job.
+    pub fn busy(&self) -> usize {
+        self.inner.lock().busy
+    }
+
+    /// Jobs waiting for a worker.
+    pub fn queued(&self) -> usize {
+        self.inner.lock().jobs.len()
+    }
+
+    /// Queue `job` for the next free worker.
+    pub fn execute(&self, job: Job) {
+        self.inner.lock().jobs.push_back(job);
+        self.inner.condvar.notify_one();
+    }
+
+    /// Hand `job` to a worker only if one is idle, giving it back otherwise.
+    pub fn try_execute(&self, job: Job) -> Result<(), Job> {
+        let mut state = self.inner.lock();
+        if state.busy + state.jobs.len() >= self.inner.size {
+            return Err(job);
+        }
+        state.jobs.push_back(job);
+        drop(state);
+        self.inner.condvar.notify_one();
+        Ok(())
+    }
+
+    fn work(inner: &PoolInner, shutdown: &ShutdownHandle) {
+        loop {
+            let job = {
+                let mut state = inner.lock();
+                loop {
+                    if shutdown.is_shutdown() {
+                        state.jobs.clear();
+                        return;
+                    }
+                    if let Some(job) = state.jobs.pop_front() {
+                        state.busy += 1;
+                        break job;
+                    }
+                    if state.closed {
+                        return;
+                    }
+                    state = inner.condvar.wait(state).unwrap_or_else(|e| e.into_inner());
+                }
+            };
+
+            // A panicking job must not take the worker with it.
+            if catch_unwind(AssertUnwindSafe(job)).is_err() {
+                error!("Worker job panicked.");
+            }
+            inner.lock().busy -= 1;
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.inner.lock().closed = true;
+        self.inner.condvar.notify_all();
+    }
+}
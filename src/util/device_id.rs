@@ -0,0 +1,44 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use rand::RngCore;
+
+const DEVICE_ID_FILE_NAME: &str = "device_id";
+
+pub struct DeviceId;
+
+impl DeviceId {
+    /// Random UUID (version 4).
+    pub fn generate() -> String {
+        let mut bytes = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        bytes[6] = (bytes[6] & 0x0F) | 0x40;
+        bytes[8] = (bytes[8] & 0x3F) | 0x80;
+
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+    }
+
+    pub fn is_valid(id: &str) -> bool {
+        id.len() == 36 && id.chars().enumerate().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+    }
+
+    /// Read the device ID stored in `directory`, creating it on first use.
+    pub fn load_or_create(directory: &Path) -> Result<String, io::Error> {
+        let path = directory.join(DEVICE_ID_FILE_NAME);
+        if let Ok(id) = fs::read_to_string(&path) {
+            let id = id.trim();
+            if Self::is_valid(id) {
+                return Ok(id.to_string());
+            }
+        }
+
+        fs::create_dir_all(directory)?;
+        let id = Self::generate();
+        fs::write(&path, &id)?;
+        Ok(id)
+    }
+}
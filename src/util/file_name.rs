@@ -0,0 +1,98 @@
+use std::path::Path;
+
+/// Longest file name most file systems accept, in bytes.
+const MAX_FILE_NAME_LENGTH: usize = 255;
+const FALLBACK_FILE_NAME: &str = "file";
+const WINDOWS_RESERVED_NAMES: [&str; 26] = [
+    "CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$",
+    "COM0", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT0", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+/// Unicode format (Cf) characters. They are invisible, and the bidirectional
+/// ones can make "txt.exe" show as "exe.txt".
+const FORMAT_CHARACTERS: [(char, char); 21] = [
+    ('\u{00AD}', '\u{00AD}'), ('\u{0600}', '\u{0605}'), ('\u{061C}', '\u{061C}'),
+    ('\u{06DD}', '\u{06DD}'), ('\u{070F}', '\u{070F}'), ('\u{0890}', '\u{0891}'),
+    ('\u{08E2}', '\u{08E2}'), ('\u{180E}', '\u{180E}'), ('\u{200B}', '\u{200F}'),
+    ('\u{202A}', '\u{202E}'), ('\u{2060}', '\u{2064}'), ('\u{2066}', '\u{206F}'),
+    ('\u{FEFF}', '\u{FEFF}'), ('\u{FFF9}', '\u{FFFB}'), ('\u{110BD}', '\u{110BD}'),
+    ('\u{110CD}', '\u{110CD}'), ('\u{13430}', '\u{1343F}'), ('\u{1BCA0}', '\u{1BCA3}'),
+    ('\u{1D173}', '\u{1D17A}'), ('\u{E0001}', '\u{E0001}'), ('\u{E0020}', '\u{E007F}'),
+];
+
+fn is_format(c: char) -> bool {
+    FORMAT_CHARACTERS.iter().any(|(first, last)| (*first..=*last).contains(&c))
+}
+
+pub struct FileNameUtil;
+
+impl FileNameUtil {
+    /// Name of the file at `path`, without its directories.
+    pub fn display_name(path: &str) -> String {
+        match Path::new(path).file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => Self::sanitize(path),
+        }
+    }
+
+    /// Make a file name received from a peer safe to save on any platform:
+    /// directories are dropped, whatever the separator, and so are characters
+    /// Windows rejects and invisible format characters. Reserved device names
+    /// get a leading underscore.
+    pub fn sanitize(name: &str) -> String {
+        let base = name.rsplit(['/', '\\']).next().unwrap_or_default();
+        let cleaned = base
+            .chars()
+            .filter(|c| !c.is_control() && !is_format(*c) && !matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+            .collect::<String>();
+        let cleaned = cleaned.trim().trim_end_matches(['.', ' ']);
+        if cleaned.is_empty() || cleaned == "." || cleaned == ".." {
+            return String::from(FALLBACK_FILE_NAME);
+        }
+
+        let stem = cleaned.split('.').next().unwrap_or_default().trim_end();
+        let mut sanitized = match WINDOWS_RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+            true => format!("_{}", cleaned),
+            false => cleaned.to_string(),
+        };
+        if sanitized.len() > MAX_FILE_NAME_LENGTH {
+            let mut end = MAX_FILE_NAME_LENGTH;
+            while !sanitized.is_char_boundary(end) {
+                end -= 1;
+            }
+            sanitized.truncate(end);
+        }
+        sanitized
+    }
+
+    /// MIME type of common files, guessed from the extension.
+    pub fn mime_type(name: &str) -> Option<&'static str> {
+        let extension = Path::new(name).extension()?.to_str()?.to_ascii_lowercase();
+        Some(match extension.as_str() {
+            "txt" | "log" => "text/plain",
+            "md" => "text/markdown",
+            "html" | "htm" => "text/html",
+            "csv" => "text/csv",
+            "json" => "application/json",
+            "pdf" => "application/pdf",
+            "zip" => "application/zip",
+            "gz" => "application/gzip",
+            "apk" => "application/vnd.android.package-archive",
+            "png" => "image/png",
+            "jpg" | "jpeg" => "image/jpeg",
+            "gif" => "image/gif",
+            "webp" => "image/webp",
+            "heic" => "image/heic",
+            "svg" => "image/svg+xml",
+            "mp3" => "audio/mpeg",
+            "m4a" => "audio/mp4",
+            "wav" => "audio/wav",
+            "flac" => "audio/flac",
+            "mp4" => "video/mp4",
+            "mov" => "video/quicktime",
+            "mkv" => "video/x-matroska",
+            "webm" => "video/webm",
+            _ => return None,
+        })
+    }
+}
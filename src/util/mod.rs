@@ -1,2 +1,4 @@
+pub mod device_id;
 pub mod network;
 pub mod os;
+pub mod file_name;
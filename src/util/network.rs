@@ -1 +1,17 @@
-// 震惊！这个文件居然没有用到～
+pub struct NetworkUtil;
+
+impl NetworkUtil {
+    /// Index of the network interface, or 0 (let the OS choose) if unknown.
+    #[cfg(unix)]
+    pub fn interface_index(name: &str) -> u32 {
+        match std::ffi::CString::new(name) {
+            Ok(name) => unsafe { libc::if_nametoindex(name.as_ptr()) },
+            Err(_) => 0,
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn interface_index(_: &str) -> u32 {
+        0
+    }
+}
@@ -1,3 +1,9 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
 pub struct OSUtil;
 
 impl OSUtil {
@@ -10,4 +16,33 @@ impl OSUtil {
             Err(_) => String::from("<empty>"),
         }
     }
+
+    /// Write a file only we may read, through a temporary file so that
+    /// a crash never leaves half of it behind.
+    pub fn write_private(path: &Path, contents: &[u8]) -> Result<(), io::Error> {
+        let temp_path = path.with_extension("tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&temp_path)?;
+        // The mode only applies to new files, a leftover temporary file keeps its own.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        }
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)
+    }
+
+    /// Last modification of the file at `path`, in seconds since the UNIX epoch.
+    pub fn modified_secs(path: &str) -> Option<u64> {
+        let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+        Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+    }
 }
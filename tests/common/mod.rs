@@ -0,0 +1,86 @@
+// Fixtures shared by the integration tests. Each test crate uses only some of them.
+#![allow(dead_code)]
+
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use socket2::{Domain, Socket, Type};
+use airx::network::peer::Peer;
+use airx::packet::data::file_coming_packet::FileComingPacket;
+use airx::packet::data::file_part_packet::FilePartPacket;
+use airx::packet::data::local::file_sending_packet::FileSendingPacket;
+use airx::packet::data::text_packet::TextPacket;
+use airx::security::group_key::GroupKey;
+use airx::service::context::data_service_context::DataServiceContext;
+use airx::service::data_service::OnPacketReceivedFunctionType;
+use airx::service::discovery_service::DiscoveryService;
+use airx::service::metrics::ServiceMetrics;
+
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+pub fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+/// Connect to the service at 127.0.0.1 from `source`, another loopback address
+/// when a test needs a second peer. Only Linux routes all of 127.0.0.0/8 to loopback.
+pub fn connect_from(source: &str, port: u16) -> TcpStream {
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+    socket.bind(&SocketAddr::new(ip(source), 0).into()).unwrap();
+    socket.connect(&SocketAddr::new(ip("127.0.0.1"), port).into()).unwrap();
+    socket.into()
+}
+
+/// What a data service context is made of, ignoring every packet unless replaced.
+pub struct DataServiceParts {
+    pub text_callback: OnPacketReceivedFunctionType<TextPacket, ()>,
+    pub file_coming_callback: OnPacketReceivedFunctionType<FileComingPacket, ()>,
+    pub file_sending_callback: OnPacketReceivedFunctionType<FileSendingPacket, ()>,
+    pub file_part_callback: OnPacketReceivedFunctionType<FilePartPacket, bool>,
+    pub discovery_service: Arc<DiscoveryService>,
+    pub metrics: Arc<ServiceMetrics>,
+    pub group_key: Option<GroupKey>,
+}
+
+impl Default for DataServiceParts {
+    fn default() -> Self {
+        Self {
+            text_callback: Arc::new(Box::new(|_, _| ())),
+            file_coming_callback: Arc::new(Box::new(|_, _| ())),
+            file_sending_callback: Arc::new(Box::new(|_, _| ())),
+            file_part_callback: Arc::new(Box::new(|_, _| false)),
+            discovery_service: Arc::new(DiscoveryService::new()),
+            metrics: Arc::new(ServiceMetrics::new()),
+            group_key: None,
+        }
+    }
+}
+
+impl DataServiceParts {
+    pub fn into_context(self, host: &str, port: u16) -> DataServiceContext {
+        DataServiceContext::new(
+            String::from(host),
+            port,
+            self.text_callback,
+            self.file_coming_callback,
+            self.file_sending_callback,
+            self.file_part_callback,
+            self.discovery_service,
+            self.metrics,
+            self.group_key,
+        )
+    }
+}
+
+/// Text callback passing every received text on to the returned receiver.
+pub fn text_channel() -> (OnPacketReceivedFunctionType<TextPacket, ()>, mpsc::Receiver<String>) {
+    let (text_tx, texts) = mpsc::channel::<String>();
+    let text_tx = Mutex::new(text_tx);
+    let callback: OnPacketReceivedFunctionType<TextPacket, ()> =
+        Arc::new(Box::new(move |packet: &TextPacket, _: Option<&Peer>| {
+            let _ = text_tx.lock().unwrap().send(packet.text().clone());
+        }));
+    (callback, texts)
+}
@@ -0,0 +1,208 @@
+// The service tests connect from 127.0.0.2 and 127.0.0.3, which only Linux routes to loopback.
+#![cfg_attr(not(target_os = "linux"), allow(dead_code, unused_imports))]
+
+mod common;
+
+use std::io::Read;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+use airx::network::peer::Peer;
+use airx::packet::data::magic_numbers::MagicNumbers;
+use airx::packet::data::text_packet::TextPacket;
+use airx::packet::data_packet::DataPacket;
+use airx::packet::data_transmission::DataTransmit;
+use airx::packet::protocol::serialize::Serialize;
+use airx::security::access_policy::{AccessAction, AccessControl, AccessPolicy, AccessRule, Cidr};
+use airx::service::data_service::DataService;
+use airx::service::discovery_service::DiscoveryService;
+use airx::service::metrics::ServiceMetrics;
+use common::{DataServiceParts, free_port, ip, text_channel};
+
+#[test]
+fn test_cidr_contains() {
+    let cidr = Cidr::parse("192.168.1.0/24").unwrap();
+    assert!(cidr.contains(&ip("192.168.1.77")));
+    assert!(!cidr.contains(&ip("192.168.2.1")));
+    assert!(cidr.contains(&ip("::ffff:192.168.1.5")));
+
+    let cidr = Cidr::parse("10.0.0.0/9").unwrap();
+    assert!(cidr.contains(&ip("10.127.255.255")));
+    assert!(!cidr.contains(&ip("10.128.0.0")));
+
+    assert!(Cidr::parse("fd00::/8").unwrap().contains(&ip("fd12::1")));
+    assert!(Cidr::parse("0.0.0.0/0").unwrap().contains(&ip("8.8.8.8")));
+    assert!(Cidr::parse("127.0.0.1").unwrap().contains(&ip("127.0.0.1")));
+    assert!(Cidr::parse("127.0.0.1/33").is_err());
+    assert!(Cidr::parse("host/24").is_err());
+}
+
+#[test]
+fn test_rule_parse() {
+    assert!(matches!(AccessRule::parse("10.0.0.0/8").unwrap(), AccessRule::Cidr(_)));
+    assert!(matches!(AccessRule::parse("::1").unwrap(), AccessRule::Cidr(_)));
+    assert_eq!(
+        AccessRule::parse("0f8e1f3c-2b1a-4c57-9d6e-1a2b3c4d5e6f").unwrap(),
+        AccessRule::DeviceId(String::from("0f8e1f3c-2b1a-4c57-9d6e-1a2b3c4d5e6f")));
+    assert!(AccessRule::parse("").is_err());
+    assert!(AccessRule::parse("a b").is_err());
+}
+
+#[test]
+fn test_deny_wins_over_allow() {
+    let device = String::from("device-a");
+    let policy = AccessPolicy::new(AccessAction::Deny)
+        .allow("192.168.1.0/24").unwrap()
+        .deny("192.168.1.13").unwrap()
+        .deny("device-a").unwrap();
+
+    assert_eq!(policy.evaluate(&ip("192.168.1.12"), None), AccessAction::Allow);
+    assert_eq!(policy.evaluate(&ip("192.168.1.13"), None), AccessAction::Deny);
+    assert_eq!(policy.evaluate(&ip("192.168.1.12"), Some(&device)), AccessAction::Deny);
+    assert_eq!(policy.evaluate(&ip("10.0.0.1"), None), AccessAction::Deny);
+    assert_eq!(AccessPolicy::default().evaluate(&ip("10.0.0.1"), None), AccessAction::Allow);
+}
+
+struct Receiver {
+    port: u16,
+    texts: mpsc::Receiver<String>,
+    rejections: mpsc::Receiver<SocketAddr>,
+    metrics: Arc<ServiceMetrics>,
+    discovery_service: Arc<DiscoveryService>,
+    stopped: Arc<AtomicBool>,
+    service: std::thread::JoinHandle<Result<(), airx::error::AirXError>>,
+}
+
+impl Receiver {
+    fn start(access_control: Arc<AccessControl>) -> Self {
+        let port = free_port();
+        let (text_callback, texts) = text_channel();
+        let (rejection_tx, rejections) = mpsc::channel::<SocketAddr>();
+        let rejection_tx = Mutex::new(rejection_tx);
+        let metrics = Arc::new(ServiceMetrics::new());
+        let discovery_service = Arc::new(DiscoveryService::new());
+
+        let mut context = DataServiceParts {
+            text_callback,
+            discovery_service: discovery_service.clone(),
+            metrics: metrics.clone(),
+            ..DataServiceParts::default()
+        }.into_context("127.0.0.1", port);
+        context.set_access_control(access_control, Some(Arc::new(Box::new(
+            move |socket_addr: &SocketAddr, _: Option<&Peer>| {
+                let _ = rejection_tx.lock().unwrap().send(*socket_addr);
+            }))));
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = stopped.clone();
+        let service = std::thread::spawn(move || {
+            DataService::run(context, Box::new(move || thread_stopped.load(Ordering::SeqCst)))
+        });
+        std::thread::sleep(Duration::from_millis(200));
+
+        Self { port, texts, rejections, metrics, discovery_service, stopped, service }
+    }
+
+    /// Connect from `source`, another loopback address when testing outsiders.
+    fn connect_from(&self, source: &str) -> TcpStream {
+        common::connect_from(source, self.port)
+    }
+
+    fn send_from(&self, source: &str, text: &str) {
+        let mut dt = DataTransmit::from(self.connect_from(source));
+        let packet = DataPacket::new(
+            MagicNumbers::Text.value(), &TextPacket::new(text.to_string()).unwrap().serialize());
+        let _ = dt.send_data_progress_with_retry(&packet.serialize(), |_| ());
+    }
+
+    fn stop(self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.service.join().unwrap().unwrap();
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_outsider_is_rejected_before_payload() {
+    let policy = AccessPolicy::new(AccessAction::Deny).allow("127.0.0.1/32").unwrap();
+    let receiver = Receiver::start(Arc::new(AccessControl::new(policy)));
+
+    // Nothing is sent, yet the connection is closed right away.
+    let mut stream = receiver.connect_from("127.0.0.2");
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut buf = [0u8; 1];
+    assert_eq!(stream.read(&mut buf).unwrap(), 0);
+
+    let rejected = receiver.rejections.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(rejected.ip(), ip("127.0.0.2"));
+
+    receiver.send_from("127.0.0.2", "from outside");
+    receiver.send_from("127.0.0.1", "from inside");
+    assert_eq!(receiver.texts.recv_timeout(Duration::from_secs(5)).unwrap(), "from inside");
+    assert!(receiver.texts.recv_timeout(Duration::from_millis(300)).is_err());
+    assert_eq!(receiver.metrics.rejected_connections(), 2);
+
+    receiver.stop();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_policy_updates_at_runtime() {
+    let access_control = Arc::new(AccessControl::new(AccessPolicy::default()));
+    let receiver = Receiver::start(access_control.clone());
+
+    receiver.send_from("127.0.0.2", "before");
+    assert_eq!(receiver.texts.recv_timeout(Duration::from_secs(5)).unwrap(), "before");
+
+    access_control.set_policy(AccessPolicy::default().deny("127.0.0.0/30").unwrap());
+    receiver.send_from("127.0.0.2", "after");
+    assert_eq!(receiver.rejections.recv_timeout(Duration::from_secs(5)).unwrap().ip(), ip("127.0.0.2"));
+    assert!(receiver.texts.recv_timeout(Duration::from_millis(300)).is_err());
+
+    receiver.stop();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_denied_device_id() {
+    let access_control = Arc::new(AccessControl::new(AccessPolicy::default().deny("device-a").unwrap()));
+    let receiver = Receiver::start(access_control);
+
+    let mut peer = Peer::new(&String::from("127.0.0.3"), receiver.port, None);
+    peer.set_device_id(Some(String::from("device-a")));
+    peer.set_authenticated(true);
+    receiver.discovery_service.peers().lock().unwrap().insert(peer);
+
+    receiver.send_from("127.0.0.3", "from denied device");
+    receiver.send_from("127.0.0.2", "from someone else");
+    assert_eq!(receiver.rejections.recv_timeout(Duration::from_secs(5)).unwrap().ip(), ip("127.0.0.3"));
+    assert_eq!(receiver.texts.recv_timeout(Duration::from_secs(5)).unwrap(), "from someone else");
+    assert!(receiver.texts.recv_timeout(Duration::from_millis(300)).is_err());
+
+    receiver.stop();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_unauthenticated_device_id_is_not_believed() {
+    let policy = AccessPolicy::new(AccessAction::Deny).allow("device-a").unwrap();
+    let receiver = Receiver::start(Arc::new(AccessControl::new(policy)));
+
+    // Anyone can announce any device ID without the group key.
+    let mut peer = Peer::new(&String::from("127.0.0.3"), receiver.port, None);
+    peer.set_device_id(Some(String::from("device-a")));
+    receiver.discovery_service.peers().lock().unwrap().insert(peer.clone());
+
+    receiver.send_from("127.0.0.3", "from claimed device");
+    assert_eq!(receiver.rejections.recv_timeout(Duration::from_secs(5)).unwrap().ip(), ip("127.0.0.3"));
+    assert!(receiver.texts.recv_timeout(Duration::from_millis(300)).is_err());
+
+    peer.set_authenticated(true);
+    receiver.discovery_service.peers().lock().unwrap().replace(peer);
+    receiver.send_from("127.0.0.3", "from authenticated device");
+    assert_eq!(receiver.texts.recv_timeout(Duration::from_secs(5)).unwrap(), "from authenticated device");
+
+    receiver.stop();
+}
@@ -0,0 +1,130 @@
+#![cfg(feature = "tokio")]
+
+mod common;
+
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use airx::network::peer::Peer;
+use airx::packet::data::magic_numbers::MagicNumbers;
+use airx::packet::data::text_packet::TextPacket;
+use airx::packet::protocol::serialize::Serialize;
+use airx::security::group_key::GroupKey;
+use airx::security::identity::DeviceIdentity;
+use airx::service::async_data_service::AsyncDataService;
+use airx::service::async_discovery_service::AsyncDiscoveryService;
+use airx::service::context::data_service_context::DataServiceContext;
+use airx::service::context::discovery_service_context::{DiscoveryMode, DiscoveryServiceContext, MulticastConfig};
+use airx::service::data_service::DataService;
+use airx::service::shutdown::ShutdownHandle;
+use common::{DataServiceParts, free_port, text_channel};
+
+fn text_context(port: u16, group_key: Option<GroupKey>, shutdown: &ShutdownHandle) -> (DataServiceContext, mpsc::Receiver<String>) {
+    let (text_callback, texts) = text_channel();
+    let mut context = DataServiceParts { text_callback, group_key, ..DataServiceParts::default() }
+        .into_context("127.0.0.1", port);
+    context.set_shutdown(shutdown.clone());
+    (context, texts)
+}
+
+fn text(s: &str) -> Vec<u8> {
+    TextPacket::new(s.to_string()).unwrap().serialize()
+}
+
+async fn recv_text(texts: mpsc::Receiver<String>) -> String {
+    tokio::task::spawn_blocking(move || texts.recv_timeout(Duration::from_secs(5)).unwrap()).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_async_service_receives_from_blocking_sender() {
+    let port = free_port();
+    let shutdown = ShutdownHandle::new();
+    let group_key = GroupKey::from_passphrase("miku");
+    let (context, texts) = text_context(port, Some(group_key.clone()), &shutdown);
+    let service = tokio::spawn(AsyncDataService::run(context));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let identity = DeviceIdentity::generate();
+    tokio::task::spawn_blocking(move || {
+        DataService::send_once_with_retry(
+            &Peer::new(&String::from("127.0.0.1"), port, None),
+            port,
+            MagicNumbers::Text,
+            &text("from blocking"),
+            Duration::from_millis(1000),
+            Some(&group_key),
+            Some(&identity),
+        ).unwrap();
+    }).await.unwrap();
+    assert_eq!(recv_text(texts).await, "from blocking");
+
+    shutdown.shutdown();
+    service.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_async_sender_reaches_blocking_service() {
+    let port = free_port();
+    let shutdown = ShutdownHandle::new();
+    let group_key = GroupKey::from_passphrase("miku");
+    let (context, texts) = text_context(port, Some(group_key.clone()), &shutdown);
+    let stopped = Arc::new(AtomicBool::new(false));
+    let thread_stopped = stopped.clone();
+    let service = std::thread::spawn(move || {
+        DataService::run(context, Box::new(move || thread_stopped.load(Ordering::SeqCst)))
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    AsyncDataService::send_once(
+        &Peer::new(&String::from("127.0.0.1"), port, None),
+        port,
+        MagicNumbers::Text,
+        &text("from async"),
+        Duration::from_millis(1000),
+        Some(&group_key),
+        Some(&DeviceIdentity::generate()),
+    ).await.unwrap();
+    assert_eq!(recv_text(texts).await, "from async");
+
+    tokio::task::spawn_blocking(move || shutdown.stop()).await.unwrap();
+    service.join().unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_async_service_stops_with_idle_session() {
+    let port = free_port();
+    let shutdown = ShutdownHandle::new();
+    let (context, _texts) = text_context(port, None, &shutdown);
+    let service = tokio::spawn(AsyncDataService::run(context));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let _idle = tokio::net::TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let started = Instant::now();
+    shutdown.shutdown();
+    tokio::time::timeout(Duration::from_secs(2), service).await.unwrap().unwrap().unwrap();
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[tokio::test]
+async fn test_async_discovery_stops() {
+    let server_port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+    let multicast = MulticastConfig {
+        group_v4: Ipv4Addr::new(239, 255, 65, 91),
+        ..MulticastConfig::default()
+    };
+    let mut context = DiscoveryServiceContext::new(
+        0, server_port, 114514, None, false, DiscoveryMode::Multicast, multicast);
+    let shutdown = ShutdownHandle::new();
+    context.set_shutdown(shutdown.clone());
+
+    let service = tokio::spawn(AsyncDiscoveryService::run(context, Arc::new(Mutex::new(HashSet::new()))));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    shutdown.shutdown();
+    tokio::time::timeout(Duration::from_secs(2), service).await.unwrap().unwrap().unwrap();
+}
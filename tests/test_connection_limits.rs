@@ -0,0 +1,216 @@
+mod common;
+
+use std::io::Read;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::time::Duration;
+use airx::network::peer::Peer;
+use airx::packet::data::magic_numbers::MagicNumbers;
+use airx::packet::data::text_packet::TextPacket;
+use airx::packet::data_packet::DataPacket;
+use airx::packet::data_transmission::DataTransmit;
+use airx::packet::protocol::serialize::Serialize;
+use airx::service::connection_limiter::ConnectionLimiter;
+use airx::service::context::data_service_context::{BacklogPolicy, ConnectionLimits};
+use airx::service::data_service::DataService;
+use airx::service::metrics::ServiceMetrics;
+use airx::service::shutdown::ShutdownHandle;
+use airx::service::worker_pool::WorkerPool;
+use common::{DataServiceParts, free_port, ip};
+
+fn limits(worker_pool_size: usize, max_connections_per_peer: usize, backlog_policy: BacklogPolicy) -> ConnectionLimits {
+    ConnectionLimits {
+        worker_pool_size,
+        max_connections: 0,
+        max_connections_per_peer,
+        backlog_policy,
+        idle_timeout: Duration::ZERO,
+    }
+}
+
+#[test]
+fn test_limiter_counts_total_and_per_peer() {
+    let limiter = ConnectionLimiter::new(&ConnectionLimits {
+        max_connections: 3,
+        max_connections_per_peer: 2,
+        ..ConnectionLimits::default()
+    });
+
+    let a1 = limiter.try_acquire(ip("10.0.0.1")).unwrap();
+    let _a2 = limiter.try_acquire(ip("::ffff:10.0.0.1")).unwrap();
+    assert!(limiter.try_acquire(ip("10.0.0.1")).is_none());
+    assert_eq!(limiter.active_from(&ip("10.0.0.1")), 2);
+
+    let _b1 = limiter.try_acquire(ip("10.0.0.2")).unwrap();
+    assert!(limiter.try_acquire(ip("10.0.0.3")).is_none());
+
+    drop(a1);
+    assert_eq!(limiter.active(), 2);
+    assert!(limiter.try_acquire(ip("10.0.0.3")).is_some());
+}
+
+#[test]
+fn test_worker_pool_refuses_when_busy() {
+    let shutdown = ShutdownHandle::new();
+    let pool = WorkerPool::new(1, &shutdown);
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    let (done_tx, done_rx) = mpsc::channel::<u32>();
+
+    let first_done = done_tx.clone();
+    pool.execute(Box::new(move || {
+        release_rx.recv().unwrap();
+        first_done.send(1).unwrap();
+    }));
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(pool.busy(), 1);
+    assert!(pool.try_execute(Box::new(|| ())).is_err());
+
+    // Queued jobs wait for the busy worker.
+    pool.execute(Box::new(move || done_tx.send(2).unwrap()));
+    assert_eq!(pool.queued(), 1);
+    release_tx.send(()).unwrap();
+    assert_eq!(done_rx.recv_timeout(Duration::from_secs(5)).unwrap(), 1);
+    assert_eq!(done_rx.recv_timeout(Duration::from_secs(5)).unwrap(), 2);
+
+    shutdown.stop();
+}
+
+/// Text that keeps the worker handling it busy until released.
+const HOLD: &str = "hold";
+
+struct Receiver {
+    port: u16,
+    texts: mpsc::Receiver<String>,
+    release: mpsc::Sender<()>,
+    metrics: Arc<ServiceMetrics>,
+    shutdown: ShutdownHandle,
+}
+
+impl Receiver {
+    fn start(connection_limits: ConnectionLimits) -> Self {
+        let port = free_port();
+        let (text_tx, texts) = mpsc::channel::<String>();
+        let text_tx = Mutex::new(text_tx);
+        let (release, released) = mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        let metrics = Arc::new(ServiceMetrics::new());
+        let shutdown = ShutdownHandle::new();
+
+        let mut context = DataServiceParts {
+            text_callback: Arc::new(Box::new(move |packet: &TextPacket, _: Option<&Peer>| {
+                let _ = text_tx.lock().unwrap().send(packet.text().clone());
+                if packet.text() == HOLD {
+                    let _ = released.lock().unwrap().recv();
+                }
+            })),
+            metrics: metrics.clone(),
+            ..DataServiceParts::default()
+        }.into_context("127.0.0.1", port);
+        context.set_shutdown(shutdown.clone());
+        context.set_connection_limits(connection_limits);
+
+        std::thread::spawn(move || DataService::run(context, Box::new(|| false)));
+        std::thread::sleep(Duration::from_millis(200));
+        Self { port, texts, release, metrics, shutdown }
+    }
+
+    fn connect_from(&self, source: &str) -> TcpStream {
+        let stream = common::connect_from(source, self.port);
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream
+    }
+
+    fn send_from(&self, source: &str, text: &str) {
+        let mut dt = DataTransmit::from(self.connect_from(source));
+        let packet = DataPacket::new(
+            MagicNumbers::Text.value(), &TextPacket::new(text.to_string()).unwrap().serialize());
+        let _ = dt.send_data_progress_with_retry(&packet.serialize(), |_| ());
+    }
+}
+
+fn is_closed(stream: &mut TcpStream) -> bool {
+    let mut buf = [0u8; 1];
+    matches!(stream.read(&mut buf), Ok(0))
+}
+
+#[test]
+fn test_busy_workers_reject_new_connections() {
+    let receiver = Receiver::start(limits(1, 0, BacklogPolicy::Reject));
+
+    receiver.send_from("127.0.0.1", HOLD);
+    assert_eq!(receiver.texts.recv_timeout(Duration::from_secs(5)).unwrap(), HOLD);
+    let mut refused = receiver.connect_from("127.0.0.1");
+    assert!(is_closed(&mut refused));
+    assert_eq!(receiver.metrics.limited_connections(), 1);
+
+    receiver.release.send(()).unwrap();
+    receiver.shutdown.stop();
+}
+
+#[test]
+fn test_busy_workers_queue_new_connections() {
+    let receiver = Receiver::start(limits(1, 0, BacklogPolicy::Queue));
+
+    let idle = receiver.connect_from("127.0.0.1");
+    std::thread::sleep(Duration::from_millis(100));
+    receiver.send_from("127.0.0.1", "waited");
+    assert!(receiver.texts.recv_timeout(Duration::from_millis(300)).is_err());
+
+    drop(idle);
+    assert_eq!(receiver.texts.recv_timeout(Duration::from_secs(5)).unwrap(), "waited");
+    assert_eq!(receiver.metrics.limited_connections(), 0);
+
+    receiver.shutdown.stop();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_per_peer_limit() {
+    let receiver = Receiver::start(limits(4, 1, BacklogPolicy::Queue));
+
+    let _idle = receiver.connect_from("127.0.0.2");
+    std::thread::sleep(Duration::from_millis(100));
+    let mut refused = receiver.connect_from("127.0.0.2");
+    assert!(is_closed(&mut refused));
+
+    receiver.send_from("127.0.0.3", "someone else");
+    assert_eq!(receiver.texts.recv_timeout(Duration::from_secs(5)).unwrap(), "someone else");
+    assert_eq!(receiver.metrics.limited_connections(), 1);
+
+    receiver.shutdown.stop();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_idle_peer_does_not_starve_others() {
+    let defaults = ConnectionLimits::default();
+    assert!(defaults.max_connections_per_peer < defaults.worker_pool_size);
+    let receiver = Receiver::start(defaults);
+
+    let idle: Vec<TcpStream> = (0..defaults.worker_pool_size)
+        .map(|_| receiver.connect_from("127.0.0.2"))
+        .collect();
+    receiver.send_from("127.0.0.3", "someone else");
+    assert_eq!(receiver.texts.recv_timeout(Duration::from_secs(5)).unwrap(), "someone else");
+
+    drop(idle);
+    receiver.shutdown.stop();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_idle_connections_are_closed() {
+    let receiver = Receiver::start(ConnectionLimits {
+        idle_timeout: Duration::from_millis(300),
+        ..limits(1, 0, BacklogPolicy::Queue)
+    });
+
+    let mut idle = receiver.connect_from("127.0.0.2");
+    receiver.send_from("127.0.0.3", "after the idle one");
+    assert_eq!(receiver.texts.recv_timeout(Duration::from_secs(5)).unwrap(), "after the idle one");
+    assert!(is_closed(&mut idle));
+
+    receiver.shutdown.stop();
+}
+
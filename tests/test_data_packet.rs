@@ -1,5 +1,5 @@
 use airx::packet::data::text_packet::TextPacket;
-use airx::packet::data_packet::DataPacket;
+use airx::packet::data_packet::{DataPacket, DataPacketError, LEGACY_DATA_PACKET_VERSION};
 use airx::packet::protocol::serialize::Serialize;
 
 #[test]
@@ -22,3 +22,39 @@ fn test_text_packet_serializable() {
 
     assert_eq!(packet2.text, test_string);
 }
+
+#[test]
+fn test_data_packet_detects_corruption() {
+    let data_packet = DataPacket::new(1145u16, &vec![1, 9, 1, 9, 8, 1, 0]);
+    let mut bytes = data_packet.serialize();
+
+    // Same length, different payload.
+    bytes[8] ^= 0xff;
+
+    assert!(matches!(DataPacket::deserialize(&bytes), Err(DataPacketError::ChecksumMismatch)));
+}
+
+#[test]
+fn test_data_packet_accepts_legacy_format() {
+    // magic number, data length, data, hash of (data_length).
+    let mut bytes = vec![0x40, 0x39, 4, 0, 0, 0];
+    bytes.extend_from_slice(&[1, 2, 3, 4]);
+    bytes.extend_from_slice(&[2, 0]);
+
+    let data_packet = DataPacket::deserialize(&bytes).unwrap();
+    assert_eq!(data_packet.version(), LEGACY_DATA_PACKET_VERSION);
+    assert_eq!(data_packet.magic_number(), 0x3940);
+    assert_eq!(data_packet.data(), &vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn test_data_packet_rejects_malformed() {
+    let bytes = DataPacket::new(0x3940, &vec![1, 2, 3, 4]).serialize();
+    for len in 0..bytes.len() {
+        assert!(DataPacket::deserialize(&bytes[..len].to_vec()).is_err());
+    }
+
+    // Legacy data length far past the end.
+    let bytes = vec![0x40, 0x39, 0xff, 0xff, 0xff, 0xff, 0, 0];
+    assert!(matches!(DataPacket::deserialize(&bytes), Err(DataPacketError::CorruptedData)));
+}
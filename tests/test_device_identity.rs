@@ -0,0 +1,83 @@
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+use airx::extension::ip_to_u32::ConvertIpU32;
+use airx::network::peer::Peer;
+use airx::proto::discovery_packet::DiscoveryPacket;
+use airx::service::context::discovery_service_context::{DiscoveryMode, DiscoveryServiceContext, MulticastConfig};
+use airx::service::discovery_service::DiscoveryService;
+use airx::util::device_id::DeviceId;
+
+const DEVICE_ID: &str = "0b3c7c1e-5a8e-4f61-9d2a-3f0e6b7d8c9a";
+
+fn discovery_packet(address: Ipv4Addr, device_id: &str) -> DiscoveryPacket {
+    let mut packet = DiscoveryPacket::new();
+    packet.set_address(address.to_u32());
+    packet.set_server_port(9818);
+    packet.set_group_identifier(114514);
+    packet.set_need_response(false);
+    packet.set_host_name(String::from("B612"));
+    packet.set_device_id(String::from(device_id));
+    packet
+}
+
+#[test]
+fn test_device_id_is_persistent() {
+    let directory = std::env::temp_dir().join(format!("airx-test-{}", DeviceId::generate()));
+    let id = DeviceId::load_or_create(&directory).unwrap();
+    assert!(DeviceId::is_valid(&id));
+    assert_eq!(DeviceId::load_or_create(&directory).unwrap(), id);
+    assert_ne!(DeviceId::generate(), id);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+/// One device on two NICs is one peer, found by either address.
+#[test]
+fn test_peer_keyed_by_device_id() {
+    let service = DiscoveryService::new();
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut context = DiscoveryServiceContext::new(
+        0, 0, 114514, None, false, DiscoveryMode::Broadcast, MulticastConfig::default());
+    context.set_device_id(Some(DeviceId::generate()));
+
+    for address in [Ipv4Addr::new(10, 9, 9, 9), Ipv4Addr::new(192, 168, 1, 9)] {
+        let source = SocketAddr::new(address.into(), 9818);
+        DiscoveryService::handle_new_peer(
+            HashSet::new(), &socket, service.peers(), discovery_packet(address, DEVICE_ID), source, &context,
+        ).unwrap();
+    }
+
+    assert_eq!(service.peers().lock().unwrap().len(), 1);
+    for address in ["10.9.9.9:1", "192.168.1.9:1"] {
+        let peer = service.peer_lookup(&address.parse().unwrap()).unwrap();
+        assert_eq!(peer.device_id().unwrap(), DEVICE_ID);
+        assert_eq!(peer.addresses().len(), 2);
+    }
+    assert!(service.peer_lookup(&"10.0.0.1:1".parse().unwrap()).is_none());
+
+    // Our own packets are recognized by device ID even from an unknown address.
+    let own = discovery_packet(Ipv4Addr::new(10, 7, 7, 7), context.device_id().unwrap());
+    assert!(DiscoveryService::handle_new_peer(
+        HashSet::new(), &socket, service.peers(), own, "10.7.7.7:9818".parse().unwrap(), &context,
+    ).is_err());
+}
+
+#[test]
+fn test_peer_addresses_age_out() {
+    let host = |h: &str| Peer::new(&String::from(h), 9818, None);
+    let mut peer = host("10.9.9.9");
+    peer.merge_addresses(&host("192.168.1.9"), Duration::from_secs(60));
+    assert_eq!(peer.addresses().len(), 2);
+
+    std::thread::sleep(Duration::from_millis(50));
+    let mut moved = host("172.16.0.9");
+    moved.merge_addresses(&peer, Duration::from_millis(20));
+    assert_eq!(moved.addresses(), vec!["172.16.0.9"]);
+    assert!(!moved.has_ip(&"10.9.9.9".parse().unwrap()));
+
+    // Unauthenticated announcements can't lend their addresses to an authenticated peer.
+    let mut authenticated = host("10.9.9.9");
+    authenticated.set_authenticated(true);
+    authenticated.merge_addresses(&host("10.6.6.6"), Duration::from_secs(60));
+    assert!(!authenticated.has_ip(&"10.6.6.6".parse().unwrap()));
+}
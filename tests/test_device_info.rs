@@ -0,0 +1,133 @@
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::os::raw::c_char;
+use protobuf::Message;
+use airx::error::{AIRX_ERROR_INVALID_ARGUMENT, AIRX_OK};
+use airx::extension::ip_to_u32::ConvertIpU32;
+use airx::lib_generic::{airx_create_service, airx_get_peer_details, airx_set_device_info};
+use airx::network::device_info::{DeviceInfo, DeviceType};
+use airx::packet::hello_packet::{MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use airx::packet::protocol::features::ProtocolFeatures;
+use airx::proto::discovery_packet::DiscoveryPacket;
+use airx::service::airx_service::AirXService;
+use airx::service::context::discovery_service_context::{DiscoveryMode, DiscoveryServiceContext, MulticastConfig};
+use airx::service::discovery_service::{DiscoveryService, PeerCollectionType};
+
+fn legacy_packet(address: Ipv4Addr) -> DiscoveryPacket {
+    let mut packet = DiscoveryPacket::new();
+    packet.set_address(address.to_u32());
+    packet.set_server_port(9818);
+    packet.set_group_identifier(114514);
+    packet.set_need_response(false);
+    packet.set_host_name(String::from("B612"));
+    packet
+}
+
+fn receive(peers: PeerCollectionType, packet: DiscoveryPacket) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let context = DiscoveryServiceContext::new(
+        0, 0, 114514, None, false, DiscoveryMode::Broadcast, MulticastConfig::default());
+    let source = SocketAddr::new(Ipv4Addr::from(packet.address()).into(), 9818);
+    DiscoveryService::handle_new_peer(HashSet::new(), &socket, peers, packet, source, &context).unwrap();
+}
+
+#[test]
+fn test_device_info_fields_roundtrip() {
+    let mut packet = legacy_packet(Ipv4Addr::new(10, 9, 9, 9));
+    packet.set_protocol_version(PROTOCOL_VERSION);
+    packet.set_min_protocol_version(MIN_PROTOCOL_VERSION);
+    packet.set_platform(String::from("android"));
+    packet.set_device_type(DeviceType::Tablet.value());
+    packet.set_features(ProtocolFeatures::CRC32_CHECKSUM.bits());
+    packet.set_display_name(String::from("Living room tablet"));
+    packet.set_data_port_tls(true);
+
+    let parsed = DiscoveryPacket::parse_from_bytes(&packet.write_to_bytes().unwrap()).unwrap();
+    assert_eq!(parsed, packet);
+
+    let service = DiscoveryService::new();
+    receive(service.peers(), parsed);
+    let peer = service.peer_lookup(&"10.9.9.9:1".parse().unwrap()).unwrap();
+    let info = peer.device_info();
+    assert_eq!(info.protocol_version, Some(PROTOCOL_VERSION));
+    assert_eq!(info.platform.as_deref(), Some("android"));
+    assert_eq!(info.device_type, DeviceType::Tablet);
+    assert!(info.features.contains(ProtocolFeatures::CRC32_CHECKSUM));
+    assert!(info.data_port_tls);
+    assert_eq!(peer.display_name(), "Living room tablet");
+    assert_eq!(peer.host_name(), "B612");
+    assert!(peer.is_compatible());
+}
+
+#[test]
+fn test_legacy_peer_has_no_device_info() {
+    let service = DiscoveryService::new();
+    let mut packet = legacy_packet(Ipv4Addr::new(10, 9, 9, 8));
+    // Types added later read as unknown.
+    packet.set_device_type(42);
+    receive(service.peers(), packet);
+
+    let peer = service.peer_lookup(&"10.9.9.8:1".parse().unwrap()).unwrap();
+    assert_eq!(peer.device_info().protocol_version, None);
+    assert_eq!(peer.device_info().device_type, DeviceType::Unknown);
+    assert_eq!(peer.display_name(), "B612");
+    assert!(!peer.is_compatible());
+}
+
+#[test]
+fn test_compatibility() {
+    assert!(DeviceInfo::local(None, DeviceType::Desktop, false).is_compatible());
+    assert!(!DeviceInfo::default().is_compatible());
+
+    let too_new = DeviceInfo {
+        protocol_version: Some(PROTOCOL_VERSION + 2),
+        min_protocol_version: Some(PROTOCOL_VERSION + 1),
+        ..DeviceInfo::default()
+    };
+    assert!(!too_new.is_compatible());
+
+    let too_old = DeviceInfo {
+        protocol_version: Some(MIN_PROTOCOL_VERSION - 1),
+        ..DeviceInfo::default()
+    };
+    assert!(!too_old.is_compatible());
+
+    for device_type in [DeviceType::Unknown, DeviceType::Phone, DeviceType::Tablet, DeviceType::Desktop] {
+        assert_eq!(DeviceType::from(device_type.value()), Some(device_type));
+    }
+}
+
+#[test]
+fn test_ffi_device_info_and_peer_details() {
+    let mut addr = String::from("127.0.0.1");
+    let airx = unsafe { airx_create_service(0, 0, addr.as_mut_ptr() as *mut c_char, addr.len() as u32, 0, 0) };
+    assert!(!airx.is_null());
+    let airx_ref: &mut AirXService = unsafe { &mut *airx };
+
+    let name = "Study desktop";
+    assert_eq!(airx_set_device_info(airx, name.as_ptr() as *const c_char, name.len() as u32, 9), AIRX_ERROR_INVALID_ARGUMENT);
+    assert_eq!(airx_set_device_info(airx, name.as_ptr() as *const c_char, name.len() as u32, 3), AIRX_OK);
+    let announced = airx_ref.config().discovery_service_context().device_info().clone();
+    assert_eq!(announced.display_name.as_deref(), Some(name));
+    assert_eq!(announced.device_type, DeviceType::Desktop);
+    assert_eq!(announced.protocol_version, Some(PROTOCOL_VERSION));
+
+    let mut packet = legacy_packet(Ipv4Addr::new(10, 9, 9, 7));
+    packet.set_protocol_version(PROTOCOL_VERSION);
+    packet.set_device_type(DeviceType::Phone.value());
+    packet.set_display_name(String::from("Pocket\tphone"));
+    receive(airx_ref.discovery_service().peers(), packet);
+
+    let mut buffer = [0u8; 512];
+    let len = airx_get_peer_details(airx, buffer.as_mut_ptr() as *mut c_char, buffer.len() as u32);
+    let details = String::from_utf8_lossy(&buffer[..len as usize]).to_string();
+    let fields = details.split('\t').collect::<Vec<&str>>();
+    assert_eq!(fields.len(), 10);
+    assert_eq!(fields[0], "B612@10.9.9.7:9818");
+    assert_eq!(fields[1], "Pocketphone");
+    assert_eq!(fields[3], "1");
+    assert_eq!(fields[5], PROTOCOL_VERSION.to_string());
+    assert_eq!(fields[9], "1");
+
+    unsafe { drop(Box::from_raw(airx)) };
+}
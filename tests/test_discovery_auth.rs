@@ -0,0 +1,73 @@
+use protobuf::Message;
+use airx::proto::discovery_packet::DiscoveryPacket;
+use airx::security::discovery_auth::{DiscoveryAuthError, DiscoveryVerifier, seal_discovery_packet, SIGNATURE_WINDOW_MILLIS, unix_millis};
+use airx::security::group_key::GroupKey;
+
+fn discovery_packet() -> DiscoveryPacket {
+    let mut packet = DiscoveryPacket::new();
+    packet.set_address(0x7f000001);
+    packet.set_server_port(9818);
+    packet.set_group_identifier(114514);
+    packet.set_need_response(true);
+    packet.set_host_name(String::from("B612"));
+    packet
+}
+
+#[test]
+fn test_signed_discovery_packet_verifies() {
+    let key = GroupKey::from_bytes([1u8; 32]);
+    let bytes = seal_discovery_packet(&mut discovery_packet(), Some(&key)).unwrap();
+
+    let packet = DiscoveryVerifier::new(Some(&key), false).open(&bytes).unwrap();
+    assert_eq!(packet.host_name(), "B612");
+    assert_eq!(packet.group_identifier(), 114514);
+}
+
+#[test]
+fn test_tampered_or_foreign_discovery_packet_is_rejected() {
+    let key = GroupKey::from_bytes([1u8; 32]);
+    let mut bytes = seal_discovery_packet(&mut discovery_packet(), Some(&key)).unwrap();
+
+    let other_key = GroupKey::from_bytes([2u8; 32]);
+    assert!(matches!(
+        DiscoveryVerifier::new(Some(&other_key), false).open(&bytes),
+        Err(DiscoveryAuthError::InvalidSignature)
+    ));
+
+    // Flip a byte of the host name.
+    let position = bytes.windows(4).position(|w| w == b"B612").unwrap();
+    bytes[position] = b'C';
+    assert!(matches!(
+        DiscoveryVerifier::new(Some(&key), false).open(&bytes),
+        Err(DiscoveryAuthError::InvalidSignature)
+    ));
+}
+
+#[test]
+fn test_replayed_or_stale_discovery_packet_is_rejected() {
+    let key = GroupKey::from_bytes([1u8; 32]);
+    let bytes = seal_discovery_packet(&mut discovery_packet(), Some(&key)).unwrap();
+    let verifier = DiscoveryVerifier::new(Some(&key), false);
+
+    assert!(verifier.open(&bytes).is_ok());
+    assert!(matches!(verifier.open(&bytes), Err(DiscoveryAuthError::Replayed)));
+
+    let later = unix_millis() + SIGNATURE_WINDOW_MILLIS * 2;
+    assert!(matches!(
+        DiscoveryVerifier::new(Some(&key), false).open_at(&bytes, later),
+        Err(DiscoveryAuthError::Expired)
+    ));
+}
+
+#[test]
+fn test_unsigned_discovery_packet_needs_compatibility_flag() {
+    let key = GroupKey::from_bytes([1u8; 32]);
+    let bytes = discovery_packet().write_to_bytes().unwrap();
+
+    assert!(matches!(
+        DiscoveryVerifier::new(Some(&key), false).open(&bytes),
+        Err(DiscoveryAuthError::MissingSignature)
+    ));
+    assert!(DiscoveryVerifier::new(Some(&key), true).open(&bytes).is_ok());
+    assert!(DiscoveryVerifier::new(None, false).open(&bytes).is_ok());
+}
@@ -0,0 +1,86 @@
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use protobuf::Message;
+use airx::compatibility::unified_endian::UnifiedEndian;
+use airx::extension::ip_to_u32::ConvertIpU32;
+use airx::packet::discovery_frame::frame_discovery_packet;
+use airx::proto::discovery_packet::DiscoveryPacket;
+use airx::service::context::discovery_service_context::{DiscoveryMode, DiscoveryServiceContext, MulticastConfig};
+use airx::service::discovery_service::{DISCOVERY_MULTICAST_V6, DiscoveryService};
+use airx::util::device_id::DeviceId;
+
+#[test]
+fn test_discovery_mode_values() {
+    for mode in [DiscoveryMode::Broadcast, DiscoveryMode::Multicast, DiscoveryMode::Hybrid] {
+        assert_eq!(DiscoveryMode::from(mode.value()), Some(mode));
+    }
+    assert_eq!(DiscoveryMode::from(3), None);
+
+    let context = |mode| DiscoveryServiceContext::new(
+        0, 0, 0, None, false, mode, MulticastConfig::default());
+    let multicast = MulticastConfig::default();
+    assert_eq!(context(DiscoveryMode::Broadcast).ipv6_groups(), vec![DISCOVERY_MULTICAST_V6]);
+    assert_eq!(context(DiscoveryMode::Multicast).ipv6_groups(), vec![multicast.group_v6]);
+    assert_eq!(context(DiscoveryMode::Hybrid).ipv6_groups(), vec![DISCOVERY_MULTICAST_V6, multicast.group_v6]);
+}
+
+/// Packets sent to the configured group reach a service in multicast mode,
+/// framed or in the legacy two-datagram form.
+#[test]
+fn test_multicast_discovery_receives_group_packet() {
+    let server_port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+    let multicast = MulticastConfig {
+        group_v4: Ipv4Addr::new(239, 255, 65, 89),
+        ..MulticastConfig::default()
+    };
+    let mut context = DiscoveryServiceContext::new(
+        0, server_port, 114514, None, false, DiscoveryMode::Multicast, multicast);
+    context.set_device_id(Some(DeviceId::generate()));
+
+    let peers = Arc::new(Mutex::new(HashSet::new()));
+    let stopped = Arc::new(AtomicBool::new(false));
+    let thread_peers = peers.clone();
+    let thread_stopped = stopped.clone();
+    let service = std::thread::spawn(move || {
+        DiscoveryService::run(context, thread_peers, Box::new(move || thread_stopped.load(Ordering::SeqCst)))
+    });
+    std::thread::sleep(Duration::from_millis(300));
+
+    // Sent from this host, so the device ID tells it apart from our own packets.
+    let packet_bytes = |device_id: &str| {
+        let mut packet = DiscoveryPacket::new();
+        packet.set_address(Ipv4Addr::new(10, 254, 254, 254).to_u32());
+        packet.set_server_port(server_port as u32);
+        packet.set_group_identifier(114514);
+        packet.set_need_response(false);
+        packet.set_host_name(String::from("B612"));
+        packet.set_device_id(String::from(device_id));
+        packet.write_to_bytes().unwrap()
+    };
+    let framed = frame_discovery_packet(&packet_bytes("framed"));
+    let legacy = packet_bytes("legacy");
+
+    let sender = UdpSocket::bind("0.0.0.0:0").unwrap();
+    sender.set_multicast_loop_v4(true).unwrap();
+    let found = |device_id: &str| peers.lock().unwrap().iter()
+        .find(|p| p.device_id().map(|id| id.as_str()) == Some(device_id))
+        .cloned();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while (found("framed").is_none() || found("legacy").is_none()) && Instant::now() < deadline {
+        sender.send_to(&framed, (multicast.group_v4, server_port)).unwrap();
+        sender.send_to(&(legacy.len() as u32).to_bytes(), (multicast.group_v4, server_port)).unwrap();
+        sender.send_to(&legacy, (multicast.group_v4, server_port)).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    stopped.store(true, Ordering::SeqCst);
+    service.join().unwrap().unwrap();
+    for device_id in ["framed", "legacy"] {
+        // Known by the address it sent from, not the one it claims.
+        let peer = found(device_id).unwrap();
+        assert_ne!(peer.host(), "10.254.254.254");
+    }
+}
@@ -1,6 +1,8 @@
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
 use protobuf::Message;
+use airx::compatibility::unified_endian::UnifiedEndian;
 use airx::extension::ip_to_u32::ConvertIpU32;
+use airx::packet::discovery_frame::*;
 use airx::proto::discovery_packet::DiscoveryPacket;
 
 #[test]
@@ -20,3 +22,53 @@ fn test_discovery_packet_serializable() {
     assert_eq!(packet2.address(), Ipv4Addr::new(114, 51, 41, 91).to_u32());
     assert_eq!(packet2.need_response(), true);
 }
+
+#[test]
+fn test_discovery_packet_address_v6() {
+    let mut packet = DiscoveryPacket::new();
+    packet.set_address(0);
+    packet.set_address_v6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1).octets().to_vec());
+    packet.set_server_port(9818);
+    packet.set_group_identifier(0);
+    packet.set_need_response(false);
+    packet.set_host_name(String::from("B612"));
+
+    let bytes = packet.write_to_bytes().unwrap();
+    let packet2 = DiscoveryPacket::parse_from_bytes(bytes.as_slice()).unwrap();
+
+    assert_eq!(packet2.address_v6(), Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1).octets());
+}
+
+#[test]
+fn test_discovery_frame() {
+    let body = b"protobuf body".to_vec();
+    let framed = frame_discovery_packet(&body);
+    assert_eq!(&framed[0..4], b"AXDG");
+    assert_eq!(framed[4], DISCOVERY_FRAME_VERSION);
+    assert_eq!(parse_discovery_datagram(&framed), DiscoveryDatagram::Packet(&body));
+
+    let mut future = framed.clone();
+    future[4] = DISCOVERY_FRAME_VERSION + 1;
+    assert_eq!(parse_discovery_datagram(&future), DiscoveryDatagram::UnsupportedVersion(DISCOVERY_FRAME_VERSION + 1));
+
+    assert_eq!(parse_discovery_datagram(&13u32.to_bytes()), DiscoveryDatagram::LegacySize(13));
+    assert_eq!(parse_discovery_datagram(&body), DiscoveryDatagram::Unframed(&body));
+}
+
+/// Legacy datagrams of two senders interleaving are each paired with their own size.
+#[test]
+fn test_legacy_pairing_by_source() {
+    let alice: SocketAddr = "10.0.0.1:9818".parse().unwrap();
+    let bob: SocketAddr = "10.0.0.2:9818".parse().unwrap();
+    let mut pairing = LegacyDiscoveryPairing::new();
+
+    pairing.expect(alice, 3);
+    pairing.expect(bob, 5);
+    assert!(pairing.take(&bob, b"bobby"));
+    assert!(pairing.take(&alice, b"ali"));
+
+    // No size announced, or the wrong one.
+    assert!(!pairing.take(&alice, b"ali"));
+    pairing.expect(bob, 4);
+    assert!(!pairing.take(&bob, b"bobby"));
+}
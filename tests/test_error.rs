@@ -0,0 +1,80 @@
+use std::io;
+use std::os::raw::c_char;
+use airx::compatibility::unified_endian::UnifiedEndian;
+use airx::error::*;
+use airx::lib_generic::{airx_create_service, airx_last_error_message, airx_set_discovery_mode, airx_set_frame_limits, airx_unpair};
+use airx::packet::data::magic_numbers::MagicNumbers;
+use airx::packet::data_packet::DataPacket;
+use airx::packet::frame_limits::FrameLimits;
+use airx::packet::protocol::serialize::Serialize;
+use airx::security::access_policy::AccessRule;
+use airx::service::airx_service::AirXService;
+
+fn last_error_message() -> String {
+    let mut buffer = [0u8; 256];
+    let len = airx_last_error_message(buffer.as_mut_ptr() as *mut c_char, buffer.len() as u32);
+    String::from_utf8_lossy(&buffer[..len as usize]).to_string()
+}
+
+fn create_service() -> *mut AirXService {
+    let mut addr = String::from("127.0.0.1");
+    unsafe { airx_create_service(0, 0, addr.as_mut_ptr() as *mut c_char, addr.len() as u32, 0, 0) }
+}
+
+#[test]
+fn test_codes_are_stable() {
+    assert_eq!(AIRX_OK, 0);
+    assert_eq!(AirXError::InvalidArgument(String::new()).code(), 1);
+    assert_eq!(AirXError::NotFound(String::new()).code(), 2);
+    assert_eq!(AirXError::Io(io::Error::other("")).code(), 3);
+    assert_eq!(AirXError::TimedOut(String::new()).code(), 4);
+    assert_eq!(AirXError::PermissionDenied(String::new()).code(), 5);
+    assert_eq!(AirXError::MalformedPacket(String::new()).code(), 6);
+    assert_eq!(AirXError::FrameTooLarge { size: 2, limit: 1 }.code(), 7);
+    assert_eq!(AirXError::Crypto(String::new()).code(), 8);
+    assert_eq!(AirXError::IncompatiblePeer(String::new()).code(), 9);
+}
+
+#[test]
+fn test_conversions() {
+    let packet_error = AirXError::from(DataPacket::deserialize(&vec![0x39]).err().unwrap());
+    assert_eq!(packet_error.code(), AIRX_ERROR_MALFORMED_PACKET);
+
+    let rule_error = AirXError::from(AccessRule::parse("10.0.0.0/99").err().unwrap());
+    assert_eq!(rule_error.code(), AIRX_ERROR_INVALID_ARGUMENT);
+
+    let timed_out = AirXError::from(io::Error::new(io::ErrorKind::TimedOut, "slow"));
+    assert_eq!(timed_out.code(), AIRX_ERROR_TIMED_OUT);
+
+    let io_error = AirXError::from(io::Error::new(io::ErrorKind::BrokenPipe, "gone"));
+    assert!(std::error::Error::source(&io_error).is_some());
+
+    // The frame error wrapped by the data transmit keeps its kind.
+    let head = MagicNumbers::Text.value().to_bytes();
+    let frame_error = FrameLimits { text: 10, ..FrameLimits::default() }.check(&head, 20).unwrap_err();
+    assert!(matches!(AirXError::from(frame_error), AirXError::FrameTooLarge { size: 20, limit: 10 }));
+}
+
+#[test]
+fn test_ffi_reports_codes_and_messages() {
+    let airx = create_service();
+    assert!(!airx.is_null());
+
+    assert_eq!(airx_set_discovery_mode(airx, 1), AIRX_OK);
+    assert_eq!(airx_set_discovery_mode(airx, 99), AIRX_ERROR_INVALID_ARGUMENT);
+    assert!(last_error_message().contains("99"));
+
+    assert_eq!(airx_set_frame_limits(airx, 0, 0, 0), AIRX_ERROR_INVALID_ARGUMENT);
+
+    let device_id = "not-paired";
+    assert_eq!(airx_unpair(airx, device_id.as_ptr() as *const c_char, device_id.len() as u32), AIRX_ERROR_NOT_FOUND);
+    assert!(last_error_message().contains(device_id));
+
+    // Truncated, but still terminated, and the full length is returned.
+    let mut small = [0xffu8; 4];
+    let len = airx_last_error_message(small.as_mut_ptr() as *mut c_char, small.len() as u32);
+    assert!(len as usize > small.len());
+    assert_eq!(small[3], 0);
+
+    unsafe { drop(Box::from_raw(airx)) };
+}
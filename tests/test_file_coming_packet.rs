@@ -1,9 +1,11 @@
-use airx::packet::data::file_coming_packet::FileComingPacket;
+use airx::packet::data::file_coming_packet::{FileComingPacket, FileMetadata};
+use airx::packet::data::transfer_id::{TransferId, TransferIdFormat};
 use airx::packet::protocol::serialize::Serialize;
 
 #[test]
 fn test_file_coming_packet_serializable() {
     let packet = FileComingPacket::new(
+        TransferId::generate(),
         1024,
         String::from("test中文测试 \\^O^/ 😃 RTL test سلام عليكم 🇯🇵こんにちは؟ *&%^.txt"),
     );
@@ -13,3 +15,88 @@ fn test_file_coming_packet_serializable() {
 
     assert_eq!(packet, packet2);
 }
+
+#[test]
+fn test_file_coming_packet_legacy_format() {
+    let packet = FileComingPacket::new(TransferId::generate(), 1024, String::from("miku.txt"));
+    let bytes = packet.serialize_as(TransferIdFormat::Legacy);
+    assert_eq!(bytes.len(), 14 + 8);
+
+    // Legacy senders announce no id.
+    let packet2 = FileComingPacket::deserialize_as(&bytes, TransferIdFormat::Legacy).unwrap();
+    assert_eq!(packet2.transfer_id(), TransferId::default());
+    assert_eq!(packet2.file_name(), packet.file_name());
+    assert_eq!(packet2.file_size(), packet.file_size());
+}
+
+#[test]
+fn test_file_coming_packet_rejects_malformed() {
+    let bytes = FileComingPacket::new(TransferId::generate(), 1024, String::from("miku.txt")).serialize();
+    for len in 0..bytes.len() {
+        assert!(FileComingPacket::deserialize(&bytes[..len].to_vec()).is_err());
+    }
+
+    // File size and name length summing past u32 in the hash.
+    let packet = FileComingPacket::new(TransferId::generate(), u32::MAX as u64, String::from("miku.txt"));
+    let packet2 = FileComingPacket::deserialize(&packet.serialize()).unwrap();
+    assert_eq!(packet, packet2);
+}
+
+#[test]
+fn test_file_coming_packet_metadata() {
+    let metadata = FileMetadata {
+        mime_type: Some(String::from("image/png")),
+        modified_at: Some(1_700_000_000),
+        permissions: Some(0o644),
+    };
+    let packet = FileComingPacket::new(TransferId::generate(), 1024, String::from("miku.png"))
+        .with_metadata(metadata.clone());
+    let bytes = packet.serialize();
+    let packet2 = FileComingPacket::deserialize(&bytes).unwrap();
+    assert_eq!(packet2.metadata(), &metadata);
+    assert_eq!(packet, packet2);
+
+    // Only some of it.
+    let partial = FileMetadata { modified_at: Some(42), ..FileMetadata::default() };
+    let packet = FileComingPacket::new(TransferId::generate(), 1024, String::from("miku.png"))
+        .with_metadata(partial.clone());
+    assert_eq!(FileComingPacket::deserialize(&packet.serialize()).unwrap().metadata(), &partial);
+
+    // Truncated metadata is malformed.
+    assert!(FileComingPacket::deserialize(&bytes[..bytes.len() - 1].to_vec()).is_err());
+}
+
+#[test]
+fn test_file_coming_packet_without_metadata() {
+    let id = TransferId::generate();
+    let packet = FileComingPacket::new(id, 1024, String::from("miku.txt"));
+    let with_metadata = FileComingPacket::new(id, 1024, String::from("miku.txt")).with_metadata(FileMetadata {
+        mime_type: Some(String::from("text/plain")),
+        ..FileMetadata::default()
+    });
+
+    // Without metadata the packet ends at the hash, as it always did.
+    let bytes = packet.serialize();
+    assert_eq!(bytes.len(), 16 + 14 + 8);
+    assert!(with_metadata.serialize().starts_with(&bytes));
+    assert!(FileComingPacket::deserialize(&bytes).unwrap().metadata().is_empty());
+
+    // Legacy receivers never get metadata.
+    let legacy = with_metadata.serialize_as(TransferIdFormat::Legacy);
+    assert_eq!(legacy.len(), 14 + 8);
+}
+
+#[test]
+fn test_file_coming_packet_rejects_trailing_data() {
+    let packet = FileComingPacket::new(TransferId::generate(), 1024, String::from("miku.png"));
+    let with_metadata = FileComingPacket::new(TransferId::generate(), 1024, String::from("miku.png"))
+        .with_metadata(FileMetadata { permissions: Some(0o644), ..FileMetadata::default() });
+    for format in [TransferIdFormat::Wide, TransferIdFormat::Legacy] {
+        for packet in [&packet, &with_metadata] {
+            // A single zero byte would read as empty metadata.
+            let mut bytes = packet.serialize_as(format);
+            bytes.extend_from_slice(&[0, 0]);
+            assert!(FileComingPacket::deserialize_as(&bytes, format).is_err());
+        }
+    }
+}
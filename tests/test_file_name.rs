@@ -0,0 +1,52 @@
+use airx::util::file_name::FileNameUtil;
+
+#[test]
+fn test_sanitize_file_name() {
+    assert_eq!(FileNameUtil::sanitize("miku.txt"), "miku.txt");
+    assert_eq!(FileNameUtil::sanitize("../../etc/passwd"), "passwd");
+    assert_eq!(FileNameUtil::sanitize("C:\\Users\\miku\\a.txt"), "a.txt");
+    assert_eq!(FileNameUtil::sanitize("what?<is>:this*.txt"), "whatisthis.txt");
+    assert_eq!(FileNameUtil::sanitize("tab\tand\nnewline.txt"), "tabandnewline.txt");
+    assert_eq!(FileNameUtil::sanitize("  trailing. . "), "trailing");
+    assert_eq!(FileNameUtil::sanitize("CON.txt"), "_CON.txt");
+    assert_eq!(FileNameUtil::sanitize("lpt1"), "_lpt1");
+    assert_eq!(FileNameUtil::sanitize("CONSOLE.txt"), "CONSOLE.txt");
+    for reserved in ["COM0", "lpt0.log", "CONIN$", "conout$.txt"] {
+        assert_eq!(FileNameUtil::sanitize(reserved), format!("_{}", reserved));
+    }
+    assert_eq!(FileNameUtil::sanitize("中文 😃.txt"), "中文 😃.txt");
+    for empty in ["", ".", "..", "dir/", "../..", "???"] {
+        assert_eq!(FileNameUtil::sanitize(empty), "file");
+    }
+}
+
+#[test]
+fn test_sanitize_strips_format_characters() {
+    assert_eq!(FileNameUtil::sanitize("photo\u{202E}gnp.exe"), "photognp.exe");
+    assert_eq!(FileNameUtil::sanitize("\u{200E}a\u{200F}b\u{2066}c\u{2067}d\u{2068}e\u{2069}.txt"), "abcde.txt");
+    assert_eq!(FileNameUtil::sanitize("zero\u{200B}width\u{FEFF}.txt"), "zerowidth.txt");
+    assert_eq!(FileNameUtil::sanitize("\u{202E}\u{2069}"), "file");
+}
+
+#[test]
+fn test_sanitize_truncates_long_names() {
+    let long = "好".repeat(100);
+    let sanitized = FileNameUtil::sanitize(&long);
+    assert!(sanitized.len() <= 255);
+    assert_eq!(sanitized, "好".repeat(85));
+}
+
+#[test]
+fn test_display_name() {
+    assert_eq!(FileNameUtil::display_name("/home/miku/Documents/report.pdf"), "report.pdf");
+    assert_eq!(FileNameUtil::display_name("report.pdf"), "report.pdf");
+    assert_eq!(FileNameUtil::display_name("/"), "file");
+}
+
+#[test]
+fn test_mime_type() {
+    assert_eq!(FileNameUtil::mime_type("photo.JPG"), Some("image/jpeg"));
+    assert_eq!(FileNameUtil::mime_type("notes.txt"), Some("text/plain"));
+    assert_eq!(FileNameUtil::mime_type("archive.unknown"), None);
+    assert_eq!(FileNameUtil::mime_type("Makefile"), None);
+}
@@ -1,11 +1,37 @@
 use airx::packet::data::file_part_packet::FilePartPacket;
+use airx::packet::data::transfer_id::{TransferId, TransferIdFormat};
 use airx::packet::protocol::serialize::Serialize;
 
 #[test]
 fn test_test_file_part_packet() {
     let data = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
-    let packet = FilePartPacket::new(11, 45, data.len() as u64, data);
+    let packet = FilePartPacket::new(TransferId::generate(), 45, data.len() as u64, data);
     let bytes = packet.serialize();
     let packet2 = FilePartPacket::deserialize(&bytes).unwrap();
     assert!(packet.eq(&packet2));
+    assert_eq!(FilePartPacket::peek_transfer_id(&bytes, TransferIdFormat::Wide), Some(packet.transfer_id()));
+}
+
+#[test]
+fn test_file_part_packet_legacy_format() {
+    let packet = FilePartPacket::new(TransferId::from_legacy(11), 45, 3, vec![1, 2, 3]);
+    let bytes = packet.serialize_as(TransferIdFormat::Legacy);
+    assert_eq!(bytes.len(), 17 + 3);
+    assert_eq!(bytes[0], 11);
+    assert_eq!(FilePartPacket::deserialize_as(&bytes, TransferIdFormat::Legacy).unwrap(), packet);
+    assert!(FilePartPacket::deserialize(&bytes).is_err());
+}
+
+#[test]
+fn test_file_part_packet_rejects_malformed() {
+    let packet = FilePartPacket::new(TransferId::generate(), 0, 3, vec![1, 2, 3]);
+    let bytes = packet.serialize();
+    for len in 0..bytes.len() {
+        assert!(FilePartPacket::deserialize(&bytes[..len].to_vec()).is_err());
+    }
+
+    // Length that would overflow when added to the header size.
+    let mut bytes = bytes;
+    bytes[24..32].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(FilePartPacket::deserialize(&bytes).is_err());
 }
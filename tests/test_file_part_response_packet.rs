@@ -0,0 +1,41 @@
+use airx::packet::data::file_part_response_packet::{FilePartResponsePacket, FilePartResponsePacketError, ResponseKind};
+use airx::packet::data::transfer_id::{TransferId, TransferIdFormat};
+use airx::packet::protocol::serialize::Serialize;
+
+#[test]
+fn test_file_part_response_packet() {
+    let packet = FilePartResponsePacket::new(TransferId::generate(), ResponseKind::StopReceiving);
+    let packet2 = FilePartResponsePacket::deserialize(&packet.serialize()).unwrap();
+    assert_eq!(packet, packet2);
+    assert_eq!(packet2.response_kind(), ResponseKind::StopReceiving);
+
+    let legacy = FilePartResponsePacket::new(TransferId::from_legacy(11), ResponseKind::StopSending);
+    assert_eq!(legacy.serialize_as(TransferIdFormat::Legacy), vec![11, 0x1]);
+    assert_eq!(FilePartResponsePacket::deserialize_as(&[11, 0x1], TransferIdFormat::Legacy).unwrap(), legacy);
+}
+
+#[test]
+fn test_file_part_response_packet_rejects_unknown_kind() {
+    let mut bytes = FilePartResponsePacket::new(TransferId::generate(), ResponseKind::StopSending).serialize();
+    bytes[16] = 0x7f;
+    assert!(matches!(
+        FilePartResponsePacket::deserialize(&bytes),
+        Err(FilePartResponsePacketError::UnknownResponseKind)
+    ));
+    assert!(FilePartResponsePacket::deserialize(&bytes[..16].to_vec()).is_err());
+    assert!(FilePartResponsePacket::deserialize(&vec![11, 0x1]).is_err());
+    assert!(FilePartResponsePacket::deserialize_as(&[11, 0x1, 0], TransferIdFormat::Legacy).is_err());
+}
+
+#[test]
+fn test_transfer_id_hex() {
+    let id = TransferId::generate();
+    let hex = id.to_string();
+    assert_eq!(hex.len(), 32);
+    assert_eq!(TransferId::parse(&hex), Some(id));
+    assert_eq!(TransferId::parse(&hex.to_uppercase()), Some(id));
+    assert_eq!(TransferId::parse(&hex[1..]), None);
+    assert_eq!(TransferId::parse("zz000000000000000000000000000000"), None);
+    assert_ne!(TransferId::generate(), id);
+    assert_eq!(TransferId::from_legacy(7).legacy_id(), 7);
+}
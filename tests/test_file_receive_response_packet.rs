@@ -1,10 +1,12 @@
+use airx::packet::data::byte_ranges::ByteRanges;
 use airx::packet::data::file_receive_response_packet::FileReceiveResponsePacket;
+use airx::packet::data::transfer_id::{TransferId, TransferIdFormat};
 use airx::packet::protocol::serialize::Serialize;
 
 #[test]
 fn test_test_file_receive_response_packet() {
     let packet = FileReceiveResponsePacket::new(
-        11,
+        TransferId::generate(),
         1024,
         String::from("test中文测试 \\^O^/ 😃 RTL test سلام عليكم 🇯🇵こんにちは؟ *&%^.txt"),
         true,
@@ -13,3 +15,58 @@ fn test_test_file_receive_response_packet() {
     let packet2 = FileReceiveResponsePacket::deserialize(&bytes).unwrap();
     assert!(packet.eq(&packet2));
 }
+
+#[test]
+fn test_file_receive_response_packet_legacy_format() {
+    let packet = FileReceiveResponsePacket::new(TransferId::from_legacy(11), 1024, String::from("miku.txt"), true);
+    let bytes = packet.serialize_as(TransferIdFormat::Legacy);
+    assert_eq!(bytes.len(), 14 + 8);
+    assert_eq!(bytes[0], 11);
+    assert_eq!(FileReceiveResponsePacket::deserialize_as(&bytes, TransferIdFormat::Legacy).unwrap(), packet);
+}
+
+#[test]
+fn test_file_receive_response_packet_rejects_malformed() {
+    let bytes = FileReceiveResponsePacket::new(TransferId::generate(), 1024, String::from("miku.txt"), true).serialize();
+    for len in 0..bytes.len() {
+        assert!(FileReceiveResponsePacket::deserialize(&bytes[..len].to_vec()).is_err());
+    }
+
+    let mut bytes = bytes;
+    bytes[24..28].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(FileReceiveResponsePacket::deserialize(&bytes).is_err());
+}
+
+#[test]
+fn test_file_receive_response_packet_received_ranges() {
+    let id = TransferId::generate();
+    let plain = FileReceiveResponsePacket::new(id, 1024, String::from("miku.txt"), true);
+    let received = ByteRanges::parse("0-100,512-1000").unwrap();
+    let resuming = FileReceiveResponsePacket::new(id, 1024, String::from("miku.txt"), true)
+        .with_received(received.clone());
+
+    let bytes = resuming.serialize();
+    let packet = FileReceiveResponsePacket::deserialize(&bytes).unwrap();
+    assert_eq!(packet.received(), &received);
+    assert_eq!(packet, resuming);
+
+    // Older senders read the packet they know and ignore the ranges.
+    assert!(bytes.starts_with(&plain.serialize()));
+    assert!(FileReceiveResponsePacket::deserialize(&plain.serialize()).unwrap().received().is_empty());
+    assert_eq!(resuming.serialize_as(TransferIdFormat::Legacy).len(), 14 + 8);
+    assert!(FileReceiveResponsePacket::deserialize(&bytes[..bytes.len() - 1].to_vec()).is_err());
+}
+
+#[test]
+fn test_file_receive_response_packet_rejects_trailing_data() {
+    let packet = FileReceiveResponsePacket::new(TransferId::generate(), 1024, String::from("miku.txt"), true);
+    let resuming = FileReceiveResponsePacket::new(TransferId::generate(), 1024, String::from("miku.txt"), true)
+        .with_received(ByteRanges::parse("0-100").unwrap());
+    for format in [TransferIdFormat::Wide, TransferIdFormat::Legacy] {
+        for packet in [&packet, &resuming] {
+            let mut bytes = packet.serialize_as(format);
+            bytes.push(0);
+            assert!(FileReceiveResponsePacket::deserialize_as(&bytes, format).is_err());
+        }
+    }
+}
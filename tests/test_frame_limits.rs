@@ -0,0 +1,117 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+use airx::compatibility::unified_endian::UnifiedEndian;
+use airx::packet::data::magic_numbers::MagicNumbers;
+use airx::packet::data::text_packet::TextPacket;
+use airx::packet::data_packet::DataPacket;
+use airx::packet::data_transmission::DataTransmit;
+use airx::packet::frame_limits::{FrameError, FrameLimits};
+use airx::packet::protocol::serialize::Serialize;
+use airx::security::group_key::GroupKey;
+use airx::service::data_service::DataService;
+use airx::service::metrics::ServiceMetrics;
+use airx::service::shutdown::ShutdownHandle;
+use common::{DataServiceParts, free_port};
+
+fn stream_pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    (client, server)
+}
+
+fn text_packet(len: usize) -> Vec<u8> {
+    let text = TextPacket::new("a".repeat(len)).unwrap();
+    DataPacket::new(MagicNumbers::Text.value(), &text.serialize()).serialize()
+}
+
+fn is_too_large(result: Result<Vec<u8>, std::io::Error>) -> bool {
+    match result {
+        Err(e) => matches!(FrameError::of(&e), Some(FrameError::TooLarge { .. })),
+        Ok(_) => false,
+    }
+}
+
+#[test]
+fn test_limit_by_magic_number() {
+    let limits = FrameLimits::default();
+    assert_eq!(limits.limit_of(&MagicNumbers::Text.value().to_bytes()), limits.text);
+    assert_eq!(limits.limit_of(&MagicNumbers::FilePart.value().to_bytes()), limits.file_part);
+    assert_eq!(limits.limit_of(b"AXSC"), limits.control);
+    assert_eq!(limits.limit_of(&[0x39]), limits.control);
+    assert_eq!(limits.max_frame_size(), limits.file_part);
+}
+
+#[test]
+fn test_huge_size_rejected_before_allocation() {
+    let (mut client, server) = stream_pair();
+    let mut dt = DataTransmit::from(server);
+
+    client.write_all(&u32::MAX.to_bytes()).unwrap();
+    assert!(is_too_large(dt.read_data_progress_with_retry(|_| ())));
+}
+
+#[test]
+fn test_text_held_to_its_own_limit() {
+    let (client, server) = stream_pair();
+    let mut sender = DataTransmit::from(client);
+    let mut receiver = DataTransmit::from(server);
+    receiver.set_frame_limits(FrameLimits { text: 1024, ..FrameLimits::default() });
+
+    sender.send_data_progress_with_retry(&text_packet(100), |_| ()).unwrap();
+    assert!(receiver.read_data_progress_with_retry(|_| ()).is_ok());
+
+    // Far below the file part limit, but a text.
+    sender.send_data_progress_with_retry(&text_packet(4096), |_| ()).unwrap();
+    assert!(is_too_large(receiver.read_data_progress_with_retry(|_| ())));
+}
+
+#[test]
+fn test_sealed_text_checked_once_opened() {
+    let (client, server) = stream_pair();
+    let group_key = GroupKey::from_passphrase("miku");
+    let sender_key = group_key.clone();
+    let sender = std::thread::spawn(move || {
+        let mut sender = DataTransmit::from(client);
+        sender.handshake_as_initiator(&sender_key).unwrap();
+        sender.send_data_progress_with_retry(&text_packet(4096), |_| ()).unwrap();
+    });
+
+    let mut receiver = DataTransmit::from(server);
+    receiver.set_frame_limits(FrameLimits { text: 1024, ..FrameLimits::default() });
+    receiver.handshake_as_responder(&group_key).unwrap();
+    assert!(is_too_large(receiver.read_data_progress_with_retry(|_| ())));
+    sender.join().unwrap();
+}
+
+#[test]
+fn test_service_closes_and_penalizes_oversized_sender() {
+    let port = free_port();
+    let metrics = Arc::new(ServiceMetrics::new());
+    let shutdown = ShutdownHandle::new();
+    let mut context = DataServiceParts { metrics: metrics.clone(), ..DataServiceParts::default() }
+        .into_context("127.0.0.1", port);
+    context.set_shutdown(shutdown.clone());
+    context.set_frame_limits(FrameLimits::default(), Some(Duration::from_secs(60)));
+    std::thread::spawn(move || DataService::run(context, Box::new(|| false)));
+    std::thread::sleep(Duration::from_millis(200));
+
+    let mut offender = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    offender.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    offender.write_all(&u32::MAX.to_bytes()).unwrap();
+    let mut buf = [0u8; 1];
+    assert!(matches!(offender.read(&mut buf), Ok(0) | Err(_)));
+    assert_eq!(metrics.oversized_frames(), 1);
+
+    // Denied for a while afterwards.
+    let mut again = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    again.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    assert!(matches!(again.read(&mut buf), Ok(0) | Err(_)));
+    assert_eq!(metrics.rejected_connections(), 1);
+
+    shutdown.stop();
+}
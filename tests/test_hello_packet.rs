@@ -0,0 +1,220 @@
+mod common;
+
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+use airx::error::{AirXError, AIRX_ERROR_INCOMPATIBLE_PEER};
+use airx::network::peer::Peer;
+use airx::packet::data::file_coming_packet::FileComingPacket;
+use airx::packet::data::magic_numbers::MagicNumbers;
+use airx::packet::data::text_packet::TextPacket;
+use airx::packet::data::transfer_id::{TransferId, TransferIdFormat};
+use airx::packet::data_packet::DataPacket;
+use airx::packet::data_transmission::DataTransmit;
+use airx::packet::hello_packet::*;
+use airx::packet::protocol::features::ProtocolFeatures;
+use airx::packet::protocol::serialize::Serialize;
+use airx::service::data_service::DataService;
+use common::{DataServiceParts, free_port, text_channel};
+
+/// Run a plaintext data service on `port`, forwarding received texts.
+fn spawn_service(port: u16) -> (mpsc::Receiver<String>, Arc<AtomicBool>, std::thread::JoinHandle<Result<(), AirXError>>) {
+    let (text_callback, text_rx) = text_channel();
+    let context = DataServiceParts { text_callback, ..DataServiceParts::default() }.into_context("127.0.0.1", port);
+
+    let stopped = Arc::new(AtomicBool::new(false));
+    let thread_stopped = stopped.clone();
+    let service = std::thread::spawn(move || {
+        DataService::run(context, Box::new(move || thread_stopped.load(Ordering::SeqCst)))
+    });
+    std::thread::sleep(Duration::from_millis(200));
+    (text_rx, stopped, service)
+}
+
+fn text_frame(text: &str) -> Vec<u8> {
+    let text_packet = TextPacket::new(String::from(text)).unwrap();
+    DataPacket::new(MagicNumbers::Text.value(), &text_packet.serialize()).serialize()
+}
+
+#[test]
+fn test_serialize_roundtrip() {
+    let hello = HelloPacket::new(ProtocolFeatures::local(true), Some([3u8; 32]));
+    let bytes = hello.serialize();
+    assert_eq!(bytes.len(), 49);
+    assert!(is_hello_packet(&bytes));
+    assert_eq!(HelloPacket::deserialize(&bytes).unwrap(), hello);
+
+    let anonymous = HelloPacket::new(ProtocolFeatures::local(false), None);
+    assert_eq!(anonymous.serialize().len(), 17);
+    assert_eq!(HelloPacket::deserialize(&anonymous.serialize()).unwrap(), anonymous);
+}
+
+#[test]
+fn test_malformed_hello() {
+    let bytes = HelloPacket::new(ProtocolFeatures::local(false), None).serialize();
+
+    assert!(HelloPacket::deserialize(&bytes[..bytes.len() - 1].to_vec()).is_err());
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(HelloPacket::deserialize(&trailing).is_err());
+
+    let mut bad_flag = bytes.clone();
+    bad_flag[16] = 2;
+    assert!(HelloPacket::deserialize(&bad_flag).is_err());
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    assert!(!is_hello_packet(&bad_magic));
+    assert!(matches!(HelloPacket::deserialize(&bad_magic), Err(HelloPacketError::InvalidMagicNumber)));
+
+    // A legacy data packet is never mistaken for a hello.
+    assert!(!is_hello_packet(&text_frame("hi")));
+}
+
+#[test]
+fn test_negotiate_common_version_and_features() {
+    let unknown = ProtocolFeatures::from_bits(1 << 20);
+    let ours = HelloPacket::new(ProtocolFeatures::local(false), None);
+    let theirs = HelloPacket::with_versions(
+        PROTOCOL_VERSION + 3,
+        MIN_PROTOCOL_VERSION,
+        ProtocolFeatures::local(false).with(ProtocolFeatures::COMPRESSION).with(unknown),
+        Some([9u8; 32]),
+    );
+
+    let negotiated = ours.negotiate(&theirs).unwrap();
+    let mirrored = theirs.negotiate(&ours).unwrap();
+    assert_eq!((negotiated.version, negotiated.features), (mirrored.version, mirrored.features));
+    assert_eq!(negotiated.version, PROTOCOL_VERSION);
+    assert!(negotiated.features.contains(ProtocolFeatures::CRC32_CHECKSUM));
+    assert!(negotiated.features.contains(ProtocolFeatures::RESUMABLE_TRANSFER));
+    assert!(!negotiated.features.contains(ProtocolFeatures::COMPRESSION));
+    assert!(!negotiated.features.contains(unknown));
+    assert_eq!(negotiated.peer_identity_key, Some([9u8; 32]));
+
+    assert!(negotiated.check_identity(&[9u8; 32]).is_ok());
+    assert!(matches!(negotiated.check_identity(&[1u8; 32]), Err(HandshakeError::IdentityMismatch)));
+}
+
+#[test]
+fn test_negotiate_refuses_incompatible_peers() {
+    let ours = HelloPacket::new(ProtocolFeatures::local(false), None);
+
+    let too_new = HelloPacket::with_versions(PROTOCOL_VERSION + 5, PROTOCOL_VERSION + 1, ProtocolFeatures::NONE, None);
+    assert!(matches!(ours.negotiate(&too_new), Err(HandshakeError::Incompatible { .. })));
+    assert!(matches!(too_new.negotiate(&ours), Err(HandshakeError::Incompatible { .. })));
+
+    let too_old = HelloPacket::with_versions(MIN_PROTOCOL_VERSION - 1, 1, ProtocolFeatures::NONE, None);
+    let error = ours.negotiate(&too_old).err().unwrap();
+    assert!(error.to_string().contains(&(MIN_PROTOCOL_VERSION - 1).to_string()));
+
+    // No silent downgrade to plaintext.
+    let encrypted = HelloPacket::new(ProtocolFeatures::local(true), None);
+    assert!(matches!(
+        ours.negotiate(&encrypted),
+        Err(HandshakeError::EncryptionMismatch { peer_encrypted: true })
+    ));
+    assert!(matches!(
+        encrypted.negotiate(&ours),
+        Err(HandshakeError::EncryptionMismatch { peer_encrypted: false })
+    ));
+
+    let airx_error = AirXError::from(std::io::Error::from(HandshakeError::NoHello));
+    assert_eq!(airx_error.code(), AIRX_ERROR_INCOMPATIBLE_PEER);
+}
+
+#[test]
+fn test_service_accepts_hello_and_legacy_peers() {
+    let port = free_port();
+    let (text_rx, stopped, service) = spawn_service(port);
+
+    // Current peer, with a hello.
+    DataService::send_once_with_retry(
+        &Peer::new(&String::from("127.0.0.1"), port, None),
+        port,
+        MagicNumbers::Text,
+        &TextPacket::new(String::from("with hello")).unwrap().serialize(),
+        Duration::from_millis(1000),
+        None,
+        None,
+    ).unwrap();
+    assert_eq!(text_rx.recv_timeout(Duration::from_secs(5)).unwrap(), "with hello");
+
+    // Legacy peer, straight to the data packet.
+    let mut legacy = DataTransmit::from(TcpStream::connect(("127.0.0.1", port)).unwrap());
+    legacy.send_data_progress_with_retry(&text_frame("without hello"), |_| ()).unwrap();
+    assert_eq!(text_rx.recv_timeout(Duration::from_secs(5)).unwrap(), "without hello");
+    let _ = legacy.close();
+
+    stopped.store(true, Ordering::SeqCst);
+    service.join().unwrap().unwrap();
+}
+
+/// Peers without a hello hang up on it, the offer is sent again without one.
+#[test]
+fn test_legacy_session_for_peers_without_hello() {
+    let port = free_port();
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+    let legacy_peer = std::thread::spawn(move || {
+        let mut first = DataTransmit::from(listener.accept().unwrap().0);
+        let _ = first.read_data_progress_with_retry(|_| ());
+        let _ = first.close();
+
+        let mut second = DataTransmit::from(listener.accept().unwrap().0);
+        let data = second.read_data_progress_with_retry(|_| ()).unwrap();
+        DataPacket::deserialize(&data).unwrap()
+    });
+
+    let packet = FileComingPacket::new(TransferId::generate(), 42, String::from("legacy.txt"));
+    DataService::send_packet_with_retry(
+        &Peer::new(&String::from("127.0.0.1"), port, None),
+        port,
+        MagicNumbers::FileComing,
+        &|format| packet.serialize_as(format),
+        Duration::from_millis(1000),
+        None,
+        None,
+    ).unwrap();
+
+    let received = legacy_peer.join().unwrap();
+    assert_eq!(received.magic_number(), MagicNumbers::FileComing.value());
+    assert_eq!(received.data(), &packet.serialize_as(TransferIdFormat::Legacy));
+}
+
+#[test]
+fn test_transfer_id_format_follows_version() {
+    assert_eq!(TransferIdFormat::of(None), TransferIdFormat::Legacy);
+    assert_eq!(TransferIdFormat::of(Some(MIN_PROTOCOL_VERSION)), TransferIdFormat::Legacy);
+    assert_eq!(TransferIdFormat::of(Some(PROTOCOL_VERSION)), TransferIdFormat::Wide);
+}
+
+#[test]
+fn test_incompatible_peer_gets_clear_error() {
+    let port = free_port();
+    let (text_rx, stopped, service) = spawn_service(port);
+
+    let mut future = DataTransmit::from(TcpStream::connect(("127.0.0.1", port)).unwrap());
+    let hello = HelloPacket::with_versions(PROTOCOL_VERSION + 5, PROTOCOL_VERSION + 1, ProtocolFeatures::NONE, None);
+    let error = future.negotiate_as_initiator(&hello).unwrap_err();
+    assert!(matches!(HandshakeError::of(&error), Some(HandshakeError::Incompatible { .. })));
+    let _ = future.close();
+
+    // Encrypted peer against a plaintext service.
+    let mut encrypted = DataTransmit::from(TcpStream::connect(("127.0.0.1", port)).unwrap());
+    let hello = HelloPacket::new(ProtocolFeatures::local(true), None);
+    let error = encrypted.negotiate_as_initiator(&hello).unwrap_err();
+    assert!(matches!(
+        HandshakeError::of(&error),
+        Some(HandshakeError::EncryptionMismatch { peer_encrypted: false })
+    ));
+    assert_eq!(AirXError::from(error).code(), AIRX_ERROR_INCOMPATIBLE_PEER);
+    let _ = encrypted.close();
+
+    assert!(text_rx.recv_timeout(Duration::from_millis(300)).is_err());
+
+    stopped.store(true, Ordering::SeqCst);
+    service.join().unwrap().unwrap();
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+use airx::network::peer::Peer;
+use airx::packet::data::magic_numbers::MagicNumbers;
+use airx::packet::data::text_packet::TextPacket;
+use airx::packet::protocol::serialize::Serialize;
+use airx::service::data_service::DataService;
+use common::{DataServiceParts, free_port};
+
+/// Listening on 0.0.0.0 accepts IPv6 connections as well.
+#[test]
+fn test_text_over_ipv6_loopback() {
+    let service_port = free_port();
+    let (text_tx, text_rx) = mpsc::channel::<(String, String)>();
+    let text_tx = Mutex::new(text_tx);
+
+    let context = DataServiceParts {
+        text_callback: Arc::new(Box::new(move |packet: &TextPacket, peer: Option<&Peer>| {
+            let host = peer.map(|p| p.host().clone()).unwrap_or_default();
+            let _ = text_tx.lock().unwrap().send((packet.text().clone(), host));
+        })),
+        ..DataServiceParts::default()
+    }.into_context("0.0.0.0", service_port);
+
+    let stopped = Arc::new(AtomicBool::new(false));
+    let thread_stopped = stopped.clone();
+    let service = std::thread::spawn(move || {
+        DataService::run(context, Box::new(move || thread_stopped.load(Ordering::SeqCst)))
+    });
+    std::thread::sleep(Duration::from_millis(200));
+
+    let packet = TextPacket::new(String::from("Hello over IPv6")).unwrap();
+    DataService::send_once_with_retry(
+        &Peer::new(&String::from("::1"), service_port, None),
+        service_port,
+        MagicNumbers::Text,
+        &packet.serialize(),
+        Duration::from_millis(1000),
+        None,
+        None,
+    ).unwrap();
+
+    let (text, _) = text_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(text, "Hello over IPv6");
+
+    stopped.store(true, Ordering::SeqCst);
+    service.join().unwrap().unwrap();
+}
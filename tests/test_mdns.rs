@@ -0,0 +1,126 @@
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use airx::packet::mdns_packet::{MdnsPacket, MdnsQuestion, MdnsRecordData, RECORD_TYPE_PTR};
+use airx::packet::protocol::serialize::Serialize;
+use airx::service::context::discovery_service_context::{DiscoveryMode, DiscoveryServiceContext, MulticastConfig};
+use airx::service::mdns_service::{AirXServiceInstance, group_hash, MDNS_SERVICE_TYPE, MdnsConfig, MdnsService};
+
+#[test]
+fn test_mdns_records_roundtrip() {
+    let instance = AirXServiceInstance::new(
+        "B612", 9818, group_hash(114514), true, vec![IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3))]);
+    let bytes = MdnsPacket::response(instance.to_records()).serialize();
+
+    let packet = MdnsPacket::deserialize(&bytes).unwrap();
+    assert!(packet.is_response());
+    assert_eq!(AirXServiceInstance::from_records(packet.records()), vec![instance]);
+
+    // Truncated packets are rejected rather than read out of bounds.
+    for len in 0..bytes.len() {
+        assert!(MdnsPacket::deserialize(&bytes[..len].to_vec()).is_err());
+    }
+}
+
+#[test]
+fn test_mdns_compressed_names() {
+    // Query for _airx._tcp.local, then a PTR answer whose names point back into the question.
+    let mut bytes = vec![0, 0, 0x84, 0, 0, 1, 0, 1, 0, 0, 0, 0];
+    bytes.extend_from_slice(b"\x05_airx\x04_tcp\x05local\x00");
+    bytes.extend_from_slice(&[0, 12, 0, 1]);
+    bytes.extend_from_slice(&[0xC0, 12, 0, 12, 0, 1, 0, 0, 0, 120, 0, 7]);
+    bytes.extend_from_slice(b"\x04B612\xC0\x0C");
+
+    let packet = MdnsPacket::deserialize(&bytes).unwrap();
+    assert_eq!(packet.questions()[0].name(), MDNS_SERVICE_TYPE);
+    assert_eq!(packet.records()[0].name(), MDNS_SERVICE_TYPE);
+    assert_eq!(*packet.records()[0].data(), MdnsRecordData::Ptr(format!("B612.{}", MDNS_SERVICE_TYPE)));
+
+    // A pointer to itself must not loop forever.
+    let mut looping = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    looping.extend_from_slice(&[0xC0, 12, 0, 12, 0, 1]);
+    assert!(MdnsPacket::deserialize(&looping).is_err());
+}
+
+/// Browse and advertise against a stand-in responder on loopback.
+#[test]
+fn test_mdns_browse_and_advertise_on_loopback() {
+    let responder = UdpSocket::bind("127.0.0.1:0").unwrap();
+    responder.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+    let responder_addr = match responder.local_addr().unwrap() {
+        std::net::SocketAddr::V4(addr) => addr,
+        _ => unreachable!(),
+    };
+
+    let mut context = DiscoveryServiceContext::new(
+        0, 0, 114514, None, false, DiscoveryMode::Broadcast, MulticastConfig::default());
+    let config = MdnsConfig {
+        endpoint: SocketAddrV4::new(*responder_addr.ip(), responder_addr.port()),
+        bind_port: 0,
+        data_port: 9818,
+    };
+    context.set_mdns(Some(config.clone()));
+
+    let peers = Arc::new(Mutex::new(HashSet::new()));
+    let stopped = Arc::new(AtomicBool::new(false));
+    let thread_peers = peers.clone();
+    let thread_stopped = stopped.clone();
+    let service = std::thread::spawn(move || {
+        let should_interrupt: Box<dyn Fn() -> bool + Send + Sync> =
+            Box::new(move || thread_stopped.load(Ordering::SeqCst));
+        MdnsService::run(&context, &config, thread_peers, &should_interrupt)
+    });
+
+    // The service queries for AirX instances and announces itself.
+    let mut buf = [0u8; 9000];
+    let mut queried = false;
+    let mut announced = None;
+    let mut service_addr = None;
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !(queried && announced.is_some()) && Instant::now() < deadline {
+        if let Ok((n, source)) = responder.recv_from(&mut buf) {
+            service_addr = Some(source);
+            let packet = MdnsPacket::deserialize(&buf[..n].to_vec()).unwrap();
+            if packet.is_response() {
+                announced = AirXServiceInstance::from_records(packet.records()).pop();
+            } else {
+                queried |= packet.questions().iter()
+                    .any(|q| q.name() == MDNS_SERVICE_TYPE && q.record_type() == RECORD_TYPE_PTR);
+            }
+        }
+    }
+    assert!(queried);
+    let announced = announced.unwrap();
+    assert_eq!(announced.port(), 9818);
+    assert_eq!(*announced.group_hash(), group_hash(114514));
+    let service_addr = service_addr.unwrap();
+
+    // Legacy unicast queries are answered directly.
+    let query = MdnsPacket::query(vec![MdnsQuestion::new(MDNS_SERVICE_TYPE, RECORD_TYPE_PTR)]);
+    responder.send_to(&query.serialize(), service_addr).unwrap();
+    let (n, _) = responder.recv_from(&mut buf).unwrap();
+    assert!(MdnsPacket::deserialize(&buf[..n].to_vec()).unwrap().is_response());
+
+    // Peers of the same group are merged, others are dropped.
+    let mut records = AirXServiceInstance::new(
+        "B612", 9818, group_hash(114514), false, vec![IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3))]).to_records();
+    records.extend(AirXServiceInstance::new(
+        "M78", 9818, group_hash(1919810), false, vec![IpAddr::V4(Ipv4Addr::new(10, 1, 2, 4))]).to_records());
+    responder.send_to(&MdnsPacket::response(records).serialize(), service_addr).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while peers.lock().unwrap().is_empty() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    stopped.store(true, Ordering::SeqCst);
+    service.join().unwrap().unwrap();
+
+    let peers = peers.lock().unwrap();
+    assert_eq!(peers.len(), 1);
+    let peer = peers.iter().next().unwrap();
+    assert_eq!(peer.host(), "10.1.2.3");
+    assert_eq!(peer.host_name(), "B612");
+}
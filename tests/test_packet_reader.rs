@@ -0,0 +1,26 @@
+use airx::packet::protocol::reader::{PacketReader, ReadError};
+
+#[test]
+fn test_packet_reader_reads_in_order() {
+    let bytes = [7, 0x40, 0x39, 3, 0, 0, 0, 1, 2, 3];
+    let mut reader = PacketReader::new(&bytes);
+
+    assert_eq!(reader.read_u8().unwrap(), 7);
+    let magic_number: u16 = reader.read().unwrap();
+    assert_eq!(magic_number, 0x3940);
+    let len: u32 = reader.read().unwrap();
+    assert_eq!(reader.read_bytes(len).unwrap(), &[1, 2, 3]);
+    assert!(reader.finish().is_ok());
+}
+
+#[test]
+fn test_packet_reader_stops_at_the_end() {
+    let bytes = [1, 2, 3];
+    let mut reader = PacketReader::new(&bytes);
+
+    assert!(matches!(reader.read::<u32, 4>(), Err(ReadError::UnexpectedEnd)));
+    assert!(matches!(reader.read_bytes(u64::MAX), Err(ReadError::UnexpectedEnd)));
+    assert_eq!(reader.remaining(), 3);
+    assert!(matches!(reader.finish(), Err(ReadError::TrailingData)));
+    assert_eq!(reader.read_rest(), &[1, 2, 3]);
+}
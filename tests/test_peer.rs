@@ -1,3 +1,4 @@
+use std::net::SocketAddr;
 use airx::network::peer::Peer;
 
 /// Equation of two peers is determined only by their hosts.
@@ -11,3 +12,16 @@ fn host() {
     assert!(peer1 != peer3);
     assert_eq!(peer2.to_string(), String::from("M78@114.51.41.91:9819"));
 }
+
+#[test]
+fn ipv6_host() {
+    let addr = "[fe80::1%3]:9818".parse::<SocketAddr>().unwrap();
+    let peer = Peer::from_socket_addr(&addr, 9818, Some(&String::from("B612")));
+
+    assert_eq!(peer.host(), "fe80::1%3");
+    assert_eq!(peer.socket_addr(9819), Some("[fe80::1%3]:9819".parse::<SocketAddr>().unwrap()));
+    assert_eq!(peer.to_string(), String::from("B612@[fe80::1%3]:9818"));
+
+    let peer = Peer::new(&String::from("::1"), 9818, None);
+    assert_eq!(peer.ip_addr(), Some("::1".parse().unwrap()));
+}
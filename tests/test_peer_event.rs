@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use airx::extension::ip_to_u32::ConvertIpU32;
+use airx::network::peer::Peer;
+use airx::proto::discovery_packet::DiscoveryPacket;
+use airx::service::context::discovery_service_context::{DiscoveryMode, DiscoveryServiceContext, MulticastConfig};
+use airx::service::discovery_service::{DiscoveryService, PeerCollectionType};
+use airx::service::peer_event::{peer_event_channel, PeerEvent};
+
+fn discovery_packet(host_name: &str, goodbye: bool) -> DiscoveryPacket {
+    let mut packet = DiscoveryPacket::new();
+    packet.set_address(Ipv4Addr::new(10, 9, 9, 9).to_u32());
+    packet.set_server_port(9818);
+    packet.set_group_identifier(114514);
+    packet.set_need_response(false);
+    packet.set_host_name(String::from(host_name));
+    packet.set_goodbye(goodbye);
+    packet
+}
+
+#[test]
+fn test_peer_events() {
+    let (callback, events) = peer_event_channel();
+    let mut context = DiscoveryServiceContext::new(
+        0, 0, 114514, None, false, DiscoveryMode::Broadcast, MulticastConfig::default());
+    context.set_peer_event_callback(Some(callback));
+
+    let peers: PeerCollectionType = Arc::new(Mutex::new(HashSet::new()));
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let source: SocketAddr = "10.9.9.9:9818".parse().unwrap();
+    let receive = |packet| DiscoveryService::handle_new_peer(
+        HashSet::new(), &socket, peers.clone(), packet, source, &context).unwrap();
+
+    receive(discovery_packet("B612", false));
+    match events.try_recv().unwrap() {
+        PeerEvent::Added(peer) => assert_eq!(peer.host_name(), "B612"),
+        _ => panic!("expected Added"),
+    }
+
+    // A heartbeat with nothing new is not an event.
+    receive(discovery_packet("B612", false));
+    assert!(events.try_recv().is_err());
+
+    receive(discovery_packet("M78", false));
+    match events.try_recv().unwrap() {
+        PeerEvent::Updated { previous, current } => {
+            assert_eq!(previous.host_name(), "B612");
+            assert_eq!(current.host_name(), "M78");
+        }
+        _ => panic!("expected Updated"),
+    }
+
+    receive(discovery_packet("M78", true));
+    assert_eq!(events.try_recv().unwrap().value(), 2);
+    assert!(peers.lock().unwrap().is_empty());
+
+    let mut stale = Peer::new(&String::from("10.0.0.1"), 9818, None);
+    stale.set_last_seen(Instant::now() - Duration::from_secs(3600));
+    peers.lock().unwrap().insert(stale);
+    DiscoveryService::evict_expired_peers(&peers, &context);
+    match events.try_recv().unwrap() {
+        PeerEvent::Removed(peer) => assert_eq!(peer.host(), "10.0.0.1"),
+        _ => panic!("expected Removed"),
+    }
+}
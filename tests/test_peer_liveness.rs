@@ -0,0 +1,60 @@
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use airx::extension::ip_to_u32::ConvertIpU32;
+use airx::network::peer::Peer;
+use airx::proto::discovery_packet::DiscoveryPacket;
+use airx::service::context::discovery_service_context::{DiscoveryMode, DiscoveryServiceContext, MulticastConfig};
+use airx::service::discovery_service::{DiscoveryService, PeerCollectionType};
+
+fn context() -> DiscoveryServiceContext {
+    let mut context = DiscoveryServiceContext::new(
+        0, 0, 114514, None, false, DiscoveryMode::Broadcast, MulticastConfig::default());
+    context.set_liveness(Duration::from_secs(10), Duration::from_secs(30));
+    context
+}
+
+fn discovery_packet(goodbye: bool) -> DiscoveryPacket {
+    let mut packet = DiscoveryPacket::new();
+    packet.set_address(Ipv4Addr::new(10, 9, 9, 9).to_u32());
+    packet.set_server_port(9818);
+    packet.set_group_identifier(114514);
+    packet.set_need_response(false);
+    packet.set_host_name(String::from("B612"));
+    packet.set_goodbye(goodbye);
+    packet
+}
+
+#[test]
+fn test_stale_peers_are_evicted() {
+    let peers: PeerCollectionType = Arc::new(Mutex::new(HashSet::new()));
+    let mut stale = Peer::new(&String::from("10.0.0.1"), 9818, None);
+    stale.set_last_seen(Instant::now() - Duration::from_secs(60));
+    let fresh = Peer::new(&String::from("10.0.0.2"), 9818, None);
+    peers.lock().unwrap().insert(stale.clone());
+    peers.lock().unwrap().insert(fresh.clone());
+
+    let evicted = DiscoveryService::evict_expired_peers(&peers, &context());
+    assert!(evicted == vec![stale]);
+    assert!(peers.lock().unwrap().iter().collect::<Vec<_>>() == vec![&fresh]);
+}
+
+#[test]
+fn test_heartbeat_refreshes_and_goodbye_removes_peer() {
+    let peers: PeerCollectionType = Arc::new(Mutex::new(HashSet::new()));
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let source: SocketAddr = "10.9.9.9:9818".parse().unwrap();
+
+    let mut stale = Peer::new(&String::from("10.9.9.9"), 9818, None);
+    stale.set_last_seen(Instant::now() - Duration::from_secs(60));
+    peers.lock().unwrap().insert(stale);
+
+    DiscoveryService::handle_new_peer(
+        HashSet::new(), &socket, peers.clone(), discovery_packet(false), source, &context()).unwrap();
+    assert!(!peers.lock().unwrap().iter().next().unwrap().is_expired(Duration::from_secs(30)));
+
+    DiscoveryService::handle_new_peer(
+        HashSet::new(), &socket, peers.clone(), discovery_packet(true), source, &context()).unwrap();
+    assert!(peers.lock().unwrap().is_empty());
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use airx::network::peer::Peer;
+use airx::packet::data::magic_numbers::MagicNumbers;
+use airx::packet::data::text_packet::TextPacket;
+use airx::packet::protocol::serialize::Serialize;
+use airx::security::group_key::GroupKey;
+use airx::security::identity::{DeviceIdentity, identity_transcript, random_nonce};
+use airx::security::secure_channel::{HandshakeRole, random_hello, SecureChannel};
+use airx::service::data_service::DataService;
+use common::{DataServiceParts, free_port, text_channel};
+
+/// Forward one connection from `listener` to `upstream_port`, recording what the client sends.
+fn spawn_recording_relay(listener: TcpListener, upstream_port: u16, recorded: Arc<Mutex<Vec<u8>>>) {
+    std::thread::spawn(move || {
+        let (mut client, _) = listener.accept().unwrap();
+        let mut upstream = TcpStream::connect(("127.0.0.1", upstream_port)).unwrap();
+        let mut client_reader = client.try_clone().unwrap();
+        let mut upstream_reader = upstream.try_clone().unwrap();
+
+        std::thread::spawn(move || {
+            let _ = std::io::copy(&mut upstream_reader, &mut client);
+        });
+
+        let mut buf = [0u8; 4096];
+        loop {
+            match client_reader.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    recorded.lock().unwrap().extend_from_slice(&buf[..n]);
+                    if upstream.write_all(&buf[..n]).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+        let _ = upstream.shutdown(std::net::Shutdown::Write);
+    });
+}
+
+#[test]
+fn test_secure_channel_roundtrip() {
+    let key = GroupKey::from_bytes([7u8; 32]);
+    let initiator_random = random_hello();
+    let responder_random = random_hello();
+    let mut initiator = SecureChannel::new(&key, HandshakeRole::Initiator, &initiator_random, &responder_random);
+    let mut responder = SecureChannel::new(&key, HandshakeRole::Responder, &initiator_random, &responder_random);
+
+    let sealed = initiator.seal(b"hello").unwrap();
+    assert_eq!(responder.open(&sealed).unwrap(), b"hello".to_vec());
+
+    // Replaying the same frame fails because the nonce has moved on.
+    assert!(responder.open(&sealed).is_err());
+
+    let other_key = GroupKey::from_bytes([8u8; 32]);
+    let mut stranger = SecureChannel::new(&other_key, HandshakeRole::Responder, &initiator_random, &responder_random);
+    assert!(stranger.open(&initiator.seal(b"hello").unwrap()).is_err());
+}
+
+#[test]
+fn test_identity_proof_is_bound_to_channel() {
+    let key = GroupKey::from_bytes([7u8; 32]);
+    let (initiator_random, responder_random) = (random_hello(), random_hello());
+    let initiator = SecureChannel::new(&key, HandshakeRole::Initiator, &initiator_random, &responder_random);
+    let responder = SecureChannel::new(&key, HandshakeRole::Responder, &initiator_random, &responder_random);
+    assert_eq!(initiator.binding(), responder.binding());
+
+    // Someone relaying between two channels of their own gets a different binding on each.
+    let relayed = SecureChannel::new(&key, HandshakeRole::Responder, &random_hello(), &responder_random);
+    assert_ne!(initiator.binding(), relayed.binding());
+
+    let identity = DeviceIdentity::generate();
+    let peer_key = DeviceIdentity::generate().public_key();
+    let (peer_nonce, own_nonce) = (random_nonce(), random_nonce());
+    let signature = identity.sign(&identity_transcript(true, &peer_nonce, &own_nonce, &peer_key, initiator.binding()));
+    assert!(DeviceIdentity::verify(&identity.public_key(),
+        &identity_transcript(true, &peer_nonce, &own_nonce, &peer_key, responder.binding()), &signature));
+    assert!(!DeviceIdentity::verify(&identity.public_key(),
+        &identity_transcript(true, &peer_nonce, &own_nonce, &peer_key, relayed.binding()), &signature));
+}
+
+#[test]
+fn test_encrypted_text_over_loopback() {
+    let key = GroupKey::from_passphrase("correct horse battery staple");
+    let secret_text = String::from("This clipboard text must never appear on the wire.");
+
+    let service_port = free_port();
+    let (text_callback, text_rx) = text_channel();
+    let context = DataServiceParts { text_callback, group_key: Some(key.clone()), ..DataServiceParts::default() }
+        .into_context("127.0.0.1", service_port);
+
+    let stopped = Arc::new(AtomicBool::new(false));
+    let thread_stopped = stopped.clone();
+    let service = std::thread::spawn(move || {
+        DataService::run(context, Box::new(move || thread_stopped.load(Ordering::SeqCst)))
+    });
+    std::thread::sleep(Duration::from_millis(200));
+
+    let relay_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let relay_port = relay_listener.local_addr().unwrap().port();
+    let recorded = Arc::new(Mutex::new(Vec::new()));
+    spawn_recording_relay(relay_listener, service_port, recorded.clone());
+
+    let packet = TextPacket::new(secret_text.clone()).unwrap();
+    DataService::send_once_with_retry(
+        &Peer::new(&String::from("127.0.0.1"), relay_port, None),
+        relay_port,
+        MagicNumbers::Text,
+        &packet.serialize(),
+        Duration::from_millis(1000),
+        Some(&key),
+        None,
+    ).unwrap();
+
+    let received = text_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(received, secret_text);
+
+    let recorded = recorded.lock().unwrap();
+    assert!(!recorded.is_empty());
+    assert!(!recorded
+        .windows(secret_text.len())
+        .any(|window| window == secret_text.as_bytes()));
+
+    stopped.store(true, Ordering::SeqCst);
+    service.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_peer_requiring_encryption_is_not_sent_plaintext() {
+    let mut peer = Peer::new(&String::from("127.0.0.1"), free_port(), None);
+    peer.set_encryption_required(true);
+
+    let result = DataService::send_once_with_retry(
+        &peer,
+        peer.port(),
+        MagicNumbers::Text,
+        &TextPacket::new(String::from("hi")).unwrap().serialize(),
+        Duration::from_millis(1000),
+        None,
+        None,
+    );
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::PermissionDenied);
+}
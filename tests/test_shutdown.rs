@@ -0,0 +1,244 @@
+// The file sending tests receive at 127.0.0.2, which only Linux routes to loopback.
+#![cfg_attr(not(target_os = "linux"), allow(dead_code, unused_imports))]
+
+mod common;
+
+use std::collections::HashSet;
+use std::io::Read;
+use std::net::{Ipv4Addr, TcpListener, TcpStream, UdpSocket};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use airx::network::peer::Peer;
+use airx::packet::data::file_part_packet::FilePartPacket;
+use airx::packet::data::file_part_response_packet::{FilePartResponsePacket, ResponseKind};
+use airx::packet::data::file_receive_response_packet::FileReceiveResponsePacket;
+use airx::packet::data::local::file_sending_packet::{FileSendingPacket, FileSendingStatus};
+use airx::packet::data::magic_numbers::MagicNumbers;
+use airx::packet::data::transfer_id::{TransferId, TransferIdFormat};
+use airx::packet::data_packet::DataPacket;
+use airx::packet::data_transmission::DataTransmit;
+use airx::packet::hello_packet::HelloPacket;
+use airx::packet::protocol::features::ProtocolFeatures;
+use airx::packet::protocol::serialize::Serialize;
+use airx::security::identity::DeviceIdentity;
+use airx::service::context::data_service_context::DataServiceContext;
+use airx::service::context::discovery_service_context::{DiscoveryMode, DiscoveryServiceContext, MulticastConfig};
+use airx::service::data_service::DataService;
+use airx::service::discovery_service::DiscoveryService;
+use airx::service::shutdown::ShutdownHandle;
+use common::{connect_from, DataServiceParts, free_port};
+
+fn data_context(
+    host: &str,
+    port: u16,
+    file_sending_callback: Box<dyn Fn(&FileSendingPacket, Option<&Peer>) + Send + Sync>,
+    file_part_callback: Box<dyn Fn(&FilePartPacket, Option<&Peer>) -> bool + Send + Sync>,
+    shutdown: &ShutdownHandle,
+) -> DataServiceContext {
+    let mut context = DataServiceParts {
+        file_sending_callback: Arc::new(file_sending_callback),
+        file_part_callback: Arc::new(file_part_callback),
+        ..DataServiceParts::default()
+    }.into_context(host, port);
+    context.set_shutdown(shutdown.clone());
+    context
+}
+
+/// Runs until shut down, whatever the interrupt callback says.
+fn start_data_service(context: DataServiceContext) -> std::thread::JoinHandle<Result<(), airx::error::AirXError>> {
+    let service = std::thread::spawn(move || DataService::run(context, Box::new(|| false)));
+    std::thread::sleep(Duration::from_millis(200));
+    service
+}
+
+fn temp_file(name: &str, size: usize) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("airx_{}_{}", name, std::process::id()));
+    std::fs::write(&path, vec![0x39u8; size]).unwrap();
+    path
+}
+
+/// Have the sender at 127.0.0.1 offer `path` to 127.0.0.2 and start sending it, as if the receiver accepted it.
+/// The response comes in the legacy format, without a hello.
+fn accept_file_from(context: &DataServiceContext, port: u16, path: &PathBuf, size: u64) {
+    context.transfer_manager().offer(
+        TransferId::from_legacy(7), String::from("127.0.0.2"), path.to_string_lossy().to_string(), size);
+    let response = FileReceiveResponsePacket::new(
+        TransferId::from_legacy(7), size, path.to_string_lossy().to_string(), true);
+    let packet = DataPacket::new(MagicNumbers::FileReceiveResponse.value(), &response.serialize_as(TransferIdFormat::Legacy));
+    let mut dt = DataTransmit::from(connect_from("127.0.0.2", port));
+    dt.send_data_progress_with_retry(&packet.serialize(), |_| ()).unwrap();
+}
+
+fn status_channel() -> (Box<dyn Fn(&FileSendingPacket, Option<&Peer>) + Send + Sync>, mpsc::Receiver<u8>) {
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+    (Box::new(move |packet: &FileSendingPacket, _: Option<&Peer>| {
+        let _ = tx.lock().unwrap().send(packet.status().to_u8());
+    }), rx)
+}
+
+/// Statuses reported after the transfer started.
+fn final_status(statuses: &mpsc::Receiver<u8>) -> FileSendingStatus {
+    loop {
+        let status = FileSendingStatus::from_u8(
+            statuses.recv_timeout(Duration::from_secs(10)).unwrap()).unwrap();
+        match status {
+            FileSendingStatus::Requested | FileSendingStatus::Accepted | FileSendingStatus::InProgress => continue,
+            _ => return status,
+        }
+    }
+}
+
+#[test]
+fn test_sleep_wakes_on_shutdown() {
+    let shutdown = ShutdownHandle::new();
+    let sleeper = shutdown.clone();
+    let started = Instant::now();
+    let thread = std::thread::spawn(move || sleeper.sleep(Duration::from_secs(30)));
+
+    std::thread::sleep(Duration::from_millis(50));
+    shutdown.shutdown();
+    assert!(thread.join().unwrap());
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // Late hooks run right away.
+    let (tx, rx) = mpsc::channel();
+    let _guard = shutdown.on_shutdown(Box::new(move || tx.send(()).unwrap()));
+    assert!(rx.try_recv().is_ok());
+}
+
+#[test]
+fn test_join_waits_for_threads_spawned_on_shutdown() {
+    let shutdown = ShutdownHandle::new();
+    let finished = Arc::new(AtomicBool::new(false));
+    let (spawner, thread_finished) = (shutdown.clone(), finished.clone());
+    let _guard = shutdown.on_shutdown(Box::new(move || {
+        let thread_finished = thread_finished.clone();
+        spawner.spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            thread_finished.store(true, Ordering::SeqCst);
+        });
+    }));
+
+    shutdown.stop();
+    assert!(finished.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_data_service_stops_with_idle_session() {
+    let shutdown = ShutdownHandle::new();
+    let port = free_port();
+    let service = start_data_service(data_context("127.0.0.1", port, Box::new(|_, _| ()), Box::new(|_, _| false), &shutdown));
+
+    let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+
+    let started = Instant::now();
+    shutdown.stop();
+    assert!(started.elapsed() < Duration::from_secs(1));
+    service.join().unwrap().unwrap();
+
+    let mut buf = [0u8; 1];
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn test_discovery_service_stops() {
+    let server_port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+    let multicast = MulticastConfig {
+        group_v4: Ipv4Addr::new(239, 255, 65, 90),
+        ..MulticastConfig::default()
+    };
+    let mut context = DiscoveryServiceContext::new(
+        0, server_port, 114514, None, false, DiscoveryMode::Multicast, multicast);
+    let shutdown = ShutdownHandle::new();
+    context.set_shutdown(shutdown.clone());
+
+    let peers = Arc::new(Mutex::new(HashSet::new()));
+    let service = std::thread::spawn(move || DiscoveryService::run(context, peers, Box::new(|| false)));
+    std::thread::sleep(Duration::from_millis(300));
+
+    let started = Instant::now();
+    shutdown.stop();
+    service.join().unwrap().unwrap();
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_file_sending_cancelled_by_sender() {
+    let size = 40 * 1024 * 1024;
+    let path = temp_file("cancel_by_sender", size);
+    let port = free_port();
+    let receiver = TcpListener::bind(("127.0.0.2", port)).unwrap();
+
+    let shutdown = ShutdownHandle::new();
+    let (callback, statuses) = status_channel();
+    let context = data_context("127.0.0.1", port, callback, Box::new(|_, _| false), &shutdown);
+    let offer_context = context.clone();
+    let service = start_data_service(context);
+    accept_file_from(&offer_context, port, &path, size as u64);
+
+    // Read the first part, then shut the sender down and expect it to say so.
+    let (stream, _) = receiver.accept().unwrap();
+    let mut dt = DataTransmit::from(stream);
+    let identity = DeviceIdentity::generate();
+    dt.negotiate_as_responder(&HelloPacket::new(ProtocolFeatures::local(false), Some(identity.public_key()))).unwrap();
+    let hello = dt.read_data_progress_with_retry(|_| ()).unwrap();
+    dt.identify_as_responder(&identity, &hello).unwrap();
+    let first = DataPacket::deserialize(&dt.read_data_progress_with_retry(|_| ()).unwrap()).unwrap();
+    assert_eq!(first.magic_number(), MagicNumbers::FilePart.value());
+    shutdown.shutdown();
+
+    let stop = loop {
+        let packet = DataPacket::deserialize(&dt.read_data_progress_with_retry(|_| ()).unwrap()).unwrap();
+        if packet.magic_number() == MagicNumbers::FilePartResponse.value() {
+            break FilePartResponsePacket::deserialize(packet.data()).unwrap();
+        }
+    };
+    assert!(matches!(stop.response_kind(), ResponseKind::StopSending));
+    assert_eq!(stop.transfer_id(), TransferId::from_legacy(7));
+    assert!(matches!(final_status(&statuses), FileSendingStatus::CancelledBySender));
+
+    shutdown.join();
+    service.join().unwrap().unwrap();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_file_sending_cancelled_by_receiver() {
+    let size = 40 * 1024 * 1024;
+    let path = temp_file("cancel_by_receiver", size);
+    let port = free_port();
+
+    // The receiver shuts down as soon as the first part arrives.
+    let receiver_shutdown = ShutdownHandle::new();
+    let part_shutdown = receiver_shutdown.clone();
+    let receiver = start_data_service(data_context(
+        "127.0.0.2", port, Box::new(|_, _| ()),
+        Box::new(move |_, _| {
+            part_shutdown.shutdown();
+            false
+        }),
+        &receiver_shutdown));
+
+    let sender_shutdown = ShutdownHandle::new();
+    let (callback, statuses) = status_channel();
+    let context = data_context("127.0.0.1", port, callback, Box::new(|_, _| false), &sender_shutdown);
+    let offer_context = context.clone();
+    let sender = start_data_service(context);
+    accept_file_from(&offer_context, port, &path, size as u64);
+
+    assert!(matches!(final_status(&statuses), FileSendingStatus::CancelledByReceiver));
+    receiver_shutdown.join();
+    receiver.join().unwrap().unwrap();
+
+    sender_shutdown.stop();
+    sender.join().unwrap().unwrap();
+    let _ = std::fs::remove_file(&path);
+}
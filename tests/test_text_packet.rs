@@ -19,3 +19,23 @@ fn test_text_packet_serializable() {
 
     assert_eq!(packet2.text, test_string);
 }
+
+#[test]
+fn test_text_packet_rejects_malformed() {
+    let bytes = TextPacket::new("miku".to_string()).unwrap().serialize();
+    for len in 0..bytes.len() {
+        assert!(TextPacket::deserialize(&bytes[..len].to_vec()).is_err());
+    }
+
+    // Text length far past the end.
+    let mut bytes = bytes;
+    bytes[0..4].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+    assert!(TextPacket::deserialize(&bytes).is_err());
+}
+
+#[test]
+fn test_text_packet_rejects_trailing_data() {
+    let mut bytes = TextPacket::new("miku".to_string()).unwrap().serialize();
+    bytes.push(0);
+    assert!(TextPacket::deserialize(&bytes).is_err());
+}
@@ -0,0 +1,144 @@
+use std::path::PathBuf;
+use airx::compatibility::unified_endian::UnifiedEndian;
+use airx::packet::data::byte_ranges::{ByteRanges, MAX_BYTE_RANGES};
+use airx::packet::data::transfer_id::TransferId;
+use airx::packet::protocol::reader::PacketReader;
+use airx::service::transfer_journal::{JournalEntry, TransferJournal};
+use airx::service::transfer_manager::{TransferManager, TransferSide, TransferState};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("airx_journal_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    path
+}
+
+#[test]
+fn test_byte_ranges_merge() {
+    let mut ranges = ByteRanges::new();
+    ranges.insert(100, 200);
+    ranges.insert(300, 400);
+    ranges.insert(0, 50);
+    assert_eq!(ranges.ranges(), &[(0, 50), (100, 200), (300, 400)]);
+
+    // Touching and overlapping ranges merge, empty ones are ignored.
+    ranges.insert(50, 100);
+    ranges.insert(150, 350);
+    ranges.insert(500, 500);
+    assert_eq!(ranges.ranges(), &[(0, 400)]);
+    assert_eq!(ranges.len(), 400);
+
+    ranges.insert(600, 700);
+    assert_eq!(ranges.missing(1000), vec![(400, 600), (700, 1000)]);
+    assert_eq!(ranges.missing(650), vec![(400, 600)]);
+    assert!(!ranges.covers(1000));
+    assert!(ranges.covers(400));
+    assert_eq!(ByteRanges::new().missing(10), vec![(0, 10)]);
+    assert!(ByteRanges::new().covers(0));
+}
+
+#[test]
+fn test_byte_ranges_serialization() {
+    let ranges = ByteRanges::parse("0-100,512-1000").unwrap();
+    assert_eq!(ranges.to_string(), "0-100,512-1000");
+    assert_eq!(ByteRanges::parse("").unwrap(), ByteRanges::new());
+    assert!(ByteRanges::parse("12").is_none());
+    assert!(ByteRanges::parse("a-b").is_none());
+
+    let mut bytes = Vec::new();
+    ranges.write(&mut bytes);
+    assert_eq!(bytes.len(), 4 + 2 * 16);
+    let mut reader = PacketReader::new(&bytes);
+    assert_eq!(ByteRanges::read(&mut reader).unwrap(), ranges);
+    assert!(ByteRanges::read(&mut PacketReader::new(&bytes[..bytes.len() - 1])).is_err());
+
+    // Ranges past the limit are left out, they are simply sent again.
+    let count = MAX_BYTE_RANGES as u32 + 10;
+    let mut bytes = count.to_bytes().to_vec();
+    for i in 0..count as u64 {
+        bytes.extend_from_slice(&(i * 10).to_bytes());
+        bytes.extend_from_slice(&(i * 10 + 5).to_bytes());
+    }
+    let read = ByteRanges::read(&mut PacketReader::new(&bytes)).unwrap();
+    assert_eq!(read.ranges().len(), MAX_BYTE_RANGES);
+}
+
+#[test]
+fn test_journal_survives_restart() {
+    let directory = temp_dir("restart");
+    let sent = TransferId::generate();
+    let received = TransferId::generate();
+
+    let journal = TransferJournal::new();
+    journal.attach(&directory).unwrap();
+    journal.record(JournalEntry::sending(
+        sent, String::from("10.0.0.2"), String::from("/tmp/miku\tvideo.mp4"), 4096, Some(1_700_000_000))).unwrap();
+    journal.record(JournalEntry::receiving(received, String::from("10.0.0.3"), String::from("clip.mp4"), 4096)).unwrap();
+    journal.record_received(&received, 0, 1024).unwrap();
+    let progress = journal.record_received(&received, 2048, 3072).unwrap();
+    assert_eq!(progress.received().len(), 2048);
+
+    // Parts of files we do not receive are not journaled.
+    assert!(journal.record_received(&sent, 0, 1024).is_none());
+    journal.flush().unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let journal_file = std::fs::read_dir(&directory).unwrap().next().unwrap().unwrap();
+        assert_eq!(journal_file.metadata().unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    let restarted = TransferJournal::new();
+    restarted.attach(&directory).unwrap();
+    let entry = restarted.get(&sent, TransferSide::Sender).unwrap();
+    assert_eq!(entry.file(), "/tmp/miku\tvideo.mp4");
+    assert_eq!(entry.modified_at(), Some(1_700_000_000));
+    let entry = restarted.get(&received, TransferSide::Receiver).unwrap();
+    assert_eq!(entry.host(), "10.0.0.3");
+    assert_eq!(entry.received().to_string(), "0-1024,2048-3072");
+    assert!(restarted.get(&received, TransferSide::Sender).is_none());
+
+    // A file received in full leaves the journal.
+    restarted.record_received(&received, 1024, 2048).unwrap();
+    restarted.record_received(&received, 3072, 4096).unwrap();
+    assert!(restarted.entries(TransferSide::Receiver).is_empty());
+    let reloaded = TransferJournal::new();
+    reloaded.attach(&directory).unwrap();
+    assert!(reloaded.get(&received, TransferSide::Receiver).is_none());
+    assert_eq!(reloaded.entries(TransferSide::Sender).len(), 1);
+
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn test_unfinished_transfers_come_back_paused() {
+    let directory = temp_dir("paused");
+    let paused = TransferId::generate();
+    let completed = TransferId::generate();
+
+    let transfers = TransferManager::new();
+    transfers.attach_journal(&directory).unwrap();
+    for id in [paused, completed] {
+        transfers.offer(id, String::from("10.0.0.2"), String::from("/tmp/miku.txt"), 100);
+        transfers.transition(&id, TransferState::Accepted, 0).unwrap();
+        transfers.transition(&id, TransferState::Streaming, 0).unwrap();
+    }
+    transfers.transition(&paused, TransferState::Paused, 40).unwrap();
+    transfers.transition(&completed, TransferState::Completed, 100).unwrap();
+
+    let restarted = TransferManager::new();
+    restarted.attach_journal(&directory).unwrap();
+    let transfer = restarted.get(&paused).unwrap();
+    assert_eq!(transfer.state(), TransferState::Paused);
+    assert_eq!(transfer.host(), "10.0.0.2");
+    assert_eq!(transfer.file_path(), "/tmp/miku.txt");
+    assert_eq!(transfer.file_size(), 100);
+    assert!(restarted.get(&completed).is_none());
+
+    // Cancelled, it is gone for good.
+    restarted.transition(&paused, TransferState::Cancelled(TransferSide::Receiver), 40).unwrap();
+    let reloaded = TransferManager::new();
+    reloaded.attach_journal(&directory).unwrap();
+    assert!(reloaded.get(&paused).is_none());
+
+    let _ = std::fs::remove_dir_all(&directory);
+}
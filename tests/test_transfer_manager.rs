@@ -0,0 +1,395 @@
+// The sending tests tell peers apart by 127.0.0.2 and 127.0.0.3, which only Linux routes to loopback.
+#![cfg_attr(not(target_os = "linux"), allow(dead_code, unused_imports))]
+
+mod common;
+
+use std::net::TcpListener;
+use std::os::raw::c_char;
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+use airx::error::AIRX_OK;
+use airx::lib_generic::{airx_create_service, airx_try_send_file};
+use airx::network::peer::Peer;
+use airx::packet::data::byte_ranges::{ByteRanges, MAX_BYTE_RANGES};
+use airx::packet::data::file_coming_packet::FileComingPacket;
+use airx::packet::data::file_part_packet::FilePartPacket;
+use airx::packet::data::file_receive_response_packet::FileReceiveResponsePacket;
+use airx::packet::data::local::file_sending_packet::FileSendingPacket;
+use airx::packet::data::magic_numbers::MagicNumbers;
+use airx::packet::data::transfer_id::{TransferId, TransferIdFormat};
+use airx::packet::data_packet::DataPacket;
+use airx::packet::data_transmission::DataTransmit;
+use airx::packet::hello_packet::HelloPacket;
+use airx::packet::protocol::features::ProtocolFeatures;
+use airx::packet::protocol::serialize::Serialize;
+use airx::security::identity::DeviceIdentity;
+use airx::service::airx_service::AirXService;
+use airx::service::context::data_service_context::DataServiceContext;
+use airx::service::data_service::DataService;
+use airx::service::metrics::ServiceMetrics;
+use airx::service::shutdown::ShutdownHandle;
+use airx::service::transfer_manager::{TransferError, TransferManager, TransferSide, TransferState};
+use common::{connect_from, DataServiceParts, free_port};
+
+/// Statuses reported by `transfers`, as their `to_u8` values.
+fn record_statuses(transfers: &TransferManager) -> Arc<Mutex<Vec<u8>>> {
+    let statuses = Arc::new(Mutex::new(Vec::new()));
+    let recorded = statuses.clone();
+    transfers.set_listener(Arc::new(Box::new(move |packet: &FileSendingPacket, _: Option<&Peer>| {
+        recorded.lock().unwrap().push(packet.status().to_u8());
+    })));
+    statuses
+}
+
+fn temp_file(name: &str, content: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("airx_{}_{}", name, std::process::id()));
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+/// Send a file receive response to the data service at 127.0.0.1 from `source`, with a hello.
+fn respond_from(source: &str, port: u16, response: &FileReceiveResponsePacket) {
+    let mut dt = DataTransmit::from(connect_from(source, port));
+    dt.negotiate_as_initiator(&HelloPacket::new(ProtocolFeatures::local(false), None)).unwrap();
+    let packet = DataPacket::new(MagicNumbers::FileReceiveResponse.value(), &response.serialize());
+    dt.send_data_progress_with_retry(&packet.serialize(), |_| ()).unwrap();
+}
+
+/// Data service at 127.0.0.1 sending the files offered through its transfer manager.
+fn sender_context(port: u16, metrics: Arc<ServiceMetrics>, shutdown: &ShutdownHandle) -> DataServiceContext {
+    let mut context = DataServiceParts { metrics, ..DataServiceParts::default() }.into_context("127.0.0.1", port);
+    context.set_shutdown(shutdown.clone());
+    context
+}
+
+/// Wait until the transfer `id` is in `state`.
+fn wait_for_state(transfers: &TransferManager, id: &TransferId, state: TransferState) -> bool {
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while std::time::Instant::now() < deadline {
+        if transfers.get(id).map(|t| t.state()) == Some(state) {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
+
+/// Wait for the sender to connect to `receiver` and return the first file part it streams.
+fn first_part(receiver: &TcpListener) -> Option<FilePartPacket> {
+    receiver.set_nonblocking(true).unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_millis(1500);
+    let stream = loop {
+        match receiver.accept() {
+            Ok((stream, _)) => break stream,
+            Err(_) if std::time::Instant::now() < deadline => std::thread::sleep(Duration::from_millis(20)),
+            Err(_) => return None,
+        }
+    };
+    stream.set_nonblocking(false).unwrap();
+    let mut dt = DataTransmit::from(stream);
+    let identity = DeviceIdentity::generate();
+    dt.negotiate_as_responder(&HelloPacket::new(ProtocolFeatures::local(false), Some(identity.public_key()))).unwrap();
+    let hello = dt.read_data_progress_with_retry(|_| ()).unwrap();
+    dt.identify_as_responder(&identity, &hello).unwrap();
+    let packet = DataPacket::deserialize(&dt.read_data_progress_with_retry(|_| ()).unwrap()).unwrap();
+    Some(FilePartPacket::deserialize(packet.data()).unwrap())
+}
+
+/// Like `first_part`, for a receiver older than the hello, which hangs up on it.
+fn first_legacy_part(receiver: &TcpListener) -> FilePartPacket {
+    let mut hello = DataTransmit::from(receiver.accept().unwrap().0);
+    let _ = hello.read_data_progress_with_retry(|_| ());
+    let _ = hello.close();
+
+    let mut dt = DataTransmit::from(receiver.accept().unwrap().0);
+    let packet = DataPacket::deserialize(&dt.read_data_progress_with_retry(|_| ()).unwrap()).unwrap();
+    FilePartPacket::deserialize_as(packet.data(), TransferIdFormat::Legacy).unwrap()
+}
+
+#[test]
+fn test_transfer_lifecycle() {
+    let transfers = TransferManager::new();
+    let statuses = record_statuses(&transfers);
+    let id = TransferId::generate();
+
+    transfers.offer(id, String::from("10.0.0.2"), String::from("/tmp/miku.txt"), 100);
+    assert_eq!(transfers.get(&id).unwrap().state(), TransferState::Offered);
+
+    transfers.transition(&id, TransferState::Accepted, 0).unwrap();
+    transfers.transition(&id, TransferState::Streaming, 0).unwrap();
+    transfers.transition(&id, TransferState::Paused, 40).unwrap();
+    transfers.transition(&id, TransferState::Streaming, 40).unwrap();
+    let done = transfers.transition(&id, TransferState::Completed, 100).unwrap();
+    assert_eq!(done.progress(), 100);
+    assert_eq!(done.file_path(), "/tmp/miku.txt");
+
+    // Requested, Accepted, InProgress x3, Completed.
+    assert_eq!(*statuses.lock().unwrap(), vec![1, 3, 4, 4, 4, 7]);
+
+    // Finished transfers stay finished.
+    assert!(matches!(
+        transfers.transition(&id, TransferState::Streaming, 0),
+        Err(TransferError::InvalidTransition { from: TransferState::Completed, .. })
+    ));
+    assert_eq!(statuses.lock().unwrap().len(), 6);
+}
+
+#[test]
+fn test_invalid_transitions() {
+    let transfers = TransferManager::new();
+    let id = TransferId::generate();
+    assert!(matches!(
+        transfers.transition(&id, TransferState::Accepted, 0),
+        Err(TransferError::UnknownTransfer(_))
+    ));
+
+    transfers.offer(id, String::from("10.0.0.2"), String::from("a"), 1);
+    assert!(transfers.transition(&id, TransferState::Streaming, 0).is_err());
+    assert!(transfers.transition(&id, TransferState::Completed, 1).is_err());
+    transfers.transition(&id, TransferState::Rejected, 0).unwrap();
+    assert!(transfers.transition(&id, TransferState::Accepted, 0).is_err());
+
+    let cancelled = TransferId::generate();
+    transfers.offer(cancelled, String::from("10.0.0.2"), String::from("b"), 1);
+    transfers.transition(&cancelled, TransferState::Accepted, 0).unwrap();
+    let transfer = transfers.transition(&cancelled, TransferState::Cancelled(TransferSide::Receiver), 0).unwrap();
+    assert!(transfer.state().is_finished());
+    assert_eq!(transfer.sending_packet().status().to_u8(), 6);
+}
+
+#[test]
+fn test_unanswered_offers_expire() {
+    let transfers = TransferManager::with_offer_timeout(Duration::from_millis(50));
+    let statuses = record_statuses(&transfers);
+    let late = TransferId::generate();
+    let expired = TransferId::generate();
+    let answered = TransferId::generate();
+    for id in [late, expired, answered] {
+        transfers.offer(id, String::from("10.0.0.2"), String::from("a"), 1);
+    }
+    transfers.transition(&answered, TransferState::Accepted, 0).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+
+    // Answered too late, it fails instead.
+    assert!(matches!(
+        transfers.transition(&late, TransferState::Accepted, 0),
+        Err(TransferError::OfferExpired)
+    ));
+    assert_eq!(transfers.get(&late).unwrap().state(), TransferState::Failed);
+
+    let ids = transfers.expire_offers().iter().map(|t| t.id()).collect::<Vec<TransferId>>();
+    assert_eq!(ids, vec![expired]);
+    assert!(transfers.expire_offers().is_empty());
+    assert_eq!(transfers.get(&answered).unwrap().state(), TransferState::Accepted);
+
+    // Requested x3, Accepted, Error x2.
+    assert_eq!(*statuses.lock().unwrap(), vec![1, 1, 1, 3, 8, 8]);
+}
+
+#[test]
+fn test_finished_transfers_are_forgotten() {
+    let transfers = TransferManager::new();
+    let first = TransferId::generate();
+    transfers.offer(first, String::from("10.0.0.2"), String::from("a"), 1);
+    transfers.transition(&first, TransferState::Rejected, 0).unwrap();
+    for _ in 0..100 {
+        let id = TransferId::generate();
+        transfers.offer(id, String::from("10.0.0.2"), String::from("a"), 1);
+        transfers.transition(&id, TransferState::Rejected, 0).unwrap();
+    }
+    assert!(transfers.get(&first).is_none());
+
+    // Transfers still going are kept.
+    let pending = TransferId::generate();
+    transfers.offer(pending, String::from("10.0.0.2"), String::from("a"), 1);
+    for _ in 0..100 {
+        transfers.offer(TransferId::generate(), String::from("10.0.0.2"), String::from("a"), 1);
+    }
+    assert!(transfers.get(&pending).is_some());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_sender_serves_only_offered_files() {
+    let offered = temp_file("offered", b"meant to be shared");
+    let secret = temp_file("secret", b"id_rsa");
+    let port = free_port();
+    let receiver = TcpListener::bind(("127.0.0.2", port)).unwrap();
+
+    let shutdown = ShutdownHandle::new();
+    let metrics = Arc::new(ServiceMetrics::new());
+    let context = sender_context(port, metrics.clone(), &shutdown);
+    let transfers = context.transfer_manager();
+    let service = std::thread::spawn(move || DataService::run(context, Box::new(|| false)));
+    std::thread::sleep(Duration::from_millis(200));
+
+    // Any path, under an id we never handed out.
+    let secret_path = secret.to_string_lossy().to_string();
+    respond_from("127.0.0.2", port, &FileReceiveResponsePacket::new(TransferId::generate(), 6, secret_path.clone(), true));
+    assert!(first_part(&receiver).is_none());
+    assert_eq!(metrics.unsolicited_file_requests(), 1);
+
+    // A real offer, answered by another device.
+    let id = TransferId::generate();
+    transfers.offer(id, String::from("127.0.0.2"), offered.to_string_lossy().to_string(), 18);
+    respond_from("127.0.0.3", port, &FileReceiveResponsePacket::new(id, 6, secret_path.clone(), true));
+    assert!(first_part(&receiver).is_none());
+    assert_eq!(metrics.unsolicited_file_requests(), 2);
+    assert_eq!(transfers.get(&id).unwrap().state(), TransferState::Offered);
+
+    // The receiver it was offered to gets the offered file, whatever path it names.
+    respond_from("127.0.0.2", port, &FileReceiveResponsePacket::new(id, 6, secret_path, true));
+    let part = first_part(&receiver).unwrap();
+    assert_eq!(part.transfer_id(), id);
+    assert_eq!(part.data().as_slice(), b"meant to be shared");
+
+    shutdown.stop();
+    service.join().unwrap().unwrap();
+    let _ = std::fs::remove_file(&offered);
+    let _ = std::fs::remove_file(&secret);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_legacy_receiver_answers_by_file_name() {
+    let offered = temp_file("legacy", b"for an older peer");
+    let port = free_port();
+    let receiver = TcpListener::bind(("127.0.0.2", port)).unwrap();
+
+    let shutdown = ShutdownHandle::new();
+    let context = sender_context(port, Arc::new(ServiceMetrics::new()), &shutdown);
+    let transfers = context.transfer_manager();
+    let service = std::thread::spawn(move || DataService::run(context, Box::new(|| false)));
+    std::thread::sleep(Duration::from_millis(200));
+
+    let id = TransferId::generate();
+    let offered_path = offered.to_string_lossy().to_string();
+    transfers.offer(id, String::from("127.0.0.2"), offered_path.clone(), 17);
+
+    // Older receivers name the file and pick the id, without a hello.
+    let saved_as = format!("/sdcard/Download/{}", offered.file_name().unwrap().to_string_lossy());
+    let response = FileReceiveResponsePacket::new(TransferId::from_legacy(9), 17, saved_as, true);
+    let mut dt = DataTransmit::from(connect_from("127.0.0.2", port));
+    let packet = DataPacket::new(MagicNumbers::FileReceiveResponse.value(), &response.serialize_as(TransferIdFormat::Legacy));
+    dt.send_data_progress_with_retry(&packet.serialize(), |_| ()).unwrap();
+
+    let part = first_legacy_part(&receiver);
+    assert_eq!(part.transfer_id().legacy_id(), 9);
+    assert_eq!(part.data().as_slice(), b"for an older peer");
+    assert!(wait_for_state(&transfers, &id, TransferState::Completed));
+
+    shutdown.stop();
+    service.join().unwrap().unwrap();
+    let _ = std::fs::remove_file(&offered);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_interrupted_transfer_resumes() {
+    let content = (0..100u8).collect::<Vec<u8>>();
+    let file = temp_file("resume", &content);
+    let file_path = file.to_string_lossy().to_string();
+    let port = free_port();
+
+    let shutdown = ShutdownHandle::new();
+    let context = sender_context(port, Arc::new(ServiceMetrics::new()), &shutdown);
+    let transfers = context.transfer_manager();
+    let service = std::thread::spawn(move || DataService::run(context, Box::new(|| false)));
+    std::thread::sleep(Duration::from_millis(200));
+
+    // Nobody listens at the receiver, which could resume, so the transfer waits for it.
+    let id = TransferId::generate();
+    transfers.offer(id, String::from("127.0.0.2"), file_path.clone(), 100);
+    respond_from("127.0.0.2", port, &FileReceiveResponsePacket::new(id, 100, String::from("a"), true));
+    assert!(wait_for_state(&transfers, &id, TransferState::Paused));
+
+    // Back, it has all but bytes 40..60.
+    let receiver = TcpListener::bind(("127.0.0.2", port)).unwrap();
+    let received = ByteRanges::parse("0-40,60-100").unwrap();
+    respond_from("127.0.0.2", port, &FileReceiveResponsePacket::new(id, 100, String::from("a"), true)
+        .with_received(received));
+    let part = first_part(&receiver).unwrap();
+    assert_eq!(part.offset(), 40);
+    assert_eq!(part.data().as_slice(), &content[40..60]);
+    assert!(wait_for_state(&transfers, &id, TransferState::Completed));
+
+    shutdown.stop();
+    service.join().unwrap().unwrap();
+    let _ = std::fs::remove_file(&file);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_response_with_most_ranges_fits_frame_limit() {
+    let content = (0..2 * MAX_BYTE_RANGES).map(|i| i as u8).collect::<Vec<u8>>();
+    let file = temp_file("many_ranges", &content);
+    let port = free_port();
+
+    let shutdown = ShutdownHandle::new();
+    let metrics = Arc::new(ServiceMetrics::new());
+    let context = sender_context(port, metrics.clone(), &shutdown);
+    let transfers = context.transfer_manager();
+    let service = std::thread::spawn(move || DataService::run(context, Box::new(|| false)));
+    std::thread::sleep(Duration::from_millis(200));
+
+    // The receiver has every other byte, as many ranges as a response carries.
+    let id = TransferId::generate();
+    transfers.offer(id, String::from("127.0.0.2"), file.to_string_lossy().to_string(), content.len() as u64);
+    let mut received = ByteRanges::new();
+    for i in 0..MAX_BYTE_RANGES as u64 {
+        received.insert(2 * i, 2 * i + 1);
+    }
+    assert_eq!(received.ranges().len(), MAX_BYTE_RANGES);
+    let receiver = TcpListener::bind(("127.0.0.2", port)).unwrap();
+    respond_from("127.0.0.2", port, &FileReceiveResponsePacket::new(id, content.len() as u64, String::from("a"), true)
+        .with_received(received));
+    let part = first_part(&receiver).unwrap();
+    assert_eq!(part.offset(), 1);
+    assert_eq!(part.data().as_slice(), &content[1..2]);
+    assert_eq!(metrics.oversized_frames(), 0);
+
+    shutdown.stop();
+    service.join().unwrap().unwrap();
+    let _ = std::fs::remove_file(&file);
+}
+
+#[test]
+fn test_ffi_try_send_file_returns_transfer_id() {
+    let file = temp_file("ffi_offer", b"offered through C");
+    let file_path = file.to_string_lossy().to_string();
+    let port = free_port();
+
+    let (offer_tx, offer_rx) = mpsc::channel();
+    let offer_tx = Mutex::new(offer_tx);
+    let file_coming_callback = Arc::new(Box::new(move |packet: &FileComingPacket, _: Option<&Peer>| {
+        let _ = offer_tx.lock().unwrap().send(packet.transfer_id());
+    }) as Box<dyn Fn(&FileComingPacket, Option<&Peer>) + Send + Sync>);
+    let mut context = DataServiceParts { file_coming_callback, ..DataServiceParts::default() }.into_context("127.0.0.1", port);
+    let shutdown = ShutdownHandle::new();
+    context.set_shutdown(shutdown.clone());
+    let service = std::thread::spawn(move || DataService::run(context, Box::new(|| false)));
+    std::thread::sleep(Duration::from_millis(200));
+
+    let mut addr = String::from("127.0.0.1");
+    let airx: *mut AirXService = unsafe { airx_create_service(0, 0, addr.as_mut_ptr() as *mut c_char, addr.len() as u32, port, 0) };
+    let host = "127.0.0.1";
+    let mut buffer = [0u8; 33];
+    let result = airx_try_send_file(
+        airx,
+        host.as_ptr() as *const c_char, host.len() as u32,
+        file_path.as_ptr() as *const c_char, file_path.len() as u32,
+        buffer.as_mut_ptr() as *mut c_char, buffer.len() as u32,
+    );
+    assert_eq!(result, AIRX_OK);
+    assert_eq!(buffer[32], 0);
+
+    // The id handed back is the one the receiver was offered.
+    let offered = offer_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(String::from_utf8_lossy(&buffer[..32]), offered.to_string());
+
+    shutdown.stop();
+    service.join().unwrap().unwrap();
+    unsafe { drop(Box::from_raw(airx)) };
+    let _ = std::fs::remove_file(&file);
+}
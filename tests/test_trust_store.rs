@@ -0,0 +1,275 @@
+mod common;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+use airx::network::peer::Peer;
+use airx::packet::data::file_part_packet::FilePartPacket;
+use airx::packet::data::magic_numbers::MagicNumbers;
+use airx::packet::data::text_packet::TextPacket;
+use airx::packet::data::transfer_id::{TransferId, TransferIdFormat};
+use airx::packet::protocol::serialize::Serialize;
+use airx::security::identity::{DeviceIdentity, fingerprint, verification_code};
+use airx::security::trust_store::{TrustedDevice, TrustStore, UntrustedPolicy};
+use airx::service::data_service::DataService;
+use airx::service::metrics::ServiceMetrics;
+use airx::service::quarantine::{Quarantine, QuarantinedPacket};
+use common::{DataServiceParts, free_port, text_channel};
+
+fn temp_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("airx_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    directory
+}
+
+#[test]
+fn test_verification_code_is_symmetric() {
+    let a = DeviceIdentity::generate().public_key();
+    let b = DeviceIdentity::generate().public_key();
+
+    let code = verification_code(&a, &b);
+    assert_eq!(code, verification_code(&b, &a));
+    assert_eq!(code.len(), 6);
+    assert!(code.chars().all(|c| c.is_ascii_digit()));
+    assert_eq!(fingerprint(&a).len(), 19);
+}
+
+#[test]
+fn test_identity_signature() {
+    let identity = DeviceIdentity::generate();
+    let signature = identity.sign(b"hello");
+    assert!(DeviceIdentity::verify(&identity.public_key(), b"hello", &signature));
+    assert!(!DeviceIdentity::verify(&identity.public_key(), b"hellp", &signature));
+    assert!(!DeviceIdentity::verify(&DeviceIdentity::generate().public_key(), b"hello", &signature));
+}
+
+#[test]
+fn test_identity_persists() {
+    let directory = temp_directory("identity");
+    let first = DeviceIdentity::load_or_create(&directory).unwrap();
+    let second = DeviceIdentity::load_or_create(&directory).unwrap();
+    assert_eq!(first.public_key(), second.public_key());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let key_file = std::fs::read_dir(&directory).unwrap().next().unwrap().unwrap();
+        assert_eq!(key_file.metadata().unwrap().permissions().mode() & 0o777, 0o600);
+    }
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn test_corrupted_identity_is_not_replaced() {
+    let directory = temp_directory("corrupted_identity");
+    DeviceIdentity::load_or_create(&directory).unwrap();
+    let key_file = std::fs::read_dir(&directory).unwrap().next().unwrap().unwrap().path();
+    std::fs::write(&key_file, b"garbage").unwrap();
+
+    assert!(DeviceIdentity::load_or_create(&directory).is_err());
+    assert_eq!(std::fs::read(&key_file).unwrap(), b"garbage");
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn test_trust_store_persists() {
+    let directory = temp_directory("trust_store");
+    let key = DeviceIdentity::generate().public_key();
+    let other_key = DeviceIdentity::generate().public_key();
+
+    let store = TrustStore::open(&directory).unwrap();
+    store.pair(TrustedDevice::new("device-a", "Laptop", key)).unwrap();
+    store.pair(TrustedDevice::new("device-b", "Phone", other_key)).unwrap();
+    assert!(store.is_trusted(&key));
+
+    let reopened = TrustStore::open(&directory).unwrap();
+    assert_eq!(reopened.list(), store.list());
+    assert_eq!(reopened.find("device-a").unwrap().host_name(), "Laptop");
+
+    assert!(reopened.unpair("device-a").unwrap());
+    assert!(!reopened.unpair("device-a").unwrap());
+    assert!(!TrustStore::open(&directory).unwrap().is_trusted(&key));
+    assert!(TrustStore::open(&directory).unwrap().is_trusted(&other_key));
+
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn test_attach_keeps_devices_paired_in_memory() {
+    let directory = temp_directory("trust_attach");
+    let key = DeviceIdentity::generate().public_key();
+
+    let store = TrustStore::new();
+    store.pair(TrustedDevice::new("device-a", "Laptop", key)).unwrap();
+    store.attach(&directory).unwrap();
+    assert!(TrustStore::open(&directory).unwrap().is_trusted(&key));
+
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+struct Receiver {
+    port: u16,
+    texts: mpsc::Receiver<String>,
+    metrics: Arc<ServiceMetrics>,
+    quarantine: Arc<Quarantine>,
+    stopped: Arc<AtomicBool>,
+    service: std::thread::JoinHandle<Result<(), airx::error::AirXError>>,
+}
+
+impl Receiver {
+    fn start(identity: DeviceIdentity, trust_store: Arc<TrustStore>, policy: UntrustedPolicy) -> Self {
+        let port = free_port();
+        let (text_callback, texts) = text_channel();
+        let metrics = Arc::new(ServiceMetrics::new());
+        let quarantine = Arc::new(Quarantine::new());
+
+        let mut context = DataServiceParts { text_callback, metrics: metrics.clone(), ..DataServiceParts::default() }
+            .into_context("127.0.0.1", port);
+        context.set_identity(identity);
+        context.set_trust(trust_store, policy, quarantine.clone());
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = stopped.clone();
+        let service = std::thread::spawn(move || {
+            DataService::run(context, Box::new(move || thread_stopped.load(Ordering::SeqCst)))
+        });
+        std::thread::sleep(Duration::from_millis(200));
+
+        Self { port, texts, metrics, quarantine, stopped, service }
+    }
+
+    fn send(&self, text: &str, identity: Option<&DeviceIdentity>) {
+        self.send_packet(MagicNumbers::Text, &TextPacket::new(text.to_string()).unwrap().serialize(), identity);
+    }
+
+    fn send_packet(&self, magic_number: MagicNumbers, data: &Vec<u8>, identity: Option<&DeviceIdentity>) {
+        DataService::send_once_with_retry(
+            &Peer::new(&String::from("127.0.0.1"), self.port, None),
+            self.port,
+            magic_number,
+            data,
+            Duration::from_millis(1000),
+            None,
+            identity,
+        ).unwrap();
+    }
+
+    fn stop(self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.service.join().unwrap().unwrap();
+    }
+}
+
+#[test]
+fn test_untrusted_text_is_dropped() {
+    let paired = DeviceIdentity::generate();
+    let stranger = DeviceIdentity::generate();
+    let trust_store = Arc::new(TrustStore::new());
+    trust_store.pair(TrustedDevice::new("paired", "Paired", paired.public_key())).unwrap();
+
+    let receiver = Receiver::start(DeviceIdentity::generate(), trust_store, UntrustedPolicy::Drop);
+
+    receiver.send("from stranger", Some(&stranger));
+    receiver.send("from legacy peer", None);
+    receiver.send("from paired", Some(&paired));
+
+    assert_eq!(receiver.texts.recv_timeout(Duration::from_secs(5)).unwrap(), "from paired");
+    assert!(receiver.texts.recv_timeout(Duration::from_millis(300)).is_err());
+    assert_eq!(receiver.metrics.untrusted_packets(), 2);
+    assert!(receiver.quarantine.is_empty());
+
+    receiver.stop();
+}
+
+#[test]
+fn test_untrusted_text_is_quarantined() {
+    let stranger = DeviceIdentity::generate();
+    let receiver = Receiver::start(
+        DeviceIdentity::generate(), Arc::new(TrustStore::new()), UntrustedPolicy::Quarantine);
+
+    receiver.send("held back", Some(&stranger));
+    receiver.send("also held back", None);
+    std::thread::sleep(Duration::from_millis(300));
+    assert!(receiver.texts.try_recv().is_err());
+    assert_eq!(receiver.quarantine.len(), 2);
+
+    let released = receiver.quarantine.release(&stranger.public_key());
+    assert_eq!(released.len(), 1);
+    assert_eq!(released[0].magic_number(), MagicNumbers::Text);
+    assert_eq!(released[0].transfer_id_format(), TransferIdFormat::Wide);
+    let text = TextPacket::deserialize(released[0].data()).unwrap();
+    assert_eq!(text.text(), "held back");
+    assert_eq!(receiver.quarantine.len(), 1);
+
+    receiver.stop();
+}
+
+#[test]
+fn test_pairing_delivers_quarantined_text() {
+    let stranger = DeviceIdentity::generate();
+    let trust_store = Arc::new(TrustStore::new());
+    let receiver = Receiver::start(DeviceIdentity::generate(), trust_store.clone(), UntrustedPolicy::Quarantine);
+
+    receiver.send("held until paired", Some(&stranger));
+    receiver.send("from someone else", None);
+    std::thread::sleep(Duration::from_millis(300));
+    assert!(receiver.texts.try_recv().is_err());
+
+    trust_store.pair(TrustedDevice::new("stranger", "Stranger", stranger.public_key())).unwrap();
+    assert_eq!(receiver.quarantine.deliver(&stranger.public_key()), 1);
+    assert_eq!(receiver.texts.recv_timeout(Duration::from_secs(5)).unwrap(), "held until paired");
+    assert_eq!(receiver.quarantine.len(), 1);
+
+    // Released without pairing, by address.
+    assert_eq!(receiver.quarantine.deliver_from("127.0.0.1".parse().unwrap()), 1);
+    assert_eq!(receiver.texts.recv_timeout(Duration::from_secs(5)).unwrap(), "from someone else");
+    assert!(receiver.quarantine.is_empty());
+
+    // Nothing is delivered once the data service is gone, it stays held.
+    let quarantine = receiver.quarantine.clone();
+    receiver.stop();
+    quarantine.hold(QuarantinedPacket::new(
+        MagicNumbers::Text,
+        TextPacket::new(String::from("late")).unwrap().serialize(),
+        "127.0.0.1:1".parse().unwrap(),
+        None,
+        TransferIdFormat::Wide,
+    ));
+    assert_eq!(quarantine.deliver_from("127.0.0.1".parse().unwrap()), 0);
+    assert_eq!(quarantine.len(), 1);
+}
+
+#[test]
+fn test_untrusted_file_parts_are_dropped() {
+    let stranger = DeviceIdentity::generate();
+    let receiver = Receiver::start(
+        DeviceIdentity::generate(), Arc::new(TrustStore::new()), UntrustedPolicy::Quarantine);
+
+    let part = FilePartPacket::new(TransferId::generate(), 0, 4, vec![1, 2, 3, 4]);
+    receiver.send_packet(MagicNumbers::FilePart, &part.serialize(), Some(&stranger));
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(receiver.metrics.untrusted_packets(), 1);
+    assert!(receiver.quarantine.is_empty());
+
+    receiver.stop();
+}
+
+#[test]
+fn test_allow_policy_keeps_legacy_behavior() {
+    let receiver = Receiver::start(
+        DeviceIdentity::generate(), Arc::new(TrustStore::new()), UntrustedPolicy::Allow);
+
+    receiver.send("hello", None);
+    receiver.send("hello again", Some(&DeviceIdentity::generate()));
+    let mut texts = vec![
+        receiver.texts.recv_timeout(Duration::from_secs(5)).unwrap(),
+        receiver.texts.recv_timeout(Duration::from_secs(5)).unwrap(),
+    ];
+    texts.sort();
+    assert_eq!(texts, vec!["hello", "hello again"]);
+    assert_eq!(receiver.metrics.untrusted_packets(), 0);
+
+    receiver.stop();
+}